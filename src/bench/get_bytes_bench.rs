@@ -0,0 +1,84 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicU64, Ordering};
+use bytes::Bytes;
+use criterion::{Criterion, criterion_group, criterion_main};
+use itertools::Itertools;
+use tempfile::TempDir;
+use kip_db::kernel::KVStore;
+use kip_db::kernel::lsm::lsm_kv::LsmStore;
+
+/// 统计内存分配次数与字节数的全局分配器，用于对比不同读取方式的分配
+/// 会为每次分配增加原子计数的开销，因此独立于其他bench，避免影响其测量结果
+struct CountingAllocator;
+
+static ALLOC_COUNT: AtomicU64 = AtomicU64::new(0);
+
+static ALLOC_BYTES: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOC_COUNT.fetch_add(1, Ordering::Relaxed);
+        let _ = ALLOC_BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// 统计执行f期间的分配次数与分配字节数
+fn count_allocs<T>(f: impl FnOnce() -> T) -> (u64, u64) {
+    let (start_count, start_bytes) = (ALLOC_COUNT.load(Ordering::Relaxed), ALLOC_BYTES.load(Ordering::Relaxed));
+    let _ = f();
+    (ALLOC_COUNT.load(Ordering::Relaxed) - start_count, ALLOC_BYTES.load(Ordering::Relaxed) - start_bytes)
+}
+
+/// 大value读取后在多处共享时get与get_bytes的对比
+/// get返回的Vec<u8>每次共享都需要拷贝，get_bytes返回的Bytes仅增加引用计数
+fn get_bytes_benchmark(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let key: Vec<u8> = bincode::serialize("key1").unwrap();
+    let store = rt.block_on(async {
+        let store = LsmStore::open(temp_dir.path()).await.unwrap();
+        store.set(&key, vec![b'v'; 1024 * 1024]).await.unwrap();
+        store.flush().await.unwrap();
+        store
+    });
+    let store = &store;
+    let key = &key;
+
+    let share_with_get = || async {
+        let value = store.get(key).await.unwrap().unwrap();
+        (0..4).map(|_| value.clone()).collect_vec()
+    };
+    let share_with_get_bytes = || async {
+        let bytes = store.get_bytes(key).await.unwrap().unwrap();
+        (0..4).map(|_| bytes.clone()).collect_vec()
+    };
+
+    // 预热缓存后校验单次读取并共享时的分配，get_bytes共享时不会拷贝1MB的value
+    let _ = rt.block_on(share_with_get());
+    let _: Vec<Bytes> = rt.block_on(share_with_get_bytes());
+    let (get_allocs, get_alloc_bytes) = count_allocs(|| rt.block_on(share_with_get()));
+    let (get_bytes_allocs, get_bytes_alloc_bytes) = count_allocs(|| rt.block_on(share_with_get_bytes()));
+    assert!(get_bytes_allocs < get_allocs);
+    assert!(get_bytes_alloc_bytes + 3 * 1024 * 1024 < get_alloc_bytes);
+
+    c.bench_function("LsmStore: get 1MB value and share by 4 holders", |b|
+        b.to_async(&rt).iter(share_with_get));
+
+    c.bench_function("LsmStore: get_bytes 1MB value and share by 4 holders", |b|
+        b.to_async(&rt).iter(share_with_get_bytes));
+}
+
+criterion_group!(benches, get_bytes_benchmark);
+criterion_main!(benches);
@@ -1,9 +1,14 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 use chrono::Local;
 use criterion::{Criterion, criterion_group, criterion_main};
+use futures::future;
 use itertools::Itertools;
 use tempfile::TempDir;
 use kip_db::kernel::{KVStore, hash_kv::HashStore};
-use kip_db::kernel::lsm::lsm_kv::LsmStore;
+use kip_db::kernel::lsm::lsm_kv::{Config, LsmStore};
+use kip_db::kernel::sharded_kv::ShardedStore;
 use kip_db::kernel::sled_kv::SledStore;
 use kip_db::kernel::Result;
 
@@ -78,11 +83,373 @@ fn kv_benchmark(c: &mut Criterion) {
     kv_benchmark_with_store::<SledStore>(c);
 }
 
+/// LsmStore并发写入下wal同步写入与组提交的bench对比
+fn group_commit_benchmark(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let vec_config = vec![
+        ("wal sync put", Config::default().wal_async_put_enable(false)),
+        ("wal group commit", Config::default().group_commit_interval(Duration::from_micros(100))),
+    ];
+
+    for (test_name, config) in vec_config {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let store = rt.block_on(async {
+            LsmStore::open_with_config(config.dir_path(temp_dir.path().to_path_buf())).await.unwrap()
+        });
+        let store = &store;
+        let count = AtomicU64::new(0);
+
+        c.bench_function(&store_name_with_test::<LsmStore>(&format!("concurrent set 100 with {}", test_name)), |b|
+            b.to_async(&rt).iter(|| {
+                async {
+                    let map_set = (0..100)
+                        .map(|_| {
+                            let key = bincode::serialize(&count.fetch_add(1, Ordering::Relaxed)).unwrap();
+                            async move {
+                                store.set(&key, key.clone()).await
+                            }
+                        });
+                    let _ = future::try_join_all(map_set).await
+                        .unwrap();
+                }
+            }));
+    }
+}
+
+/// 大value负载下LsmStore开启与关闭Key-Value分离的压缩bench对比
+/// Key-Value分离时SSTable仅存储Key与指针，压缩时的写入量更小
+fn kv_separation_benchmark(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let vec_config = vec![
+        ("without kv separation", Config::default().kv_separation_enable(false)),
+        ("with kv separation", Config::default().kv_separation_enable(true)),
+    ];
+
+    for (test_name, config) in vec_config {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let store = rt.block_on(async {
+            LsmStore::open_with_config(config
+                .dir_path(temp_dir.path().to_path_buf())
+                .level_sst_magnification(2)
+                .wal_enable(false)
+            ).await.unwrap()
+        });
+        let store = &store;
+        let count = AtomicU64::new(0);
+
+        c.bench_function(&store_name_with_test::<LsmStore>(&format!("set 100 16KB value and compaction {}", test_name)), |b|
+            b.to_async(&rt).iter(|| {
+                async {
+                    for _ in 0..100 {
+                        let key = bincode::serialize(&count.fetch_add(1, Ordering::Relaxed)).unwrap();
+                        store.set(&key, vec![b'v'; 16 * 1024]).await
+                            .unwrap();
+                    }
+                    store.flush().await
+                        .unwrap();
+                }
+            }));
+    }
+}
+
+/// 并发写入下单实例与多分片的吞吐bench对比
+/// 各分片的锁互相独立，多分片时并发的写入不会在同一把锁上排队
+fn sharded_store_benchmark(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    fn concurrent_set<T: KVStore + Sync>(c: &mut Criterion, rt: &tokio::runtime::Runtime, store: Arc<T>) {
+        let count = Arc::new(AtomicU64::new(0));
+
+        c.bench_function(&store_name_with_test::<T>("concurrent set 100"), |b|
+            b.to_async(rt).iter(|| {
+                let store = Arc::clone(&store);
+                let count = Arc::clone(&count);
+                async move {
+                    let vec_handle = (0..100)
+                        .map(|_| {
+                            let store = Arc::clone(&store);
+                            let key = bincode::serialize(&count.fetch_add(1, Ordering::Relaxed)).unwrap();
+                            tokio::spawn(async move {
+                                store.set(&key, key.clone()).await
+                            })
+                        })
+                        .collect_vec();
+                    for handle in vec_handle {
+                        handle.await.unwrap().unwrap();
+                    }
+                }
+            }));
+    }
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let (single_store, sharded_store) = rt.block_on(async {
+        (
+            Arc::new(HashStore::open(temp_dir.path().join("single")).await.unwrap()),
+            Arc::new(ShardedStore::<HashStore>::open(temp_dir.path().join("sharded")).await.unwrap())
+        )
+    });
+    concurrent_set(c, &rt, single_store);
+    concurrent_set(c, &rt, sharded_store);
+}
+
+/// Key的候选SSTable分布于多个Level时LsmStore的读取bench
+/// 候选SSTable由新往旧逐个查询并在命中后停止，以命中最浅Level的读取作为串行查询的基准，
+/// 与需查询所有Level的读取进行对比
+fn multi_level_get_benchmark(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    // 缓存设为最小以使每次读取都需要读取数据段
+    let config = Config::default()
+        .dir_path(temp_dir.path().to_path_buf())
+        .minor_threshold_with_data_size(4 * 1024)
+        .sst_file_size(16 * 1024)
+        .level_sst_magnification(1)
+        .cache_size(1)
+        .wal_enable(false);
+    let store = rt.block_on(async {
+        let store = LsmStore::open_with_config(config).await.unwrap();
+        // 每轮写入的数据Key交错且范围相同，写入后由深往浅逐层下压，
+        // 使越早写入的数据所处的Level越深，每个Level都存在Key可能处于其范围内的SSTable
+        for round in 0..5_u32 {
+            for i in (0..2000_u32).filter(|i| i % 5 == round) {
+                let key = bincode::serialize(&i).unwrap();
+                store.set(&key, key.clone()).await.unwrap();
+            }
+            store.flush().await.unwrap();
+            for level in (0..=round as usize).rev() {
+                store.major_compaction_sync(level).await.unwrap();
+            }
+        }
+        store
+    });
+    let store = &store;
+    let count = AtomicU64::new(0);
+    let count = &count;
+
+    c.bench_function(&store_name_with_test::<LsmStore>("get key in shallowest level"), |b|
+        b.to_async(&rt).iter(|| async {
+            let i = (count.fetch_add(1, Ordering::Relaxed) % 400) as u32 * 5 + 4;
+            store.get(&bincode::serialize(&i).unwrap()).await
+                .unwrap()
+        }));
+
+    c.bench_function(&store_name_with_test::<LsmStore>("get key in deepest level"), |b|
+        b.to_async(&rt).iter(|| async {
+            let i = (count.fetch_add(1, Ordering::Relaxed) % 400) as u32 * 5;
+            store.get(&bincode::serialize(&i).unwrap()).await
+                .unwrap()
+        }));
+
+    c.bench_function(&store_name_with_test::<LsmStore>("get not exist key with candidates in all levels"), |b|
+        b.to_async(&rt).iter(|| async {
+            let i = (count.fetch_add(1, Ordering::Relaxed) % 2000) as u32;
+            let key = bincode::serialize(&i).unwrap()
+                .into_iter()
+                .chain([0])
+                .collect_vec();
+            store.get(&key).await
+                .unwrap()
+        }));
+}
+
+/// 突发写入下LsmStore开启与关闭自适应压缩调度的写入延迟对比
+/// 固定阈值时突发写入会频繁触发落盘与压缩，自适应调度时按写入速率与积压调整落盘阈值与并发度，
+/// 分别统计各次突发写入的耗时，以及每次突发中逐条写入延迟的标准差(突发之间留有空闲期且不计入耗时)，
+/// 标准差越小延迟越平滑
+fn adaptive_compaction_benchmark(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let vec_config = vec![
+        ("fixed threshold", Config::default()),
+        ("adaptive compaction", Config::default().adaptive_compaction(true)),
+    ];
+
+    for (test_name, config) in vec_config {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let store = rt.block_on(async {
+            LsmStore::open_with_config(config
+                .dir_path(temp_dir.path().to_path_buf())
+                .minor_threshold_with_data_size(256 * 1024)
+                .wal_enable(false)
+            ).await.unwrap()
+        });
+        let store = &store;
+        let count = AtomicU64::new(0);
+        let count = &count;
+        let burst_set = || async move {
+            for _ in 0..1000 {
+                let key = bincode::serialize(&count.fetch_add(1, Ordering::Relaxed)).unwrap();
+                store.set(&key, vec![b'v'; 1024]).await
+                    .unwrap();
+            }
+        };
+
+        c.bench_function(&store_name_with_test::<LsmStore>(&format!("burst set 1000 1KB value with {}", test_name)), |b|
+            b.to_async(&rt).iter(burst_set));
+        c.bench_function(&store_name_with_test::<LsmStore>(&format!("burst set 1000 1KB value latency std dev with {}", test_name)), |b|
+            b.to_async(&rt).iter_custom(|iters| async move {
+                let mut total = Duration::ZERO;
+                for _ in 0..iters {
+                    let mut vec_latency = Vec::with_capacity(1000);
+                    for _ in 0..1000 {
+                        let key = bincode::serialize(&count.fetch_add(1, Ordering::Relaxed)).unwrap();
+                        let start = Instant::now();
+                        store.set(&key, vec![b'v'; 1024]).await
+                            .unwrap();
+                        vec_latency.push(start.elapsed().as_secs_f64());
+                    }
+                    let mean = vec_latency.iter().sum::<f64>() / vec_latency.len() as f64;
+                    let variance = vec_latency.iter()
+                        .map(|latency| (latency - mean).powi(2))
+                        .sum::<f64>() / vec_latency.len() as f64;
+                    total += Duration::from_secs_f64(variance.sqrt());
+                    // 突发之间的空闲期
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                }
+                total
+            }));
+    }
+}
+
+/// 大MemTable落盘时逐个与并行序列化data block的耗时对比
+/// 仅统计minor_compaction_sync的耗时，并行序列化时落盘耗时应随并行度下降
+fn minor_compaction_serialize_benchmark(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let vec_config = vec![
+        ("serialize sequentially", Config::default().serialize_parallelism(1)),
+        ("serialize in parallel", Config::default().serialize_parallelism(4)),
+    ];
+
+    for (test_name, config) in vec_config {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let store = rt.block_on(async {
+            LsmStore::open_with_config(config
+                .dir_path(temp_dir.path().to_path_buf())
+                .minor_threshold_with_data_size(256 * 1024 * 1024)
+                .wal_enable(false)
+            ).await.unwrap()
+        });
+        let store = &store;
+        let count = AtomicU64::new(0);
+        let count = &count;
+
+        c.bench_function(&store_name_with_test::<LsmStore>(&format!("minor compaction 20000 256B value with {}", test_name)), |b|
+            b.to_async(&rt).iter_custom(|iters| async move {
+                let mut elapsed = Duration::ZERO;
+                for _ in 0..iters {
+                    for _ in 0..20000 {
+                        let key = bincode::serialize(&count.fetch_add(1, Ordering::Relaxed)).unwrap();
+                        store.set(&key, vec![b'v'; 256]).await
+                            .unwrap();
+                    }
+                    let start = Instant::now();
+                    store.minor_compaction_sync().await
+                        .unwrap();
+                    elapsed += start.elapsed();
+                }
+                elapsed
+            }));
+    }
+}
+
+/// MemTable读取在并发写入下的bench
+/// MemTable的读取基于无锁的只读快照，不与写入竞争同一把锁，
+/// 后台持续写入时get的延迟应与无写入时相近，可通过criterion的baseline与加锁读取的实现进行对比
+fn mem_table_concurrent_get_benchmark(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    // 落盘阈值足够大，使读写均只涉及MemTable
+    let config = Config::default()
+        .dir_path(temp_dir.path().to_path_buf())
+        .minor_threshold_with_data_size(1024 * 1024 * 1024)
+        .wal_enable(false);
+    let store = rt.block_on(async {
+        let store = LsmStore::open_with_config(config).await.unwrap();
+        for i in 0..10000_u32 {
+            let key = bincode::serialize(&i).unwrap();
+            store.set(&key, key.clone()).await.unwrap();
+        }
+        Arc::new(store)
+    });
+    let count = Arc::new(AtomicU64::new(0));
+
+    for (test_name, writer_count) in [("without background set", 0), ("with 4 background set tasks", 4)] {
+        let is_stopped = Arc::new(AtomicBool::new(false));
+        let vec_writer = (0..writer_count)
+            .map(|writer_id: u32| {
+                let store = Arc::clone(&store);
+                let is_stopped = Arc::clone(&is_stopped);
+                rt.spawn(async move {
+                    let mut i = 0_u32;
+                    while !is_stopped.load(Ordering::Relaxed) {
+                        let key = bincode::serialize(&(writer_id, i % 10000)).unwrap();
+                        store.set(&key, key.clone()).await.unwrap();
+                        i = i.wrapping_add(1);
+                    }
+                })
+            })
+            .collect_vec();
+
+        c.bench_function(&store_name_with_test::<LsmStore>(&format!("concurrent get 100 in MemTable {}", test_name)), |b|
+            b.to_async(&rt).iter(|| {
+                let store = Arc::clone(&store);
+                let count = Arc::clone(&count);
+                async move {
+                    let vec_handle = (0..100)
+                        .map(|_| {
+                            let store = Arc::clone(&store);
+                            let key = bincode::serialize(&((count.fetch_add(1, Ordering::Relaxed) % 10000) as u32)).unwrap();
+                            tokio::spawn(async move {
+                                store.get(&key).await
+                            })
+                        })
+                        .collect_vec();
+                    for handle in vec_handle {
+                        let _ = handle.await.unwrap().unwrap();
+                    }
+                }
+            }));
+
+        is_stopped.store(true, Ordering::Relaxed);
+        rt.block_on(async {
+            for writer in vec_writer {
+                writer.await.unwrap();
+            }
+        });
+    }
+}
+
 fn store_name_with_test<T: KVStore>(test_name :& str) -> String {
     format!("{}: {}",T::name(), test_name)
 }
 
-criterion_group!(benches, kv_benchmark);
+criterion_group!(benches, kv_benchmark, group_commit_benchmark, kv_separation_benchmark, sharded_store_benchmark, multi_level_get_benchmark, adaptive_compaction_benchmark, minor_compaction_serialize_benchmark, mem_table_concurrent_get_benchmark);
 criterion_main!(benches);
 
 // 测试用序列化方法
@@ -22,6 +22,11 @@ struct Cli {
 
     #[clap(long, default_value_t = DEFAULT_PORT)]
     port: u16,
+
+    /// 通过Unix domain socket文件路径进行连接，指定时忽略hostname与port
+    #[cfg(unix)]
+    #[clap(long)]
+    uds: Option<String>,
 }
 
 /// Entry point for CLI tool.
@@ -43,6 +48,12 @@ async fn main() -> Result<()> {
 
     let addr = format!("{}:{}", cli.host, cli.port);
 
+    #[cfg(unix)]
+    let mut client = match &cli.uds {
+        Some(path) => Client::connect_uds(path).await?,
+        None => Client::connect(&addr).await?
+    };
+    #[cfg(not(unix))]
     let mut client = Client::connect(&addr).await?;
 
     match cli.command {
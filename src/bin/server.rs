@@ -1,8 +1,10 @@
+use std::time::Duration;
 use clap::Parser;
 use tokio::net::TcpListener;
 
 use kip_db::{DEFAULT_PORT, LOCAL_IP};
 use kip_db::net::{server, Result};
+use kip_db::net::server::Transport;
 
 /// 服务启动方法
 /// 二进制执行文件调用方法:./kip-db-cli
@@ -11,13 +13,26 @@ pub async fn main() -> Result<()> {
     tracing_subscriber::fmt::try_init().unwrap();
 
     let cli = Cli::parse();
+    let command_timeout = cli.command_timeout_ms
+        .map_or(server::DEFAULT_COMMAND_TIMEOUT, Duration::from_millis);
+
+    #[cfg(unix)]
+    {
+        if let Some(path) = cli.uds {
+            // 绑定Unix domain socket监听
+            server::run_with_command_timeout(Transport::bind_uds(path)?, quit(), command_timeout).await?;
+
+            return Ok(());
+        }
+    }
+
     let ip = cli.ip.unwrap_or(LOCAL_IP.to_string());
     let port = cli.port.unwrap_or(DEFAULT_PORT);
 
     // Bind a TCP listener
     let listener = TcpListener::bind(&format!("{ip}:{port}")).await?;
 
-    server::run(listener, quit()).await?;
+    server::run_with_command_timeout(Transport::Tcp(listener), quit(), command_timeout).await?;
 
     Ok(())
 }
@@ -45,5 +60,12 @@ struct Cli {
     #[clap(long)]
     ip: Option<String>,
     #[clap(long)]
-    port: Option<u16>
+    port: Option<u16>,
+    /// 单个只读命令的处理超时时间(毫秒)，写入命令不设超时
+    #[clap(long)]
+    command_timeout_ms: Option<u64>,
+    /// 使用Unix domain socket文件路径进行监听，指定时忽略ip与port
+    #[cfg(unix)]
+    #[clap(long)]
+    uds: Option<String>
 }
\ No newline at end of file
@@ -1,8 +1,12 @@
 use std::io;
 use failure::Fail;
 use tokio::sync::oneshot::error::RecvError;
+use crate::kernel::FileKind;
 
 /// Error type for kvs
+///
+/// 各变体均有通过`KvsError::code`获取的稳定错误码，供跨语言、跨版本的客户端识别错误
+/// 新增变体时需分配新的错误码，已有变体的错误码不可修改
 #[derive(Fail, Debug)]
 #[non_exhaustive]
 pub enum KvsError {
@@ -45,11 +49,169 @@ pub enum KvsError {
     #[fail(display = "Could not found the SSTable")]
     SSTableLostError,
 
+    /// Manifest自检时发现level_slice与ss_tables_map不一致
+    #[fail(display = "Manifest inconsistent: {}", _0)]
+    ManifestInconsistent(String),
+
+    /// 开启时日志文件或SSTable文件损坏
+    /// kind为损坏文件的种类，gen为损坏文件的序号，offset为损坏数据在文件中的偏移量
+    #[fail(display = "Corrupted {:?} file: gen {} at offset {}, reason: {}", kind, gen, offset, reason)]
+    CorruptedFile { kind: FileKind, gen: i64, offset: u64, reason: String },
+
+    /// 数据文件的格式版本无法识别
+    #[fail(display = "Unsupported file format version: {}", _0)]
+    UnsupportedFormatVersion(String),
+
+    /// SSTable中的ValuePtr所指向的vLog数据不存在
+    #[fail(display = "Could not found the value in value log")]
+    ValueLogLostError,
+
     /// Unexpected command type error.
     /// It indicated a corrupted log or a program bug.
     #[fail(display = "Unexpected command type")]
     UnexpectedCommandType,
 
+    /// SSTable中经lz4压缩的value解压失败
+    #[fail(display = "Decompress error: {}", _0)]
+    DecompressError(String),
+
+    /// 分片存储开启时目录中已存在的分片数量与指定的分片数量不一致
+    #[fail(display = "Shard num mismatch: expected {}, found {}", expected, found)]
+    ShardNumMismatch { expected: usize, found: usize },
+
+    /// 磁盘空间不足，由`io::ErrorKind::StorageFull`映射而来
+    /// 此时写入不会生效，释放空间后可继续写入
+    #[fail(display = "Disk is full")]
+    DiskFull,
+
+    /// 事务的数据大小超出服务端的上限
+    #[fail(display = "Transaction too large: {} bytes, limit {} bytes", size, limit)]
+    TransactionTooLarge { size: usize, limit: usize },
+
+    /// Key未通过`Config::validate_key`所注入的校验器的校验
+    #[fail(display = "Invalid key: {}", _0)]
+    InvalidKey(String),
+
+    /// 目录已被其他实例开启，即目录中的LOCK文件已被占用
+    #[fail(display = "Directory is already opened by another instance")]
+    AlreadyOpen,
+
+    /// 读取文件时数据不足，即文件中由start起始的数据少于len
+    #[fail(display = "Unexpected EOF: {}.log at {} with len {}", gen, start, len)]
+    UnexpectedEof { gen: i64, start: u64, len: usize },
+
+    /// 组提交时该时间窗口的写盘失败
+    #[fail(display = "Group commit error: {}", _0)]
+    GroupCommitError(String),
+
+    /// 存在Merge数据而未设置`Config::merge_operator`
+    #[fail(display = "Merge operator is not set")]
+    MergeOperatorNotSet,
+
+    /// 在异步运行时中调用同步接口，阻塞等待会导致运行时panic
+    #[fail(display = "Cannot block on the KVStore within an asynchronous runtime")]
+    NestedRuntime,
+
+    /// 校验和清单中记录的SSTable已被外部修改或删除，内容为这些SSTable的Gen
+    #[fail(display = "SSTables changed outside: {:?}", _0)]
+    ChecksumManifestMismatch(Vec<i64>),
+
+    /// 无法解析的存储引擎名称
+    #[fail(display = "Unknown engine: {}", _0)]
+    UnknownEngine(String),
+
+    /// 开启时检测到其他进程仍在写入该目录，内容为其pid
+    #[fail(display = "Directory is being written by another process: {}", _0)]
+    ActiveWriter(u32),
+
+    /// 数据解密失败，密钥错误或数据已被篡改
+    #[fail(display = "Failed to decrypt data, the key is wrong or the data has been tampered")]
+    CipherError,
+
+    /// 该配置项不支持运行时热更新，内容为配置项名称
+    #[fail(display = "Config cannot be reloaded at runtime: {}", _0)]
+    ConfigNotReloadable(String),
+
+    /// 读取的范围越出文件末尾，即start与len之和大于文件大小
+    #[fail(display = "Out of bounds: {}.log at {} with len {} exceeds file size {}", gen, start, len, file_size)]
+    OutOfBounds { gen: i64, start: u64, len: usize, file_size: u64 },
+
+    /// 相互冲突、无法同时启用的配置项，内容为冲突的配置项名称
+    #[fail(display = "Conflicting config: {}", _0)]
+    ConfigConflict(String),
+
+}
+
+impl KvsError {
+    /// 获取错误的稳定错误码
+    #[inline]
+    pub fn code(&self) -> u32 {
+        match self {
+            KvsError::Io(_) => 1,
+            KvsError::Recv(_) => 2,
+            KvsError::SerdeMPEncode(_) => 3,
+            KvsError::SerdeMPDecode(_) => 4,
+            KvsError::SerdeBinCode(_) => 5,
+            KvsError::KeyNotFound => 6,
+            KvsError::DataEmpty => 7,
+            KvsError::LevelOver => 8,
+            KvsError::NotMatchCmd => 9,
+            KvsError::CrcMisMatch => 10,
+            KvsError::CacheSizeOverFlow => 11,
+            KvsError::Sled(_) => 12,
+            KvsError::FileNotFound => 13,
+            KvsError::WalLoadError => 14,
+            KvsError::SSTableLostError => 15,
+            KvsError::ManifestInconsistent(_) => 16,
+            KvsError::CorruptedFile { .. } => 17,
+            KvsError::UnsupportedFormatVersion(_) => 18,
+            KvsError::ValueLogLostError => 19,
+            KvsError::UnexpectedCommandType => 20,
+            KvsError::DecompressError(_) => 21,
+            KvsError::ShardNumMismatch { .. } => 22,
+            KvsError::DiskFull => 23,
+            KvsError::TransactionTooLarge { .. } => 24,
+            KvsError::InvalidKey(_) => 25,
+            KvsError::AlreadyOpen => 26,
+            KvsError::UnexpectedEof { .. } => 27,
+            KvsError::GroupCommitError(_) => 28,
+            KvsError::MergeOperatorNotSet => 29,
+            KvsError::NestedRuntime => 30,
+            KvsError::ChecksumManifestMismatch(_) => 31,
+            KvsError::UnknownEngine(_) => 32,
+            KvsError::ActiveWriter(_) => 33,
+            KvsError::CipherError => 34,
+            KvsError::ConfigNotReloadable(_) => 35,
+            KvsError::OutOfBounds { .. } => 36,
+            KvsError::ConfigConflict(_) => 37
+        }
+    }
+
+    /// 通过错误码还原错误
+    ///
+    /// 错误码仅能还原不携带数据的变体，携带数据的变体与未知的错误码返回None
+    #[inline]
+    pub fn from_code(code: u32) -> Option<Self> {
+        match code {
+            6 => Some(KvsError::KeyNotFound),
+            7 => Some(KvsError::DataEmpty),
+            8 => Some(KvsError::LevelOver),
+            9 => Some(KvsError::NotMatchCmd),
+            10 => Some(KvsError::CrcMisMatch),
+            11 => Some(KvsError::CacheSizeOverFlow),
+            13 => Some(KvsError::FileNotFound),
+            14 => Some(KvsError::WalLoadError),
+            15 => Some(KvsError::SSTableLostError),
+            19 => Some(KvsError::ValueLogLostError),
+            20 => Some(KvsError::UnexpectedCommandType),
+            23 => Some(KvsError::DiskFull),
+            26 => Some(KvsError::AlreadyOpen),
+            29 => Some(KvsError::MergeOperatorNotSet),
+            30 => Some(KvsError::NestedRuntime),
+            34 => Some(KvsError::CipherError),
+            _ => None
+        }
+    }
 }
 
 #[derive(Fail, Debug)]
@@ -71,8 +233,28 @@ pub enum ConnectionError {
     SerdeMPDecode(#[cause] rmp_serde::decode::Error),
     #[fail(display = "server flush error")]
     RemoteFlushError,
+    /// 服务端拒绝或回滚了事务
+    #[fail(display = "transaction aborted: {}", _0)]
+    TransactionAborted(String),
+    /// 服务端处理命令超时
+    #[fail(display = "server command timeout")]
+    Timeout,
     #[fail(display = "{}", _0)]
     KvStoreError(#[cause] KvsError),
+    /// 服务端处理失败的错误码与错误信息，其对应的错误携带数据而无法通过`KvsError::from_code`还原
+    #[fail(display = "remote error with code {}: {}", _0, _1)]
+    RemoteError(u32, String),
+}
+
+impl ConnectionError {
+    /// 通过服务端回复的错误码与错误信息获取错误
+    ///
+    /// 错误码无法还原时保留错误信息，如`KvsError::TransactionTooLarge`中的大小
+    #[inline]
+    pub fn from_remote(code: u32, message: String) -> Self {
+        KvsError::from_code(code)
+            .map_or(ConnectionError::RemoteError(code, message), ConnectionError::KvStoreError)
+    }
 }
 
 impl From<io::Error> for ConnectionError {
@@ -85,7 +267,10 @@ impl From<io::Error> for ConnectionError {
 impl From<io::Error> for KvsError {
     #[inline]
     fn from(err: io::Error) -> Self {
-        KvsError::Io(err)
+        match err.kind() {
+            io::ErrorKind::StorageFull => KvsError::DiskFull,
+            _ => KvsError::Io(err)
+        }
     }
 }
 
@@ -150,4 +335,83 @@ impl From<KvsError> for ConnectionError {
     fn from(err: KvsError) -> Self {
         ConnectionError::KvStoreError(err)
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_error_code_stability() {
+    use std::collections::HashSet;
+    use tokio::sync::oneshot;
+
+    let recv_error = tokio_test::block_on(async {
+        let (sender, receiver) = oneshot::channel::<()>();
+        drop(sender);
+        receiver.await
+    }).expect_err("sender is dropped");
+    // 错误码一经分配便不可修改
+    let errors_with_code = [
+        (KvsError::Io(io::Error::from(io::ErrorKind::Other)), 1),
+        (KvsError::Recv(recv_error), 2),
+        (KvsError::SerdeMPEncode(rmp_serde::encode::Error::Syntax(String::new())), 3),
+        (KvsError::SerdeMPDecode(rmp_serde::decode::Error::Syntax(String::new())), 4),
+        (KvsError::SerdeBinCode(Box::new(bincode::ErrorKind::Custom(String::new()))), 5),
+        (KvsError::KeyNotFound, 6),
+        (KvsError::DataEmpty, 7),
+        (KvsError::LevelOver, 8),
+        (KvsError::NotMatchCmd, 9),
+        (KvsError::CrcMisMatch, 10),
+        (KvsError::CacheSizeOverFlow, 11),
+        (KvsError::Sled(sled::Error::Unsupported(String::new())), 12),
+        (KvsError::FileNotFound, 13),
+        (KvsError::WalLoadError, 14),
+        (KvsError::SSTableLostError, 15),
+        (KvsError::ManifestInconsistent(String::new()), 16),
+        (KvsError::CorruptedFile { kind: FileKind::Log, gen: 0, offset: 0, reason: String::new() }, 17),
+        (KvsError::UnsupportedFormatVersion(String::new()), 18),
+        (KvsError::ValueLogLostError, 19),
+        (KvsError::UnexpectedCommandType, 20),
+        (KvsError::DecompressError(String::new()), 21),
+        (KvsError::ShardNumMismatch { expected: 0, found: 0 }, 22),
+        (KvsError::DiskFull, 23),
+        (KvsError::TransactionTooLarge { size: 0, limit: 0 }, 24),
+        (KvsError::InvalidKey(String::new()), 25),
+        (KvsError::AlreadyOpen, 26),
+        (KvsError::UnexpectedEof { gen: 0, start: 0, len: 0 }, 27),
+        (KvsError::GroupCommitError(String::new()), 28),
+        (KvsError::MergeOperatorNotSet, 29),
+        (KvsError::NestedRuntime, 30),
+        (KvsError::ChecksumManifestMismatch(Vec::new()), 31),
+        (KvsError::UnknownEngine(String::new()), 32),
+        (KvsError::ActiveWriter(0), 33),
+        (KvsError::CipherError, 34),
+        (KvsError::ConfigNotReloadable(String::new()), 35),
+        (KvsError::OutOfBounds { gen: 0, start: 0, len: 0, file_size: 0 }, 36),
+        (KvsError::ConfigConflict(String::new()), 37),
+    ];
+
+    let mut codes = HashSet::new();
+    for (error, code) in errors_with_code {
+        assert_eq!(error.code(), code, "code of {:?} changed", error);
+        assert!(codes.insert(code));
+        // 可还原的错误码还原后错误码不变
+        if let Some(restored) = KvsError::from_code(code) {
+            assert_eq!(restored.code(), code);
+        }
+    }
+    assert!(KvsError::from_code(0).is_none());
+    assert!(KvsError::from_code(u32::MAX).is_none());
+    assert!(matches!(KvsError::from_code(6), Some(KvsError::KeyNotFound)));
+    assert!(KvsError::from_code(24).is_none());
+    assert!(matches!(ConnectionError::from_remote(6, String::new()), ConnectionError::KvStoreError(KvsError::KeyNotFound)));
+    let message = KvsError::TransactionTooLarge { size: 2, limit: 1 }.to_string();
+    let remote_error = ConnectionError::from_remote(24, message.clone());
+    assert!(matches!(&remote_error, ConnectionError::RemoteError(24, remote_message) if *remote_message == message));
+    assert!(remote_error.to_string().contains(&message));
+}
+
+#[test]
+fn test_corrupted_file_display() {
+    let error = KvsError::CorruptedFile { kind: FileKind::SsTable, gen: 7, offset: 16, reason: KvsError::CrcMisMatch.to_string() };
+    let display = error.to_string();
+    assert!(display.contains("SsTable"));
+    assert!(!display.contains(".log"));
+}
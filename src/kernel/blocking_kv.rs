@@ -0,0 +1,127 @@
+use std::future::Future;
+use std::path::PathBuf;
+use tokio::runtime::{Builder, Handle, Runtime};
+use crate::kernel::{KVStore, Result};
+use crate::KvsError;
+
+/// 同步存储
+///
+/// 对底层内核的包装，内部持有独立的tokio运行时并以`Runtime::block_on`执行各操作，
+/// 使同步代码无需自行引入运行时即可读写
+/// 在异步运行时中调用时直接返回`KvsError::NestedRuntime`，而不是像`block_on`一样panic
+#[derive(Debug)]
+pub struct BlockingStore<K: KVStore> {
+    kv_store: K,
+    // 仅在drop时被取出
+    runtime: Option<Runtime>
+}
+
+impl<K: KVStore> BlockingStore<K> {
+
+    /// 创建独立的运行时并开启数据库
+    ///
+    /// 运行时为多线程运行时，使内核的后台任务(如压缩)在两次调用之间也能继续执行
+    #[inline]
+    pub fn open(path: impl Into<PathBuf> + Send) -> Result<Self> {
+        if Handle::try_current().is_ok() {
+            return Err(KvsError::NestedRuntime);
+        }
+        let runtime = Builder::new_multi_thread()
+            .enable_all()
+            .build()?;
+        let kv_store = runtime.block_on(K::open(path))?;
+
+        Ok(BlockingStore { kv_store, runtime: Some(runtime) })
+    }
+
+    /// 获取底层内核
+    #[inline]
+    pub fn inner(&self) -> &K {
+        &self.kv_store
+    }
+
+    /// 设置键值对
+    #[inline]
+    pub fn set(&self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        self.block_on(self.kv_store.set(key, value))?
+    }
+
+    /// 获取Key对应的value
+    #[inline]
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.block_on(self.kv_store.get(key))?
+    }
+
+    /// 删除Key
+    #[inline]
+    pub fn remove(&self, key: &[u8]) -> Result<()> {
+        self.block_on(self.kv_store.remove(key))?
+    }
+
+    /// 强制将数据刷入硬盘
+    #[inline]
+    pub fn flush(&self) -> Result<()> {
+        self.block_on(self.kv_store.flush())?
+    }
+
+    /// 阻塞等待future完成
+    ///
+    /// 当前线程已处于某个运行时之中时返回`KvsError::NestedRuntime`
+    fn block_on<F: Future>(&self, future: F) -> Result<F::Output> {
+        match (&self.runtime, Handle::try_current()) {
+            (Some(runtime), Err(_)) => Ok(runtime.block_on(future)),
+            _ => Err(KvsError::NestedRuntime)
+        }
+    }
+}
+
+impl<K: KVStore> Drop for BlockingStore<K> {
+    /// 在异步上下文中直接drop运行时会panic，因此此时改为不阻塞地关闭运行时
+    #[inline]
+    fn drop(&mut self) {
+        if let Some(runtime) = self.runtime.take() {
+            if Handle::try_current().is_ok() {
+                runtime.shutdown_background();
+            }
+        }
+    }
+}
+
+#[test]
+fn test_blocking_store() -> Result<()> {
+    use tempfile::TempDir;
+    use crate::HashStore;
+    use crate::kernel::lsm::lsm_kv::LsmStore;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    {
+        let blocking_store = BlockingStore::<LsmStore>::open(temp_dir.path())?;
+        blocking_store.set(b"key1", b"value1".to_vec())?;
+        blocking_store.set(b"key2", b"value2".to_vec())?;
+        assert_eq!(blocking_store.get(b"key1")?, Some(b"value1".to_vec()));
+        assert_eq!(blocking_store.get(b"key3")?, None);
+
+        blocking_store.remove(b"key2")?;
+        assert_eq!(blocking_store.get(b"key2")?, None);
+        assert!(matches!(blocking_store.remove(b"key2"), Err(KvsError::KeyNotFound)));
+        blocking_store.flush()?;
+    }
+
+    // 重新开启后仍能读取
+    let blocking_store = BlockingStore::<LsmStore>::open(temp_dir.path())?;
+    assert_eq!(blocking_store.get(b"key1")?, Some(b"value1".to_vec()));
+    assert_eq!(blocking_store.get(b"key2")?, None);
+
+    // 在异步运行时中调用时返回错误而不是panic，且在其中drop同样不会panic
+    tokio_test::block_on(async move {
+        assert!(matches!(blocking_store.get(b"key1"), Err(KvsError::NestedRuntime)));
+        assert!(matches!(
+            BlockingStore::<HashStore>::open(temp_dir.path().join("hash")),
+            Err(KvsError::NestedRuntime)
+        ));
+        drop(blocking_store);
+    });
+
+    Ok(())
+}
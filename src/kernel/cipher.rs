@@ -0,0 +1,126 @@
+use std::{fmt, fs};
+use std::path::Path;
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use crate::kernel::Result;
+use crate::KvsError;
+
+/// AES-GCM的nonce长度
+const NONCE_LEN: usize = 12;
+
+/// 密钥校验文件名
+pub(crate) const CIPHER_CHECK_FILE_NAME: &str = "CIPHER";
+
+/// 密钥校验文件中被加密的明文
+const CIPHER_CHECK_PLAINTEXT: &[u8] = b"KipDB";
+
+/// 落盘数据的加密器
+///
+/// 以用户提供的256位密钥进行AES-GCM加密，每条数据加密时随机生成nonce，并与密文一同存储于该数据的开头，
+/// 因此同一密钥加密的数据不会重复使用nonce，且每条数据均可独立解密，不依赖于其在文件中的位置
+/// 密文附带GCM的认证标签，解密时以此校验数据未被篡改
+pub struct Cipher {
+    aes_gcm: Aes256Gcm
+}
+
+impl Cipher {
+    /// 以256位的密钥创建
+    #[inline]
+    pub fn new(key: &[u8; 32]) -> Self {
+        Cipher { aes_gcm: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)) }
+    }
+
+    /// 加密数据，返回nonce与密文拼接后的数据
+    pub(crate) fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let mut ciphertext = self.aes_gcm.encrypt(&nonce, plaintext)
+            .map_err(|_| KvsError::CipherError)?;
+
+        let mut vec_u8 = nonce.to_vec();
+        vec_u8.append(&mut ciphertext);
+        Ok(vec_u8)
+    }
+
+    /// 解密由encrypt生成的数据，密钥错误或数据被篡改时返回`KvsError::CipherError`
+    pub(crate) fn decrypt(&self, vec_u8: &[u8]) -> Result<Vec<u8>> {
+        if vec_u8.len() < NONCE_LEN {
+            return Err(KvsError::CipherError);
+        }
+        let (nonce, ciphertext) = vec_u8.split_at(NONCE_LEN);
+
+        self.aes_gcm.decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| KvsError::CipherError)
+    }
+}
+
+/// 校验目录的加密方式与cipher一致
+///
+/// 加密的目录中存有以其密钥加密的校验文件，以未加密的方式或错误的密钥开启时返回`KvsError::CipherError`，
+/// 避免无法解密的数据被当作损坏的数据跳过；is_new_dir为true时以cipher写入校验文件
+/// 引入校验文件之前创建的加密目录不存在校验文件，因此不进行校验
+pub(crate) fn check_cipher(dir_path: &Path, cipher: Option<&Cipher>, is_new_dir: bool) -> Result<()> {
+    let check_path = dir_path.join(CIPHER_CHECK_FILE_NAME);
+
+    if check_path.exists() {
+        let cipher = cipher.ok_or(KvsError::CipherError)?;
+        if cipher.decrypt(&fs::read(check_path)?)? != CIPHER_CHECK_PLAINTEXT {
+            return Err(KvsError::CipherError);
+        }
+    } else if let (Some(cipher), true) = (cipher, is_new_dir) {
+        fs::write(check_path, cipher.encrypt(CIPHER_CHECK_PLAINTEXT)?)?;
+    }
+
+    Ok(())
+}
+
+impl fmt::Debug for Cipher {
+    /// 不输出密钥
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cipher").finish_non_exhaustive()
+    }
+}
+
+#[test]
+fn test_cipher() -> Result<()> {
+    let cipher = Cipher::new(&[7; 32]);
+    let plaintext = b"plaintext".to_vec();
+
+    let encrypted = cipher.encrypt(&plaintext)?;
+    assert!(!encrypted.windows(plaintext.len()).any(|window| window == plaintext.as_slice()));
+    assert_eq!(cipher.decrypt(&encrypted)?, plaintext);
+    // 每次加密使用不同的nonce
+    assert_ne!(cipher.encrypt(&plaintext)?, encrypted);
+
+    // 密钥错误或数据被篡改时解密失败
+    assert!(matches!(Cipher::new(&[8; 32]).decrypt(&encrypted), Err(KvsError::CipherError)));
+    let mut tampered = encrypted.clone();
+    let last = tampered.len() - 1;
+    tampered[last] ^= 1;
+    assert!(matches!(cipher.decrypt(&tampered), Err(KvsError::CipherError)));
+    assert!(matches!(cipher.decrypt(&encrypted[..NONCE_LEN - 1]), Err(KvsError::CipherError)));
+
+    Ok(())
+}
+
+#[test]
+fn test_check_cipher() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let path = temp_dir.path();
+    let cipher = Cipher::new(&[7; 32]);
+
+    // 非新目录时不写入校验文件
+    check_cipher(path, Some(&cipher), false)?;
+    assert!(!path.join(CIPHER_CHECK_FILE_NAME).exists());
+
+    check_cipher(path, Some(&cipher), true)?;
+    assert!(path.join(CIPHER_CHECK_FILE_NAME).exists());
+    check_cipher(path, Some(&cipher), false)?;
+
+    assert!(matches!(check_cipher(path, None, false), Err(KvsError::CipherError)));
+    assert!(matches!(check_cipher(path, Some(&Cipher::new(&[8; 32])), false), Err(KvsError::CipherError)));
+
+    Ok(())
+}
@@ -0,0 +1,147 @@
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use crate::kernel::{KVStore, Result};
+
+/// diff时每次从各内核中分页读取的键值对数量
+const DIFF_PAGE_SIZE: usize = 1024;
+
+/// 两个内核之间数据的差异报告
+///
+/// 各列表中的Key由小到大排列，且至多记录limit个，count则为包括未记录在内的差异总数
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiffReport {
+    pub(crate) only_left: Vec<Vec<u8>>,
+    pub(crate) only_right: Vec<Vec<u8>>,
+    pub(crate) value_mismatch: Vec<Vec<u8>>,
+    pub(crate) count: usize
+}
+
+/// 以Key由小到大分页读取内核中的键值对
+struct PageCursor<'a, K: KVStore + Sync> {
+    kv_store: &'a K,
+    start: Vec<u8>,
+    end: Vec<u8>,
+    buffer: VecDeque<(Vec<u8>, Vec<u8>)>,
+    is_exhausted: bool
+}
+
+impl DiffReport {
+    /// 仅存在于左侧的Key
+    #[inline]
+    pub fn only_left(&self) -> &[Vec<u8>] {
+        &self.only_left
+    }
+
+    /// 仅存在于右侧的Key
+    #[inline]
+    pub fn only_right(&self) -> &[Vec<u8>] {
+        &self.only_right
+    }
+
+    /// 两侧均存在但value不同的Key
+    #[inline]
+    pub fn value_mismatch(&self) -> &[Vec<u8>] {
+        &self.value_mismatch
+    }
+
+    /// 差异总数，超出limit而未被记录的差异同样计入其中
+    #[inline]
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// 两侧数据是否完全一致
+    #[inline]
+    pub fn is_consistent(&self) -> bool {
+        self.count == 0
+    }
+
+    fn record(vec_key: &mut Vec<Vec<u8>>, count: &mut usize, limit: usize, key: Vec<u8>) {
+        *count += 1;
+        if vec_key.len() < limit {
+            vec_key.push(key);
+        }
+    }
+}
+
+impl<'a, K: KVStore + Sync> PageCursor<'a, K> {
+    fn new(kv_store: &'a K, end: Vec<u8>) -> Self {
+        PageCursor { kv_store, start: MIN_KEY.to_vec(), end, buffer: VecDeque::new(), is_exhausted: false }
+    }
+
+    /// 缓冲为空时读取下一页
+    async fn fill(&mut self) -> Result<()> {
+        if self.buffer.is_empty() && !self.is_exhausted {
+            let page = self.kv_store.scan(&self.start, &self.end, DIFF_PAGE_SIZE).await?;
+
+            self.is_exhausted = page.len() < DIFF_PAGE_SIZE;
+            if let Some((last_key, _)) = page.last() {
+                self.start = successor(last_key);
+            }
+            self.buffer.extend(page);
+        }
+
+        Ok(())
+    }
+}
+
+/// 最小的非空Key
+///
+/// 各内核均不支持空Key，因此以其作为扫描的起点即等同于不设下界
+pub(crate) const MIN_KEY: &[u8] = &[0];
+
+/// 大于key的最小Key
+pub(crate) fn successor(key: &[u8]) -> Vec<u8> {
+    let mut successor = key.to_vec();
+    successor.push(0);
+    successor
+}
+
+/// 比较两个内核之间的数据差异
+///
+/// 先以各内核的max_key获取两侧最大的Key作为扫描的上界，再以Key由小到大分页扫描两侧并归并比较，
+/// 因此内存中至多同时存在两页数据与至多3 * limit个差异Key，不随数据量增长
+pub(crate) async fn diff<L: KVStore + Sync, R: KVStore + Sync>(left: &L, right: &R, limit: usize) -> Result<DiffReport> {
+    let mut report = DiffReport::default();
+    let end = match left.max_key().await?.max(right.max_key().await?) {
+        Some(max_key) => successor(&max_key),
+        None => return Ok(report)
+    };
+    let mut left_cursor = PageCursor::new(left, end.clone());
+    let mut right_cursor = PageCursor::new(right, end);
+
+    loop {
+        left_cursor.fill().await?;
+        right_cursor.fill().await?;
+
+        let ordering = match (left_cursor.buffer.front(), right_cursor.buffer.front()) {
+            (None, None) => break,
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some((left_key, _)), Some((right_key, _))) => left_key.cmp(right_key)
+        };
+        let DiffReport { only_left, only_right, value_mismatch, count } = &mut report;
+        match ordering {
+            Ordering::Less => {
+                if let Some((key, _)) = left_cursor.buffer.pop_front() {
+                    DiffReport::record(only_left, count, limit, key);
+                }
+            }
+            Ordering::Greater => {
+                if let Some((key, _)) = right_cursor.buffer.pop_front() {
+                    DiffReport::record(only_right, count, limit, key);
+                }
+            }
+            Ordering::Equal => {
+                if let (Some((key, left_value)), Some((_, right_value)))
+                        = (left_cursor.buffer.pop_front(), right_cursor.buffer.pop_front()) {
+                    if left_value != right_value {
+                        DiffReport::record(value_mismatch, count, limit, key);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(report)
+}
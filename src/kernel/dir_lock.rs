@@ -0,0 +1,170 @@
+use std::collections::BTreeSet;
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use fs2::FileExt;
+use tracing::warn;
+use crate::kernel::Result;
+use crate::KvsError;
+
+/// 目录锁文件名
+pub(crate) const LOCK_FILE_NAME: &str = "LOCK";
+
+/// 心跳文件名
+pub(crate) const HEARTBEAT_FILE_NAME: &str = "HEARTBEAT";
+
+/// 心跳文件的更新间隔
+pub(crate) const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 心跳文件的过期时间
+/// 超过该时间未更新的心跳文件视为进程崩溃后的残留
+pub(crate) const DEFAULT_HEARTBEAT_EXPIRE: Duration = Duration::from_secs(3);
+
+/// 目录锁
+///
+/// 开启时创建并独占目录中的LOCK文件，防止多个进程同时开启同一目录而互相破坏数据
+/// 锁随文件句柄存在，Drop时文件句柄关闭即释放，进程异常退出时同样由系统释放
+///
+/// 文件锁在部分文件系统(如NFS)上可能失效，且不使用LOCK文件的旧版本不会占用该锁，
+/// 因此可选择在持有锁期间定期更新目录中的心跳文件，开启时以此检测是否存在其他活跃的写入者
+#[derive(Debug)]
+pub(crate) struct DirLock {
+    _file: File,
+    _heartbeat: Option<Heartbeat>
+}
+
+/// 已注册的心跳文件，Drop时注销并删除心跳文件
+#[derive(Debug)]
+struct Heartbeat {
+    path: PathBuf
+}
+
+/// 所有需要更新的心跳文件
+///
+/// 由同一个线程统一更新，该线程在首个心跳注册时启动，并在心跳全部注销后退出
+#[derive(Debug)]
+struct HeartbeatRegistry {
+    paths: BTreeSet<PathBuf>,
+    is_running: bool
+}
+
+static HEARTBEAT_REGISTRY: Mutex<HeartbeatRegistry> = Mutex::new(HeartbeatRegistry {
+    paths: BTreeSet::new(),
+    is_running: false
+});
+
+impl DirLock {
+    /// 获取目录的独占锁，目录已被占用时返回`KvsError::AlreadyOpen`
+    ///
+    /// 获取锁后若检测到其他活跃的写入者，is_reject为true时返回`KvsError::ActiveWriter`，否则仅进行警告
+    /// is_heartbeat为true时在持有锁期间定期更新该目录的心跳文件
+    pub(crate) fn lock_exclusive(dir_path: &Path, is_reject: bool, is_heartbeat: bool) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(dir_path.join(LOCK_FILE_NAME))?;
+
+        if let Err(err) = file.try_lock_exclusive() {
+            return Err(if err.raw_os_error() == fs2::lock_contended_error().raw_os_error() {
+                KvsError::AlreadyOpen
+            } else {
+                err.into()
+            });
+        }
+        if let Some(pid) = active_writer(dir_path, DEFAULT_HEARTBEAT_EXPIRE)? {
+            if is_reject {
+                return Err(KvsError::ActiveWriter(pid));
+            }
+            warn!("[DirLock][Active writer detected][Pid: {pid}][Dir: {dir_path:?}]");
+        }
+
+        let heartbeat = is_heartbeat
+            .then(|| Heartbeat::start(dir_path, DEFAULT_HEARTBEAT_INTERVAL))
+            .transpose()?;
+
+        Ok(DirLock { _file: file, _heartbeat: heartbeat })
+    }
+}
+
+impl Heartbeat {
+    /// 写入首次心跳后注册至共享的更新线程，该线程未运行时启动
+    #[allow(clippy::unwrap_used)]
+    fn start(dir_path: &Path, interval: Duration) -> Result<Self> {
+        let path = dir_path.join(HEARTBEAT_FILE_NAME);
+        write_heartbeat(&path)?;
+
+        let mut registry = HEARTBEAT_REGISTRY.lock().unwrap();
+        let _ignore = registry.paths.insert(path.clone());
+        if !registry.is_running {
+            registry.is_running = true;
+            let _ignore = thread::spawn(move || Self::run(interval));
+        }
+
+        Ok(Heartbeat { path })
+    }
+
+    /// 每隔interval更新所有已注册的心跳文件，心跳全部注销后退出
+    #[allow(clippy::unwrap_used)]
+    fn run(interval: Duration) {
+        loop {
+            thread::sleep(interval);
+            let mut registry = HEARTBEAT_REGISTRY.lock().unwrap();
+            if registry.paths.is_empty() {
+                registry.is_running = false;
+                break
+            }
+            for path in registry.paths.iter() {
+                if let Err(err) = write_heartbeat(path) {
+                    warn!("[DirLock][Heartbeat][Error: {err:?}]");
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Heartbeat {
+    /// 持有注册表的锁时注销并删除心跳文件，因此更新线程不会在删除后重新写入
+    #[allow(clippy::unwrap_used)]
+    fn drop(&mut self) {
+        let mut registry = HEARTBEAT_REGISTRY.lock().unwrap();
+        let _ignore = registry.paths.remove(&self.path);
+        let _ignore1 = fs::remove_file(&self.path);
+    }
+}
+
+/// 以"pid 毫秒时间戳"的形式写入心跳文件
+///
+/// 先写入临时文件再替换，避免读取到写入一半的心跳
+fn write_heartbeat(path: &Path) -> Result<()> {
+    let temp_path = path.with_extension("tmp");
+    fs::write(&temp_path, format!("{} {}", std::process::id(), now_millis()))?;
+    fs::rename(temp_path, path)?;
+
+    Ok(())
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// 检测目录中是否存在活跃的写入者，存在时返回其pid
+///
+/// 心跳文件不存在、无法解析或超过expire未更新(进程崩溃后的残留)时视为不存在
+pub(crate) fn active_writer(dir_path: &Path, expire: Duration) -> Result<Option<u32>> {
+    let path = dir_path.join(HEARTBEAT_FILE_NAME);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(path)?;
+    let mut split = content.split_whitespace();
+
+    Ok(match (split.next().and_then(|pid| pid.parse().ok()), split.next().and_then(|millis| millis.parse::<u64>().ok())) {
+        (Some(pid), Some(millis)) if now_millis().saturating_sub(millis) <= expire.as_millis() as u64 => Some(pid),
+        _ => None
+    })
+}
@@ -0,0 +1,197 @@
+use std::fmt;
+use std::ops::Bound;
+use std::path::PathBuf;
+use std::str::FromStr;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use crate::HashStore;
+use crate::kernel::{FileRef, KVStore, Result};
+use crate::kernel::lsm::lsm_kv::LsmStore;
+use crate::kernel::sled_kv::SledStore;
+use crate::KvsError;
+
+/// 存储引擎的种类
+///
+/// 可由"hash"、"sled"与"lsm"解析得到，便于通过配置在运行时选择引擎
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum EngineKind {
+    Hash,
+    Sled,
+    Lsm
+}
+
+/// 对象安全的存储内核
+///
+/// `KVStore`含有关联函数`open`、泛型方法且要求`Sized`，因此无法作为trait object使用，
+/// 该trait仅保留不依赖于具体类型的操作，并为所有`KVStore`实现，使调用方能以`Box<dyn DynKVStore>`持有任意内核
+/// 与`KVStore`中的同名方法语义一致
+#[async_trait]
+pub trait DynKVStore: Send + Sync + 'static {
+    /// 内核名称
+    fn name(&self) -> &'static str;
+
+    async fn flush(&self) -> Result<()>;
+
+    async fn set(&self, key: &[u8], value: Vec<u8>) -> Result<()>;
+
+    async fn set_batch(&self, pairs: Vec<(Vec<u8>, Vec<u8>)>) -> Result<()>;
+
+    async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>>;
+
+    async fn contains_key(&self, key: &[u8]) -> Result<bool>;
+
+    async fn remove(&self, key: &[u8]) -> Result<()>;
+
+    async fn remove_and_get(&self, key: &[u8]) -> Result<Option<Vec<u8>>>;
+
+    async fn remove_batch(&self, keys: &[&[u8]]) -> Result<usize>;
+
+    async fn scan(&self, start: &[u8], end: &[u8], limit: usize) -> Result<Vec<(Vec<u8>, Vec<u8>)>>;
+
+    async fn scan_with_bound(&self, start: Bound<&[u8]>, end: Bound<&[u8]>, limit: usize) -> Result<Vec<(Vec<u8>, Vec<u8>)>>;
+
+    async fn scan_keys(&self, start: &[u8], end: &[u8], limit: usize) -> Result<Vec<Vec<u8>>>;
+
+    async fn size_of_disk(&self) -> Result<u64>;
+
+    async fn live_files(&self) -> Result<Vec<FileRef>>;
+
+    async fn pause_file_deletion(&self) -> Result<()>;
+
+    async fn resume_file_deletion(&self) -> Result<()>;
+
+    async fn len(&self) -> Result<usize>;
+
+    async fn is_empty(&self) -> bool;
+}
+
+#[async_trait]
+impl<K: KVStore + Sync> DynKVStore for K {
+    #[inline]
+    fn name(&self) -> &'static str {
+        K::name()
+    }
+
+    #[inline]
+    async fn flush(&self) -> Result<()> {
+        KVStore::flush(self).await
+    }
+
+    #[inline]
+    async fn set(&self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        KVStore::set(self, key, value).await
+    }
+
+    #[inline]
+    async fn set_batch(&self, pairs: Vec<(Vec<u8>, Vec<u8>)>) -> Result<()> {
+        KVStore::set_batch(self, pairs).await
+    }
+
+    #[inline]
+    async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        KVStore::get(self, key).await
+    }
+
+    #[inline]
+    async fn contains_key(&self, key: &[u8]) -> Result<bool> {
+        KVStore::contains_key(self, key).await
+    }
+
+    #[inline]
+    async fn remove(&self, key: &[u8]) -> Result<()> {
+        KVStore::remove(self, key).await
+    }
+
+    #[inline]
+    async fn remove_and_get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        KVStore::remove_and_get(self, key).await
+    }
+
+    #[inline]
+    async fn remove_batch(&self, keys: &[&[u8]]) -> Result<usize> {
+        KVStore::remove_batch(self, keys).await
+    }
+
+    #[inline]
+    async fn scan(&self, start: &[u8], end: &[u8], limit: usize) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        KVStore::scan(self, start, end, limit).await
+    }
+
+    #[inline]
+    async fn scan_with_bound(&self, start: Bound<&[u8]>, end: Bound<&[u8]>, limit: usize) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        KVStore::scan_with_bound(self, start, end, limit).await
+    }
+
+    #[inline]
+    async fn scan_keys(&self, start: &[u8], end: &[u8], limit: usize) -> Result<Vec<Vec<u8>>> {
+        KVStore::scan_keys(self, start, end, limit).await
+    }
+
+    #[inline]
+    async fn size_of_disk(&self) -> Result<u64> {
+        KVStore::size_of_disk(self).await
+    }
+
+    #[inline]
+    async fn live_files(&self) -> Result<Vec<FileRef>> {
+        KVStore::live_files(self).await
+    }
+
+    #[inline]
+    async fn pause_file_deletion(&self) -> Result<()> {
+        KVStore::pause_file_deletion(self).await
+    }
+
+    #[inline]
+    async fn resume_file_deletion(&self) -> Result<()> {
+        KVStore::resume_file_deletion(self).await
+    }
+
+    #[inline]
+    async fn len(&self) -> Result<usize> {
+        KVStore::len(self).await
+    }
+
+    #[inline]
+    async fn is_empty(&self) -> bool {
+        KVStore::is_empty(self).await
+    }
+}
+
+/// 以指定的存储引擎开启数据库
+#[inline]
+pub async fn open_engine(kind: EngineKind, path: impl Into<PathBuf> + Send) -> Result<Box<dyn DynKVStore>> {
+    Ok(match kind {
+        EngineKind::Hash => Box::new(HashStore::open(path).await?),
+        EngineKind::Sled => Box::new(SledStore::open(path).await?),
+        EngineKind::Lsm => Box::new(LsmStore::open(path).await?)
+    })
+}
+
+impl FromStr for EngineKind {
+    type Err = KvsError;
+
+    /// 忽略大小写，无法解析时返回`KvsError::UnknownEngine`
+    #[inline]
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "hash" => Ok(EngineKind::Hash),
+            "sled" => Ok(EngineKind::Sled),
+            "lsm" => Ok(EngineKind::Lsm),
+            _ => Err(KvsError::UnknownEngine(s.to_owned()))
+        }
+    }
+}
+
+impl fmt::Display for EngineKind {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            EngineKind::Hash => "hash",
+            EngineKind::Sled => "sled",
+            EngineKind::Lsm => "lsm"
+        };
+        f.write_str(name)
+    }
+}
@@ -0,0 +1,55 @@
+/// Key中0x00的转义序列
+const ESCAPED_ZERO: [u8; 2] = [0x00, 0xFF];
+
+/// Key与field之间的分隔符
+///
+/// Key中的0x00均被转义为`ESCAPED_ZERO`，因此转义后的Key中不会出现分隔符，
+/// 分隔符之后的部分即为field，field无需转义
+const FIELD_SEPARATOR: [u8; 2] = [0x00, 0x01];
+
+/// 获取Key下所有field共有的前缀，即转义后的Key与分隔符
+///
+/// 任意两个不同Key的前缀互不为前缀，因此以某个Key的前缀扫描时不会读取到其他Key的field
+pub(crate) fn field_prefix(key: &[u8]) -> Vec<u8> {
+    let mut prefix = Vec::with_capacity(key.len() + FIELD_SEPARATOR.len());
+
+    for byte in key {
+        if *byte == 0x00 {
+            prefix.extend_from_slice(&ESCAPED_ZERO);
+        } else {
+            prefix.push(*byte);
+        }
+    }
+    prefix.extend_from_slice(&FIELD_SEPARATOR);
+    prefix
+}
+
+/// 将Key与field编码为复合Key
+pub(crate) fn encode_field_key(key: &[u8], field: &[u8]) -> Vec<u8> {
+    let mut field_key = field_prefix(key);
+
+    field_key.extend_from_slice(field);
+    field_key
+}
+
+#[test]
+fn test_encode_field_key() {
+    // 分隔符出现在Key或field中时不会与其他的Key与field混淆
+    let vec_field_key = [
+        encode_field_key(b"a", b"b\x00\x01c"),
+        encode_field_key(b"a\x00\x01b", b"c"),
+        encode_field_key(b"a\x00", b"\x01b\x00\x01c"),
+        encode_field_key(b"a", b""),
+        encode_field_key(b"a\x00", b"")
+    ];
+    for (i, field_key) in vec_field_key.iter().enumerate() {
+        for other in vec_field_key.iter().skip(i + 1) {
+            assert_ne!(field_key, other);
+        }
+    }
+
+    assert!(encode_field_key(b"a", b"b").starts_with(&field_prefix(b"a")));
+    assert!(!encode_field_key(b"a\x00", b"b").starts_with(&field_prefix(b"a")));
+    assert!(!encode_field_key(b"ab", b"c").starts_with(&field_prefix(b"a")));
+    assert!(!field_prefix(b"a\x00\x01").starts_with(&field_prefix(b"a")));
+}
@@ -1,24 +1,45 @@
 use std::{path::PathBuf, collections::HashMap, fs};
+use std::path::Path;
+use std::sync::Arc;
 use std::cmp::Ordering;
 use std::collections::{BTreeMap, HashSet};
+use std::collections::hash_map::Entry;
+use std::time::{Duration, Instant};
 use itertools::Itertools;
 use async_trait::async_trait;
 use futures::future;
-use tokio::sync::RwLock;
-use tracing::error;
+use tokio::sync::{Mutex, RwLock, RwLockWriteGuard};
+use tracing::{error, info};
 
-use crate::kernel::{CommandData, CommandPackage, CommandPos, KVStore, Result, sorted_gen_list};
+use crate::kernel::{batch_check, CommandData, CommandPackage, CommandPos, CompactionStats, FileKind, FileRef, FormatVersion, key_check, KVStore, log_path, Result, sorted_gen_list, VersionedCommand};
+use crate::kernel::cipher::{check_cipher, Cipher};
+use crate::kernel::dir_lock::DirLock;
 use crate::kernel::io_handler::{IOHandler, IOHandlerFactory};
+use crate::kernel::value_reader::ValueReader;
 use crate::KvsError;
 
 /// 默认压缩大小触发阈值
 pub(crate) const DEFAULT_COMPACTION_THRESHOLD: u64 = 1024 * 1024 * 64;
 
+/// 默认写入触发压缩的冷却时间
+pub(crate) const DEFAULT_COMPACTION_COOLDOWN: Duration = Duration::from_secs(1);
+
+/// 索引快照文件名
+pub(crate) const INDEX_SNAPSHOT_FILE_NAME: &str = "snapshot.index";
+
+/// 索引快照
+/// 记录快照时的index、压缩阈值、各gen日志文件的长度与墓碑
+type IndexSnapshot = (HashMap<Vec<u8>, CommandPos>, u64, Vec<(i64, u64)>, HashMap<Vec<u8>, u64>);
+
 /// The `HashKvStore` stores string key/value pairs.
 #[derive(Debug)]
 pub struct HashStore {
     io_handler_factory: IOHandlerFactory,
-    manifest: RwLock<Manifest>
+    manifest: RwLock<Manifest>,
+    /// 压缩锁，使压缩串行进行
+    compaction_lock: Mutex<()>,
+    /// 目录锁，防止其他实例同时开启该目录
+    _dir_lock: DirLock
 }
 /// 用于状态方面的管理
 #[derive(Debug)]
@@ -27,11 +48,71 @@ pub(crate) struct Manifest {
     current_gen: i64,
     un_compacted: u64,
     compaction_threshold: u64,
-    io_handler_index: BTreeMap<i64, IOHandler>
+    io_handler_index: BTreeMap<i64, IOHandler>,
+    compaction_stats: CompactionStats,
+    /// 写入触发压缩的冷却时间，距上次压缩未超过该时间时写入不再触发压缩
+    compaction_cooldown: Duration,
+    /// 上次完成压缩的时间
+    last_compacted_at: Option<Instant>,
+    /// 暂停文件删除的次数，大于0时不进行压缩
+    deletion_paused: usize,
+    /// 带有版本时间戳的Remove所保留的墓碑，记录已删除的Key及其ts，使之后ts更小的Set不再生效
+    /// 墓碑于该Key再次写入时移除，压缩时以Remove命令重新写入压缩文件，因此不会随过期文件被清除
+    tombstones: HashMap<Vec<u8>, u64>,
+    /// 被多个Key共同引用的SetBatch数据(以gen与pos标识)在索引中的引用数
+    batch_refs: HashMap<(i64, u64), usize>
+}
+
+/// 压缩任务
+///
+/// 记录压缩开始时新写入文件之前的索引快照，以及读取快照数据所需的IOHandler
+#[derive(Debug)]
+struct CompactionTask {
+    compact_gen: i64,
+    compact_handler: IOHandler,
+    vec_cmd_pos: Vec<(Vec<u8>, CommandPos)>,
+    vec_tombstone: Vec<(Vec<u8>, u64)>,
+    skip_index: usize,
+    io_handler_index: BTreeMap<i64, IOHandler>,
+    size_of_disk: u64,
+    // 切换写入文件时已记录的逻辑删除数据大小，即本次压缩能够回收的部分
+    unreclaimed_bytes: u64,
+    start: Instant
 }
 
 impl HashStore {
 
+    /// 获取压缩统计
+    ///
+    /// 其中包括被逻辑删除但尚未被压缩物理回收的数据大小
+    #[inline]
+    pub async fn stats(&self) -> CompactionStats {
+        self.manifest.read().await
+            .compaction_stats
+    }
+
+    /// 主动触发压缩以物理回收被删除与被覆盖的旧数据，返回释放的磁盘大小(单位: 字节)
+    ///
+    /// 不受压缩阈值与冷却时间的限制
+    #[inline]
+    pub async fn reclaim(&self) -> Result<u64> {
+        self.compact_with_snapshot(true).await
+    }
+
+    /// 调整压缩大小触发阈值，于下次写入时生效
+    #[inline]
+    pub async fn set_compaction_threshold(&self, compaction_threshold: u64) {
+        self.manifest.write().await
+            .compaction_threshold = compaction_threshold;
+    }
+
+    /// 对当前写入的日志文件进行fsync，使此前写入的数据均已落盘
+    pub(crate) async fn sync(&self) -> Result<()> {
+        self.manifest.read().await
+            .current_io_handler()?
+            .sync().await
+    }
+
     /// 获取索引中的所有keys
     #[inline]
     pub async fn keys_from_index(&self) -> Vec<Vec<u8>> {
@@ -48,7 +129,8 @@ impl HashStore {
         // 若index中获取到了该数据命令
         if let Some(cmd_pos) = manifest.get_pos_with_key(key) {
             let io_handler = manifest.current_io_handler()?;
-            Ok(CommandPackage::from_pos_unpack(io_handler, cmd_pos.pos, cmd_pos.len).await?)
+            Ok(CommandPackage::from_pos_unpack(io_handler, cmd_pos.pos, cmd_pos.len).await?
+                .and_then(|cmd_data| cmd_data.split_with_key(key)))
         } else {
             Ok(None)
         }
@@ -59,26 +141,57 @@ impl HashStore {
     pub async fn open_with_compaction_threshold(
         path: impl Into<PathBuf>,
         compaction_threshold: u64
+    ) -> Result<Self> where Self: Sized {
+        Self::open_with_compaction_cooldown(path, compaction_threshold, DEFAULT_COMPACTION_COOLDOWN).await
+    }
+
+    /// 通过目录路径启动数据库，并指定写入触发压缩的冷却时间
+    ///
+    /// 压缩后存活数据仍超出阈值时，连续的大量写入会使每次写入都触发压缩，
+    /// 冷却时间内写入不再触发压缩以避免抖动，`compact`的显式调用则不受其限制
+    #[inline]
+    pub async fn open_with_compaction_cooldown(
+        path: impl Into<PathBuf>,
+        compaction_threshold: u64,
+        compaction_cooldown: Duration
+    ) -> Result<Self> where Self: Sized {
+        Self::open_with_cipher_option(path, compaction_threshold, compaction_cooldown, None).await
+    }
+
+    /// 通过目录路径启动加密存储的数据库
+    ///
+    /// 日志中的数据与索引快照均以cipher加密后写盘，开启已有的目录时需使用相同的密钥，
+    /// 密钥错误时返回`KvsError::CipherError`
+    /// 未加密的目录无法以加密的方式开启，反之亦然；加密的目录以未加密的方式开启时同样返回`KvsError::CipherError`
+    #[inline]
+    pub async fn open_with_cipher(path: impl Into<PathBuf>, cipher: Cipher) -> Result<Self> where Self: Sized {
+        Self::open_with_cipher_option(path, DEFAULT_COMPACTION_THRESHOLD, DEFAULT_COMPACTION_COOLDOWN, Some(Arc::new(cipher))).await
+    }
+
+    pub(crate) async fn open_with_cipher_option(
+        path: impl Into<PathBuf>,
+        compaction_threshold: u64,
+        compaction_cooldown: Duration,
+        cipher: Option<Arc<Cipher>>
     ) -> Result<Self> where Self: Sized {
         // 获取地址
         let path = path.into();
         // 创建文件夹（如果他们缺失）
         fs::create_dir_all(&path)?;
-        let mut io_handler_index = BTreeMap::new();
-        // 创建索引
-        let mut index = HashMap::<Vec<u8>, CommandPos>::new();
+        // 占用目录，已被其他实例开启时直接返回
+        let dir_lock = DirLock::lock_exclusive(&path, false, false)?;
         // 通过path获取有序的log序名Vec
         let gen_list = sorted_gen_list(&path)?;
-        // 创建IOHandlerFactory
-        let io_handler_factory = IOHandlerFactory::new(path);
-        // 初始化压缩阈值
-        let mut un_compacted = 0;
-        // 对读入其Map进行初始化并计算对应的压缩阈值
-        for &gen in &gen_list {
-            let handler = io_handler_factory.create(gen)?;
-            un_compacted += load(&handler, &mut index).await? as u64;
-            let _ignore1 = io_handler_index.insert(gen, handler);
-        }
+        // 校验加密方式与目录一致，新目录则记录其加密方式
+        check_cipher(&path, cipher.as_deref(), gen_list.is_empty())?;
+        // 以目录的格式版本创建IOHandlerFactory
+        let format_version = FormatVersion::load_or_init(&path)?;
+        let io_handler_factory = IOHandlerFactory::new_with_format_version(path, format_version)
+            .cipher(cipher);
+        // 通过索引快照与日志恢复索引与对应的压缩阈值
+        let (index, un_compacted, mut io_handler_index, tombstones, unreclaimed_bytes) =
+            restore_index(&gen_list, &io_handler_factory).await?;
+        let batch_refs = batch_refs_from_index(&index);
         let last_gen = *gen_list.last().unwrap_or(&0);
         // 获取当前最新的写入序名
         let current_gen = last_gen;
@@ -90,12 +203,20 @@ impl HashStore {
             current_gen,
             un_compacted,
             compaction_threshold,
-            io_handler_index
+            io_handler_index,
+            compaction_stats: CompactionStats { unreclaimed_bytes, ..CompactionStats::default() },
+            compaction_cooldown,
+            last_compacted_at: None,
+            deletion_paused: 0,
+            tombstones,
+            batch_refs
         });
 
         let store = HashStore {
             io_handler_factory,
-            manifest
+            manifest,
+            compaction_lock: Mutex::new(()),
+            _dir_lock: dir_lock
         };
         store.compact().await?;
 
@@ -105,56 +226,242 @@ impl HashStore {
     /// 核心压缩方法
     /// 通过compaction_gen决定压缩位置
     async fn compact(&self) -> Result<()> {
-        let mut manifest = self.manifest.write().await;
+        let _ignore = self.compact_with_snapshot(true).await?;
+        Ok(())
+    }
+
+    /// 在已持有Manifest写锁时以版本时间戳ts设置键值对，未带时间戳时ts为0
+    async fn set_with_manifest(&self, manifest: &mut Manifest, key: &[u8], value: Vec<u8>, ts: u64) -> Result<()> {
+        //将数据包装为命令
+        let gen = manifest.current_gen;
+        let cmd = CommandData::Set { key: key.to_vec(), value };
+        // 获取写入器当前地址
+        let io_handler = manifest.current_io_handler()?;
+        let (pos, cmd_len) = CommandPackage::write_with_ts(io_handler, &cmd, ts).await?;
+
+        // 模式匹配获取key值
+        if let CommandData::Set { key: cmd_key, .. } = cmd {
+            // 封装为CommandPos
+            let cmd_pos = CommandPos {gen, pos, len: cmd_len, ts };
+
+            // 将封装CommandPos存入索引Map中
+            if let Some(old_cmd) = manifest.insert_command_pos(cmd_key, cmd_pos) {
+                // 将阈值提升至该命令的大小
+                let stale_len = manifest.stale_len(&old_cmd);
+                manifest.un_compacted_add(stale_len as u64);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 在已持有Manifest写锁时进行逻辑删除
+    ///
+    /// 仅写入Remove命令并从索引中移除，旧数据与Remove命令本身在压缩时才被物理回收，
+    /// 期间其大小计入压缩阈值与统计中的未回收大小
+    /// 以版本时间戳ts写入Remove命令，未带时间戳时ts为0；ts大于0时保留墓碑
+    async fn remove_with_manifest(&self, manifest: &mut Manifest, key: &[u8], ts: u64) -> Result<()> {
+        let cmd = CommandData::Remove { key: key.to_vec() };
+        let (_, cmd_len) = CommandPackage::write_with_ts(manifest.current_io_handler()?, &cmd, ts).await?;
+
+        if let Some(old_cmd) = manifest.remove_key_with_pos(key) {
+            let removed_bytes = (manifest.stale_len(&old_cmd) + cmd_len) as u64;
+
+            manifest.un_compacted_add(removed_bytes);
+            manifest.compaction_stats.record_removed(removed_bytes);
+        }
+        if ts > 0 {
+            let _ignore = manifest.tombstones.insert(key.to_vec(), ts);
+        }
 
-        let compaction_threshold = manifest.compaction_threshold;
+        Ok(())
+    }
+
+    /// 释放写入时持有的Manifest写锁，超出压缩阈值且不处于冷却期内时进行压缩
+    ///
+    /// 数据已写入，压缩失败时中止压缩而不影响本次写入的结果
+    async fn compact_if_needed(&self, manifest: RwLockWriteGuard<'_, Manifest>) {
+        let is_compaction_needed = manifest.is_compaction_needed();
+        drop(manifest);
+
+        if is_compaction_needed {
+            if let Err(err) = self.compact_with_snapshot(false).await {
+                error!("[HashStore][compact][error happen]: {:?}", err);
+            }
+        }
+    }
+
+    /// 以索引快照进行压缩，返回回收的磁盘大小
+    ///
+    /// 压缩分为三个阶段，仅在开始与结束时短暂持有Manifest写锁，期间的读写不会被压缩阻塞：
+    /// 1. 持有写锁切换至新的写入文件，再持有读锁对新写入文件之前的索引进行快照
+    /// 2. 不持有锁，以快照读取数据写入压缩文件并落盘
+    /// 3. 持有写锁将快照中的数据切换至压缩文件并清除过期文件，
+    /// 压缩期间被覆盖或删除的Key在索引中已不同于快照，以新写入为准而不进行切换
+    ///
+    /// 压缩文件写入并落盘前不修改索引，写入失败(如磁盘空间不足)时中止压缩并清除压缩文件，
+    /// 原有的日志文件与索引保持不变
+    /// is_forced为false时仅在需要压缩时进行，即写入触发的压缩
+    async fn compact_with_snapshot(&self, is_forced: bool) -> Result<u64> {
+        // 同一时间仅进行一次压缩
+        let _guard = self.compaction_lock.lock().await;
+
+        let task = match self.prepare_compaction(is_forced).await? {
+            Some(task) => task,
+            None => return Ok(0)
+        };
+        let vec_new_pos = match Self::write_compaction(&task).await {
+            Ok(vec_new_pos) => vec_new_pos,
+            Err(err) => {
+                let compact_gen = task.compact_gen;
+                drop(task);
+                self.io_handler_factory.clean(compact_gen)?;
+                return Err(err);
+            }
+        };
+
+        self.apply_compaction(task, vec_new_pos).await
+    }
+
+    /// 压缩的第一阶段，创建压缩任务
+    ///
+    /// 暂停文件删除、无需压缩或不存在可回收的数据时返回None
+    /// 数据均已被删除时仍存在可回收的旧数据，同样进行压缩
+    async fn prepare_compaction(&self, is_forced: bool) -> Result<Option<CompactionTask>> {
+        let start = Instant::now();
+        let (compact_gen, compact_handler, size_of_disk, has_unreclaimed, compaction_threshold, unreclaimed_bytes) = {
+            let mut manifest = self.manifest.write().await;
 
-        let (compact_gen,compact_handler) = manifest.compaction_increment(&self.io_handler_factory).await?;
+            // 压缩会删除旧的日志文件，暂停文件删除期间不进行压缩
+            // 写入触发的压缩可能在等待期间已由其他压缩完成，需要再次判断
+            if manifest.deletion_paused > 0 || (!is_forced && !manifest.is_compaction_needed()) {
+                return Ok(None);
+            }
+            let size_of_disk = manifest.size_of_disk().await?;
+            let (compact_gen, compact_handler) = manifest.compaction_increment(&self.io_handler_factory).await?;
+
+            (compact_gen, compact_handler, size_of_disk, manifest.un_compacted > 0, manifest.compaction_threshold, manifest.compaction_stats.unreclaimed_bytes)
+        };
+
+        let manifest = self.manifest.read().await;
         // 压缩时对values进行顺序排序
         // 以gen,pos为最新数据的指标
-        let (mut vec_cmd_pos, io_handler_index) = manifest.sort_by_last_vec_mut();
-
+        let vec_cmd_pos = manifest.sorted_index_snapshot(compact_gen);
+        if vec_cmd_pos.is_empty() && !has_unreclaimed {
+            return Ok(None);
+        }
+        let vec_tombstone = manifest.tombstones.iter()
+            .map(|(key, ts)| (key.clone(), *ts))
+            .collect_vec();
+        // 以独立的IOHandler读取过期文件，使压缩时无需持有Manifest的锁
+        let io_handler_index = manifest.io_handler_index.keys()
+            .filter(|gen| **gen < compact_gen)
+            .map(|gen| Ok((*gen, self.io_handler_factory.create(*gen)?)))
+            .collect::<Result<BTreeMap<_, _>>>()?;
         // 获取最后一位数据进行可容载数据的范围
-        if let Some(last_cmd_pos) = vec_cmd_pos.last() {
-            let last_pos = last_cmd_pos.pos + last_cmd_pos.len as u64;
-            let skip_index = Self::get_max_new_pos(&vec_cmd_pos, last_pos, compaction_threshold);
-
-            let mut write_len = 0;
-            // 对skip_index进行旧数据跳过处理
-            // 抛弃超过文件大小且数据写入时间最久的数据
-            for (i, cmd_pos) in vec_cmd_pos.iter_mut().enumerate() {
-                if i >= skip_index {
-                    match io_handler_index.get(&cmd_pos.gen) {
-                        Some(io_handler) => {
-                            if let Some(cmd_data) =
-                            CommandPackage::from_pos_unpack(io_handler, cmd_pos.pos, cmd_pos.len).await? {
-                                let (pos, len) = CommandPackage::write(&compact_handler, &cmd_data).await?;
-                                write_len += len;
-                                cmd_pos.change(compact_gen, pos, len);
-                            }
-                        }
-                        None => {
-                            error!("[HashStore][compact][Index data not found!!]")
-                        }
+        let skip_index = vec_cmd_pos.last()
+            .map_or(0, |(_, last_cmd_pos)| {
+                let last_pos = last_cmd_pos.pos + last_cmd_pos.len as u64;
+                Self::get_max_new_pos(&vec_cmd_pos, last_pos, compaction_threshold)
+            });
+
+        Ok(Some(CompactionTask {
+            compact_gen,
+            compact_handler,
+            vec_cmd_pos,
+            vec_tombstone,
+            skip_index,
+            io_handler_index,
+            size_of_disk,
+            unreclaimed_bytes,
+            start
+        }))
+    }
+
+    /// 压缩的第三阶段，将数据切换至压缩文件并清除过期文件，返回回收的磁盘大小
+    ///
+    /// 压缩期间暂停了文件删除时放弃本次压缩并清除压缩文件
+    async fn apply_compaction(&self, task: CompactionTask, vec_new_pos: Vec<(usize, u64, usize)>) -> Result<u64> {
+        let CompactionTask { compact_gen, compact_handler, vec_cmd_pos, size_of_disk, unreclaimed_bytes, start, .. } = task;
+        let mut manifest = self.manifest.write().await;
+
+        if manifest.deletion_paused > 0 {
+            drop(compact_handler);
+            self.io_handler_factory.clean(compact_gen)?;
+            return Ok(0);
+        }
+        let mut write_len = 0;
+        for (i, pos, len) in vec_new_pos {
+            let (key, snapshot_cmd_pos) = &vec_cmd_pos[i];
+
+            if let Some(cmd_pos) = manifest.get_mut_pos_with_key(key)
+                .filter(|cmd_pos| *cmd_pos == snapshot_cmd_pos)
+            {
+                cmd_pos.change(compact_gen, pos, len);
+            }
+            write_len += len;
+        }
+
+        manifest.insert_io_handler(compact_handler);
+        // 清除过期文件等信息
+        manifest.retain(compact_gen, &self.io_handler_factory)?;
+        manifest.un_compacted_add(write_len as u64);
+        // 压缩后原有快照指向的日志已被清除，需要重新快照
+        manifest.write_index_snapshot(&self.io_handler_factory).await?;
+
+        let reclaimed_bytes = size_of_disk.saturating_sub(manifest.size_of_disk().await?);
+        manifest.compaction_stats.record(reclaimed_bytes);
+        manifest.compaction_stats.record_removed_reclaimed(unreclaimed_bytes);
+        manifest.last_compacted_at = Some(Instant::now());
+        info!(
+            compact_gen,
+            reclaimed_bytes,
+            elapsed = ?start.elapsed(),
+            "[HashStore][compact][finished]"
+        );
+
+        Ok(reclaimed_bytes)
+    }
+
+    /// 压缩的第二阶段，将skip_index之后的数据写入压缩文件并落盘，返回各数据在vec_cmd_pos中的序号及其新的位置与长度
+    ///
+    /// 对skip_index进行旧数据跳过处理，抛弃超过文件大小且数据写入时间最久的数据
+    /// SetBatch会被拆分为各Key对应的Set写入，墓碑则以带有ts的Remove写入
+    async fn write_compaction(task: &CompactionTask) -> Result<Vec<(usize, u64, usize)>> {
+        let CompactionTask { compact_handler, vec_cmd_pos, vec_tombstone, skip_index, io_handler_index, .. } = task;
+        let mut vec_new_pos = Vec::new();
+
+        for (i, (key, cmd_pos)) in vec_cmd_pos.iter().enumerate().skip(*skip_index) {
+            match io_handler_index.get(&cmd_pos.gen) {
+                Some(io_handler) => {
+                    if let Some(cmd_data) =
+                    CommandPackage::from_pos_unpack(io_handler, cmd_pos.pos, cmd_pos.len).await?
+                        .and_then(|cmd_data| cmd_data.split_with_key(key.as_slice())) {
+                        // 保留数据的版本时间戳，使重新开启后仍能以LWW比较
+                        let (pos, len) = CommandPackage::write_with_ts(compact_handler, &cmd_data, cmd_pos.ts).await?;
+                        vec_new_pos.push((i, pos, len));
                     }
                 }
+                None => {
+                    error!("[HashStore][compact][Index data not found!!]")
+                }
             }
-
-            // 将所有写入刷入压缩文件中
-            compact_handler.flush().await?;
-            manifest.insert_io_handler(compact_handler);
-            // 清除过期文件等信息
-            manifest.retain(compact_gen, &self.io_handler_factory)?;
-            manifest.un_compacted_add(write_len as u64);
         }
+        // 墓碑与索引中的Key互不重叠，重新开启时重放压缩文件即可恢复
+        for (key, ts) in vec_tombstone {
+            let cmd = CommandData::Remove { key: key.clone() };
+            let _ignore = CommandPackage::write_with_ts(compact_handler, &cmd, *ts).await?;
+        }
+        // 将所有写入同步至压缩文件中，落盘后才能清除过期文件
+        compact_handler.sync().await?;
 
-        Ok(())
+        Ok(vec_new_pos)
     }
 
     /// 获取可承载范围内最新的数据的起始索引
     /// 要求vec_cmd_pos是有序的
-    fn get_max_new_pos(vec_cmd_pos: &[&mut CommandPos], last_pos: u64, compaction_threshold: u64) -> usize {
-        for (i, item) in vec_cmd_pos.iter().enumerate() {
+    fn get_max_new_pos(vec_cmd_pos: &[(Vec<u8>, CommandPos)], last_pos: u64, compaction_threshold: u64) -> usize {
+        for (i, (_, item)) in vec_cmd_pos.iter().enumerate() {
             if last_pos - item.pos < compaction_threshold {
                 return i;
             }
@@ -176,46 +483,147 @@ impl KVStore for HashStore {
         HashStore::open_with_compaction_threshold(path, DEFAULT_COMPACTION_THRESHOLD).await
     }
 
+    /// 仅将缓冲刷入日志文件，索引快照只在压缩时(包括开启时的压缩)重新写入，
+    /// 因此开启时至多重放上次压缩之后写入的日志
     #[inline]
     async fn flush(&self) -> Result<()> {
         let manifest = self.manifest.write().await;
 
-        Ok(manifest.current_io_handler()?
-            .flush().await?)
+        manifest.current_io_handler()?
+            .flush().await
     }
 
     #[inline]
     async fn set(&self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        key_check(key)?;
+        let mut manifest = self.manifest.write().await;
+
+        self.set_with_manifest(&mut manifest, key, value, 0).await?;
+        self.compact_if_needed(manifest).await;
+
+        Ok(())
+    }
+
+    /// 持有Manifest写锁完成存在判断与写入
+    #[inline]
+    async fn set_if_absent(&self, key: &[u8], value: Vec<u8>) -> Result<bool> {
+        key_check(key)?;
+        let mut manifest = self.manifest.write().await;
+
+        if manifest.get_pos_with_key(key).is_some() {
+            return Ok(false);
+        }
+        self.set_with_manifest(&mut manifest, key, value, 0).await?;
+        self.compact_if_needed(manifest).await;
+
+        Ok(true)
+    }
+
+    /// 持有Manifest写锁完成校验与写入，使校验与写入之间不会插入其他写入
+    #[inline]
+    async fn write_batch_atomic(&self, vec_cmd: Vec<CommandData>) -> Result<()> {
+        let vec_remove_key = batch_check(&vec_cmd)?;
+        let mut manifest = self.manifest.write().await;
+
+        if !vec_remove_key.into_iter().all(|key| manifest.contains_key_with_pos(key)) {
+            return Err(KvsError::KeyNotFound);
+        }
+        for cmd in vec_cmd {
+            match cmd {
+                CommandData::Set { key, value } => self.set_with_manifest(&mut manifest, &key, value, 0).await?,
+                CommandData::Remove { key } => self.remove_with_manifest(&mut manifest, &key, 0).await?,
+                _ => return Err(KvsError::NotMatchCmd)
+            }
+        }
+        self.compact_if_needed(manifest).await;
+
+        Ok(())
+    }
+
+    /// 持有Manifest写锁以LWW写入带有版本时间戳的Set与Remove
+    ///
+    /// ts小于该Key当前记录的ts(包括墓碑的ts)时不写入并返回false，ts相同时以本次写入为准；
+    /// ts大于0的Remove保留墓碑，因此删除后ts更小的Set不会生效，Key不存在时同样写入墓碑
+    /// 日志中仅存在已生效的命令，因此重新开启时按写入顺序重放即可，压缩时也会保留数据与墓碑的ts
+    /// 未带时间戳的写入ts视为0
+    #[inline]
+    async fn apply_versioned(&self, versioned: VersionedCommand) -> Result<bool> {
+        let VersionedCommand { cmd, ts } = versioned;
+
+        match cmd {
+            CommandData::Set { key, value } => {
+                key_check(&key)?;
+                let mut manifest = self.manifest.write().await;
+
+                if manifest.get_ts_with_key(&key).map_or(false, |current_ts| current_ts > ts) {
+                    return Ok(false);
+                }
+                self.set_with_manifest(&mut manifest, &key, value, ts).await?;
+                self.compact_if_needed(manifest).await;
+                Ok(true)
+            }
+            CommandData::Remove { key } => {
+                key_check(&key)?;
+                let mut manifest = self.manifest.write().await;
+
+                // Key与墓碑均不存在时，ts为0的Remove无需写入
+                let is_stale = manifest.get_ts_with_key(&key)
+                    .map_or(ts == 0, |current_ts| current_ts > ts);
+                if is_stale {
+                    return Ok(false);
+                }
+                self.remove_with_manifest(&mut manifest, &key, ts).await?;
+                self.compact_if_needed(manifest).await;
+                Ok(true)
+            }
+            cmd => {
+                let _ignore = cmd.apply(self).await?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// 写入后仅对当前写入的日志文件进行fsync
+    /// 写入时触发的压缩会在清除过期文件前同步压缩文件，因此之前的数据同样已落盘
+    #[inline]
+    async fn set_sync(&self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        self.set(key, value).await?;
+        self.sync().await
+    }
+
+    /// 以单条SetBatch写入一组键值对，各Key的索引共同指向该条数据
+    #[inline]
+    async fn set_batch(&self, pairs: Vec<(Vec<u8>, Vec<u8>)>) -> Result<()> {
+        for (key, _) in pairs.iter() {
+            key_check(key)?;
+        }
+        if pairs.is_empty() {
+            return Ok(());
+        }
         let mut manifest = self.manifest.write().await;
 
-        //将数据包装为命令
         let gen = manifest.current_gen;
-        let cmd = CommandData::Set { key: key.to_vec(), value };
-        // 获取写入器当前地址
+        let cmd = CommandData::SetBatch { pairs };
         let io_handler = manifest.current_io_handler()?;
         let (pos, cmd_len) = CommandPackage::write(io_handler, &cmd).await?;
 
-        // 模式匹配获取key值
-        if let CommandData::Set { key: cmd_key, .. } = cmd {
-            // 封装为CommandPos
-            let cmd_pos = CommandPos {gen, pos, len: cmd_len };
-
-            // 将封装CommandPos存入索引Map中
-            if let Some(old_cmd) = manifest.insert_command_pos(cmd_key, cmd_pos) {
-                // 将阈值提升至该命令的大小
-                manifest.un_compacted_add(old_cmd.len as u64);
-            }
-            // 阈值过高进行压缩
-            if manifest.is_threshold_exceeded() {
-                self.compact().await?
+        if let CommandData::SetBatch { pairs } = cmd {
+            let _ignore = manifest.batch_refs.insert((gen, pos), pairs.len());
+            for (key, _) in pairs {
+                if let Some(old_cmd) = manifest.insert_command_pos(key, CommandPos { gen, pos, len: cmd_len, ts: 0 }) {
+                    let stale_len = manifest.stale_len(&old_cmd);
+                    manifest.un_compacted_add(stale_len as u64);
+                }
             }
         }
+        self.compact_if_needed(manifest).await;
 
         Ok(())
     }
 
     #[inline]
     async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        key_check(key)?;
         let manifest = self.manifest.read().await;
 
         // 若index中获取到了该数据命令
@@ -223,7 +631,7 @@ impl KVStore for HashStore {
             if let Some(io_handler) = manifest.get_io_handler(&cmd_pos.gen) {
                 if let Some(cmd) = CommandPackage::from_pos_unpack(io_handler, cmd_pos.pos, cmd_pos.len).await? {
                     // 将命令进行转换
-                    return if let CommandData::Set { value, .. } = cmd {
+                    return if let Some(CommandData::Set { value, .. }) = cmd.split_with_key(key) {
                         //返回匹配成功的数据
                         Ok(Some(value))
                     } else {
@@ -237,33 +645,205 @@ impl KVStore for HashStore {
         Ok(None)
     }
 
+    /// value以Set命令单独存储于日志时直接从日志文件中分块读取，否则(如SetBatch)读取完整的value
+    /// 读取器持有独立的文件句柄，不持有Manifest的锁，因此读取期间的写入与压缩不会被阻塞
+    #[inline]
+    async fn get_reader(&self, key: &[u8]) -> Result<Option<ValueReader>> {
+        key_check(key)?;
+        {
+            let manifest = self.manifest.read().await;
+
+            let cmd_pos = match manifest.get_pos_with_key(key) {
+                Some(cmd_pos) => *cmd_pos,
+                None => return Ok(None)
+            };
+            if let Some(io_handler) = manifest.get_io_handler(&cmd_pos.gen) {
+                if let Some(reader) = ValueReader::from_log(io_handler, key, cmd_pos.pos, cmd_pos.ts).await? {
+                    return Ok(Some(reader));
+                }
+            }
+        }
+
+        Ok(self.get(key).await?.map(ValueReader::from_value))
+    }
+
     #[inline]
     async fn remove(&self, key: &[u8]) -> Result<()> {
+        key_check(key)?;
         let mut manifest = self.manifest.write().await;
 
         // 若index中存在这个key
-        if manifest.contains_key_with_pos(key) {
-            // 对这个key做命令封装
-            let cmd = CommandData::Remove { key: key.to_vec() };
-            let _ignore = CommandPackage::write(manifest.current_io_handler()?, &cmd).await?;
-            let _ignore1 = manifest.remove_key_with_pos(key);
-            Ok(())
-        } else {
-            Err(KvsError::KeyNotFound)
+        if !manifest.contains_key_with_pos(key) {
+            return Err(KvsError::KeyNotFound);
         }
+        self.remove_with_manifest(&mut manifest, key, 0).await?;
+        self.compact_if_needed(manifest).await;
+
+        Ok(())
+    }
+
+    #[inline]
+    async fn remove_and_get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        key_check(key)?;
+        let mut manifest = self.manifest.write().await;
+
+        // 旧值可能仍处于写入缓冲中，读取前需要先刷入
+        manifest.current_io_handler()?
+            .flush().await?;
+        // 持有写锁读取旧值，保证读取与删除之间不会插入其他写入
+        let option_value = match manifest.get_pos_with_key(key) {
+            Some(cmd_pos) => {
+                match manifest.get_io_handler(&cmd_pos.gen) {
+                    Some(io_handler) => {
+                        CommandPackage::from_pos_unpack(io_handler, cmd_pos.pos, cmd_pos.len).await?
+                            .and_then(|cmd_data| cmd_data.split_with_key(key))
+                            .and_then(CommandData::get_value_owner)
+                    }
+                    None => None
+                }
+            }
+            None => return Ok(None)
+        };
+        self.remove_with_manifest(&mut manifest, key, 0).await?;
+        self.compact_if_needed(manifest).await;
+
+        Ok(option_value)
+    }
+
+    /// 在一次Manifest写锁内删除所有存在的Key
+    #[inline]
+    async fn remove_batch(&self, keys: &[&[u8]]) -> Result<usize> {
+        for key in keys {
+            key_check(key)?;
+        }
+        let mut manifest = self.manifest.write().await;
+
+        let mut count = 0;
+        for key in keys {
+            if manifest.contains_key_with_pos(key) {
+                self.remove_with_manifest(&mut manifest, key, 0).await?;
+                count += 1;
+            }
+        }
+        self.compact_if_needed(manifest).await;
+
+        Ok(count)
+    }
+
+    #[inline]
+    async fn for_each<F>(&self, mut f: F) -> Result<()>
+        where F: FnMut(&[u8], &[u8]) -> Result<()> + Send
+    {
+        let manifest = self.manifest.read().await;
+
+        // index中仅存有未被删除的数据
+        for (key, cmd_pos) in manifest.index.iter() {
+            if let Some(io_handler) = manifest.get_io_handler(&cmd_pos.gen) {
+                if let Some(CommandData::Set { value, .. }) =
+                CommandPackage::from_pos_unpack(io_handler, cmd_pos.pos, cmd_pos.len).await?
+                    .and_then(|cmd_data| cmd_data.split_with_key(key)) {
+                    f(key.as_slice(), value.as_slice())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    async fn scan(&self, start: &[u8], end: &[u8], limit: usize) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        key_check(start)?;
+        let manifest = self.manifest.read().await;
+
+        // 仅对范围内的Key排序，并只读取前limit个数据
+        let vec_key_pos = manifest.index.iter()
+            .filter(|(key, _)| start <= key.as_slice() && key.as_slice() < end)
+            .sorted_unstable_by(|(key_a, _), (key_b, _)| key_a.cmp(key_b))
+            .take(limit)
+            .collect_vec();
+
+        let mut vec_kv = Vec::with_capacity(vec_key_pos.len());
+        for (key, cmd_pos) in vec_key_pos {
+            if let Some(io_handler) = manifest.get_io_handler(&cmd_pos.gen) {
+                if let Some(CommandData::Set { value, .. }) =
+                CommandPackage::from_pos_unpack(io_handler, cmd_pos.pos, cmd_pos.len).await?
+                    .and_then(|cmd_data| cmd_data.split_with_key(key)) {
+                    vec_kv.push((key.clone(), value));
+                }
+            }
+        }
+
+        Ok(vec_kv)
+    }
+
+    /// 直接遍历索引中的Key，不读取日志文件
+    #[inline]
+    async fn for_each_key<F>(&self, mut f: F) -> Result<()>
+        where F: FnMut(&[u8]) -> Result<()> + Send
+    {
+        for key in self.manifest.read().await.index.keys() {
+            f(key.as_slice())?;
+        }
+
+        Ok(())
+    }
+
+    /// 直接从索引中获取，不读取日志文件
+    #[inline]
+    async fn max_key(&self) -> Result<Option<Vec<u8>>> {
+        Ok(self.manifest.read().await
+            .index.keys()
+            .max()
+            .cloned())
+    }
+
+    /// 直接从索引中获取Key，不读取日志文件
+    #[inline]
+    async fn scan_keys(&self, start: &[u8], end: &[u8], limit: usize) -> Result<Vec<Vec<u8>>> {
+        Ok(self.manifest.read().await
+            .index.keys()
+            .filter(|key| start <= key.as_slice() && key.as_slice() < end)
+            .sorted_unstable()
+            .take(limit)
+            .cloned()
+            .collect_vec())
     }
 
     #[inline]
     async fn size_of_disk(&self) -> Result<u64> {
+        self.manifest.read().await
+            .size_of_disk().await
+    }
+
+    /// 获取前会将写入缓冲刷入当前的日志文件，包括目录的格式版本文件
+    #[inline]
+    async fn live_files(&self) -> Result<Vec<FileRef>> {
         let manifest = self.manifest.read().await;
 
-        let map_futures = manifest.io_handler_index
-            .values()
-            .map(|io_handler| io_handler.file_size());
-        Ok(future::try_join_all(map_futures)
-            .await?
-            .into_iter()
-            .sum::<u64>())
+        manifest.current_io_handler()?
+            .flush().await?;
+        let mut vec_file = manifest.io_handler_index.values()
+            .map(|io_handler| io_handler.file_ref(FileKind::Log))
+            .collect::<Result<Vec<_>>>()?;
+        vec_file.extend(FileRef::format_version(&self.io_handler_factory.get_dir_path())?);
+
+        Ok(vec_file)
+    }
+
+    /// 暂停期间不进行压缩
+    #[inline]
+    async fn pause_file_deletion(&self) -> Result<()> {
+        self.manifest.write().await
+            .deletion_paused += 1;
+        Ok(())
+    }
+
+    #[inline]
+    async fn resume_file_deletion(&self) -> Result<()> {
+        let mut manifest = self.manifest.write().await;
+
+        manifest.deletion_paused = manifest.deletion_paused.saturating_sub(1);
+        Ok(())
     }
 
     #[inline]
@@ -279,40 +859,167 @@ impl KVStore for HashStore {
     }
 }
 
-/// 通过目录地址加载数据并返回数据总大小
-async fn load(io_handler: &IOHandler, index: &mut HashMap<Vec<u8>, CommandPos>) -> Result<usize> {
+/// 通过索引快照与日志恢复索引
+///
+/// 快照与日志一致时直接加载快照，并仅重放各日志在快照之后写入的增量部分，否则重放全部日志
+/// 返回索引、压缩阈值、各gen对应的IOHandler、墓碑与重放的日志中被逻辑删除但尚未回收的数据大小
+async fn restore_index(
+    gen_list: &[i64],
+    io_handler_factory: &IOHandlerFactory
+) -> Result<(HashMap<Vec<u8>, CommandPos>, u64, BTreeMap<i64, IOHandler>, HashMap<Vec<u8>, u64>, u64)> {
+    let snapshot_path = io_handler_factory.get_dir_path()
+        .join(INDEX_SNAPSHOT_FILE_NAME);
+    let (mut index, mut un_compacted, vec_gen_len, mut tombstones) = read_index_snapshot(&snapshot_path, gen_list, io_handler_factory.get_cipher())
+        .unwrap_or_default();
+    let map_gen_len: HashMap<i64, u64> = vec_gen_len.into_iter().collect();
+    let mut batch_refs = batch_refs_from_index(&index);
+
+    let mut io_handler_index = BTreeMap::new();
+    let mut unreclaimed_bytes = 0;
+    // 对读入其Map进行初始化并计算对应的压缩阈值
+    for &gen in gen_list {
+        let handler = io_handler_factory.create(gen)?;
+        let start = map_gen_len.get(&gen).copied().unwrap_or(0);
+        let (load_un_compacted, load_removed) = load(&handler, &mut index, &mut tombstones, &mut batch_refs, start).await?;
+        un_compacted += load_un_compacted as u64;
+        unreclaimed_bytes += load_removed as u64;
+        let _ignore = io_handler_index.insert(gen, handler);
+    }
+
+    Ok((index, un_compacted, io_handler_index, tombstones, unreclaimed_bytes))
+}
+
+/// 统计索引中被多个Key共同引用的数据(即SetBatch)的引用数
+fn batch_refs_from_index(index: &HashMap<Vec<u8>, CommandPos>) -> HashMap<(i64, u64), usize> {
+    index.values()
+        .map(|cmd_pos| (cmd_pos.gen, cmd_pos.pos))
+        .counts()
+        .into_iter()
+        .filter(|(_, refs)| *refs > 1)
+        .collect()
+}
+
+/// 获取被覆盖或删除的数据中可被压缩回收的大小
+///
+/// SetBatch的数据被其中的各Key共同引用，仅在最后一个引用被覆盖或删除时计入其大小，
+/// 使每条过期数据只被统计一次
+fn stale_len(batch_refs: &mut HashMap<(i64, u64), usize>, old_cmd: &CommandPos) -> usize {
+    match batch_refs.entry((old_cmd.gen, old_cmd.pos)) {
+        Entry::Occupied(mut entry) if *entry.get() > 1 => {
+            *entry.get_mut() -= 1;
+            0
+        }
+        Entry::Occupied(entry) => {
+            let _ignore = entry.remove();
+            old_cmd.len
+        }
+        Entry::Vacant(_) => old_cmd.len
+    }
+}
+
+/// 读取索引快照并校验其与日志是否一致
+///
+/// 快照所记录的日志均需存在且长度不小于记录时的长度，
+/// 且不存在比快照中最新日志更旧却未被记录的日志，否则视为不一致
+/// 快照无法解密时同样视为不一致
+fn read_index_snapshot(snapshot_path: &Path, gen_list: &[i64], cipher: Option<&Cipher>) -> Option<IndexSnapshot> {
+    let mut bytes = fs::read(snapshot_path).ok()?;
+    if let Some(cipher) = cipher {
+        bytes = cipher.decrypt(&bytes).ok()?;
+    }
+    let snapshot: IndexSnapshot = bincode::deserialize(&bytes).ok()?;
+    let vec_gen_len = &snapshot.2;
+    let max_gen = vec_gen_len.iter()
+        .map(|(gen, _)| *gen)
+        .max()?;
+    let dir_path = snapshot_path.parent()?;
+
+    let is_logs_consistent = vec_gen_len.iter()
+        .all(|(gen, len)| {
+            gen_list.contains(gen) && fs::metadata(log_path(dir_path, *gen))
+                .map(|metadata| metadata.len() >= *len)
+                .unwrap_or(false)
+        });
+    let is_not_missing = gen_list.iter()
+        .filter(|gen| **gen <= max_gen)
+        .all(|gen| vec_gen_len.iter().any(|(snapshot_gen, _)| snapshot_gen == gen));
+    let is_index_consistent = snapshot.0.values()
+        .all(|cmd_pos| vec_gen_len.iter().any(|(gen, _)| *gen == cmd_pos.gen));
+
+    (is_logs_consistent && is_not_missing && is_index_consistent).then_some(snapshot)
+}
+
+/// 通过目录地址加载start之后的数据，返回其中可被压缩的数据大小与被逻辑删除的数据大小
+///
+/// ts大于0的Remove记录为墓碑，Set则移除该Key的墓碑
+/// 被逻辑删除的数据大小与`HashStore::remove`时统计的一致，即旧数据与Remove命令的大小之和
+/// SetBatch的数据仅在其中所有Key均被覆盖或删除时计入一次，与写入时的统计一致
+async fn load(io_handler: &IOHandler, index: &mut HashMap<Vec<u8>, CommandPos>, tombstones: &mut HashMap<Vec<u8>, u64>, batch_refs: &mut HashMap<(i64, u64), usize>, start: u64) -> Result<(usize, usize)> {
     let gen = io_handler.get_gen();
 
     // 流式读取将数据序列化为Command
-    let vec_package = CommandPackage::from_read_to_vec(io_handler).await?;
+    let vec_package = CommandPackage::from_read_to_vec_with_start(io_handler, start, FileKind::Log).await?;
     // 初始化空间占用为0
     let mut un_compacted = 0;
+    let mut removed = 0;
     // 迭代数据
     for package in vec_package {
         match package.cmd {
             CommandData::Set { key, .. } => {
+                let _ignore = tombstones.remove(&key);
                 //数据插入索引之中，成功则对空间占用值进行累加
-                if let Some(old_cmd) = index.insert(key, CommandPos {gen, pos: package.pos, len: package.len }) {
-                    un_compacted += old_cmd.len + 1;
+                if let Some(old_cmd) = index.insert(key, CommandPos {gen, pos: package.pos, len: package.len, ts: package.ts }) {
+                    un_compacted += stale_len(batch_refs, &old_cmd) + 1;
                 }
             }
             CommandData::Remove { key } => {
                 //索引删除该数据之中，成功则对空间占用值进行累加
                 if let Some(old_cmd) = index.remove(&key) {
-                    un_compacted += old_cmd.len + 1;
+                    let stale_len = stale_len(batch_refs, &old_cmd);
+                    un_compacted += stale_len + 1;
+                    removed += stale_len + package.len;
                 };
+                if package.ts > 0 {
+                    let _ignore = tombstones.insert(key, package.ts);
+                }
+            }
+            CommandData::SetBatch { pairs } => {
+                let _ignore = batch_refs.insert((gen, package.pos), pairs.len());
+                for (key, _) in pairs {
+                    let _ignore = tombstones.remove(&key);
+                    if let Some(old_cmd) = index.insert(key, CommandPos {gen, pos: package.pos, len: package.len, ts: package.ts }) {
+                        un_compacted += stale_len(batch_refs, &old_cmd) + 1;
+                    }
+                }
             }
-            CommandData::Get{ .. }  => {}
+            CommandData::Get{ .. } | CommandData::SetPtr{ .. } | CommandData::SetCompressed{ .. } | CommandData::Merge{ .. } => {}
         }
     }
-    Ok(un_compacted)
+    Ok((un_compacted, removed))
 }
 
 impl Manifest {
+    /// 获取所有日志文件占用的磁盘大小
+    async fn size_of_disk(&self) -> Result<u64> {
+        let map_futures = self.io_handler_index
+            .values()
+            .map(IOHandler::file_size);
+        Ok(future::try_join_all(map_futures)
+            .await?
+            .into_iter()
+            .sum::<u64>())
+    }
+
     /// 通过Key获取对应的CommandPos
     fn get_pos_with_key(&self, key: &[u8]) -> Option<&CommandPos> {
         self.index.get(key)
     }
+    /// 通过Key获取其当前记录的ts，已删除时为墓碑的ts
+    fn get_ts_with_key(&self, key: &[u8]) -> Option<u64> {
+        self.index.get(key)
+            .map(|cmd_pos| cmd_pos.ts)
+            .or_else(|| self.tombstones.get(key).copied())
+    }
     /// 获取当前最新的IOHandler
     fn current_io_handler(&self) -> Result<&IOHandler> {
         self.io_handler_index.get(&self.current_gen)
@@ -326,6 +1033,10 @@ impl Manifest {
     fn get_mut_io_handler(&mut self, gen: &i64) -> Option<&mut IOHandler> {
         self.io_handler_index.get_mut(gen)
     }
+    /// 通过Key获取对应的可变CommandPos
+    fn get_mut_pos_with_key(&mut self, key: &[u8]) -> Option<&mut CommandPos> {
+        self.index.get_mut(key)
+    }
     /// 判断Index中是否存在对应的Key
     fn contains_key_with_pos(&self, key: &[u8]) -> bool {
         self.index.contains_key(key)
@@ -344,8 +1055,9 @@ impl Manifest {
     fn gen_add(&mut self, num: i64) {
         self.current_gen += num;
     }
-    /// 插入新的CommandPos
+    /// 插入新的CommandPos，并移除该Key的墓碑
     fn insert_command_pos(&mut self, key: Vec<u8>, cmd_pos: CommandPos) -> Option<CommandPos> {
+        let _ignore = self.tombstones.remove(&key);
         self.index.insert(key, cmd_pos)
     }
     /// 插入新的IOHandler
@@ -369,9 +1081,14 @@ impl Manifest {
         // 清除索引中过期Key
         self.index.retain(|_, v| !stale_gens.contains(&v.gen));
         self.io_handler_index.retain(|k, _| !stale_gens.contains(k));
+        self.batch_refs.retain(|(gen, _), _| !stale_gens.contains(gen));
 
         Ok(())
     }
+    /// 获取被覆盖或删除的数据中可被压缩回收的大小
+    fn stale_len(&mut self, old_cmd: &CommandPos) -> usize {
+        stale_len(&mut self.batch_refs, old_cmd)
+    }
     /// 增加压缩阈值
     fn un_compacted_add(&mut self, new_len: u64) {
         // 将压缩阈值调整为为压缩后大小
@@ -381,18 +1098,48 @@ impl Manifest {
     fn is_threshold_exceeded(&self) -> bool {
         self.un_compacted > self.compaction_threshold
     }
-    /// 将Index中的CommandPos以最新为基准进行排序，由旧往新
-    fn sort_by_last_vec_mut(&mut self) -> (Vec<&mut CommandPos>, &BTreeMap<i64, IOHandler>) {
-        let vec_values = self.index.values_mut()
-            .sorted_unstable_by(|a, b| {
+    /// 判断写入时是否需要触发压缩，即超出压缩阈值且不处于冷却期内
+    fn is_compaction_needed(&self) -> bool {
+        self.is_threshold_exceeded() && self.last_compacted_at
+            .map_or(true, |last_compacted_at| last_compacted_at.elapsed() >= self.compaction_cooldown)
+    }
+    /// 克隆出Index中位于expired_gen之前的CommandPos作为快照，并以最新为基准进行排序，由旧往新
+    fn sorted_index_snapshot(&self, expired_gen: i64) -> Vec<(Vec<u8>, CommandPos)> {
+        self.index.iter()
+            .filter(|(_, cmd_pos)| cmd_pos.gen < expired_gen)
+            .map(|(key, cmd_pos)| (key.clone(), *cmd_pos))
+            .sorted_unstable_by(|(_, a), (_, b)| {
                 match a.gen.cmp(&b.gen) {
                     Ordering::Less => Ordering::Less,
                     Ordering::Equal => a.pos.cmp(&b.pos),
                     Ordering::Greater => Ordering::Greater,
                 }
             })
-            .collect_vec();
-        (vec_values, &self.io_handler_index)
+            .collect_vec()
+    }
+    /// 将当前的index写入快照文件
+    ///
+    /// 先写入临时文件再重命名，避免写入中断时损坏原有快照
+    /// Factory设有加密器时快照同样加密后写入
+    async fn write_index_snapshot(&self, io_handler_factory: &IOHandlerFactory) -> Result<()> {
+        self.current_io_handler()?
+            .flush().await?;
+        let mut vec_gen_len = Vec::with_capacity(self.io_handler_index.len());
+        for (gen, io_handler) in self.io_handler_index.iter() {
+            vec_gen_len.push((*gen, io_handler.file_size().await?));
+        }
+        let mut bytes = bincode::serialize(&(&self.index, self.un_compacted, vec_gen_len, &self.tombstones))?;
+        if let Some(cipher) = io_handler_factory.get_cipher() {
+            bytes = cipher.encrypt(&bytes)?;
+        }
+
+        let snapshot_path = io_handler_factory.get_dir_path()
+            .join(INDEX_SNAPSHOT_FILE_NAME);
+        let temp_path = snapshot_path.with_extension("tmp");
+        fs::write(&temp_path, bytes)?;
+        fs::rename(temp_path, snapshot_path)?;
+
+        Ok(())
     }
     /// 压缩前gen自增
     /// 用于数据压缩前将最新写入位置偏移至新位置
@@ -410,4 +1157,654 @@ impl Manifest {
         let compaction_gen = current + 1;
         Ok((compaction_gen, factory.create(compaction_gen)?))
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_index_snapshot() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let path = temp_dir.path();
+        {
+            let kv_store = HashStore::open(path).await?;
+            // 反复覆盖写入使日志远大于index
+            for i in 0..50 {
+                for key_id in 0..1000 {
+                    kv_store.set(format!("key{}", key_id).as_bytes(), vec![i; 100]).await?;
+                }
+            }
+            // flush不会写入快照
+            kv_store.flush().await?;
+            assert!(!path.join(INDEX_SNAPSHOT_FILE_NAME).exists());
+        }
+        {
+            // 开启时的压缩写入快照，之后为快照之后的增量部分
+            let kv_store = HashStore::open(path).await?;
+            assert!(path.join(INDEX_SNAPSHOT_FILE_NAME).exists());
+            for key_id in 0..10 {
+                kv_store.set(format!("key{}", key_id).as_bytes(), vec![b'k']).await?;
+            }
+            kv_store.remove(b"key10").await?;
+            kv_store.flush().await?;
+            kv_store.set(b"key11", vec![b'k']).await?;
+        }
+        let gen_list = sorted_gen_list(path)?;
+
+        // 以快照恢复时仅读取快照之后的增量部分
+        let io_handler_factory = IOHandlerFactory::new(path);
+        let (index_with_snapshot, ..) = restore_index(&gen_list, &io_handler_factory).await?;
+        let read_bytes_with_snapshot = io_handler_factory.read_bytes();
+
+        fs::remove_file(path.join(INDEX_SNAPSHOT_FILE_NAME))?;
+        let io_handler_factory = IOHandlerFactory::new(path);
+        let (index_with_log, ..) = restore_index(&gen_list, &io_handler_factory).await?;
+        let read_bytes_with_log = io_handler_factory.read_bytes();
+
+        assert_eq!(index_with_snapshot, index_with_log);
+        assert!(read_bytes_with_snapshot < read_bytes_with_log);
+
+        // 快照与日志不一致时回退至重放全部日志
+        fs::write(path.join(INDEX_SNAPSHOT_FILE_NAME), b"broken")?;
+        let kv_store = HashStore::open(path).await?;
+        assert_eq!(kv_store.len().await?, 999);
+        assert_eq!(kv_store.get(b"key0").await?, Some(vec![b'k']));
+        assert_eq!(kv_store.get(b"key10").await?, None);
+        assert_eq!(kv_store.get(b"key11").await?, Some(vec![b'k']));
+        assert_eq!(kv_store.get(b"key999").await?, Some(vec![49; 100]));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_open_with_corrupted_log() -> Result<()> {
+    use std::fs::OpenOptions;
+    use std::io::{Seek, SeekFrom, Write};
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let path = temp_dir.path();
+        {
+            let kv_store = HashStore::open(path).await?;
+            for key_id in 0..100 {
+                kv_store.set(format!("key{}", key_id).as_bytes(), vec![b'v'; 100]).await?;
+            }
+            kv_store.flush().await?;
+        }
+        // 移除索引快照使开启时重放全部日志
+        fs::remove_file(path.join(INDEX_SNAPSHOT_FILE_NAME))?;
+
+        let gen = sorted_gen_list(path)?.into_iter()
+            .filter(|gen| fs::metadata(log_path(path, *gen))
+                .map(|metadata| metadata.len() > 0)
+                .unwrap_or(false))
+            .last()
+            .expect("no log with data");
+        // 将第一条数据的首个字节改为MessagePack中不会被使用的0xc1
+        // 第一条数据的长度小于128，因此varint长度头仅占用1字节
+        let mut file = OpenOptions::new()
+            .write(true)
+            .open(log_path(path, gen))?;
+        let _ignore = file.seek(SeekFrom::Start(1))?;
+        file.write_all(&[0xc1])?;
+        file.flush()?;
+
+        match HashStore::open(path).await {
+            Err(KvsError::CorruptedFile { gen: corrupted_gen, offset, .. }) => {
+                assert_eq!(corrupted_gen, gen);
+                assert_eq!(offset, 0);
+            }
+            other => panic!("unexpected result: {:?}", other.map(|_| ()))
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_open_with_fixed_len_head_log() -> Result<()> {
+    use tempfile::TempDir;
+    use crate::kernel::FORMAT_VERSION_FILE_NAME;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let path = temp_dir.path();
+        // 模拟引入格式版本前以定长4字节长度头写入的日志
+        let io_handler = IOHandlerFactory::new_with_format_version(path, FormatVersion::V0).create(1)?;
+        for key_id in 0..100 {
+            let cmd_data = CommandData::set(format!("key{}", key_id).into_bytes(), vec![b'v'; key_id]);
+            let _ignore = CommandPackage::write(&io_handler, &cmd_data).await?;
+        }
+        io_handler.flush().await?;
+
+        {
+            let kv_store = HashStore::open(path).await?;
+            assert!(!path.join(FORMAT_VERSION_FILE_NAME).exists());
+            assert_eq!(kv_store.len().await?, 100);
+            for key_id in 0..100 {
+                assert_eq!(kv_store.get(format!("key{}", key_id).as_bytes()).await?, Some(vec![b'v'; key_id]));
+            }
+            kv_store.set(b"key100", vec![b'v'; 200]).await?;
+            kv_store.flush().await?;
+        }
+
+        let kv_store = HashStore::open(path).await?;
+        assert_eq!(kv_store.get(b"key100").await?, Some(vec![b'v'; 200]));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_set_sync() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let crash_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    // 模拟断电：仅复制已写入文件的数据至新目录，未落盘的缓冲数据随之丢失
+    let crash = |to: &Path| -> Result<()> {
+        for entry in fs::read_dir(temp_dir.path())? {
+            let path = entry?.path();
+            if let Some(file_name) = path.file_name() {
+                let _ignore = fs::copy(&path, to.join(file_name))?;
+            }
+        }
+        Ok(())
+    };
+
+    tokio_test::block_on(async move {
+        let kv_store = HashStore::open(temp_dir.path()).await?;
+
+        kv_store.set(b"key1", b"value1".to_vec()).await?;
+        crash(crash_dir.path())?;
+        assert_eq!(HashStore::open(crash_dir.path()).await?.get(b"key1").await?, None);
+
+        kv_store.set_sync(b"key2", b"value2".to_vec()).await?;
+        crash(crash_dir.path())?;
+        let crashed_store = HashStore::open(crash_dir.path()).await?;
+        assert_eq!(crashed_store.get(b"key1").await?, Some(b"value1".to_vec()));
+        assert_eq!(crashed_store.get(b"key2").await?, Some(b"value2".to_vec()));
+
+        Ok(())
+    })
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_disk_full() -> Result<()> {
+    use std::os::unix::fs::symlink;
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    // 写入/dev/full时总是返回ENOSPC，以指向其的符号链接作为日志文件模拟磁盘写满
+    tokio_test::block_on(async move {
+        let path = temp_dir.path();
+        {
+            let kv_store = HashStore::open(path).await?;
+            for i in 0..100_u32 {
+                kv_store.set(&i.to_be_bytes(), vec![b'v'; 100]).await?;
+            }
+            kv_store.remove(&0_u32.to_be_bytes()).await?;
+
+            // 压缩文件写满时中止压缩，原有的日志文件与索引保持不变
+            let compact_gen = kv_store.manifest.read().await.current_gen + 1;
+            symlink("/dev/full", log_path(path, compact_gen))?;
+            assert!(matches!(kv_store.compact().await, Err(KvsError::DiskFull)));
+            assert!(!log_path(path, compact_gen).exists());
+            for i in 1..100_u32 {
+                assert_eq!(kv_store.get(&i.to_be_bytes()).await?, Some(vec![b'v'; 100]));
+            }
+            kv_store.set(&100_u32.to_be_bytes(), vec![b'v'; 100]).await?;
+            kv_store.flush().await?;
+        }
+
+        let kv_store = HashStore::open(path).await?;
+        assert_eq!(kv_store.get(&0_u32.to_be_bytes()).await?, None);
+        for i in 1..101_u32 {
+            assert_eq!(kv_store.get(&i.to_be_bytes()).await?, Some(vec![b'v'; 100]));
+        }
+
+        // 当前日志文件写满时set失败，索引不更新且写入位置不前进
+        let full_gen = kv_store.manifest.read().await.current_gen + 1;
+        symlink("/dev/full", log_path(path, full_gen))?;
+        {
+            let mut manifest = kv_store.manifest.write().await;
+            manifest.insert_io_handler(kv_store.io_handler_factory.create(full_gen)?);
+            manifest.current_gen = full_gen;
+        }
+        assert!(matches!(kv_store.set(b"key", vec![b'v'; 1024 * 1024]).await, Err(KvsError::DiskFull)));
+        assert_eq!(kv_store.manifest.read().await.current_io_handler()?.write_pos().await?, 0);
+        assert_eq!(kv_store.get(b"key").await?, None);
+        assert_eq!(kv_store.len().await?, 100);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_open_locked() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let kv_store = HashStore::open(temp_dir.path()).await?;
+
+        assert!(matches!(HashStore::open(temp_dir.path()).await, Err(KvsError::AlreadyOpen)));
+        drop(kv_store);
+        let _kv_store = HashStore::open(temp_dir.path()).await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_compaction_stats() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let kv_store = HashStore::open_with_compaction_threshold(temp_dir.path(), u64::MAX).await?;
+        // 空目录开启时无需压缩
+        assert_eq!(kv_store.stats().await, CompactionStats::default());
+
+        for i in 0..10 {
+            for key_id in 0..100 {
+                kv_store.set(format!("key{}", key_id).as_bytes(), vec![i; 100]).await?;
+            }
+        }
+        kv_store.compact().await?;
+        let stats = kv_store.stats().await;
+        assert_eq!(stats.count(), 1);
+        // 覆盖写入的旧数据被回收
+        assert!(stats.reclaimed_bytes() > 100 * 100 * 8);
+
+        kv_store.compact().await?;
+        kv_store.compact().await?;
+        assert_eq!(kv_store.stats().await.count(), 3);
+        for key_id in 0..100 {
+            assert_eq!(kv_store.get(format!("key{}", key_id).as_bytes()).await?, Some(vec![9; 100]));
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_compaction_cooldown() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        // 冷却期内短时间的大量写入只触发一次压缩
+        let kv_store = HashStore::open_with_compaction_cooldown(temp_dir.path().join("cooldown"), 1024, Duration::from_secs(3600)).await?;
+        for i in 0..10 {
+            for key_id in 0..100 {
+                kv_store.set(format!("key{}", key_id).as_bytes(), vec![i; 100]).await?;
+            }
+        }
+        assert_eq!(kv_store.stats().await.count(), 1);
+        kv_store.flush().await?;
+        for key_id in 0..100 {
+            assert_eq!(kv_store.get(format!("key{}", key_id).as_bytes()).await?, Some(vec![9; 100]));
+        }
+
+        // 无冷却期时存活数据超出阈值后每次写入都会触发压缩
+        let kv_store = HashStore::open_with_compaction_cooldown(temp_dir.path().join("no_cooldown"), 1024, Duration::ZERO).await?;
+        for i in 0..10 {
+            for key_id in 0..100 {
+                kv_store.set(format!("key{}", key_id).as_bytes(), vec![i; 100]).await?;
+            }
+        }
+        assert!(kv_store.stats().await.count() > 100);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_remove_and_reclaim() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let kv_store = HashStore::open_with_compaction_threshold(temp_dir.path(), u64::MAX).await?;
+        for key_id in 0..100 {
+            kv_store.set(format!("key{}", key_id).as_bytes(), vec![b'v'; 1000]).await?;
+        }
+        kv_store.flush().await?;
+        assert_eq!(kv_store.stats().await.unreclaimed_bytes(), 0);
+
+        // 逻辑删除仅从索引中移除，旧数据仍占用磁盘
+        let size_before_remove = kv_store.size_of_disk().await?;
+        for key_id in 0..50 {
+            kv_store.remove(format!("key{}", key_id).as_bytes()).await?;
+        }
+        let _ignore = kv_store.remove_and_get(b"key50").await?;
+        kv_store.flush().await?;
+        let size_before_reclaim = kv_store.size_of_disk().await?;
+        assert!(size_before_reclaim >= size_before_remove);
+        assert!(kv_store.stats().await.unreclaimed_bytes() > 51 * 1000);
+
+        // 主动回收后释放被删除的数据
+        let reclaimed_bytes = kv_store.reclaim().await?;
+        assert!(reclaimed_bytes > 51 * 1000);
+        assert_eq!(kv_store.size_of_disk().await?, size_before_reclaim - reclaimed_bytes);
+        let stats = kv_store.stats().await;
+        assert_eq!(stats.unreclaimed_bytes(), 0);
+        assert_eq!(stats.count(), 1);
+        for key_id in 0..51 {
+            assert_eq!(kv_store.get(format!("key{}", key_id).as_bytes()).await?, None);
+        }
+        for key_id in 51..100 {
+            assert_eq!(kv_store.get(format!("key{}", key_id).as_bytes()).await?, Some(vec![b'v'; 1000]));
+        }
+
+        // 数据全部被删除时同样能够回收
+        for key_id in 51..100 {
+            kv_store.remove(format!("key{}", key_id).as_bytes()).await?;
+        }
+        assert!(kv_store.reclaim().await? > 49 * 1000);
+        assert!(kv_store.size_of_disk().await? < 1000);
+        assert!(kv_store.is_empty().await);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_unreclaimed_bytes() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let kv_store = HashStore::open_with_compaction_threshold(temp_dir.path(), u64::MAX).await?;
+        for key_id in 0..100 {
+            kv_store.set(format!("key{}", key_id).as_bytes(), vec![b'v'; 1000]).await?;
+        }
+        for key_id in 0..10 {
+            kv_store.remove(format!("key{}", key_id).as_bytes()).await?;
+        }
+        kv_store.flush().await?;
+        let unreclaimed_bytes = kv_store.stats().await.unreclaimed_bytes();
+        assert!(unreclaimed_bytes > 10 * 1000);
+
+        // 重放日志时重新统计被逻辑删除的数据大小
+        let gen_list = sorted_gen_list(temp_dir.path())?;
+        let (.., restored_unreclaimed_bytes) = restore_index(&gen_list, &kv_store.io_handler_factory).await?;
+        assert_eq!(restored_unreclaimed_bytes, unreclaimed_bytes);
+
+        // 压缩期间的逻辑删除不会被本次压缩回收，仍计入未回收的数据大小
+        let task = kv_store.prepare_compaction(true).await?
+            .expect("compaction task not created");
+        kv_store.remove(b"key10").await?;
+        let unreclaimed_bytes_during_compaction = kv_store.stats().await.unreclaimed_bytes() - unreclaimed_bytes;
+        let vec_new_pos = HashStore::write_compaction(&task).await?;
+        let _ignore = kv_store.apply_compaction(task, vec_new_pos).await?;
+        assert_eq!(kv_store.stats().await.unreclaimed_bytes(), unreclaimed_bytes_during_compaction);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_un_compacted_with_set_batch() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let kv_store = HashStore::open_with_compaction_threshold(temp_dir.path(), u64::MAX).await?;
+        kv_store.set_batch((0..10_u8)
+            .map(|i| (vec![i], vec![i; 100]))
+            .collect_vec()).await?;
+        let batch_len = kv_store.manifest.read().await
+            .get_pos_with_key(&[0])
+            .map(|cmd_pos| cmd_pos.len)
+            .expect("key not found");
+
+        // SetBatch被其中的各Key共同引用，仅在所有Key均被覆盖或删除后计入一次
+        for i in 0..9_u8 {
+            kv_store.set(&[i], vec![b'v']).await?;
+        }
+        assert_eq!(kv_store.manifest.read().await.un_compacted, 0);
+        kv_store.remove(&[9]).await?;
+        let un_compacted = kv_store.manifest.read().await.un_compacted;
+        assert_eq!(un_compacted, kv_store.stats().await.unreclaimed_bytes());
+        assert!(un_compacted > batch_len as u64 && un_compacted < 2 * batch_len as u64);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_write_batch_atomic() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let kv_store = HashStore::open(temp_dir.path()).await?;
+        kv_store.set(b"key1", b"value1".to_vec()).await?;
+
+        // Remove的Key不存在时整批均不生效
+        assert!(matches!(
+            kv_store.write_batch_atomic(vec![CommandData::set(b"key2".to_vec(), b"value2".to_vec()), CommandData::remove(b"absent".to_vec())]).await,
+            Err(KvsError::KeyNotFound)
+        ));
+        assert_eq!(kv_store.get(b"key2").await?, None);
+
+        kv_store.write_batch_atomic(vec![CommandData::set(b"key2".to_vec(), b"value2".to_vec()), CommandData::remove(b"key1".to_vec())]).await?;
+        assert_eq!(kv_store.get(b"key1").await?, None);
+        assert_eq!(kv_store.get(b"key2").await?, Some(b"value2".to_vec()));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_apply_versioned() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        // 未带时间戳的旧记录ts视为0
+        let old_cmd_u8 = CommandPackage::encode(&CommandData::set(b"key".to_vec(), b"value".to_vec()))?;
+        assert_eq!(CommandPackage::decode_versioned(&old_cmd_u8)?.ts(), 0);
+        assert_eq!(
+            VersionedCommand::latest(vec![
+                VersionedCommand::new(CommandData::set(b"k".to_vec(), vec![1]), 3),
+                VersionedCommand::new(CommandData::set(b"k".to_vec(), vec![2]), 7),
+                VersionedCommand::new(CommandData::set(b"k".to_vec(), vec![3]), 7),
+                VersionedCommand::new(CommandData::set(b"k".to_vec(), vec![4]), 1)
+            ]).map(VersionedCommand::into_cmd),
+            Some(CommandData::set(b"k".to_vec(), vec![3]))
+        );
+
+        {
+            let kv_store = HashStore::open(temp_dir.path()).await?;
+            kv_store.set(b"old", b"old".to_vec()).await?;
+            // 按ts选出最新的数据，而非最后写入的数据
+            assert!(VersionedCommand::new(CommandData::set(b"key".to_vec(), b"ts10".to_vec()), 10).apply(&kv_store).await?);
+            assert!(!VersionedCommand::new(CommandData::set(b"key".to_vec(), b"ts5".to_vec()), 5).apply(&kv_store).await?);
+            assert_eq!(kv_store.get(b"key").await?, Some(b"ts10".to_vec()));
+            assert!(!kv_store.apply_versioned(VersionedCommand::new(CommandData::remove(b"key".to_vec()), 3)).await?);
+            assert!(kv_store.apply_versioned(VersionedCommand::new(CommandData::set(b"key".to_vec(), b"ts10b".to_vec()), 10)).await?);
+            assert_eq!(kv_store.get(b"key").await?, Some(b"ts10b".to_vec()));
+            // 旧记录的ts为0，任意带有时间戳的写入均可覆盖
+            assert!(kv_store.apply_versioned(VersionedCommand::new(CommandData::set(b"old".to_vec(), b"ts1".to_vec()), 1)).await?);
+            kv_store.flush().await?;
+        }
+
+        {
+            // 开启时的压缩保留数据的ts
+            let kv_store = HashStore::open(temp_dir.path()).await?;
+            assert_eq!(kv_store.stats().await.count(), 1);
+            assert_eq!(kv_store.get(b"key").await?, Some(b"ts10b".to_vec()));
+            assert_eq!(kv_store.get(b"old").await?, Some(b"ts1".to_vec()));
+            assert!(!kv_store.apply_versioned(VersionedCommand::new(CommandData::set(b"key".to_vec(), b"ts9".to_vec()), 9)).await?);
+            assert!(kv_store.apply_versioned(VersionedCommand::new(CommandData::remove(b"key".to_vec()), 11)).await?);
+            assert_eq!(kv_store.get(b"key").await?, None);
+            // 墓碑使ts更小的Set不再生效，Key不存在时的Remove同样保留墓碑
+            assert!(!kv_store.apply_versioned(VersionedCommand::new(CommandData::set(b"key".to_vec(), b"ts10".to_vec()), 10)).await?);
+            assert!(kv_store.apply_versioned(VersionedCommand::new(CommandData::remove(b"absent".to_vec()), 5)).await?);
+            assert!(!kv_store.apply_versioned(VersionedCommand::new(CommandData::set(b"absent".to_vec(), b"ts4".to_vec()), 4)).await?);
+            assert_eq!(kv_store.get(b"absent").await?, None);
+            kv_store.flush().await?;
+        }
+
+        // 压缩并重新开启后墓碑仍被保留
+        let kv_store = HashStore::open(temp_dir.path()).await?;
+        assert_eq!(kv_store.stats().await.count(), 1);
+        assert!(!kv_store.apply_versioned(VersionedCommand::new(CommandData::set(b"key".to_vec(), b"ts10".to_vec()), 10)).await?);
+        assert!(!kv_store.apply_versioned(VersionedCommand::new(CommandData::set(b"absent".to_vec(), b"ts4".to_vec()), 4)).await?);
+        assert!(kv_store.apply_versioned(VersionedCommand::new(CommandData::set(b"key".to_vec(), b"ts12".to_vec()), 12)).await?);
+        assert_eq!(kv_store.get(b"key").await?, Some(b"ts12".to_vec()));
+        assert_eq!(kv_store.len().await?, 2);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_compaction_without_blocking() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        {
+            let kv_store = HashStore::open_with_compaction_threshold(temp_dir.path(), u64::MAX).await?;
+            for i in 0..10 {
+                for key_id in 0..100 {
+                    kv_store.set(format!("key{}", key_id).as_bytes(), vec![i; 100]).await?;
+                }
+            }
+            kv_store.remove(b"key0").await?;
+
+            let task = kv_store.prepare_compaction(true).await?
+                .expect("compaction task not created");
+            // 写入压缩文件前后均不持有Manifest的锁，读写不被阻塞
+            assert!(kv_store.manifest.try_write().is_ok());
+            assert_eq!(kv_store.get(b"key1").await?, Some(vec![9; 100]));
+            kv_store.set(b"key1", b"during_snapshot".to_vec()).await?;
+            kv_store.remove(b"key2").await?;
+            kv_store.set(b"key100", b"during_snapshot".to_vec()).await?;
+
+            let vec_new_pos = HashStore::write_compaction(&task).await?;
+            assert!(kv_store.manifest.try_write().is_ok());
+            assert_eq!(kv_store.get(b"key1").await?, Some(b"during_snapshot".to_vec()));
+            kv_store.set(b"key3", b"during_write".to_vec()).await?;
+            kv_store.remove(b"key4").await?;
+
+            assert!(kv_store.apply_compaction(task, vec_new_pos).await? > 0);
+            assert_eq!(kv_store.stats().await.count(), 1);
+
+            // 压缩期间的写入与删除不被快照中的旧数据覆盖
+            for key in [b"key0", b"key2", b"key4"] {
+                assert_eq!(kv_store.get(key).await?, None);
+            }
+            assert_eq!(kv_store.get(b"key1").await?, Some(b"during_snapshot".to_vec()));
+            assert_eq!(kv_store.get(b"key3").await?, Some(b"during_write".to_vec()));
+            assert_eq!(kv_store.get(b"key100").await?, Some(b"during_snapshot".to_vec()));
+            for key_id in 5..100 {
+                assert_eq!(kv_store.get(format!("key{}", key_id).as_bytes()).await?, Some(vec![9; 100]));
+            }
+            assert_eq!(kv_store.len().await?, 98);
+            kv_store.flush().await?;
+        }
+
+        // 重新开启后保持一致
+        let kv_store = HashStore::open(temp_dir.path()).await?;
+        for key in [b"key0", b"key2", b"key4"] {
+            assert_eq!(kv_store.get(key).await?, None);
+        }
+        assert_eq!(kv_store.get(b"key1").await?, Some(b"during_snapshot".to_vec()));
+        assert_eq!(kv_store.get(b"key3").await?, Some(b"during_write".to_vec()));
+        assert_eq!(kv_store.get(b"key100").await?, Some(b"during_snapshot".to_vec()));
+        assert_eq!(kv_store.len().await?, 98);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_get_reader_with_constant_memory() -> Result<()> {
+    use tokio::io::AsyncReadExt;
+    use tempfile::TempDir;
+    use crate::kernel::value_reader::DEFAULT_VALUE_READER_BUFFER_SIZE;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let kv_store = HashStore::open(temp_dir.path()).await?;
+        let value = (0..16 * 1024 * 1024_usize).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+        kv_store.set(b"big", value.clone()).await?;
+        kv_store.set_batch(vec![(b"batch".to_vec(), b"value".to_vec())]).await?;
+
+        let read_bytes = kv_store.io_handler_factory.read_bytes();
+        let mut reader = kv_store.get_reader(b"big").await?
+            .expect("value not found");
+        let mut buf = vec![0; 4096];
+        let mut offset = 0;
+        loop {
+            let len = reader.read(&mut buf).await?;
+            if len == 0 {
+                break;
+            }
+            assert_eq!(&buf[..len], &value[offset..offset + len]);
+            // 读取过程中仅持有固定大小的读取缓冲
+            assert_eq!(reader.buffer_len(), DEFAULT_VALUE_READER_BUFFER_SIZE);
+            offset += len;
+        }
+        assert_eq!(offset, value.len());
+        // value不经由IOHandler完整读取
+        assert!(kv_store.io_handler_factory.read_bytes() - read_bytes < (value.len() / 100) as u64);
+
+        // SetBatch中的value读取完整的value
+        let mut vec_value = Vec::new();
+        let mut reader = kv_store.get_reader(b"batch").await?
+            .expect("value not found");
+        assert_eq!(reader.buffer_len(), 5);
+        let _ignore = reader.read_to_end(&mut vec_value).await?;
+        assert_eq!(vec_value, b"value");
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_open_with_cipher() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let path = temp_dir.path().to_path_buf();
+
+    tokio_test::block_on(async move {
+        {
+            let kv_store = HashStore::open_with_cipher(&path, Cipher::new(&[1; 32])).await?;
+            kv_store.set(b"key", b"value".to_vec()).await?;
+            kv_store.flush().await?;
+        }
+        // 未设置密钥或密钥错误时无法开启，而非跳过无法解密的数据
+        assert!(matches!(HashStore::open(&path).await, Err(KvsError::CipherError)));
+        assert!(matches!(HashStore::open_with_cipher(&path, Cipher::new(&[2; 32])).await, Err(KvsError::CipherError)));
+
+        let kv_store = HashStore::open_with_cipher(&path, Cipher::new(&[1; 32])).await?;
+        assert_eq!(kv_store.get(b"key").await?, Some(b"value".to_vec()));
+
+        Ok(())
+    })
+}
@@ -0,0 +1,200 @@
+use tokio::sync::Mutex;
+use crate::kernel::{hash_field, KVStore, Result};
+use crate::KvsError;
+
+/// 索引项Key的前缀
+const INDEX_PREFIX: &[u8] = b"idx:";
+
+/// 从value中提取索引Key，返回None时该value不建立索引
+pub type IndexExtractor = fn(&[u8]) -> Option<Vec<u8>>;
+
+/// 带有二级索引的存储
+///
+/// 对底层内核的包装，set时以extractor从value中提取索引Key，并在同一内核中写入`idx:<extracted>:<key>`形式的索引项，
+/// 查询时以`idx:<extracted>:`为前缀扫描即可得到对应的主键
+/// extracted与主键之间以hset的复合Key编码拼接，extracted中的分隔符会被转义，因此不同的extracted与主键不会编码为同一索引项
+///
+/// 索引项与主键共用同一键空间，因此主键不能以`idx:`为前缀
+/// 同一Key的写入与删除需要先读取旧value以清理旧索引项，因此通过该包装进行的写入与删除串行执行
+#[derive(Debug)]
+pub struct IndexedStore<K: KVStore> {
+    kv_store: K,
+    extractor: IndexExtractor,
+    write_lock: Mutex<()>
+}
+
+impl<K: KVStore + Sync> IndexedStore<K> {
+
+    /// 以已开启的内核与索引Key的提取函数创建
+    #[inline]
+    pub fn new(kv_store: K, extractor: IndexExtractor) -> Self {
+        IndexedStore { kv_store, extractor, write_lock: Mutex::new(()) }
+    }
+
+    /// 获取底层内核
+    #[inline]
+    pub fn inner(&self) -> &K {
+        &self.kv_store
+    }
+
+    /// 取出底层内核
+    #[inline]
+    pub fn into_inner(self) -> K {
+        self.kv_store
+    }
+
+    /// 设置键值对并更新索引
+    ///
+    /// 新索引项与键值对一同写入，之后再删除旧value的索引项
+    /// 主键以`idx:`为前缀时返回`KvsError::InvalidKey`
+    #[inline]
+    pub async fn set(&self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        Self::primary_key_check(key)?;
+        let _guard = self.write_lock.lock().await;
+
+        let old_index_key = self.extract_index_key(key, self.kv_store.get(key).await?.as_deref());
+        let new_index_key = self.extract_index_key(key, Some(&value));
+
+        let mut pairs = vec![(key.to_vec(), value)];
+        if let Some(new_index_key) = &new_index_key {
+            pairs.push((new_index_key.clone(), Vec::new()));
+        }
+        self.kv_store.set_batch(pairs).await?;
+
+        if let Some(old_index_key) = old_index_key.filter(|old| Some(old) != new_index_key.as_ref()) {
+            let _ignore = self.kv_store.remove_and_get(&old_index_key).await?;
+        }
+
+        Ok(())
+    }
+
+    /// 获取Key对应的value
+    #[inline]
+    pub async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.kv_store.get(key).await
+    }
+
+    /// 删除Key及其索引项
+    ///
+    /// Key不存在时与底层内核的remove行为一致
+    #[inline]
+    pub async fn remove(&self, key: &[u8]) -> Result<()> {
+        Self::primary_key_check(key)?;
+        let _guard = self.write_lock.lock().await;
+
+        let old_index_key = self.extract_index_key(key, self.kv_store.get(key).await?.as_deref());
+        self.kv_store.remove(key).await?;
+
+        if let Some(old_index_key) = old_index_key {
+            let _ignore = self.kv_store.remove_and_get(&old_index_key).await?;
+        }
+
+        Ok(())
+    }
+
+    /// 获取索引Key为idx_key的所有主键，以主键由小到大排列
+    ///
+    /// 写入或删除中途崩溃时可能遗留过期的索引项，因此会回查主键的当前value，
+    /// 仅返回当前value提取的索引Key仍为idx_key的主键
+    #[inline]
+    pub async fn query_by_index(&self, idx_key: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let prefix = index_key(idx_key, &[]);
+        let mut vec_key = Vec::new();
+
+        for (index_key, _) in self.kv_store.prefix_scan(&prefix, usize::MAX).await? {
+            let key = &index_key[prefix.len()..];
+
+            if let Some(value) = self.kv_store.get(key).await? {
+                if (self.extractor)(&value).as_deref() == Some(idx_key) {
+                    vec_key.push(key.to_vec());
+                }
+            }
+        }
+
+        Ok(vec_key)
+    }
+
+    /// 强制将数据刷入硬盘
+    #[inline]
+    pub async fn flush(&self) -> Result<()> {
+        self.kv_store.flush().await
+    }
+
+    /// 获取主键为key、value为option_value时的索引项Key
+    fn extract_index_key(&self, key: &[u8], option_value: Option<&[u8]>) -> Option<Vec<u8>> {
+        option_value.and_then(self.extractor)
+            .map(|extracted| index_key(&extracted, key))
+    }
+
+    fn primary_key_check(key: &[u8]) -> Result<()> {
+        if key.starts_with(INDEX_PREFIX) {
+            return Err(KvsError::InvalidKey(String::from_utf8_lossy(key).into_owned()));
+        }
+        Ok(())
+    }
+}
+
+/// 将索引Key与主键编码为索引项的Key
+fn index_key(extracted: &[u8], key: &[u8]) -> Vec<u8> {
+    let mut index_key = INDEX_PREFIX.to_vec();
+
+    index_key.append(&mut hash_field::encode_field_key(extracted, key));
+    index_key
+}
+
+#[test]
+fn test_indexed_store() -> Result<()> {
+    use tempfile::TempDir;
+    use crate::HashStore;
+
+    /// 以value中':'之前的部分作为索引Key，不含':'时不建立索引
+    fn extract_city(value: &[u8]) -> Option<Vec<u8>> {
+        value.iter()
+            .position(|byte| *byte == b':')
+            .map(|pos| value[..pos].to_vec())
+    }
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        {
+            let indexed_store = IndexedStore::new(HashStore::open(temp_dir.path()).await?, extract_city);
+            indexed_store.set(b"user3", b"shanghai:c".to_vec()).await?;
+            indexed_store.set(b"user1", b"beijing:a".to_vec()).await?;
+            indexed_store.set(b"user2", b"beijing:b".to_vec()).await?;
+            indexed_store.set(b"user4", b"no_city".to_vec()).await?;
+            // 索引Key为另一个索引Key的前缀时不会混淆
+            indexed_store.set(b"user5", b"bei:e".to_vec()).await?;
+
+            assert_eq!(indexed_store.query_by_index(b"beijing").await?, vec![b"user1".to_vec(), b"user2".to_vec()]);
+            assert_eq!(indexed_store.query_by_index(b"shanghai").await?, vec![b"user3".to_vec()]);
+            assert_eq!(indexed_store.query_by_index(b"bei").await?, vec![b"user5".to_vec()]);
+            assert!(indexed_store.query_by_index(b"no_city").await?.is_empty());
+
+            // 更新后旧索引项被清理
+            indexed_store.set(b"user1", b"shanghai:a".to_vec()).await?;
+            assert_eq!(indexed_store.query_by_index(b"beijing").await?, vec![b"user2".to_vec()]);
+            assert_eq!(indexed_store.query_by_index(b"shanghai").await?, vec![b"user1".to_vec(), b"user3".to_vec()]);
+            assert_eq!(indexed_store.inner().get(&index_key(b"beijing", b"user1")).await?, None);
+
+            // 删除后索引项被清理
+            indexed_store.remove(b"user3").await?;
+            assert_eq!(indexed_store.query_by_index(b"shanghai").await?, vec![b"user1".to_vec()]);
+            assert_eq!(indexed_store.inner().get(&index_key(b"shanghai", b"user3")).await?, None);
+
+            // 过期的索引项不会被返回
+            indexed_store.inner().set(&index_key(b"beijing", b"user1"), Vec::new()).await?;
+            assert_eq!(indexed_store.query_by_index(b"beijing").await?, vec![b"user2".to_vec()]);
+
+            assert!(matches!(indexed_store.set(b"idx:user", b"beijing:x".to_vec()).await, Err(KvsError::InvalidKey(_))));
+            indexed_store.flush().await?;
+        }
+
+        // 重新开启后索引仍然有效
+        let indexed_store = IndexedStore::new(HashStore::open(temp_dir.path()).await?, extract_city);
+        assert_eq!(indexed_store.query_by_index(b"beijing").await?, vec![b"user2".to_vec()]);
+        assert_eq!(indexed_store.query_by_index(b"shanghai").await?, vec![b"user1".to_vec()]);
+
+        Ok(())
+    })
+}
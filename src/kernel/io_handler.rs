@@ -1,39 +1,247 @@
 use std::fs::{File, OpenOptions};
 use std::{fs, io};
 use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
-use std::path::PathBuf;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use lru::LruCache;
 use tokio::sync::{Mutex, RwLock};
-use crate::kernel::{log_path, Result};
+use crate::kernel::{FileKind, FileRef, FormatVersion, log_path, Result};
+use crate::kernel::cipher::Cipher;
+use crate::KvsError;
 
 pub(crate) type SyncWriter = RwLock<BufWriterWithPos<File>>;
 
 pub(crate) type SyncReader = Mutex<BufReaderWithPos<File>>;
 
+/// 读写缓冲的默认大小，与标准库的默认缓冲大小一致
+pub(crate) const DEFAULT_IO_BUFFER_SIZE: usize = 8 * 1024;
+
+/// 只读句柄池的默认容量
+pub(crate) const DEFAULT_READER_POOL_SIZE: usize = 256;
+
+/// 以gen为Key的只读句柄池
+type ReaderPool = std::sync::Mutex<LruCache<i64, Arc<SyncReader>>>;
+
+/// IOHandler的创建工厂
+///
+/// create与clean可被并发调用，二者在打开与删除文件期间互斥，
+/// 因此对同一gen的create与clean不会交错，经由clean删除的文件不会残留只读句柄于句柄池中
+/// 绕过Factory直接删除的文件则不会被移除，其句柄会占用该文件直至被淘汰，因此删除由该Factory打开的文件时需调用clean
+/// 但Factory不记录gen是否已被清除，clean之后再create同一gen会创建新的空文件，
+/// 调用方需保证不再为已清除的gen创建IOHandler；clean之前创建的IOHandler在类Unix系统中仍可读取原有文件
 #[derive(Debug)]
 pub struct IOHandlerFactory {
-    dir_path: Arc<PathBuf>
+    dir_path: Arc<PathBuf>,
+    /// dir_path之外同样存放文件的目录
+    /// 已存在的文件会依次在dir_path与这些目录中查找，新文件则默认创建于dir_path
+    extra_dir_paths: Vec<Arc<PathBuf>>,
+    /// 由该Factory创建的所有IOHandler的累计读取次数
+    read_count: Arc<AtomicU64>,
+    /// 由该Factory创建的所有IOHandler的累计读取字节数
+    read_bytes: Arc<AtomicU64>,
+    /// 由该Factory累计打开文件的次数
+    open_count: AtomicU64,
+    /// 由该Factory创建的IOHandler所使用的格式版本
+    format_version: FormatVersion,
+    /// 由该Factory创建的IOHandler的读写缓冲大小
+    buffer_size: usize,
+    /// 只读模式，开启时写入句柄以只读方式打开且不会创建文件，
+    /// 用于跟随其他进程正在写入的目录
+    read_only: bool,
+    /// 按gen复用的只读句柄池，为None时不进行复用
+    ///
+    /// 同一gen的IOHandler共享同一只读句柄，每次读取前均会重新定位，因此共享不影响读取的正确性
+    /// 被淘汰的句柄在仍被IOHandler持有时不会关闭，因此打开的文件数至多为池上限与存活的IOHandler数之和
+    reader_pool: Option<ReaderPool>,
+    /// 由该Factory创建的IOHandler中CommandData的加密器，为None时不加密
+    cipher: Option<Arc<Cipher>>,
+    /// create与clean的互斥锁，仅在打开或删除文件与更新句柄池期间持有
+    file_lock: std::sync::Mutex<()>
 }
 
 impl IOHandlerFactory {
 
+    /// 创建对应gen文件的IOHandler，文件不存在时创建该文件
+    ///
+    /// 只读句柄池中存在该gen的句柄时直接复用，仅需打开写入句柄
+    /// 设置了`IOHandlerFactory::extra_dir_paths`时，文件存在于其中的目录则打开该文件，否则在dir_path中创建
     #[inline]
     pub fn create(&self, gen: i64) -> Result<IOHandler> {
-        let dir_path = Arc::clone(&self.dir_path);
+        self.create_in_dir(gen, &self.locate_dir_path(gen))
+    }
 
-        IOHandler::new(dir_path, gen)
+    /// 在指定的目录中创建对应gen文件的IOHandler，文件不存在时创建该文件
+    #[inline]
+    #[allow(clippy::unwrap_used)]
+    pub fn create_in_dir(&self, gen: i64, dir_path: &Arc<PathBuf>) -> Result<IOHandler> {
+        let dir_path = Arc::clone(dir_path);
+        let path = log_path(&dir_path, gen);
+        // 避免写入句柄打开后文件被clean删除，导致只读句柄打开失败或已删除文件的句柄被放入句柄池
+        let _guard = self.file_lock.lock().unwrap();
+
+        // 写入句柄负责在文件不存在时创建文件，因此需要先于只读句柄打开
+        let writer = IOHandler::open_writer(&path, self.buffer_size, self.read_only)?;
+        let _ignore = self.open_count.fetch_add(1, Ordering::Relaxed);
+        let reader = match self.pooled_reader(gen) {
+            Some(reader) => reader,
+            None => {
+                let reader = IOHandler::open_reader(&path, self.buffer_size)?;
+                let _ignore = self.open_count.fetch_add(1, Ordering::Relaxed);
+                self.pool_reader(gen, &reader);
+                reader
+            }
+        };
+
+        Ok(IOHandler::from_parts(dir_path, gen, writer, reader, Arc::clone(&self.read_count), Arc::clone(&self.read_bytes), self.format_version)
+            .with_cipher(self.cipher.clone()))
     }
 
     #[inline]
     pub fn new(dir_path: impl Into<PathBuf>) -> Self {
+        Self::new_with_format_version(dir_path, FormatVersion::CURRENT)
+    }
+
+    /// 以指定的格式版本读写该目录下的文件
+    pub(crate) fn new_with_format_version(dir_path: impl Into<PathBuf>, format_version: FormatVersion) -> Self {
         let dir_path = Arc::new(dir_path.into());
+        let read_count = Arc::new(AtomicU64::new(0));
+
+        Self {
+            dir_path,
+            extra_dir_paths: Vec::new(),
+            read_count,
+            read_bytes: Arc::new(AtomicU64::new(0)),
+            open_count: AtomicU64::new(0),
+            format_version,
+            buffer_size: DEFAULT_IO_BUFFER_SIZE,
+            read_only: false,
+            reader_pool: Self::new_reader_pool(DEFAULT_READER_POOL_SIZE),
+            cipher: None,
+            file_lock: std::sync::Mutex::new(())
+        }
+    }
+
+    /// 设置由该Factory创建的IOHandler的读写缓冲大小
+    ///
+    /// 较大的缓冲能够减少顺序读写(如压缩与范围扫描)时的系统调用次数
+    #[inline]
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    /// 设置dir_path之外同样存放文件的目录
+    #[inline]
+    pub fn extra_dir_paths(mut self, extra_dir_paths: Vec<PathBuf>) -> Self {
+        self.extra_dir_paths = extra_dir_paths.into_iter()
+            .map(Arc::new)
+            .collect();
+        self
+    }
+
+    /// 查找gen文件所在的目录，各目录中均不存在时为dir_path
+    fn locate_dir_path(&self, gen: i64) -> Arc<PathBuf> {
+        if self.extra_dir_paths.is_empty() || log_path(&self.dir_path, gen).exists() {
+            return Arc::clone(&self.dir_path);
+        }
+        let dir_path = self.extra_dir_paths.iter()
+            .find(|dir_path| log_path(dir_path, gen).exists())
+            .unwrap_or(&self.dir_path);
+
+        Arc::clone(dir_path)
+    }
+
+    /// 设置是否以只读模式打开文件
+    ///
+    /// 只读模式下文件不存在时create返回错误而非创建该文件，由其创建的IOHandler不可写入
+    #[inline]
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// 设置只读句柄池的上限，为0时不复用只读句柄
+    ///
+    /// 频繁为同一批文件创建IOHandler(如压缩)时，复用只读句柄能够减少open的系统调用与占用的文件描述符
+    #[inline]
+    pub fn reader_pool_size(mut self, reader_pool_size: usize) -> Self {
+        self.reader_pool = Self::new_reader_pool(reader_pool_size);
+        self
+    }
+
+    /// 设置加密器，由其创建的IOHandler中的CommandData在写盘前加密、读取后解密
+    #[inline]
+    pub fn cipher(mut self, cipher: Option<Arc<Cipher>>) -> Self {
+        self.cipher = cipher;
+        self
+    }
+
+    fn new_reader_pool(reader_pool_size: usize) -> Option<ReaderPool> {
+        NonZeroUsize::new(reader_pool_size)
+            .map(|cap| std::sync::Mutex::new(LruCache::new(cap)))
+    }
+
+    /// 从只读句柄池中获取gen对应的句柄
+    #[allow(clippy::unwrap_used)]
+    fn pooled_reader(&self, gen: i64) -> Option<Arc<SyncReader>> {
+        self.reader_pool.as_ref()
+            .and_then(|pool| pool.lock().unwrap().get(&gen).map(Arc::clone))
+    }
+
+    /// 句柄池中是否存在gen对应的只读句柄
+    #[allow(clippy::unwrap_used)]
+    pub(crate) fn is_pooled(&self, gen: i64) -> bool {
+        self.reader_pool.as_ref()
+            .map_or(false, |pool| pool.lock().unwrap().contains(&gen))
+    }
+
+    /// 将只读句柄放入句柄池，超出上限时淘汰最久未使用的句柄
+    #[allow(clippy::unwrap_used)]
+    fn pool_reader(&self, gen: i64, reader: &Arc<SyncReader>) {
+        if let Some(pool) = &self.reader_pool {
+            let _ignore = pool.lock().unwrap().put(gen, Arc::clone(reader));
+        }
+    }
+
+    #[inline]
+    pub fn get_dir_path(&self) -> Arc<PathBuf> {
+        Arc::clone(&self.dir_path)
+    }
 
-        Self { dir_path }
+    /// 获取由该Factory创建的IOHandler所使用的加密器
+    pub(crate) fn get_cipher(&self) -> Option<&Cipher> {
+        self.cipher.as_deref()
     }
 
+    /// 获取由该Factory创建的所有IOHandler的累计读取次数
     #[inline]
+    pub fn read_count(&self) -> u64 {
+        self.read_count.load(Ordering::Relaxed)
+    }
+
+    /// 获取由该Factory创建的所有IOHandler的累计读取字节数
+    #[inline]
+    pub fn read_bytes(&self) -> u64 {
+        self.read_bytes.load(Ordering::Relaxed)
+    }
+
+    /// 获取由该Factory累计打开文件的次数
+    #[inline]
+    pub fn open_count(&self) -> u64 {
+        self.open_count.load(Ordering::Relaxed)
+    }
+
+    /// 删除gen对应的文件，并移除句柄池中该gen的只读句柄
+    #[inline]
+    #[allow(clippy::unwrap_used)]
     pub fn clean(&self, gen: i64) -> Result<()>{
-        fs::remove_file(log_path(&self.dir_path, gen))?;
+        let _guard = self.file_lock.lock().unwrap();
+        if let Some(pool) = &self.reader_pool {
+            let _ignore = pool.lock().unwrap().pop(&gen);
+        }
+        fs::remove_file(log_path(&self.locate_dir_path(gen), gen))?;
         Ok(())
     }
 }
@@ -44,7 +252,11 @@ pub struct IOHandler {
     gen: i64,
     dir_path: Arc<PathBuf>,
     writer: SyncWriter,
-    reader: SyncReader
+    reader: Arc<SyncReader>,
+    read_count: Arc<AtomicU64>,
+    read_bytes: Arc<AtomicU64>,
+    format_version: FormatVersion,
+    cipher: Option<Arc<Cipher>>
 }
 
 impl IOHandler {
@@ -52,23 +264,47 @@ impl IOHandler {
     #[inline]
     pub fn new(dir_path: Arc<PathBuf>, gen: i64) -> Result<Self> {
         let path = log_path(&dir_path, gen);
+        let writer = Self::open_writer(&path, DEFAULT_IO_BUFFER_SIZE, false)?;
+        let reader = Self::open_reader(&path, DEFAULT_IO_BUFFER_SIZE)?;
+
+        Ok(Self::from_parts(dir_path, gen, writer, reader, Arc::new(AtomicU64::new(0)), Arc::new(AtomicU64::new(0)), FormatVersion::CURRENT))
+    }
 
-        // 通过路径构造写入器
+    /// 通过路径构造写入器，文件不存在时创建该文件
+    ///
+    /// 只读模式下则以只读方式打开，文件不存在时返回错误
+    fn open_writer(path: &Path, buffer_size: usize, read_only: bool) -> Result<SyncWriter> {
         let file = OpenOptions::new()
-            .create(true)
-            .write(true)
+            .create(!read_only)
+            .write(!read_only)
             .read(true)
-            .open(&path)?;
+            .open(path)?;
 
-        let writer = RwLock::new(BufWriterWithPos::new(file)?);
-        let reader = Mutex::new(BufReaderWithPos::new(File::open(path)?)?);
+        Ok(RwLock::new(BufWriterWithPos::new(file, buffer_size)?))
+    }
 
-        Ok(Self {
+    /// 通过路径构造只读的读取器
+    fn open_reader(path: &Path, buffer_size: usize) -> Result<Arc<SyncReader>> {
+        Ok(Arc::new(Mutex::new(BufReaderWithPos::new(File::open(path)?, buffer_size)?)))
+    }
+
+    /// 使用已打开的读写器与共享的读取计数器进行构建
+    fn from_parts(dir_path: Arc<PathBuf>, gen: i64, writer: SyncWriter, reader: Arc<SyncReader>, read_count: Arc<AtomicU64>, read_bytes: Arc<AtomicU64>, format_version: FormatVersion) -> Self {
+        Self {
             gen,
             dir_path,
             writer,
-            reader
-        })
+            reader,
+            read_count,
+            read_bytes,
+            format_version,
+            cipher: None
+        }
+    }
+
+    fn with_cipher(mut self, cipher: Option<Arc<Cipher>>) -> Self {
+        self.cipher = cipher;
+        self
     }
 
     #[inline]
@@ -81,23 +317,74 @@ impl IOHandler {
         Arc::clone(&self.dir_path)
     }
 
+    /// 该文件中CommandData长度头的格式版本
+    pub(crate) fn format_version(&self) -> FormatVersion {
+        self.format_version
+    }
+
+    /// 该文件中CommandData的加密器，为None时未加密
+    pub(crate) fn cipher(&self) -> Option<&Cipher> {
+        self.cipher.as_deref()
+    }
+
+    /// 获取共享的加密器，用于在其他线程中加密
+    pub(crate) fn shared_cipher(&self) -> Option<Arc<Cipher>> {
+        self.cipher.clone()
+    }
+
     #[inline]
     pub async fn file_size(&self) -> Result<u64> {
         let path = log_path(&self.dir_path, self.gen);
         Ok(fs::metadata(path)?.len())
     }
 
+    /// 获取该文件的FileRef
+    pub(crate) fn file_ref(&self, kind: FileKind) -> Result<FileRef> {
+        let path = log_path(&self.dir_path, self.gen);
+        let size = fs::metadata(&path)?.len();
+
+        Ok(FileRef { gen: self.gen, path, size, kind })
+    }
+
     /// 使用自身的gen读取执行起始位置的指定长度的二进制数据
+    ///
+    /// 单次read不保证填满buffer，因此循环读取直至读满；
+    /// 读至文件末尾仍未读满时，数据可能仍处于写入缓冲之中，刷入后重新读取
+    /// 刷入后读取范围仍越出文件末尾时返回`KvsError::OutOfBounds`，
+    /// 而不依赖于越界seek在各平台上的行为，范围之内却仍无法读满(如文件被外部截断)时返回`KvsError::UnexpectedEof`
     #[inline]
     pub async fn read_with_pos(&self, start: u64, len: usize) -> Result<Vec<u8>> {
         let mut reader = self.reader.lock().await;
+        let _ignore_count = self.read_count.fetch_add(1, Ordering::Relaxed);
+        let _ignore_bytes = self.read_bytes.fetch_add(len as u64, Ordering::Relaxed);
 
         let mut buffer = vec![0;len];
         // 使用Vec buffer获取数据
+        if Self::read_exact_with_pos(&mut reader, start, &mut buffer)? {
+            return Ok(buffer);
+        }
+        self.writer.write().await
+            .flush()?;
+        let file_size = self.file_size().await?;
+        if start.checked_add(len as u64).map_or(true, |end| end > file_size) {
+            return Err(KvsError::OutOfBounds { gen: self.gen, start, len, file_size });
+        }
+        if Self::read_exact_with_pos(&mut reader, start, &mut buffer)? {
+            return Ok(buffer);
+        }
+
+        Err(KvsError::UnexpectedEof { gen: self.gen, start, len })
+    }
+
+    /// 由start起始读满buffer，读至文件末尾仍未读满时返回false
+    fn read_exact_with_pos(reader: &mut BufReaderWithPos<File>, start: u64, buffer: &mut [u8]) -> Result<bool> {
         let _ignore = reader.seek(SeekFrom::Start(start))?;
-        let _ignore1 = reader.read(buffer.as_mut_slice())?;
 
-        Ok(buffer)
+        match reader.read_exact(buffer) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+            Err(err) => Err(err.into())
+        }
     }
 
     /// 使用自身的gen读取执行起始位置的指定长度的二进制数据
@@ -109,13 +396,23 @@ impl IOHandler {
     }
 
     /// 写入并返回起始位置与写入长度
+    ///
+    /// 写入失败(如磁盘空间不足时的`KvsError::DiskFull`)时写入位置回滚至写入前，不会留下残缺的数据
     #[inline]
     pub async fn write(&self, buf: Vec<u8>) -> Result<(u64, usize)> {
         let mut writer = self.writer.write().await;
 
         let start_pos = writer.pos;
         let slice_buf = buf.as_slice();
-        let _ignore = writer.write(slice_buf)?;
+        if let Err(err) = writer.write_all(slice_buf) {
+            // 仅当数据不小于缓冲容量时才会绕过缓冲直接部分写入文件，而此时缓冲已被清空，
+            // 因此截断不会丢失之前尚未刷入的数据；数据写入缓冲前刷入失败时写入位置不会前进，无需回滚
+            if writer.pos > start_pos {
+                let _ignore = writer.seek(SeekFrom::Start(start_pos))?;
+                writer.writer.get_ref().set_len(start_pos)?;
+            }
+            return Err(err.into());
+        }
 
         Ok((start_pos, slice_buf.len()))
     }
@@ -126,18 +423,53 @@ impl IOHandler {
         self.write(buf.to_vec()).await
     }
 
+    /// 预分配文件空间
+    ///
+    /// 预先将文件扩展至指定大小以减少边写边扩展产生的磁盘碎片
+    /// 写入完成后需要调用`truncate`将文件截断至实际写入的大小
+    #[inline]
+    pub async fn pre_allocate(&self, len: u64) -> Result<()> {
+        let writer = self.writer.write().await;
+
+        if len > writer.pos {
+            writer.writer.get_ref().set_len(len)?;
+        }
+        Ok(())
+    }
+
+    /// 将文件截断至当前的写入位置
+    #[inline]
+    pub async fn truncate(&self) -> Result<()> {
+        let mut writer = self.writer.write().await;
+
+        writer.flush()?;
+        let pos = writer.pos;
+        writer.writer.get_ref().set_len(pos)?;
+        Ok(())
+    }
+
     #[inline]
     pub async fn write_pos(&self) -> Result<u64> {
         Ok(self.writer.read().await.pos)
     }
 
-    /// 获取文件二进制序列
+    /// 获取整个文件的crc32校验码
     #[inline]
     pub async fn get_crc_code(&self) -> Result<u32> {
         let mut buffer = Vec::new();
+        let mut reader = self.reader.lock().await;
+
+        // 读取器可能已被移动至其他位置，需要从头读取
+        let _ignore = reader.seek(SeekFrom::Start(0))?;
+        let _ignore1 = reader.read_to_end(&mut buffer)?;
+        Ok(crc32fast::hash(buffer.as_slice()))
+    }
+
+    /// 获取文件中[0, len)部分的crc32校验码
+    #[inline]
+    pub async fn get_crc_code_with_len(&self, len: u64) -> Result<u32> {
+        let buffer = self.read_with_pos(0, len as usize).await?;
 
-        let _ignore = self.reader.lock().await
-            .read_to_end(&mut buffer)?;
         Ok(crc32fast::hash(buffer.as_slice()))
     }
 
@@ -148,6 +480,18 @@ impl IOHandler {
             .flush()?;
         Ok(())
     }
+
+    /// 将缓冲刷入文件后通过fsync同步至硬盘
+    ///
+    /// 与flush不同，返回时数据已落盘，断电也不会丢失
+    #[inline]
+    pub async fn sync(&self) -> Result<()> {
+        let mut writer = self.writer.write().await;
+
+        writer.flush()?;
+        writer.writer.get_ref().sync_data()?;
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -157,10 +501,10 @@ pub(crate) struct BufReaderWithPos<R: Read + Seek> {
 }
 
 impl<R: Read + Seek> BufReaderWithPos<R> {
-    fn new(mut inner: R) -> Result<Self> {
+    fn new(mut inner: R, capacity: usize) -> Result<Self> {
         let pos = inner.stream_position()?;
         Ok(BufReaderWithPos {
-            reader: BufReader::new(inner),
+            reader: BufReader::with_capacity(capacity, inner),
             pos,
         })
     }
@@ -188,10 +532,10 @@ pub(crate) struct BufWriterWithPos<W: Write + Seek> {
 }
 
 impl<W: Write + Seek> BufWriterWithPos<W> {
-    fn new(mut inner: W) -> Result<Self> {
+    fn new(mut inner: W, capacity: usize) -> Result<Self> {
         let pos = inner.stream_position()?;
         Ok(BufWriterWithPos {
-            writer: BufWriter::new(inner),
+            writer: BufWriter::with_capacity(capacity, inner),
             pos,
         })
     }
@@ -215,3 +559,194 @@ impl<W: Write + Seek> Seek for BufWriterWithPos<W> {
         Ok(self.pos)
     }
 }
+
+#[test]
+fn test_buffer_size_with_syscall_count() -> Result<()> {
+    use std::io::Cursor;
+
+    /// 记录对内部数据的读写次数，即缓冲未命中时产生的系统调用次数
+    struct CountingIo {
+        inner: Cursor<Vec<u8>>,
+        count: usize
+    }
+
+    impl Read for CountingIo {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.count += 1;
+            self.inner.read(buf)
+        }
+    }
+
+    impl Write for CountingIo {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.count += 1;
+            self.inner.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.inner.flush()
+        }
+    }
+
+    impl Seek for CountingIo {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    let chunk = vec![b'k'; 100];
+    let mut vec_count = Vec::new();
+    for buffer_size in [DEFAULT_IO_BUFFER_SIZE, 8 * DEFAULT_IO_BUFFER_SIZE] {
+        let mut writer = BufWriterWithPos::new(CountingIo { inner: Cursor::new(Vec::new()), count: 0 }, buffer_size)?;
+        for _ in 0..10000 {
+            writer.write_all(&chunk)?;
+        }
+        writer.flush()?;
+        assert_eq!(writer.pos, 10000 * 100);
+        let write_count = writer.writer.get_ref().count;
+        let bytes = writer.writer.get_ref().inner.get_ref().clone();
+
+        let mut reader = BufReaderWithPos::new(CountingIo { inner: Cursor::new(bytes), count: 0 }, buffer_size)?;
+        let mut buf = vec![0; 100];
+        for _ in 0..10000 {
+            reader.read_exact(&mut buf)?;
+            assert_eq!(buf, chunk);
+        }
+        let read_count = reader.reader.get_ref().count;
+
+        vec_count.push((write_count, read_count));
+    }
+
+    // 顺序读写时，缓冲越大系统调用次数越少
+    assert!(vec_count[1].0 * 4 < vec_count[0].0);
+    assert!(vec_count[1].1 * 4 < vec_count[0].1);
+
+    Ok(())
+}
+
+#[test]
+fn test_read_with_pos_fully() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let io_handler = IOHandlerFactory::new(temp_dir.path()).create(1)?;
+        // 远大于读缓冲的数据需要多次read才能读满
+        let bytes = (0..4 * 1024 * 1024_u32).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+        let (start, len) = io_handler.write_with_clone(&bytes).await?;
+        io_handler.flush().await?;
+        assert_eq!(io_handler.read_with_pos(start, len).await?, bytes);
+        assert_eq!(io_handler.read_with_pos(1024 * 1024 + 7, 2 * 1024 * 1024).await?,
+                   bytes[1024 * 1024 + 7..3 * 1024 * 1024 + 7].to_vec());
+
+        // 仍处于写入缓冲中的数据刷入后读取
+        let (start, len) = io_handler.write_with_clone(b"buffered").await?;
+        assert_eq!(io_handler.read_with_pos(start, len).await?, b"buffered".to_vec());
+
+        // 超出文件末尾的读取，包括起始位置已越出文件末尾的读取
+        let file_size = io_handler.file_size().await?;
+        assert!(matches!(
+            io_handler.read_with_pos(file_size - 4, 8).await,
+            Err(KvsError::OutOfBounds { gen: 1, len: 8, file_size: size, .. }) if size == file_size
+        ));
+        assert!(matches!(
+            io_handler.read_with_pos(file_size + 1024, 8).await,
+            Err(KvsError::OutOfBounds { gen: 1, start, len: 8, .. }) if start == file_size + 1024
+        ));
+        // 长度为0的读取在文件范围内时返回空数据
+        assert_eq!(io_handler.read_with_pos(file_size, 0).await?, Vec::<u8>::new());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_reader_pool_with_open_count() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let mut vec_open_count = Vec::new();
+        for (dir_name, reader_pool_size) in [("pooled", DEFAULT_READER_POOL_SIZE), ("unpooled", 0)] {
+            let factory = IOHandlerFactory::new(temp_dir.path().join(dir_name))
+                .reader_pool_size(reader_pool_size);
+            fs::create_dir_all(&*factory.get_dir_path())?;
+
+            for gen in 0..64_i64 {
+                let io_handler = factory.create(gen)?;
+                let _ignore = io_handler.write(gen.to_le_bytes().to_vec()).await?;
+                io_handler.flush().await?;
+            }
+            // 模拟压缩等场景下反复为大量SSTable创建IOHandler并读取
+            for _ in 0..10 {
+                for gen in 0..64_i64 {
+                    let io_handler = factory.create(gen)?;
+                    assert_eq!(io_handler.read_with_pos(0, 8).await?, gen.to_le_bytes().to_vec());
+                }
+            }
+
+            // 删除后以相同的gen重新创建时不会复用已删除文件的句柄
+            factory.clean(0)?;
+            let io_handler = factory.create(0)?;
+            let _ignore = io_handler.write(b"recreate".to_vec()).await?;
+            assert_eq!(io_handler.read_with_pos(0, 8).await?, b"recreate".to_vec());
+
+            vec_open_count.push(factory.open_count());
+        }
+
+        // 复用只读句柄时仅需打开写入句柄
+        assert_eq!(vec_open_count[0], 64 * 2 + 10 * 64 + 2);
+        assert_eq!(vec_open_count[1], (64 + 10 * 64 + 1) * 2);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_concurrent_create_and_clean() -> Result<()> {
+    use std::thread;
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let factory = IOHandlerFactory::new(temp_dir.path());
+
+    // 多个线程对少量gen交替进行create与clean
+    thread::scope(|scope| {
+        for thread_id in 0..8_i64 {
+            let factory = &factory;
+            let _ignore = scope.spawn(move || {
+                for i in 0..500_i64 {
+                    let gen = (thread_id + i) % 4;
+                    if (thread_id + i) % 3 == 0 {
+                        match factory.clean(gen) {
+                            Ok(()) => (),
+                            Err(KvsError::Io(err)) if err.kind() == io::ErrorKind::NotFound => (),
+                            Err(err) => panic!("unexpected error on clean: {err:?}")
+                        }
+                    } else {
+                        let _ignore = factory.create(gen).expect("create failed");
+                    }
+                }
+            });
+        }
+    });
+
+    tokio_test::block_on(async move {
+        for gen in 0..4_i64 {
+            // 句柄池中的只读句柄均对应仍存在的文件
+            let is_pooled = factory.reader_pool.as_ref()
+                .map_or(false, |pool| pool.lock().map_or(false, |pool| pool.contains(&gen)));
+            assert!(!is_pooled || log_path(temp_dir.path(), gen).exists());
+
+            // 读取到的为当前文件中写入的数据，即复用的只读句柄不指向已删除的文件
+            let io_handler = factory.create(gen)?;
+            let _ignore = io_handler.write(gen.to_le_bytes().to_vec()).await?;
+            io_handler.flush().await?;
+            assert_eq!(io_handler.read_with_pos(0, 8).await?, gen.to_le_bytes().to_vec());
+        }
+
+        Ok(())
+    })
+}
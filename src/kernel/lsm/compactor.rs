@@ -1,18 +1,28 @@
+use std::cmp::Reverse;
+use std::iter;
 use std::sync::Arc;
 use std::time::Instant;
 use futures::future;
 use itertools::Itertools;
 use tokio::sync::RwLock;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 use crate::{HashStore, KvsError};
-use crate::kernel::io_handler::IOHandlerFactory;
+use crate::kernel::io_handler::{IOHandler, IOHandlerFactory};
 use crate::kernel::{CommandData, Result};
-use crate::kernel::lsm::lsm_kv::{CommandCodec, Config, LsmStore, wal_put};
-use crate::kernel::lsm::{data_sharding, Manifest};
+use crate::kernel::lsm::lsm_kv::{CommandCodec, Config, LsmStore, MergeOperator, wal_put};
+use crate::kernel::lsm::{data_sharding, FlushTicket, Manifest, MemTable, merge_cmd_data, resolve_merge};
+use crate::kernel::lsm::scheduler::CompactionSlot;
 use crate::kernel::lsm::ss_table::{Scope, SsTable};
+use crate::kernel::lsm::value_log::ValueLog;
 
 pub(crate) const LEVEL_0: usize = 0;
 
+/// Level的数量，即Level 0至Level 6
+pub(crate) const LEVEL_COUNT: usize = 7;
+
+/// 最深的Level
+pub(crate) const MAX_LEVEL: usize = LEVEL_COUNT - 1;
+
 /// 数据分片集
 /// 包含对应分片的Gen与数据
 pub(crate) type MergeShardingVec = Vec<(i64, Vec<CommandData>)>;
@@ -24,46 +34,229 @@ pub(crate) struct Compactor {
     config: Arc<Config>,
     io_handler_factory: Arc<IOHandlerFactory>,
     wal: Arc<HashStore>,
+    wal_in_flight: Arc<RwLock<()>>,
+    value_log: Arc<ValueLog>,
 }
 
 impl Compactor {
 
-    pub(crate) fn new(manifest: Arc<RwLock<Manifest>>, config: Arc<Config>, io_handler_factory: Arc<IOHandlerFactory>, wal: Arc<HashStore>) -> Self {
-        Self { manifest, config, io_handler_factory, wal }
+    pub(crate) fn new(manifest: Arc<RwLock<Manifest>>, config: Arc<Config>, io_handler_factory: Arc<IOHandlerFactory>, wal: Arc<HashStore>, wal_in_flight: Arc<RwLock<()>>, value_log: Arc<ValueLog>) -> Self {
+        Self { manifest, config, io_handler_factory, wal, wal_in_flight, value_log }
     }
 
-    /// 持久化immutable_table为SSTable
-    pub(crate) async fn minor_compaction(&self,vec_keys: Vec<Vec<u8>>, vec_values: Vec<CommandData>) -> Result<()> {
-        let mut manifest = self.manifest.write().await;
-        let gen = self.config.create_gen();
+    /// 在该Level的SSTable所存放的目录中创建gen文件的IOHandler
+    fn create_io_handler(&self, gen: i64, level: usize) -> Result<IOHandler> {
+        let dir_path = Arc::new(self.config.dir_path_with_level(level).clone());
 
-        let io_handler = self.io_handler_factory.create(gen)?;
+        self.io_handler_factory.create_in_dir(gen, &dir_path)
+    }
+
+    /// 持久化immutable_table为SSTable，返回vLog是否切换了文件
+    ///
+    /// SSTable的生成不持有Manifest写锁，因此多个ImmutableMemTable能以调度器的并发度并行生成SSTable，
+    /// 生成后释放执行许可，并等待先交换的ImmutableMemTable落盘完成后再写入Manifest
+    async fn minor_compaction(&self, ticket: &mut FlushTicket, slot: Option<CompactionSlot>, vec_keys: Vec<Vec<u8>>, vec_values: Vec<CommandData>) -> Result<bool> {
+        let gen = ticket.gen();
 
         // 将这些索引的key序列化后预先存入wal中作防灾准备
         // 当持久化异常时将对应gen的key反序列化出来并从wal找到对应值
         wal_put(
             &self.wal,
+            &self.wal_in_flight,
             CommandCodec::encode_gen(gen)?,
             CommandCodec::encode_keys(&vec_keys)?,
             !self.config.wal_async_put_enable
         ).await;
+        // Key-Value分离时将大value写入vLog，SSTable中仅保留指针
+        let (vec_values, is_rotated) = if self.config.kv_separation_enable {
+            self.value_log.separate(vec_values, self.config.kv_separation_threshold, &self.config).await?
+        } else {
+            (vec_values, false)
+        };
         // 从内存表中将数据持久化为ss_table
-        let ss_table = SsTable::create_for_immutable_table(&self.config
-                                                           , io_handler
-                                                           , vec_values
-                                                           , LEVEL_0).await?;
-        manifest.insert_ss_table_with_index(ss_table, 0).await;
-
-        drop(manifest);
-        if let Err(err) = self.major_compaction(LEVEL_0).await {
-            error!("[LsmStore][major_compaction][error happen]: {:?}", err);
+        // 生成失败(如磁盘空间不足)时清除写入一半的文件并以同一gen重试
+        let mut retries = 0;
+        let ss_table = loop {
+            let result = match self.create_io_handler(gen, LEVEL_0) {
+                Ok(io_handler) => SsTable::create_for_immutable_table(&self.config
+                                                                      , io_handler
+                                                                      , vec_values.clone()
+                                                                      , LEVEL_0
+                                                                      , gen as u64).await,
+                Err(err) => Err(err)
+            };
+            match result {
+                Ok(ss_table) => break ss_table,
+                Err(err) => {
+                    let _ignore = self.io_handler_factory.clean(gen);
+                    if retries >= self.config.minor_compaction_retries {
+                        return Err(err);
+                    }
+                    retries += 1;
+                    warn!("[LsmStore][minor_compaction][retry: {retries}][error happen]: {:?}", err);
+                    tokio::time::sleep(self.config.minor_compaction_retry_interval).await;
+                }
+            }
+        };
+        drop(slot);
+        ticket.wait_prev().await;
+
+        let mut manifest = self.manifest.write().await;
+        manifest.record_compaction_written(ss_table.get_size_of_disk());
+        manifest.insert_ss_table_with_index(ss_table, 0).await?;
+        manifest.record_minor_compaction();
+        manifest.warn_tombstone_ratio(self.config.tombstone_ratio_threshold);
+
+        Ok(is_rotated)
+    }
+
+    /// 将已以Key排序且去重的数据直接生成SSTable，跳过MemTable与Wal，返回SSTable所处的Level
+    ///
+    /// SSTable放置于最深的一个Level，使该Level及其之上的Level中均不存在与数据范围重叠的SSTable，
+    /// 因此导入的数据不会被更旧的数据遮盖，且Level 1-6中的SSTable仍互不重叠；
+    /// Level 0中即存在重叠时放置于Level 0
+    ///
+    /// SSTable的Gen与MemTable的写入凭证一同分配，并等待先交换的ImmutableMemTable落盘后再持有Manifest写锁选定Level，
+    /// 因此导入的数据总是新于先交换的数据，而旧于之后交换的数据；
+    /// 全程持有Manifest写锁，避免选定Level后压缩将重叠的数据移入该Level，写入后将导入的Key由负缓存中移除
+    pub(crate) async fn bulk_load(&self, mem_table: &MemTable, vec_cmd_data: Vec<CommandData>) -> Result<usize> {
+        let vec_key = vec_cmd_data.iter()
+            .map(|cmd_data| cmd_data.get_key().clone())
+            .collect_vec();
+        let scope = Scope::from_vec_cmd_data(&vec_cmd_data)?;
+        // Key-Value分离时将大value写入vLog，SSTable中仅保留指针
+        let (vec_cmd_data, _) = if self.config.kv_separation_enable {
+            self.value_log.separate(vec_cmd_data, self.config.kv_separation_threshold, &self.config).await?
+        } else {
+            (vec_cmd_data, false)
+        };
+        let vec_sharding = data_sharding(vec_cmd_data, self.config.sst_file_size, &self.config, false).await;
+        let mut vec_new_gen = Vec::with_capacity(vec_sharding.len());
+        let mut ticket = mem_table.flush_ticket(|| {
+            vec_new_gen.extend(iter::repeat_with(|| self.config.create_gen()).take(vec_sharding.len()));
+            vec_new_gen.first().copied().unwrap_or_default()
+        });
+        ticket.wait_prev().await;
+
+        let mut manifest = self.manifest.write().await;
+        let level = (LEVEL_0..7)
+            .take_while(|level| manifest.get_meet_scope_ss_tables(*level, &scope).is_empty())
+            .last()
+            .unwrap_or(LEVEL_0);
+        let ss_table_futures = vec_new_gen.iter()
+            .zip(vec_sharding)
+            .map(|(gen, (_, sharding))| async move {
+                let io_handler = self.create_io_handler(*gen, level)?;
+                SsTable::create_for_immutable_table(&self.config, io_handler, sharding, level, *gen as u64).await
+            });
+        let vec_new_ss_table = match future::try_join_all(ss_table_futures).await {
+            Ok(vec_new_ss_table) => vec_new_ss_table,
+            Err(err) => {
+                for gen in vec_new_gen {
+                    let _ignore = self.io_handler_factory.clean(gen);
+                }
+                return Err(err);
+            }
+        };
+        manifest.insert_ss_table_with_index_batch(vec_new_ss_table, 0).await?;
+        mem_table.invalidate_negative(&vec_key);
+        info!(level, new_gens = ?vec_new_gen, "[LsmStore][Bulk Load][finished]");
+
+        Ok(level)
+    }
+
+    /// 持久化immutable_table为SSTable，重试耗尽仍失败时将数据放回MemTable，避免数据随下一次交换而丢失
+    ///
+    /// ticket为交换时领取的写入凭证，写入Manifest或放回MemTable后释放，之后再进行Major压缩与vLog的垃圾回收；
+    /// slot为调度器的执行许可，于SSTable生成后释放
+    pub(crate) async fn minor_compaction_or_restore(&self, mem_table: &MemTable, mut ticket: FlushTicket, slot: Option<CompactionSlot>, vec_keys: Vec<Vec<u8>>, vec_values: Vec<CommandData>) -> Result<()> {
+        match self.minor_compaction(&mut ticket, slot, vec_keys.clone(), vec_values.clone()).await {
+            Ok(is_rotated) => {
+                drop(ticket);
+                if let Err(err) = self.major_compaction(LEVEL_0).await {
+                    error!("[LsmStore][major_compaction][error happen]: {:?}", err);
+                }
+                // vLog切换文件后，旧文件即可进行GC
+                if is_rotated {
+                    if let Err(err) = self.value_log_gc(mem_table).await {
+                        error!("[LsmStore][value_log_gc][error happen]: {:?}", err);
+                    }
+                }
+                Ok(())
+            }
+            Err(err) => {
+                // 放回的数据会遮盖之后写入Manifest的SSTable，因此同样按交换顺序进行
+                ticket.wait_prev().await;
+                mem_table.restore_immutable(vec_keys, vec_values, self.config.merge_operator).await?;
+                Err(err)
+            }
         }
+    }
+
+    /// vLog的垃圾回收
+    ///
+    /// 对每个已写满的vLog文件，以当前SSTable中该Key的指针是否仍指向该数据判断value是否有效，
+    /// 失效数据的占比达到`Config::value_log_gc_ratio`时，将有效的value重写至当前的vLog文件，
+    /// 并以新的指针生成Level 0的SSTable覆盖旧指针，随后删除该vLog文件
+    ///
+    /// 回收每个vLog文件期间持有Manifest写锁，期间新的SSTable无法写入且读取者无法通过旧指针读取该vLog文件
+    /// 生成的SSTable以MemTable的写入凭证分配Gen，并等待先交换的ImmutableMemTable落盘后写入，
+    /// 因此尚未落盘的数据总是较新，其落盘后生成的SSTable Gen也会大于此处生成的SSTable，无需处理
+    #[allow(clippy::float_arithmetic)]
+    pub(crate) async fn value_log_gc(&self, mem_table: &MemTable) -> Result<()> {
+        for gen in self.value_log.sealed_gens().await {
+            let mut ticket = mem_table.flush_ticket(|| self.config.create_gen());
+            ticket.wait_prev().await;
+
+            let mut manifest = self.manifest.write().await;
+            // 回收后会删除vLog文件，暂停文件删除期间或存在SSTable快照时跳过，
+            // 快照中的SSTable可能仍持有指向该vLog文件的旧指针
+            if manifest.is_deletion_paused() || manifest.is_snapshot_pinned() {
+                return Ok(());
+            }
+            let start = Instant::now();
+            let vec_data_with_ptr = self.value_log.read_all_with_ptr(gen).await?;
+            let total_len: usize = vec_data_with_ptr.iter()
+                .map(|(ptr, _)| ptr.get_len())
+                .sum();
+
+            let mut live_len = 0;
+            let mut vec_live = Vec::new();
+            for (ptr, cmd_data) in vec_data_with_ptr {
+                if let Some(CommandData::SetPtr { ptr: current_ptr, .. })
+                        = manifest.get_data_for_ss_tables(cmd_data.get_key()).await? {
+                    if current_ptr == ptr {
+                        live_len += ptr.get_len();
+                        vec_live.push(cmd_data);
+                    }
+                }
+            }
+            // 失效数据占比不足时跳过，避免有效数据被频繁重写
+            if total_len > 0 && ((total_len - live_len) as f64 / total_len as f64) < self.config.value_log_gc_ratio {
+                continue
+            }
+            if !vec_live.is_empty() {
+                vec_live.sort_unstable();
+                let (vec_live, _) = self.value_log.separate(vec_live, 0, &self.config).await?;
+                let new_gen = ticket.gen();
+                let ss_table = SsTable::create_for_immutable_table(&self.config,
+                                                                   self.create_io_handler(new_gen, LEVEL_0)?,
+                                                                   vec_live,
+                                                                   LEVEL_0,
+                                                                   new_gen as u64).await?;
+                manifest.record_compaction_written(ss_table.get_size_of_disk());
+                manifest.insert_ss_table_with_index(ss_table, 0).await?;
+            }
+            self.value_log.remove(gen).await?;
+            info!("[LsmStore][Value Log GC][Gen: {}][Time: {:?}]", gen, start.elapsed());
+        }
+
         Ok(())
     }
 
     /// Major压缩，负责将不同Level之间的数据向下层压缩转移
     /// 目前Major压缩的大体步骤是
-    /// 1、获取manifest读锁，读取当前Level的指定数量SSTable，命名为vec_ss_table_l
+    /// 1、获取manifest读锁，以`Config::compaction_selector`选择当前Level的SSTable，命名为vec_ss_table_l
     /// 2、vec_ss_table_l的每个SSTable中的scope属性进行融合，并以此获取下一Level与该scope相交的SSTable，命名为vec_ss_table_l_1
     /// 3、获取的vec_ss_table_l_1向上一Level进行类似第2步骤的措施，获取两级之间压缩范围内最恰当的数据
     /// 4、vec_ss_table_l与vec_ss_table_l_1之间的数据并行取出排序归并去重等处理后，分片成多个Vec<CommandData>
@@ -77,7 +270,9 @@ impl Compactor {
     /// Level0的Key基本是无序的，容易生成大量的SSTable至Level1
     /// 而Level1-7的Key排布有序，故转移至下一层的SSTable数量较小
     /// 因此大量数据压缩的情况下Level 1的SSTable数量会较多
-    /// TODO: SSTable锁,避免并行压缩时数据范围重复
+    ///
+    /// 第3步结束时会一次性选中所有参与压缩的SSTable，其中任一SSTable已被其他压缩选中时放弃本次压缩，
+    /// 因此并行压缩时同一SSTable不会被重复合并
     pub(crate) async fn major_compaction(&self, mut level: usize) -> Result<()> {
         if level > 6 {
             return Err(KvsError::LevelOver);
@@ -85,26 +280,56 @@ impl Compactor {
         let config = &self.config;
 
         while level < 7 {
-            if let Some((index, vec_expire_gen, vec_sharding))
+            if let Some((index, vec_expire_gen, vec_sharding, data_version))
                         = self.data_loading_with_level(level).await? {
 
                 let start = Instant::now();
+                let vec_new_gen = vec_sharding.iter()
+                    .map(|(gen, _)| *gen)
+                    .collect_vec();
                 // 并行创建SSTable
+                // 任一分片的IOHandler创建失败时同样视为失败，避免该分片的数据在过期SSTable被删除后丢失
                 let ss_table_futures = vec_sharding.into_iter()
-                    .filter_map(|(gen, sharding)| {
-                        self.io_handler_factory.create(gen).ok()
-                            .map(|io_handler| SsTable::create_for_immutable_table(config,
-                                                                                  io_handler,
-                                                                                  sharding,
-                                                                                  level + 1))
+                    .map(|(gen, sharding)| async move {
+                        let io_handler = self.create_io_handler(gen, level + 1)?;
+                        SsTable::create_for_immutable_table(config,
+                                                            io_handler,
+                                                            sharding,
+                                                            level + 1,
+                                                            data_version).await
                     });
-                let vec_new_ss_table: Vec<SsTable> = future::try_join_all(ss_table_futures).await?;
+                let vec_new_ss_table: Vec<SsTable> = match future::try_join_all(ss_table_futures).await {
+                    Ok(vec_new_ss_table) => vec_new_ss_table,
+                    Err(err) => {
+                        // 中止压缩并清除已生成的SSTable文件，参与压缩的SSTable保持不变
+                        for gen in vec_new_gen {
+                            let _ignore = self.io_handler_factory.clean(gen);
+                        }
+                        self.manifest.read().await
+                            .release_for_compaction(&vec_expire_gen);
+                        return Err(err);
+                    }
+                };
 
                 let mut manifest = self.manifest.write().await;
-                manifest.insert_ss_table_with_index_batch(vec_new_ss_table, index).await;
+                let size_of_disk = manifest.get_size_of_disk();
+                manifest.record_compaction_written(vec_new_ss_table.iter()
+                    .map(SsTable::get_size_of_disk)
+                    .sum());
+                manifest.insert_ss_table_with_index_batch(vec_new_ss_table, index).await?;
                 manifest.retain_with_vec_gen_and_level(&vec_expire_gen).await?;
-
-                info!("[LsmStore][Major Compaction][recreate_sst][Level: {}][Time: {:?}]", level, start.elapsed());
+                let reclaimed_bytes = size_of_disk.saturating_sub(manifest.get_size_of_disk());
+                manifest.record_compaction(reclaimed_bytes);
+                manifest.warn_tombstone_ratio(config.tombstone_ratio_threshold);
+
+                info!(
+                    level,
+                    new_gens = ?vec_new_gen,
+                    expired_gens = ?vec_expire_gen,
+                    reclaimed_bytes,
+                    elapsed = ?start.elapsed(),
+                    "[LsmStore][Major Compaction][finished]"
+                );
                 level += 1;
             } else { break }
         }
@@ -112,20 +337,19 @@ impl Compactor {
     }
 
     /// 通过Level进行归并数据加载
-    async fn data_loading_with_level(&self, level: usize) -> Result<Option<(usize, ExpiredGenVec, MergeShardingVec)>> {
+    ///
+    /// 返回值中附带参与压缩的SSTable中最大的数据版本号，作为压缩生成的SSTable的数据版本号
+    async fn data_loading_with_level(&self, level: usize) -> Result<Option<(usize, ExpiredGenVec, MergeShardingVec, u64)>> {
         let manifest = self.manifest.read().await;
         let config = &self.config;
         let next_level = level + 1;
-        let major_select_file_size = self.config.major_select_file_size;
 
         // 如果该Level的SSTables数量尚未越出阈值则提取返回空
-        if level > 5 || !manifest.is_threshold_exceeded_major(config.major_threshold_with_sst_size,
-                                                level,
-                                                config.level_sst_magnification) {
+        if level > 5 || !manifest.is_threshold_exceeded_major(config, level) {
             return Ok(None);
         }
 
-        if let Some(vec_ss_table_l) = Self::get_first_vec_ss_table(&manifest, level, major_select_file_size) {
+        if let Some(vec_ss_table_l) = Self::select_vec_ss_table(&manifest, level, config) {
             let start = Instant::now();
 
             let scope_l = Scope::fusion_from_vec_ss_table(&vec_ss_table_l)?;
@@ -137,24 +361,47 @@ impl Compactor {
 
             let index = SsTable::first_index_with_level(&vec_ss_table_ll, &manifest, next_level);
 
-            let vec_ss_table_final = match Scope::fusion_from_vec_ss_table(&vec_ss_table_ll) {
-                Ok(scope) => manifest.get_meet_scope_ss_tables(level, &scope),
-                Err(_) => vec_ss_table_l
+            let scope_ll = Scope::fusion_from_vec_ss_table(&vec_ss_table_ll)?;
+            let vec_ss_table_final = if scope_ll.is_empty() {
+                vec_ss_table_l
+            } else {
+                manifest.get_meet_scope_ss_tables(level, &scope_ll)
             }.into_iter()
                 .chain(vec_ss_table_ll)
                 .chain(vec_ss_table_l_1)
                 .unique_by(|ss_table| ss_table.get_gen())
                 .collect_vec();
 
+            // 收集需要清除的SSTable
+            let vec_expire_gen = SsTable::collect_gen(vec_ss_table_final.clone())?;
+            let data_version = vec_ss_table_final.iter()
+                .map(|ss_table| ss_table.get_data_version())
+                .max()
+                .unwrap_or(0);
+            // 其中存在已被其他压缩选中的SSTable时放弃本次压缩，避免同一SSTable被重复合并
+            if !manifest.try_select_for_compaction(&vec_expire_gen) {
+                info!("[LsmStore][Major Compaction][data_loading_with_level][Level: {}][SSTables selected by other compaction]", level);
+                return Ok(None);
+            }
+
+            // 更深Level的数据范围，用于判断墓碑是否可以丢弃
+            let vec_deeper_scope = ((next_level + 1)..7)
+                .flat_map(|level| manifest.get_vec_ss_table_with_level(level))
+                .map(SsTable::get_scope)
+                .collect_vec();
+
             // 数据合并并切片
             let vec_merge_sharding =
-                Self::data_merge_and_sharding(&vec_ss_table_final, &self.config).await?;
-
-            // 收集需要清除的SSTable
-            let vec_expire_gen = SsTable::collect_gen(vec_ss_table_final)?;
+                match Self::data_merge_and_sharding(&vec_ss_table_final, &vec_deeper_scope, &self.config).await {
+                    Ok(vec_merge_sharding) => vec_merge_sharding,
+                    Err(err) => {
+                        manifest.release_for_compaction(&vec_expire_gen);
+                        return Err(err);
+                    }
+                };
             info!("[LsmStore][Major Compaction][data_loading_with_level][Time: {:?}]", start.elapsed());
 
-            Ok(Some((index, vec_expire_gen, vec_merge_sharding)))
+            Ok(Some((index, vec_expire_gen, vec_merge_sharding, data_version)))
         } else {
             Ok(None)
         }
@@ -163,39 +410,83 @@ impl Compactor {
     /// 以SSTables的数据归并再排序后切片，获取以Command的Key值由小到大的切片排序
     /// 收集所有SSTable的get_all_data的future，并行执行并对数据进行去重以及排序
     /// 真他妈完美
-    async fn data_merge_and_sharding(vec_ss_table: &[&SsTable], config: &Config) -> Result<MergeShardingVec>{
-        // 需要对SSTable进行排序，可能并发创建的SSTable可能确实名字会重复，但是目前SSTable的判断新鲜度的依据目前为Gen
+    async fn data_merge_and_sharding(vec_ss_table: &[&SsTable], vec_deeper_scope: &[&Scope], config: &Config) -> Result<MergeShardingVec>{
+        // 需要对SSTable进行排序，Level较低的SSTable数据较新，同一Level(即Level 0)中则以数据版本号判断新鲜度
+        // 由于未被选中压缩的SSTable可能导致高Level的Gen大于低Level的Gen，因此不能仅依靠Gen排序
         // SSTable使用雪花算法进行生成，所以并行创建也不会导致名字重复(极小概率除外)
         let map_futures = vec_ss_table.iter()
-            .sorted_unstable_by_key(|ss_table| ss_table.get_gen())
+            .sorted_unstable_by_key(|ss_table| (Reverse(ss_table.get_level()), ss_table.freshness(config.version_order)))
             .map(|ss_table| ss_table.get_all_data());
-        let vec_cmd_data = future::try_join_all(map_futures)
-            .await?
-            .into_iter()
+        let vec_cmd_data = Self::data_merge(
+            future::try_join_all(map_futures).await?,
+            |key| vec_deeper_scope.iter()
+                .any(|scope| scope.contains(key)),
+            config.merge_operator
+        )?;
+        Ok(data_sharding(vec_cmd_data, config.sst_file_size, config, true).await)
+    }
+
+    /// 将多组由旧往新排列的数据归并去重，同一Key只保留最新的数据
+    ///
+    /// 最新的数据为Merge时，与同一Key的更旧数据依次折叠为一条数据
+    /// 最新的数据为墓碑时，若更深Level中不可能存在该Key则整体丢弃，
+    /// 否则保留墓碑用于覆盖更深Level中的旧数据；未能折叠至Set的Merge同理，
+    /// 更深Level中不可能存在该Key时直接以merge_operator合并为Set
+    fn data_merge<F>(vec_data: Vec<Vec<CommandData>>, is_deeper_contains: F, merge_operator: Option<MergeOperator>) -> Result<Vec<CommandData>>
+        where F: Fn(&[u8]) -> bool
+    {
+        // 稳定排序，使同一Key的数据保持由新往旧排列
+        let mut vec_merged: Vec<CommandData> = Vec::new();
+        for cmd_data in vec_data.into_iter()
             .flatten()
             .rev()
-            .unique_by(CommandData::get_key_clone)
-            .sorted_unstable()
-            .collect();
-        Ok(data_sharding(vec_cmd_data, config.sst_file_size, config, true).await)
+            .sorted_by(|cmd_a, cmd_b| cmd_a.get_key().cmp(cmd_b.get_key()))
+        {
+            let cmd_data = match vec_merged.pop() {
+                Some(newer) if newer.get_key() == cmd_data.get_key() => merge_cmd_data(newer, cmd_data, merge_operator)?,
+                Some(newer) => {
+                    vec_merged.push(newer);
+                    cmd_data
+                }
+                None => cmd_data
+            };
+            vec_merged.push(cmd_data);
+        }
+
+        vec_merged.into_iter()
+            .filter_map(|cmd_data| match cmd_data {
+                CommandData::Remove { .. } | CommandData::Merge { .. } if is_deeper_contains(cmd_data.get_key().as_slice()) => Some(Ok(cmd_data)),
+                CommandData::Remove { .. } => None,
+                cmd_data => Some(resolve_merge(cmd_data, merge_operator))
+            })
+            .collect()
     }
 
-    /// 获取对应Level的开头指定数量的SSTable
-    pub(crate) fn get_first_vec_ss_table(manifest: &Manifest, level: usize, size: usize) -> Option<Vec<&SsTable>> {
-        let level_vec = manifest.get_level_vec(level).iter()
-            .take(size)
-            .cloned()
+    /// 以`Config::compaction_selector`选择对应Level中作为压缩起点的SSTable
+    ///
+    /// 选择结果为空或含有不属于该Level的Gen时返回None
+    fn select_vec_ss_table<'a>(manifest: &'a Manifest, level: usize, config: &Config) -> Option<Vec<&'a SsTable>> {
+        let level_vec = manifest.get_level_vec(level);
+        let tombstone_ratios = level_vec.iter()
+            .map(|gen| manifest.get_ss_table(gen).map_or(0.0, SsTable::tombstone_ratio))
             .collect_vec();
-        manifest.get_ss_table_batch(&level_vec.to_vec())
+        let vec_gen = config.compaction_selector.select_with_tombstone_ratios(level, level_vec, &tombstone_ratios, config);
+
+        if vec_gen.is_empty() || !vec_gen.iter().all(|gen| level_vec.contains(gen)) {
+            return None;
+        }
+        manifest.get_ss_table_batch(&vec_gen)
     }
 
     pub(crate) fn from_lsm_kv(lsm_kv: &LsmStore) -> Self {
         let manifest = Arc::clone(lsm_kv.manifest());
         let config = Arc::clone(lsm_kv.config());
         let wal = Arc::clone(lsm_kv.wal());
+        let wal_in_flight = Arc::clone(lsm_kv.wal_in_flight());
         let io_handler_factory = Arc::clone(lsm_kv.io_handler_factory());
+        let value_log = Arc::clone(lsm_kv.value_log());
 
-        Compactor::new(manifest, config, io_handler_factory, wal)
+        Compactor::new(manifest, config, io_handler_factory, wal, wal_in_flight, value_log)
     }
 
 }
@@ -222,7 +513,199 @@ impl Clone for Compactor {
             manifest: Arc::clone(&self.manifest),
             config: Arc::clone(&self.config),
             io_handler_factory: Arc::clone(&self.io_handler_factory),
-            wal: Arc::clone(&self.wal)
+            wal: Arc::clone(&self.wal),
+            wal_in_flight: Arc::clone(&self.wal_in_flight),
+            value_log: Arc::clone(&self.value_log)
         }
     }
 }
+
+#[test]
+fn test_data_merge() -> Result<()> {
+    let key_1 = vec![b'1'];
+    let key_2 = vec![b'2'];
+    let key_3 = vec![b'3'];
+
+    // 由旧往新排列
+    let vec_data = vec![
+        vec![
+            CommandData::set(key_1.clone(), vec![b'a']),
+            CommandData::set(key_2.clone(), vec![b'a']),
+            CommandData::set(key_3.clone(), vec![b'a'])
+        ],
+        vec![
+            CommandData::set(key_1.clone(), vec![b'b']),
+            CommandData::remove(key_2.clone())
+        ],
+        vec![
+            CommandData::set(key_1.clone(), vec![b'c']),
+            CommandData::remove(key_3.clone())
+        ],
+    ];
+    // 更深的Level中可能存在key_3，因此其墓碑需要保留，而key_2的墓碑则被丢弃
+    let vec_merged = Compactor::data_merge(vec_data, |key| key == key_3.as_slice(), None)?;
+
+    assert_eq!(vec_merged, vec![
+        CommandData::set(key_1, vec![b'c']),
+        CommandData::remove(key_3)
+    ]);
+
+    Ok(())
+}
+
+#[test]
+fn test_concurrent_major_compaction() -> Result<()> {
+    use tempfile::TempDir;
+    use crate::kernel::KVStore;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let path = temp_dir.path().to_path_buf();
+        // Level 0的阈值足够大，使写入时不会自动触发Major压缩
+        let kv_store = LsmStore::open_with_config(Config::default()
+            .dir_path(path.clone())
+            .level_sst_magnification(100)
+            .wal_enable(false)
+        ).await?;
+        // 4组互不相交的Key，每组各生成5个Level 0的SSTable
+        for round in 0..20_u32 {
+            for i in 0..100_u32 {
+                let key = ((round % 4) * 1000 + i).to_be_bytes();
+                kv_store.set(&key, round.to_be_bytes().to_vec()).await?;
+            }
+            kv_store.minor_compaction_sync().await?;
+        }
+        assert_eq!(kv_store.manifest().read().await.get_level_vec(LEVEL_0).len(), 20);
+
+        let compactor = Compactor::new(
+            Arc::clone(kv_store.manifest()),
+            Arc::new(Config::default()
+                .dir_path(path)
+                .level_sst_magnification(1)
+                .node_id(2)),
+            Arc::clone(kv_store.io_handler_factory()),
+            Arc::clone(kv_store.wal()),
+            Arc::clone(kv_store.wal_in_flight()),
+            Arc::clone(kv_store.value_log())
+        );
+
+        // 并发收集时同一SSTable只会被一次压缩选中
+        let vec_option = future::join_all((0..4)
+            .map(|_| compactor.data_loading_with_level(LEVEL_0))).await;
+        let vec_selected_gen = vec_option.into_iter()
+            .filter_map(|result| result.unwrap())
+            .flat_map(|(_, vec_expire_gen, _, _)| vec_expire_gen)
+            .collect_vec();
+        assert!(!vec_selected_gen.is_empty());
+        assert!(vec_selected_gen.iter().all_unique());
+        kv_store.manifest().read().await
+            .release_for_compaction(&vec_selected_gen);
+
+        // 并发触发多次压缩，重复合并同一SSTable时会因删除已不存在的文件而失败
+        let vec_handle = (0..8)
+            .map(|_| {
+                let compactor = compactor.clone();
+                tokio::spawn(async move {
+                    for _ in 0..5 {
+                        compactor.major_compaction(LEVEL_0).await?;
+                    }
+                    Ok::<(), KvsError>(())
+                })
+            })
+            .collect_vec();
+        for handle in vec_handle {
+            handle.await.unwrap()?;
+        }
+
+        kv_store.manifest().read().await.check_consistency()?;
+        for group in 0..4_u32 {
+            for i in 0..100_u32 {
+                let key = (group * 1000 + i).to_be_bytes();
+                assert_eq!(kv_store.get(&key).await?, Some((16 + group).to_be_bytes().to_vec()));
+            }
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_major_compaction_with_fixed_selector() -> Result<()> {
+    use tempfile::TempDir;
+    use crate::kernel::KVStore;
+    use crate::kernel::lsm::lsm_kv::FixedCompactionSelector;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let path = temp_dir.path().to_path_buf();
+        let kv_store = LsmStore::open_with_config(Config::default()
+            .dir_path(path.clone())
+            .level_sst_magnification(100)
+            .wal_enable(false)
+        ).await?;
+        // 依次写入Key组0、1、0、2，每轮各生成一个Level 0的SSTable
+        for (round, group) in [(0_u32, 0_u32), (1, 1), (2, 0), (3, 2)] {
+            for i in 0..100_u32 {
+                kv_store.set(&(group * 1000 + i).to_be_bytes(), round.to_be_bytes().to_vec()).await?;
+            }
+            kv_store.minor_compaction_sync().await?;
+        }
+        // Level 0中由新往旧排列
+        let vec_gen = kv_store.manifest().read().await.get_level_vec(LEVEL_0).clone();
+        assert_eq!(vec_gen.len(), 4);
+
+        let compactor_with_positions = |positions: Vec<usize>| Compactor::new(
+            Arc::clone(kv_store.manifest()),
+            Arc::new(Config::default()
+                .dir_path(path.clone())
+                .level_sst_magnification(1)
+                .compaction_selector(FixedCompactionSelector::new(positions))),
+            Arc::clone(kv_store.io_handler_factory()),
+            Arc::clone(kv_store.wal()),
+            Arc::clone(kv_store.wal_in_flight()),
+            Arc::clone(kv_store.value_log())
+        );
+        let expected_data = |group: u32, round: u32| (0..100_u32)
+            .map(|i| CommandData::set((group * 1000 + i).to_be_bytes().to_vec(), round.to_be_bytes().to_vec()))
+            .collect_vec();
+
+        // 选中的SSTable与其他SSTable不相交时仅合并其自身
+        let (_, vec_expire_gen, vec_sharding, _) = compactor_with_positions(vec![2])
+            .data_loading_with_level(LEVEL_0).await?
+            .expect("compaction is not selected");
+        assert_eq!(vec_expire_gen, vec![vec_gen[2]]);
+        assert_eq!(vec_sharding.into_iter().flat_map(|(_, data)| data).collect_vec(), expected_data(1, 1));
+        kv_store.manifest().read().await
+            .release_for_compaction(&vec_expire_gen);
+
+        // 选中最旧的Key组0时补充与其相交的较新SSTable，合并结果为较新的数据
+        let compactor = compactor_with_positions(vec![3]);
+        let (_, vec_expire_gen, vec_sharding, _) = compactor
+            .data_loading_with_level(LEVEL_0).await?
+            .expect("compaction is not selected");
+        assert_eq!(vec_expire_gen.iter().sorted().collect_vec(), [vec_gen[1], vec_gen[3]].iter().sorted().collect_vec());
+        assert_eq!(vec_sharding.into_iter().flat_map(|(_, data)| data).collect_vec(), expected_data(0, 2));
+        kv_store.manifest().read().await
+            .release_for_compaction(&vec_expire_gen);
+
+        // 超出范围的位置不会选中任何SSTable
+        assert!(compactor_with_positions(vec![4]).data_loading_with_level(LEVEL_0).await?.is_none());
+
+        compactor.major_compaction(LEVEL_0).await?;
+        {
+            let manifest = kv_store.manifest().read().await;
+            assert_eq!(manifest.get_level_vec(LEVEL_0), &vec![vec_gen[0], vec_gen[2]]);
+            assert_eq!(manifest.get_level_data(1).await?, expected_data(0, 2));
+            manifest.check_consistency()?;
+        }
+        for (group, round) in [(0_u32, 2_u32), (1, 1), (2, 3)] {
+            for i in 0..100_u32 {
+                assert_eq!(kv_store.get(&(group * 1000 + i).to_be_bytes()).await?, Some(round.to_be_bytes().to_vec()));
+            }
+        }
+
+        Ok(())
+    })
+}
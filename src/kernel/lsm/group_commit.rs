@@ -0,0 +1,130 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time;
+use crate::HashStore;
+use crate::kernel::{KVStore, Result};
+use crate::KvsError;
+
+/// 组提交的结果
+/// 错误需要分发给窗口内的所有写入者，因此以错误信息的形式传递
+type CommitResult = Option<std::result::Result<(), String>>;
+
+/// 组提交
+///
+/// 时间窗口内的多个Wal写入会合并为一条SetBatch进行一次写盘与fsync，
+/// 窗口内的写入者等待同一个完成信号，因此收到完成信号时数据均已落盘
+#[derive(Debug)]
+pub(crate) struct GroupCommit {
+    interval: Duration,
+    group: Mutex<Option<CommitGroup>>
+}
+
+/// 当前时间窗口内尚未写盘的数据
+#[derive(Debug)]
+struct CommitGroup {
+    pairs: Vec<(Vec<u8>, Vec<u8>)>,
+    sender: watch::Sender<CommitResult>
+}
+
+impl GroupCommit {
+    pub(crate) fn new(interval: Duration) -> Self {
+        GroupCommit {
+            interval,
+            group: Mutex::new(None)
+        }
+    }
+
+    /// 将键值对加入当前时间窗口并等待该窗口写盘完成
+    ///
+    /// 窗口内的第一个写入者会开启窗口，并在窗口结束时进行写盘
+    /// 写盘失败时窗口内的所有写入者均会收到错误
+    #[allow(clippy::unwrap_used)]
+    pub(crate) async fn commit(self: &Arc<Self>, wal: &Arc<HashStore>, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
+        let mut receiver = {
+            let mut option_group = self.group.lock().unwrap();
+
+            if let Some(group) = option_group.as_mut() {
+                group.pairs.push((key, value));
+                group.sender.subscribe()
+            } else {
+                let (sender, receiver) = watch::channel(None);
+                *option_group = Some(CommitGroup { pairs: vec![(key, value)], sender });
+
+                // 写盘交由独立的Task进行，避免开启窗口的写入者被取消时窗口无法结束
+                let group_commit = Arc::clone(self);
+                let wal = Arc::clone(wal);
+                let _ignore = tokio::spawn(async move {
+                    time::sleep(group_commit.interval).await;
+                    group_commit.flush_group(&wal).await;
+                });
+                receiver
+            }
+        };
+
+        let result = loop {
+            if let Some(result) = receiver.borrow().clone() {
+                break result;
+            }
+            receiver.changed().await
+                .map_err(|err| KvsError::GroupCommitError(err.to_string()))?;
+        };
+
+        result.map_err(KvsError::GroupCommitError)
+    }
+
+    /// 取出当前时间窗口内的数据整体写入Wal并fsync，随后通知窗口内的所有写入者
+    #[allow(clippy::unwrap_used)]
+    async fn flush_group(&self, wal: &HashStore) {
+        let option_group = self.group.lock().unwrap().take();
+
+        if let Some(CommitGroup { pairs, sender }) = option_group {
+            let result = async {
+                wal.set_batch(pairs).await?;
+                wal.sync().await
+            }.await
+                .map_err(|err| err.to_string());
+            let _ignore = sender.send(Some(result));
+        }
+    }
+}
+
+#[test]
+fn test_group_commit() -> Result<()> {
+    use std::fs;
+    use futures::future;
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let crash_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let wal = Arc::new(HashStore::open(temp_dir.path()).await?);
+        let group_commit = Arc::new(GroupCommit::new(Duration::from_millis(10)));
+
+        let vec_result = future::join_all((0..100)
+            .map(|i| group_commit.commit(&wal, vec![i], vec![i]))).await;
+        assert!(vec_result.into_iter().all(|result| result.is_ok()));
+        // 模拟断电：仅复制已写入文件的数据，写入者收到完成信号时数据已fsync落盘
+        for entry in fs::read_dir(temp_dir.path())? {
+            let path = entry?.path();
+            if let Some(file_name) = path.file_name() {
+                let _ignore = fs::copy(&path, crash_dir.path().join(file_name))?;
+            }
+        }
+        let crashed_wal = HashStore::open(crash_dir.path()).await?;
+        for i in 0..100 {
+            assert_eq!(crashed_wal.get(&[i]).await?, Some(vec![i]));
+        }
+
+        // 窗口内存在空Key导致写盘失败时，窗口内的所有写入者均收到错误
+        let vec_result = future::join_all(vec![
+            group_commit.commit(&wal, vec![b'k'], vec![b'v']),
+            group_commit.commit(&wal, vec![], vec![b'v'])
+        ]).await;
+        assert!(vec_result.into_iter().all(|result| matches!(result, Err(KvsError::GroupCommitError(_)))));
+        assert_eq!(wal.get(b"k").await?, None);
+
+        Ok(())
+    })
+}
@@ -1,30 +1,53 @@
-use std::collections::BTreeMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashSet};
+use std::{fs, io, iter};
+use std::fmt::Debug;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::sync::atomic::{AtomicI32, Ordering};
-use std::time::Instant;
+use std::sync::atomic::{AtomicI32, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use async_trait::async_trait;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
 use snowflake::SnowflakeIdBucket;
-use tokio::sync::{Mutex, oneshot, RwLock};
+use tokio::sync::{Mutex, oneshot, RwLock, Semaphore};
 use tokio::sync::oneshot::Sender;
 use tracing::{error, info, warn};
 use crate::{HashStore, KvsError};
-use crate::kernel::{CommandData, CommandPackage, KVStore, sorted_gen_list};
+use crate::kernel::{batch_check, CommandData, CommandPackage, CompactionStats, FileKind, FileRef, FORMAT_VERSION_FILE_NAME, key_check, KVStore, log_path, prefix_end, sorted_gen_list, VersionedCommand};
+use crate::kernel::dir_lock::DirLock;
 use crate::kernel::io_handler::IOHandlerFactory;
-use crate::kernel::lsm::{Manifest, MemMap, MemTable};
-use crate::kernel::lsm::compactor::Compactor;
-use crate::kernel::lsm::ss_table::SsTable;
+use crate::kernel::cipher::Cipher;
+use crate::kernel::lsm::{clean_pending_delete, data_sharding, for_each_merged_key, is_after_start, read_pending_delete, DEFAULT_KEY_LOCK_STRIPES, KeyCursor, KeyGuard, KeyLocks, key_with_tombstone, locate_log_path, Manifest, MemMap, MemTable, merge_cmd_data, merge_range_sources, merge_range_sources_with, overlap_ratio, resolve_merge, SsTableSnapshot, tombstone_ratio, verify_checksum_manifest};
+use crate::kernel::lsm::compactor::{Compactor, LEVEL_0, MAX_LEVEL};
+use crate::kernel::lsm::group_commit::GroupCommit;
+use crate::kernel::lsm::scheduler::CompactionScheduler;
+use crate::kernel::lsm::ss_table::{ALIGNMENT_4K, RangeSource, Scope, SsTable, SsTableMigrator};
+use crate::kernel::migrator::{LogMigrator, Migrator};
+use crate::kernel::lsm::value_log::ValueLog;
+use crate::kernel::value_reader::ValueReader;
 use crate::kernel::Result;
 
 pub(crate) type LevelSlice = [Vec<i64>; 7];
 
-pub(crate) type SsTableMap = BTreeMap<i64, SsTable>;
+pub(crate) type SsTableMap = BTreeMap<i64, Arc<SsTable>>;
+
+/// 用户定义的合并函数
+///
+/// 参数依次为Key、已存在的value(不存在或已被删除时为None)以及由旧往新排列的操作数，返回合并后的value
+pub type MergeOperator = fn(&[u8], Option<&[u8]>, &[Vec<u8>]) -> Vec<u8>;
 
 pub(crate) const DEFAULT_WAL_PATH: &str = "wal";
 
+pub(crate) const DEFAULT_SNAPSHOT_PATH: &str = "snapshot";
+
+pub(crate) const DEFAULT_VALUE_LOG_PATH: &str = "vlog";
+
+pub(crate) const DEFAULT_TS_PATH: &str = "ts";
+
 pub(crate) const DEFAULT_MINOR_THRESHOLD_WITH_DATA_OCCUPIED: u64 = 4 * 1024 * 1024;
 
-pub(crate) const DEFAULT_SPARSE_INDEX_INTERVAL_BLOCK_SIZE: u64 = 4;
+pub(crate) const DEFAULT_BLOCK_SIZE: usize = 16 * 1024;
 
 pub(crate) const DEFAULT_SST_FILE_SIZE: usize = 32 * 1024 * 1024;
 
@@ -40,15 +63,41 @@ pub(crate) const DEFAULT_DESIRED_ERROR_PROB: f64 = 0.05;
 
 pub(crate) const DEFAULT_CACHE_SIZE: usize = 23333;
 
+pub(crate) const DEFAULT_BLOOM_CACHE_SIZE: usize = 64;
+
+pub(crate) const DEFAULT_MAX_IMMUTABLE_TABLES: usize = 4;
+
+pub(crate) const DEFAULT_MINOR_COMPACTION_RETRIES: usize = 3;
+
+pub(crate) const DEFAULT_ADAPTIVE_COMPACTION_MAX_FACTOR: u64 = 4;
+
+pub(crate) const DEFAULT_MINOR_COMPACTION_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+pub(crate) const DEFAULT_KV_SEPARATION_THRESHOLD: usize = 1024;
+
+pub(crate) const DEFAULT_VALUE_LOG_FILE_SIZE: u64 = 64 * 1024 * 1024;
+
+pub(crate) const DEFAULT_VALUE_LOG_GC_RATIO: f64 = 0.5;
+
+pub(crate) const DEFAULT_TOMBSTONE_RATIO_THRESHOLD: f64 = 0.3;
+
+pub(crate) const DEFAULT_IO_BUFFER_SIZE: usize = crate::kernel::io_handler::DEFAULT_IO_BUFFER_SIZE;
+
+pub(crate) const DEFAULT_IO_READER_POOL_SIZE: usize = crate::kernel::io_handler::DEFAULT_READER_POOL_SIZE;
+
 pub(crate) const DEFAULT_WAL_COMPACTION_THRESHOLD: u64 = crate::kernel::hash_kv::DEFAULT_COMPACTION_THRESHOLD;
 
+pub(crate) const DEFAULT_WAL_COMPACTION_COOLDOWN: Duration = crate::kernel::hash_kv::DEFAULT_COMPACTION_COOLDOWN;
+
+pub(crate) const DEFAULT_SERIALIZE_PARALLELISM: usize = 4;
+
 /// 基于LSM的KV Store存储内核
 /// Leveled Compaction压缩算法
 #[derive(Debug)]
 pub struct LsmStore {
     /// MemTable
     /// https://zhuanlan.zhihu.com/p/79064869
-    mem_table: MemTable,
+    mem_table: Arc<MemTable>,
     /// Manifest
     /// 用于管理内部SSTable的Gen映射以及Level分级结构
     /// TODO：多版本持久化
@@ -66,8 +115,35 @@ pub struct LsmStore {
     /// 2、作Key-Value分离的准备，当作vLog
     /// 3、HashStore会丢弃超出大小的数据，保证最新数据不会丢失
     wal: Arc<HashStore>,
+    /// 进行中的Wal异步写入
+    /// 每个写入持有读锁，获取写锁即等待此前的写入全部完成
+    wal_in_flight: Arc<RwLock<()>>,
+    /// 各Key带有版本时间戳的写入最近生效的ts，包括Remove的ts，即墓碑
+    /// 用于`apply_versioned`的LWW判断，与Wal同样使用HashStore
+    ts_store: HashStore,
+    /// 以Key分段的写入锁
+    /// 同一Key的Wal与MemTable双写及条件写入的判断均在持有该锁时完成
+    key_locks: KeyLocks,
+    /// Wal组提交
+    /// 仅在设置了`Config::group_commit_interval`时启用
+    group_commit: Option<Arc<GroupCommit>>,
+    /// 待落盘ImmutableMemTable的许可
+    /// 许可数量为`Config::max_immutable_tables`，每个落盘中的ImmutableMemTable持有一个许可
+    immutable_permits: Arc<Semaphore>,
+    /// 自适应压缩调度器
+    /// 仅在开启`Config::adaptive_compaction`时启用
+    scheduler: Option<Arc<CompactionScheduler>>,
+    /// vLog
+    /// 开启Key-Value分离时，SSTable中的大value存储于此
+    value_log: Arc<ValueLog>,
+    /// get从SSTable、vLog与Wal中实际读取的字节数
+    get_read_bytes: AtomicU64,
+    /// get返回的value字节数
+    get_value_bytes: AtomicU64,
     /// 异步任务阻塞监听器
     vec_rev: Mutex<Vec<oneshot::Receiver<()>>>,
+    /// 目录锁，防止其他实例同时开启该目录
+    _dir_lock: DirLock,
 }
 
 #[async_trait]
@@ -84,68 +160,384 @@ impl KVStore for LsmStore {
 
     #[inline]
     async fn flush(&self) -> Result<()> {
-        self.wal.flush().await?;
-        if !self.mem_table.mem_table_is_empty().await {
-            self.minor_compaction().await?;
-        }
-        self.wait_for_compression_down().await?;
+        self.flush_to_sst().await
+    }
 
-        Ok(())
+    /// 写入后仅对Wal当前写入的日志文件进行fsync，不会生成SSTable
+    /// 未开启Wal时只能通过flush将数据持久化为SSTable
+    #[inline]
+    async fn set_sync(&self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        self.set(key, value).await?;
+        if !self.config.wal_enable {
+            return self.flush().await;
+        }
+        let _ignore = self.wal_in_flight.write().await;
+        self.wal.sync().await
     }
 
     #[inline]
     async fn set(&self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        self.write_key_check(key)?;
         self.append_cmd_data(CommandData::Set { key: key.to_vec(), value }, true).await
     }
 
+    /// MemTable中以Key为单位存储，因此SetBatch会被拆分为各个Set，而Wal中则整批写入
+    #[inline]
+    async fn set_batch(&self, pairs: Vec<(Vec<u8>, Vec<u8>)>) -> Result<()> {
+        for (key, _) in pairs.iter() {
+            self.write_key_check(key)?;
+        }
+        let vec_cmd_data = pairs.into_iter()
+            .map(|(key, value)| CommandData::Set { key, value })
+            .collect_vec();
+
+        self.append_cmd_data_batch(vec_cmd_data, true).await
+    }
+
+    /// 持有各Key的写入锁完成校验与写入，使校验与写入之间不会插入这些Key的其他写入
+    /// 校验通过后Wal中以单条SetBatch整批写入，任意命令校验失败时不写入任何数据
+    #[inline]
+    async fn write_batch_atomic(&self, vec_cmd: Vec<CommandData>) -> Result<()> {
+        let vec_remove_key = batch_check(&vec_cmd)?;
+        for cmd in vec_cmd.iter() {
+            self.write_key_check(cmd.get_key())?;
+        }
+        let guard = self.key_locks.lock_batch(vec_cmd.iter().map(CommandData::get_key)).await;
+
+        if self.config.strict_remove {
+            for key in vec_remove_key {
+                if self.get_with_key_guard(&guard, key).await?.is_none() {
+                    return Err(KvsError::KeyNotFound);
+                }
+            }
+        }
+        if vec_cmd.is_empty() {
+            return Ok(());
+        }
+        // 同一Key仅保留批次中最后的命令，避免Wal的SetBatch中存在重复的Key
+        let vec_cmd = vec_cmd.into_iter()
+            .rev()
+            .unique_by(CommandData::get_key_clone)
+            .collect_vec();
+        self.wal_write_batch(&vec_cmd).await?;
+        self.mem_table.insert_data_batch(vec_cmd.into_iter()
+            .map(|cmd| (cmd.get_key_clone(), cmd))
+            .collect_vec()
+        ).await;
+        drop(guard);
+
+        self.minor_compaction_if_exceeded().await?;
+
+        Ok(())
+    }
+
     #[inline]
     async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
-        if let Some(CommandData::Set { value, ..}) = self.mem_table.get_cmd_data(key).await {
-            return Ok(Some(value));
+        let option_value = self.get_and_record_read_bytes(key).await?;
+
+        if let Some(value) = option_value.as_ref() {
+            let _ignore = self.get_value_bytes.fetch_add(value.len() as u64, Ordering::Relaxed);
         }
-        // 读取前等待压缩完毕
-        // 相对来说，消耗较小
-        // 当压缩时长高时，说明数据量非常大
-        // 此时直接去获取的话可能会既获取不到数据，也花费大量时间
-        self.wait_for_compression_down().await?;
+        Ok(option_value)
+    }
 
-        if let Some(value) = self.manifest.read().await
-            .get_data_for_ss_tables(key).await? {
-            return Ok(Some(value));
+    /// 被分离至vLog的value直接从vLog中分块读取，其余value本身较小，读取完整的value
+    /// 读取器持有独立的文件句柄，类Unix系统中vLog文件在读取期间被GC回收后仍能读取完毕
+    #[inline]
+    async fn get_reader(&self, key: &[u8]) -> Result<Option<ValueReader>> {
+        key_check(key)?;
+        if self.mem_table.is_negative(key) {
+            return Ok(None);
         }
-        // 尝试从Wal获取数据
-        if let Some(vec_cmd_u8) = self.wal.get(key).await? {
-            let wal_cmd = CommandPackage::decode(&vec_cmd_u8)?;
-            warn!("[Command][reload_from_wal]{:?}", wal_cmd);
-            let option_value = wal_cmd.get_value_clone();
-            self.append_cmd_data(wal_cmd, false).await?;
-            return Ok(option_value);
+        match self.mem_table.get_cmd_data(key).await {
+            Some(CommandData::Merge { .. }) => (),
+            Some(cmd_data) => return Ok(cmd_data.get_value_owner().map(ValueReader::from_value)),
+            None => {
+                self.wait_for_compression_down().await?;
+
+                let manifest = self.manifest.read().await;
+                if let Some(CommandData::SetPtr { ptr, .. }) = manifest.get_data_for_ss_tables(key).await? {
+                    return Ok(Some(self.value_log.reader(key, &ptr).await?));
+                }
+            }
         }
 
-        Ok(None)
+        Ok(self.get(key).await?.map(ValueReader::from_value))
     }
 
     #[inline]
     async fn remove(&self, key: &[u8]) -> Result<()> {
-        match self.get(key).await? {
-            Some(_) => { self.append_cmd_data(CommandData::Remove { key: key.to_vec() }, true).await }
-            None => { Err(KvsError::KeyNotFound) }
+        match self.remove_and_get(key).await? {
+            Some(_) => { Ok(()) }
+            None if self.config.strict_remove => { Err(KvsError::KeyNotFound) }
+            None => { Ok(()) }
+        }
+    }
+
+    #[inline]
+    async fn set_if_absent(&self, key: &[u8], value: Vec<u8>) -> Result<bool> {
+        self.write_key_check(key)?;
+        // 持有该Key的写入锁，使判断是否存在与写入之间不会插入该Key的其他写入
+        let guard = self.key_locks.lock(key).await;
+
+        if self.get_with_key_guard(&guard, key).await?.is_some() {
+            return Ok(false);
+        }
+        let cmd = CommandData::Set { key: key.to_vec(), value };
+        self.wal_write(&cmd).await?;
+        self.mem_table.insert_data(key.to_vec(), cmd).await;
+        drop(guard);
+
+        self.minor_compaction_if_exceeded().await?;
+
+        Ok(true)
+    }
+
+    #[inline]
+    async fn remove_and_get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.write_key_check(key)?;
+        // 持有该Key的写入锁，使读取旧值与写入墓碑之间不会插入该Key的其他写入，且Wal与MemTable中的写入顺序一致
+        let guard = self.key_locks.lock(key).await;
+
+        let option_value = self.get_with_key_guard(&guard, key).await?;
+        if option_value.is_some() {
+            let cmd = CommandData::Remove { key: key.to_vec() };
+            self.wal_write(&cmd).await?;
+            self.mem_table.insert_data(key.to_vec(), cmd).await;
+        }
+        drop(guard);
+
+        self.minor_compaction_if_exceeded().await?;
+
+        Ok(option_value)
+    }
+
+    /// 持有该Key的写入锁以LWW写入带有版本时间戳的Set与Remove
+    ///
+    /// 各Key最近生效的ts记录于dir_path下独立的HashStore之中，Remove同样记录其ts作为墓碑，
+    /// ts小于该Key记录的ts时不写入并返回false，ts相同时以本次写入为准；
+    /// ts先于数据写入，崩溃时至多使本次写入未生效，以相同的ts重试即可，而不会使更旧的写入生效
+    /// 未带时间戳的写入不改变该Key记录的ts
+    #[inline]
+    async fn apply_versioned(&self, versioned: VersionedCommand) -> Result<bool> {
+        let VersionedCommand { cmd, ts } = versioned;
+
+        if !matches!(cmd, CommandData::Set { .. } | CommandData::Remove { .. }) {
+            let _ignore = cmd.apply(self).await?;
+            return Ok(true);
+        }
+        let key = cmd.get_key_clone();
+        self.write_key_check(&key)?;
+        let guard = self.key_locks.lock(&key).await;
+
+        if let Some(ts_bytes) = self.ts_store.get(&key).await? {
+            if bincode::deserialize::<u64>(&ts_bytes)? > ts {
+                return Ok(false);
+            }
+        }
+        self.ts_store.set(&key, bincode::serialize(&ts)?).await?;
+        self.wal_write(&cmd).await?;
+        self.mem_table.insert_data(key, cmd).await;
+        drop(guard);
+
+        self.minor_compaction_if_exceeded().await?;
+
+        Ok(true)
+    }
+
+    /// 持有各Key的写入锁判断各Key是否存在，存在的Key的墓碑以单条SetBatch整批写入Wal后一并写入MemTable
+    #[inline]
+    async fn remove_batch(&self, keys: &[&[u8]]) -> Result<usize> {
+        for key in keys {
+            self.write_key_check(key)?;
+        }
+        let guard = self.key_locks.lock_batch(keys.iter()).await;
+
+        let mut vec_cmd = Vec::new();
+        for key in keys.iter().unique() {
+            if self.get_with_key_guard(&guard, key).await?.is_some() {
+                vec_cmd.push(CommandData::Remove { key: key.to_vec() });
+            }
+        }
+        let count = vec_cmd.len();
+        if count > 0 {
+            self.wal_write_batch(&vec_cmd).await?;
+            self.mem_table.insert_data_batch(vec_cmd.into_iter()
+                .map(|cmd| (cmd.get_key_clone(), cmd))
+                .collect_vec()
+            ).await;
         }
+        drop(guard);
+
+        self.minor_compaction_if_exceeded().await?;
+
+        Ok(count)
+    }
+
+    #[inline]
+    async fn for_each<F>(&self, mut f: F) -> Result<()>
+        where F: FnMut(&[u8], &[u8]) -> Result<()> + Send
+    {
+        self.wait_for_compression_down().await?;
+
+        let (start, end): (Bound<&[u8]>, Bound<&[u8]>) = (Bound::Unbounded, Bound::Unbounded);
+        let manifest = self.manifest.read().await;
+        let vec_mem_data = self.mem_table.get_range_cmd_data(start, end).await;
+        // 与scan相同，以快照锁定SSTable后即释放读锁
+        let snapshot = manifest.range_snapshot(start, end)?;
+        drop(manifest);
+
+        // 对MemTable与各SSTable进行多路归并，SSTable的数据段在遍历至该处时才逐段读取
+        let vec_source = vec_mem_data.into_iter()
+            .map(RangeSource::from_vec_cmd_data)
+            .chain(snapshot.range_sources(start, end))
+            .collect_vec();
+        let result = merge_range_sources_with(vec_source, &self.value_log, self.config.merge_operator, start, end, |key, value| {
+            f(key.as_slice(), value.as_slice()).map(|()| true)
+        }).await;
+        drop(snapshot);
+        self.manifest.read().await
+            .clean_released()?;
+
+        result
+    }
+
+    #[inline]
+    async fn scan(&self, start: &[u8], end: &[u8], limit: usize) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        key_check(start)?;
+        KVStore::scan_with_bound(self, Bound::Included(start), Bound::Excluded(end), limit).await
+    }
+
+    /// MemTable与SSTable均直接以边界进行过滤与归并，Unbounded时无需获取最大的Key
+    #[inline]
+    async fn scan_with_bound(&self, start: Bound<&[u8]>, end: Bound<&[u8]>, limit: usize) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.wait_for_compression_down().await?;
+
+        let manifest = self.manifest.read().await;
+        let vec_mem_data = self.mem_table.get_range_cmd_data(start, end).await;
+        // 以快照锁定所涉及的SSTable后即释放读锁，扫描期间的压缩不会删除快照中的SSTable文件
+        let snapshot = manifest.range_snapshot(start, end)?;
+        drop(manifest);
+
+        // 数据源由新往旧排列：MemTable、ImmutableMemTable、Level 0(Gen由大到小)、Level 1-6
+        let vec_source = vec_mem_data.into_iter()
+            .map(RangeSource::from_vec_cmd_data)
+            .chain(snapshot.range_sources(start, end))
+            .collect_vec();
+        let result = merge_range_sources(vec_source, &self.value_log, self.config.merge_operator, start, end, limit).await;
+        drop(snapshot);
+        // 删除扫描期间过期的SSTable文件
+        self.manifest.read().await
+            .clean_released()?;
+
+        result
+    }
+
+    /// 以`LsmStore::prefix_scan`实现，可利用前缀布隆过滤器跳过SSTable
+    #[inline]
+    async fn prefix_scan(&self, prefix: &[u8], limit: usize) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        LsmStore::prefix_scan(self, prefix, limit).await
+    }
+
+    /// 仅读取各SSTable的Key块，不读取数据段与vLog
+    ///
+    /// 以Key由小到大归并MemTable与各SSTable，Level 1及以上每层同时仅在内存中保留一个SSTable的Key块
+    #[inline]
+    async fn for_each_key<F>(&self, mut f: F) -> Result<()>
+        where F: FnMut(&[u8]) -> Result<()> + Send
+    {
+        self.wait_for_compression_down().await?;
+
+        // 先获取MemTable中的Key再获取SSTable快照，使期间被Flush的数据至少出现在其中之一
+        let vec_mem_table_key = self.mem_table.get_all_cmd_data().await
+            .iter()
+            .map(key_with_tombstone)
+            .collect::<BTreeMap<Vec<u8>, bool>>()
+            .into_iter()
+            .collect_vec();
+        let snapshot = self.manifest.read().await
+            .range_snapshot(Bound::Unbounded, Bound::Unbounded)?;
+        let vec_cursor = iter::once(KeyCursor::from_keys(vec_mem_table_key))
+            .chain(snapshot.key_cursors())
+            .collect_vec();
+
+        for_each_merged_key(vec_cursor, |key, is_tombstone| {
+            if is_tombstone {
+                return Ok(());
+            }
+            f(key)
+        }).await
+    }
+
+    /// 以MemTable与各SSTable的Scope获取，不读取SSTable
+    #[inline]
+    async fn max_key(&self) -> Result<Option<Vec<u8>>> {
+        self.wait_for_compression_down().await?;
+
+        let manifest = self.manifest.read().await;
+        Ok(self.max_key_with_manifest(&manifest))
+    }
+
+    /// 仅读取与范围相交的SSTable的Key块，不读取数据段与vLog
+    #[inline]
+    async fn scan_keys(&self, start: &[u8], end: &[u8], limit: usize) -> Result<Vec<Vec<u8>>> {
+        self.wait_for_compression_down().await?;
+
+        Ok(self.merge_keys(Some(&Scope::from_range(start, end))).await?
+            .into_iter()
+            .filter(|(key, is_tombstone)| !is_tombstone && start <= key.as_slice() && key.as_slice() < end)
+            .map(|(key, _)| key)
+            .take(limit)
+            .collect())
     }
 
     #[inline]
     async fn size_of_disk(&self) -> Result<u64> {
         Ok(self.manifest.read().await
             .ss_tables_map.values()
-            .map(SsTable::get_size_of_disk)
-            .sum::<u64>() + self.wal.size_of_disk().await?)
+            .map(|ss_table| ss_table.get_size_of_disk())
+            .sum::<u64>() + self.wal.size_of_disk().await? + self.value_log.size_of_disk().await? + self.ts_store.size_of_disk().await?)
+    }
+
+    /// 包括SSTable、Wal、vLog与ts记录的文件，以及其中Wal、vLog与ts记录目录的格式版本文件
+    #[inline]
+    async fn live_files(&self) -> Result<Vec<FileRef>> {
+        let mut vec_file = self.manifest.read().await
+            .ss_table_files()?;
+        vec_file.extend(self.wal.live_files().await?
+            .into_iter()
+            .map(|file| match file.kind {
+                FileKind::Log => FileRef { kind: FileKind::Wal, ..file },
+                _ => file
+            }));
+        vec_file.extend(self.value_log.live_files().await?);
+        vec_file.extend(self.ts_store.live_files().await?);
+
+        Ok(vec_file)
+    }
+
+    /// 暂停期间压缩过期的SSTable延迟至恢复后删除，且不进行vLog的垃圾回收与Wal的压缩
+    #[inline]
+    async fn pause_file_deletion(&self) -> Result<()> {
+        self.manifest.write().await
+            .pause_deletion();
+        self.wal.pause_file_deletion().await?;
+        self.ts_store.pause_file_deletion().await
+    }
+
+    #[inline]
+    async fn resume_file_deletion(&self) -> Result<()> {
+        self.manifest.write().await
+            .resume_deletion()?;
+        self.wal.resume_file_deletion().await?;
+        self.ts_store.resume_file_deletion().await
     }
 
     #[inline]
     async fn len(&self) -> Result<usize> {
         Ok(self.manifest.read().await
             .ss_tables_map.values()
-            .map(SsTable::len)
+            .map(|ss_table| ss_table.len())
             .sum::<usize>()
             + self.mem_table.mem_table_len().await)
     }
@@ -160,24 +552,125 @@ impl KVStore for LsmStore {
 
 impl LsmStore {
 
+    /// 仅将Wal中缓冲的数据刷入文件
+    ///
+    /// 会等待此前尚未完成的Wal异步写入，返回后已写入的数据在进程崩溃后可于重新开启时通过Wal恢复；
+    /// MemTable仍保留于内存中，不会触发SSTable的生成，因此代价较小，适合频繁调用
+    /// 注意：未开启Wal时无法提供该保证，且数据仅写入操作系统缓冲，断电时仍可能丢失
+    #[inline]
+    pub async fn flush_buffer(&self) -> Result<()> {
+        let _ignore = self.wal_in_flight.write().await;
+        self.wal.flush().await
+    }
+
+    /// 将MemTable持久化为SSTable并等待压缩完毕
+    ///
+    /// 返回后数据均已存储于SSTable之中，重新开启时无需依赖Wal恢复，
+    /// 但每次调用都会生成新的SSTable，频繁调用会增加压缩的负担
+    #[inline]
+    pub async fn flush_to_sst(&self) -> Result<()> {
+        self.flush_buffer().await?;
+        if !self.mem_table.mem_table_is_empty().await {
+            self.minor_compaction().await?;
+        }
+        self.wait_for_compression_down().await?;
+
+        Ok(())
+    }
+
     /// 追加数据
     async fn append_cmd_data(&self, cmd: CommandData, wal_write: bool) -> Result<()> {
         let mem_table = &self.mem_table;
-        let threshold_size = self.config.minor_threshold_with_data_size;
+        let guard = self.key_locks.lock(cmd.get_key()).await;
 
-        let key = cmd.get_key();
         // Wal与MemTable双写
-        if self.config.wal_enable && wal_write {
+        if wal_write {
+            self.wal_write(&cmd).await?;
+        }
+        mem_table.insert_data(cmd.get_key_clone(), cmd).await;
+        drop(guard);
+
+        self.minor_compaction_if_exceeded().await?;
+
+        Ok(())
+    }
+
+    /// 将数据写入Wal
+    ///
+    /// 开启组提交时会等待所在时间窗口写盘完成
+    async fn wal_write(&self, cmd: &CommandData) -> Result<()> {
+        if !self.config.wal_enable {
+            return Ok(());
+        }
+        let key = cmd.get_key_clone();
+        if let Some(group_commit) = &self.group_commit {
+            group_commit.commit(&self.wal, key, CommandPackage::encode(cmd)?).await?;
+        } else {
             wal_put(
                 &self.wal,
-                key.clone(),
-                CommandPackage::encode(&cmd)?,
+                &self.wal_in_flight,
+                key,
+                CommandPackage::encode(cmd)?,
                 !self.config.wal_async_put_enable
             ).await;
         }
-        mem_table.insert_data(key.clone(), cmd).await;
 
-        if mem_table.is_threshold_exceeded_minor(threshold_size).await {
+        Ok(())
+    }
+
+    /// 以单条SetBatch将一组数据整批写入Wal
+    async fn wal_write_batch(&self, vec_cmd: &[CommandData]) -> Result<()> {
+        if !self.config.wal_enable {
+            return Ok(());
+        }
+        let pairs = vec_cmd.iter()
+            .map(|cmd| Ok((cmd.get_key_clone(), CommandPackage::encode(cmd)?)))
+            .collect::<Result<Vec<_>>>()?;
+        wal_put_batch(&self.wal, &self.wal_in_flight, pairs, !self.config.wal_async_put_enable).await;
+
+        Ok(())
+    }
+
+    /// 批量追加数据
+    async fn append_cmd_data_batch(&self, vec_cmd: Vec<CommandData>, wal_write: bool) -> Result<()> {
+        if vec_cmd.is_empty() {
+            return Ok(());
+        }
+        let mem_table = &self.mem_table;
+        let guard = self.key_locks.lock_batch(vec_cmd.iter().map(CommandData::get_key)).await;
+
+        // Wal与MemTable双写
+        if wal_write {
+            self.wal_write_batch(&vec_cmd).await?;
+        }
+        mem_table.insert_data_batch(vec_cmd.into_iter()
+            .map(|cmd| (cmd.get_key_clone(), cmd))
+            .collect_vec()
+        ).await;
+        drop(guard);
+
+        self.minor_compaction_if_exceeded().await?;
+
+        Ok(())
+    }
+
+    /// MemTable超出落盘阈值时进行Minor压缩
+    ///
+    /// 开启自适应压缩调度时，落盘阈值由调度器根据近期的写入速率与压缩积压决定
+    async fn minor_compaction_if_exceeded(&self) -> Result<()> {
+        let threshold_size = match &self.scheduler {
+            Some(scheduler) => {
+                if scheduler.sample(self.mem_table.written_bytes()) {
+                    let backlog = self.pending_immutable_tables() + self.manifest.read().await
+                        .level_0_backlog(&self.config);
+                    let _ignore = scheduler.decide(backlog);
+                }
+                scheduler.minor_threshold()
+            }
+            None => self.config.minor_threshold_with_data_size.load(Ordering::Relaxed)
+        };
+
+        if self.mem_table.is_threshold_exceeded_minor(threshold_size).await {
             self.minor_compaction().await?;
         }
 
@@ -187,8 +680,13 @@ impl LsmStore {
     /// 使用Config进行LsmStore初始化
     #[inline]
     pub async fn open_with_config(config: Config) -> Result<Self> where Self: Sized {
+        // Merge的操作数需与value折叠，而Key-Value分离时SSTable中的value位于vLog之中
+        if config.merge_operator.is_some() && config.kv_separation_enable {
+            return Err(KvsError::ConfigConflict("merge_operator with kv_separation_enable".to_owned()));
+        }
         let path = config.dir_path.clone();
         let wal_compaction_threshold = config.wal_compaction_threshold;
+        let wal_compaction_cooldown = config.wal_compaction_cooldown;
 
         let mut mem_map = MemMap::new();
         let mut ss_tables = BTreeMap::new();
@@ -196,369 +694,4878 @@ impl LsmStore {
         let mut wal_path = path.clone();
         wal_path.push(DEFAULT_WAL_PATH);
 
+        // 占用目录，已被其他实例开启时直接返回
+        fs::create_dir_all(&path)?;
+        let dir_lock = DirLock::lock_exclusive(&path, config.reject_active_writer, config.heartbeat)?;
+        let extra_dir_paths = config.extra_dir_paths();
+        for extra_dir_path in extra_dir_paths.iter() {
+            fs::create_dir_all(extra_dir_path)?;
+        }
+        // 清除上次删除中断时残留的过期SSTable，避免其被当作有效数据恢复
+        let cleaned = clean_pending_delete(&path, &extra_dir_paths)?;
+        if cleaned > 0 {
+            warn!("[LsmKVStore][Open][Cleaned orphan SSTables: {cleaned}]");
+        }
+        // 以校验和清单检测SSTable是否被外部修改，需在迁移等改写SSTable的操作之前进行
+        let option_checksums = if config.checksum_manifest {
+            Some(verify_checksum_manifest(&path, &extra_dir_paths)?)
+        } else { None };
+
+        // 将旧格式版本的文件迁移为当前格式版本
+        if config.migrate_on_open {
+            let wal_migrated = LogMigrator.migrate(&wal_path).await?;
+            let mut ss_table_migrated = SsTableMigrator::new(&config).migrate(&path).await?;
+            for extra_dir_path in extra_dir_paths.iter() {
+                ss_table_migrated += SsTableMigrator::new(&config).migrate(extra_dir_path).await?;
+            }
+            info!("[LsmKVStore][Migrate][Wal: {wal_migrated}][SSTable: {ss_table_migrated}]");
+        }
         // 初始化wal日志
-        let wal = Arc::new(HashStore::open_with_compaction_threshold(&wal_path, wal_compaction_threshold).await?);
-        let io_handler_factory = Arc::new(IOHandlerFactory::new(path.clone()));
+        let wal = Arc::new(HashStore::open_with_cipher_option(&wal_path, wal_compaction_threshold, wal_compaction_cooldown, config.cipher.clone()).await?);
+        let ts_store = HashStore::open_with_cipher_option(path.join(DEFAULT_TS_PATH), wal_compaction_threshold, wal_compaction_cooldown, config.cipher.clone()).await?;
+        let io_handler_factory = Arc::new(IOHandlerFactory::new(path.clone())
+            .buffer_size(config.io_buffer_size)
+            .reader_pool_size(config.io_reader_pool_size)
+            .extra_dir_paths(extra_dir_paths)
+            .cipher(config.cipher.clone()));
         // 持久化数据恢复
         // 倒叙遍历，从最新的数据开始恢复
-        for gen in sorted_gen_list(&path)?.iter().rev() {
+        for gen in config.sorted_gen_list_with_levels()?.iter().rev() {
             let io_handler = io_handler_factory.create(*gen)?;
             // 尝试初始化Table
-            match SsTable::restore_from_file(io_handler).await {
+            match SsTable::restore_from_file_with_verify(io_handler, config.bloom_resident, !config.background_verify).await {
                 Ok(ss_table) => {
                     // 初始化成功时直接传入SSTable的索引中
-                    let _ignore = ss_tables.insert(*gen, ss_table);
+                    let _ignore = ss_tables.insert(*gen, Arc::new(ss_table));
                 }
+                // 密钥错误时SSTable本身并未损坏，不能以Wal恢复并删除
+                Err(KvsError::CipherError) => return Err(KvsError::CipherError),
                 Err(err) => {
                     error!("[LsmKVStore][Load SSTable: {gen}][Error]: {err:?}");
                     // 是否删除可能还是得根据用户选择
                     // io_handler_factory.clean(*gen)?;
                     // 从wal将有问题的ss_table恢复到mem_table中
-                    Self::reload_for_wal(&mut mem_map, &wal, *gen).await?;
+                    // 无法恢复时返回SSTable的损坏信息，便于定位损坏的文件
+                    if let Err(wal_err) = Self::reload_for_wal(&mut mem_map, &wal, *gen).await {
+                        error!("[LsmKVStore][Reload SSTable: {gen}][Error]: {wal_err:?}");
+                        return Err(err);
+                    }
                     // 删除有问题的ss_table
                     io_handler_factory.clean(*gen)?;
                 }
             }
         }
         // 构建SSTable信息集
-        let manifest = Manifest::new(ss_tables, Arc::new(path), config.cache_size)?;
+        let vec_gen = ss_tables.keys().copied().collect_vec();
+        let mut manifest = Manifest::new(ss_tables, Arc::new(path), &config)?;
+        manifest.set_io_handler_factory(Arc::clone(&io_handler_factory));
+        if let Some(checksums) = option_checksums {
+            manifest.enable_checksums(checksums, config.migrate_on_open)?;
+        }
+        if config.merge_operator.is_some() && config.wal_enable {
+            Self::reload_merge_for_wal(&mut mem_map, &wal, &manifest, config.merge_operator).await?;
+        }
+        if config.background_verify {
+            // 校验完成前视同已被选中，以免压缩合并尚未校验(可能已损坏)的SSTable
+            let _ignore = manifest.try_select_for_compaction(&vec_gen);
+        }
+        let manifest = Arc::new(RwLock::new(manifest));
+        if config.background_verify {
+            let _ignore = tokio::spawn(Self::verify_in_background(Arc::clone(&manifest), vec_gen, config.on_corrupted));
+        }
+        let group_commit = config.group_commit_interval
+            .map(|interval| Arc::new(GroupCommit::new(interval)));
+        let immutable_permits = Arc::new(Semaphore::new(config.max_immutable_tables));
+        let scheduler = config.adaptive_compaction
+            .then(|| Arc::new(CompactionScheduler::new(&config)));
+        let value_log = Arc::new(ValueLog::open(path.join(DEFAULT_VALUE_LOG_PATH), config.io_buffer_size, config.cipher.clone())?);
 
         Ok(LsmStore {
-            mem_table: MemTable::new(mem_map),
-            manifest: Arc::new(RwLock::new(manifest)),
+            mem_table: Arc::new(MemTable::new(mem_map).negative_cache_size(config.negative_cache_size)),
+            manifest,
             config: Arc::new(config),
             io_handler_factory,
             wal,
-            vec_rev: Mutex::new(Vec::new())
+            wal_in_flight: Arc::new(RwLock::new(())),
+            ts_store,
+            key_locks: KeyLocks::new(DEFAULT_KEY_LOCK_STRIPES),
+            group_commit,
+            immutable_permits,
+            scheduler,
+            value_log,
+            get_read_bytes: AtomicU64::new(0),
+            get_value_bytes: AtomicU64::new(0),
+            vec_rev: Mutex::new(Vec::new()),
+            _dir_lock: dir_lock
         })
     }
 
-    /// 从Wal恢复SSTable数据
-    /// 初始化失败时遍历wal的key并检测key是否为gen
-    async fn reload_for_wal(mem_table: &mut MemMap, wal: &HashStore, gen: i64) -> Result<()>{
-        // 将SSTable持久化失败前预存入的指令键集合从wal中获取
-        // 随后将每一条指令键对应的指令恢复到mem_table中
-        warn!("[SsTable: {}][reload_from_wal]", gen);
-        let key_gen = CommandCodec::encode_gen(gen)?;
-        if let Some(key_cmd_u8) = wal.get(&key_gen).await? {
-            for key in CommandCodec::decode_keys(&key_cmd_u8)? {
-                if let Some(cmd_data_u8) = wal.get(&key).await? {
-                    let cmd_data = CommandPackage::decode(&cmd_data_u8)?;
-
-                    let _ignore = mem_table.insert(cmd_data.get_key_clone(), cmd_data);
-                } else {
-                    return Err(KvsError::WalLoadError);
-                }
+    /// 由旧往新逐个校验开启时跳过校验的SSTable
+    ///
+    /// 每次仅在校验单个SSTable时持有Manifest的读锁，已被压缩移除的SSTable直接跳过；
+    /// 校验通过的SSTable才可被压缩选中，校验失败或读取失败的SSTable会被隔离，并调用`Config::on_corrupted`上报
+    async fn verify_in_background(manifest: Arc<RwLock<Manifest>>, vec_gen: Vec<i64>, on_corrupted: Option<fn(i64)>) {
+        for gen in vec_gen {
+            let is_crc_match = match manifest.read().await.get_ss_table(&gen) {
+                Some(ss_table) => ss_table.is_crc_match().await,
+                None => continue
             };
-        } else {
-            return Err(KvsError::WalLoadError);
+            match is_crc_match {
+                Ok(true) => manifest.read().await
+                    .release_for_compaction(&[gen]),
+                Ok(false) => error!("[LsmKVStore][Verify SSTable: {gen}][Error]: {:?}", KvsError::CrcMisMatch),
+                Err(err) => error!("[LsmKVStore][Verify SSTable: {gen}][Error]: {err:?}"),
+            }
+            if !matches!(is_crc_match, Ok(true)) {
+                manifest.write().await
+                    .quarantine(gen);
+                if let Some(on_corrupted) = on_corrupted {
+                    on_corrupted(gen);
+                }
+            }
         }
-        Ok(())
+        info!("[LsmKVStore][Background Verify][Finished]");
     }
 
-    /// 异步持久化immutable_table为SSTable
+    /// 运行时热更新配置，可热更新的配置项见`ConfigPatch`
+    ///
+    /// 应用前先校验所有配置项，含有不可热更新的配置项时返回`KvsError::ConfigNotReloadable`且不应用任何配置项
     #[inline]
-    #[allow(clippy::unwrap_used)]
-    pub async fn minor_compaction(&self) -> Result<()> {
-        let (keys, values) = self.mem_table.table_swap().await;
-        if !keys.is_empty() && !values.is_empty() {
-            let compactor = Compactor::from_lsm_kv(self);
-            let sender = self.live_tag().await;
+    pub async fn reconfigure(&self, patch: ConfigPatch) -> Result<()> {
+        patch.check()?;
 
-            let _ignore = tokio::spawn(async move {
-                let start = Instant::now();
-                // 目前minor触发major时是同步进行的，所以此处对live_tag是在此方法体保持存活
-                if let Err(err) = compactor.minor_compaction(keys, values).await {
-                    error!("[LsmStore][minor_compaction][error happen]: {:?}", err);
-                }
-                sender.send(()).unwrap();
-                info!("[LsmStore][Compaction Drop][Time: {:?}]", start.elapsed());
-            });
+        if let Some(cache_size) = patch.cache_size {
+            self.manifest.read().await
+                .resize_position_cache(cache_size).await?;
+        }
+        if let Some(minor_threshold) = patch.minor_threshold_with_data_size {
+            self.config.minor_threshold_with_data_size.store(minor_threshold, Ordering::Relaxed);
+            if let Some(scheduler) = &self.scheduler {
+                scheduler.set_base_threshold(minor_threshold);
+            }
+        }
+        if let Some(major_threshold) = patch.major_threshold_with_sst_size {
+            self.config.major_threshold_with_sst_size.store(major_threshold, Ordering::Relaxed);
         }
+        if let Some(wal_compaction_threshold) = patch.wal_compaction_threshold {
+            self.wal.set_compaction_threshold(wal_compaction_threshold).await;
+        }
+        info!("[LsmKVStore][Reconfigure]: {:?}", patch);
+
         Ok(())
     }
 
-    /// 同步持久化immutable_table为SSTable
+    /// 获取后台校验发现损坏而被隔离的SSTable的Gen，由小到大
     #[inline]
-    pub async fn minor_compaction_sync(&self) -> Result<()> {
-        let (keys, values) = self.mem_table.table_swap().await;
-        Compactor::from_lsm_kv(self).minor_compaction(keys, values).await
+    pub async fn corrupted_gens(&self) -> Vec<i64> {
+        self.manifest.read().await
+            .corrupted_gens()
     }
 
-    /// 同步进行SSTable基于Level的层级压缩
+    /// 以只读快照的形式开启数据库
+    ///
+    /// 开启时会将当前所有的SSTable文件与vLog文件硬链接至独立的快照目录中，
+    /// 因此之后新增的SSTable不可见，原SSTable被其他进程删除时也不影响快照的读取
+    /// 注意：快照中仅包含已持久化为SSTable的数据
+    ///
+    /// 链接时仍在写入中的SSTable(如进行中的Minor压缩)不会被纳入快照，其数据仍存在于原数据库的Wal之中
+    ///
+    /// 以默认的Config开启，原数据库设置了Level目录、密钥或合并函数等时需使用`LsmStore::open_snapshot_with_config`
     #[inline]
-    pub async fn major_compaction_sync(&self, level: usize) -> Result<()> {
-        Compactor::from_lsm_kv(self).major_compaction(level).await
+    pub async fn open_snapshot(path: impl Into<PathBuf>) -> Result<LsmSnapshot> {
+        Self::open_snapshot_with_config(Config::default().dir_path(path.into())).await
     }
 
-    /// 通过CommandData的引用解包并克隆出value值
-    #[allow(dead_code)]
-    fn value_unpack(cmd_data: &CommandData) -> Option<Vec<u8>> {
-        cmd_data.get_value_clone()
-    }
+    /// 使用Config以只读快照的形式开启数据库
+    ///
+    /// config需与原数据库一致，以相同的Level目录、密钥与合并函数等读取数据；
+    /// 各Level目录中的SSTable均链接至快照目录之中，待删除列表中已被压缩取代的SSTable则不纳入快照
+    #[inline]
+    pub async fn open_snapshot_with_config(mut config: Config) -> Result<LsmSnapshot> {
+        let path = config.dir_path.clone();
+        let snapshot_path = path.join(format!("{DEFAULT_SNAPSHOT_PATH}_{}", config.create_gen()));
+        let vec_pending_gen = read_pending_delete(&path)?;
 
-    /// 通过CommandData的所有权直接返回value值的所有权
-    #[allow(dead_code)]
-    fn value_unpack_with_owner(cmd_data: CommandData) -> Option<Vec<u8>> {
-        cmd_data.get_value_owner()
-    }
-    pub(crate) fn manifest(&self) -> &Arc<RwLock<Manifest>> {
-        &self.manifest
-    }
-    pub(crate) fn config(&self) -> &Arc<Config> {
-        &self.config
-    }
-    pub(crate) fn io_handler_factory(&self) -> &Arc<IOHandlerFactory> {
-        &self.io_handler_factory
+        fs::create_dir_all(&snapshot_path)?;
+        Self::hard_link_logs(&path, &snapshot_path, &vec_pending_gen)?;
+        for extra_dir_path in config.extra_dir_paths() {
+            Self::hard_link_logs(&extra_dir_path, &snapshot_path, &vec_pending_gen)?;
+        }
+        Self::remove_incomplete_ss_tables(&snapshot_path, &config).await?;
+        let value_log_path = path.join(DEFAULT_VALUE_LOG_PATH);
+        if value_log_path.exists() {
+            let snapshot_value_log_path = snapshot_path.join(DEFAULT_VALUE_LOG_PATH);
+
+            fs::create_dir_all(&snapshot_value_log_path)?;
+            Self::hard_link_logs(&value_log_path, &snapshot_value_log_path, &[])?;
+        }
+        // 快照中的SSTable均位于快照目录之中
+        config.level_dir_paths.clear();
+        let inner = Self::open_with_config(config
+            .dir_path(snapshot_path.clone())
+            .wal_enable(false)
+        ).await?;
+
+        Ok(LsmSnapshot { inner, snapshot_path })
     }
-    pub(crate) fn wal(&self) -> &Arc<HashStore> {
-        &self.wal
+
+    /// 以只读跟随者的形式开启数据库
+    ///
+    /// 不占用目录锁且不会写入目录，因此可与正在写入该目录的主进程同时开启，
+    /// 之后通过`LsmFollower::refresh`加载主进程新生成的SSTable
+    #[inline]
+    pub async fn open_follower(path: impl Into<PathBuf>) -> Result<LsmFollower> {
+        Self::open_follower_with_config(Config::default().dir_path(path.into())).await
     }
 
-    /// 存活标记
-    /// 返回一个Sender用于存活结束通知
-    pub(crate) async fn live_tag(&self) -> Sender<()> {
-        let (sender, receiver) = oneshot::channel();
+    /// 使用Config以只读跟随者的形式开启数据库
+    ///
+    /// config需与主进程一致，以相同的Level目录、密钥与合并函数等读取数据
+    #[inline]
+    pub async fn open_follower_with_config(config: Config) -> Result<LsmFollower> {
+        // 每个SSTable仅在加载时打开一次，无需复用只读句柄，也避免句柄池持有已被删除的文件
+        let io_handler_factory = IOHandlerFactory::new(config.dir_path.clone())
+            .buffer_size(config.io_buffer_size)
+            .reader_pool_size(0)
+            .extra_dir_paths(config.extra_dir_paths())
+            .cipher(config.cipher.clone())
+            .read_only(true);
+        let value_log = ValueLog::open_read_only(config.dir_path.join(DEFAULT_VALUE_LOG_PATH), config.io_buffer_size, config.cipher.clone())?;
+        let manifest = RwLock::new(Manifest::new(SsTableMap::new(), Arc::new(config.dir_path.clone()), &config)?);
 
-        self.vec_rev.lock()
-            .await
-            .push(receiver);
+        let follower = LsmFollower { manifest, config, io_handler_factory, value_log };
+        follower.refresh().await?;
 
-        sender
+        Ok(follower)
     }
 
-    /// 等待所有压缩结束
-    async fn wait_for_compression_down(&self) -> Result<()> {
-        // 监听异步任务是否执行完毕
-        let mut vec_rev = self.vec_rev.lock().await;
-        while let Some(rev) = vec_rev.pop() {
-            rev.await?
+    /// 将目录中除vec_excluded_gen以外的所有日志文件及格式版本文件硬链接至目标目录
+    fn hard_link_logs(path: &Path, target_path: &Path, vec_excluded_gen: &[i64]) -> Result<()> {
+        let vec_path = sorted_gen_list(path)?.into_iter()
+            .filter(|gen| !vec_excluded_gen.contains(gen))
+            .map(|gen| (log_path(path, gen), log_path(target_path, gen)))
+            .chain([(path.join(FORMAT_VERSION_FILE_NAME), target_path.join(FORMAT_VERSION_FILE_NAME))]);
+
+        for (source_path, link_path) in vec_path {
+            // 列出文件后可能已被压缩删除，或该目录不存在格式版本文件，此时跳过即可
+            if let Err(err) = fs::hard_link(source_path, link_path) {
+                if err.kind() != io::ErrorKind::NotFound {
+                    return Err(err.into());
+                }
+            }
         }
 
         Ok(())
     }
-}
 
-pub(crate) struct CommandCodec;
+    /// 移除快照目录中尚未写入完成的SSTable链接
+    ///
+    /// 快照不开启Wal，无法通过`LsmStore::reload_for_wal`恢复这些SSTable，因此以与`LsmFollower`相同的方式校验并跳过
+    async fn remove_incomplete_ss_tables(snapshot_path: &Path, config: &Config) -> Result<()> {
+        let io_handler_factory = IOHandlerFactory::new(snapshot_path)
+            .buffer_size(config.io_buffer_size)
+            .reader_pool_size(0)
+            .cipher(config.cipher.clone())
+            .read_only(true);
 
-impl CommandCodec {
-    pub(crate) fn encode_gen(gen: i64) -> Result<Vec<u8>> {
-        Ok(bincode::serialize(&gen)?)
-    }
+        for gen in sorted_gen_list(snapshot_path)? {
+            let io_handler = io_handler_factory.create(gen)?;
+            if let Err(err) = SsTable::restore_from_file_with_verify(io_handler, false, true).await {
+                warn!("[LsmSnapshot][Skip SSTable: {gen}]: {err:?}");
+                fs::remove_file(log_path(snapshot_path, gen))?;
+            }
+        }
 
-    #[allow(dead_code)]
-    pub(crate) fn decode_gen(key: &[u8]) -> Result<i64> {
-        Ok(bincode::deserialize(key)?)
+        Ok(())
     }
 
-    pub(crate) fn encode_keys(value: &Vec<Vec<u8>>) -> Result<Vec<u8>> {
-        Ok(bincode::serialize(value)?)
-    }
+    /// 从Wal恢复SSTable数据
+    /// 初始化失败时遍历wal的key并检测key是否为gen
+    async fn reload_for_wal(mem_table: &mut MemMap, wal: &HashStore, gen: i64) -> Result<()>{
+        // 将SSTable持久化失败前预存入的指令键集合从wal中获取
+        // 随后将每一条指令键对应的指令恢复到mem_table中
+        warn!("[SsTable: {}][reload_from_wal]", gen);
+        let key_gen = CommandCodec::encode_gen(gen)?;
+        if let Some(key_cmd_u8) = wal.get(&key_gen).await? {
+            for key in CommandCodec::decode_keys(&key_cmd_u8)? {
+                if let Some(cmd_data_u8) = wal.get(&key).await? {
+                    let cmd_data = CommandPackage::decode(&cmd_data_u8)?;
 
-    pub(crate) fn decode_keys(vec_u8: &[u8]) -> Result<Vec<Vec<u8>>> {
-        Ok(bincode::deserialize(vec_u8)?)
+                    let _ignore = mem_table.insert(cmd_data.get_key_clone(), cmd_data);
+                } else {
+                    return Err(KvsError::WalLoadError);
+                }
+            };
+        } else {
+            return Err(KvsError::WalLoadError);
+        }
+        Ok(())
     }
-}
-#[derive(Debug)]
-pub struct Config {
-    /// 数据目录地址
-    pub(crate) dir_path: PathBuf,
-    /// WAL持久化阈值
-    pub(crate) wal_compaction_threshold: u64,
-    /// 稀疏索引间间隔的Block(4K字节大小)数量
-    pub(crate) sparse_index_interval_block_size: u64,
-    /// SSTable文件大小
-    pub(crate) sst_file_size: usize,
-    /// 持久化阈值(单位: 字节)
-    pub(crate) minor_threshold_with_data_size: u64,
-    /// Major压缩触发阈值
-    pub(crate) major_threshold_with_sst_size: usize,
-    /// Major压缩选定文件数
-    /// Major压缩时通过选定个别SSTable(即该配置项)进行下一级的SSTable选定，
-    /// 并将确定范围的下一级SSTable再次对当前等级的SSTable进行范围判定，
-    /// 找到最合理的上下级数据范围并压缩
-    pub(crate) major_select_file_size: usize,
-    /// 节点Id
-    pub(crate) node_id: i32,
-    /// 每级SSTable数量倍率
-    pub(crate) level_sst_magnification: usize,
-    /// 用于ID生成的原子缓冲
-    /// 避免极端情况下，SSTable创建重复问题并保持时间有序性
-    pub(crate) buffer_i32: AtomicI32,
-    /// 布隆过滤器 期望的错误概率
-    pub(crate) desired_error_prob: f64,
-    /// 数据库全局Position段数据缓存的数量
-    /// 一个size大约为4kb(可能更少)
-    pub(crate) cache_size: usize,
-    /// 开启wal日志写入
-    /// 在开启状态时，会在SSTable文件读取失败时生效，避免数据丢失
-    /// 不过在设备IO容易成为瓶颈，或使用多节点冗余写入时，建议关闭以提高写入性能
-    pub(crate) wal_enable: bool,
-    /// wal写入时开启异步写入
-    /// 可以提高写入响应速度，但可能会导致wal日志在某种情况下并落盘慢于LSM内核而导致该条wal日志无效
-    pub(crate) wal_async_put_enable: bool
-}
 
-impl Config {
+    /// 将Wal中尚未落盘的Merge恢复至MemTable
+    ///
+    /// 与读取时的Wal回退相同，仅恢复SSTables中不存在数据或最新数据为墓碑的Key，并以None折叠为Set；
+    /// 之后对该Key的Merge会覆盖Wal中的这一数据，因此需在开启时而非读取时恢复
+    async fn reload_merge_for_wal(mem_map: &mut MemMap, wal: &HashStore, manifest: &Manifest, merge_operator: Option<MergeOperator>) -> Result<()> {
+        let mut vec_merge = Vec::new();
+        // Wal中同时存有落盘前预存的Key集合，无法解析为Merge的数据直接跳过
+        wal.for_each(|_, cmd_u8| {
+            if let Ok(cmd_data @ CommandData::Merge { .. }) = CommandPackage::decode(cmd_u8) {
+                vec_merge.push(cmd_data);
+            }
+            Ok(())
+        }).await?;
 
-    #[inline]
-    pub fn dir_path(mut self, dir_path: PathBuf) -> Self {
-        self.dir_path = dir_path;
-        self
+        for cmd_data in vec_merge {
+            let key = cmd_data.get_key_clone();
+            let is_flushed = manifest.get_data_for_ss_tables(&key).await?
+                .map_or(false, |older| !matches!(older, CommandData::Remove { .. }));
+            if mem_map.contains_key(&key) || is_flushed {
+                continue;
+            }
+            warn!("[Command][reload_merge_from_wal]{:?}", cmd_data);
+            let _ignore = mem_map.insert(key, resolve_merge(cmd_data, merge_operator)?);
+        }
+
+        Ok(())
     }
 
+    /// 异步持久化immutable_table为SSTable
     #[inline]
-    pub fn minor_threshold_with_data_size(mut self, minor_threshold_with_data_size: u64) -> Self {
-        self.minor_threshold_with_data_size = minor_threshold_with_data_size;
-        self
+    #[allow(clippy::unwrap_used)]
+    pub async fn minor_compaction(&self) -> Result<()> {
+        // 待落盘的ImmutableMemTable达到上限时在此等待，以此对写入进行背压
+        let permit = Arc::clone(&self.immutable_permits)
+            .acquire_owned().await
+            .unwrap();
+        let (keys, values, ticket) = self.mem_table.table_swap(|| self.config.create_gen()).await;
+        if !keys.is_empty() && !values.is_empty() {
+            let compactor = Compactor::from_lsm_kv(self);
+            let mem_table = Arc::clone(&self.mem_table);
+            let scheduler = self.scheduler.as_ref().map(Arc::clone);
+            let sender = self.live_tag().await;
+
+            let _ignore = tokio::spawn(async move {
+                // 开启自适应压缩调度时，同时生成SSTable的数量受调度器的并发度限制
+                // 写入Level 0的顺序由交换时领取的凭证保证，与获取许可的先后无关
+                let slot = match &scheduler {
+                    Some(scheduler) => Some(scheduler.acquire_slot().await),
+                    None => None
+                };
+                let start = Instant::now();
+                // 目前minor触发major时是同步进行的，所以此处对live_tag是在此方法体保持存活
+                if let Err(err) = compactor.minor_compaction_or_restore(&mem_table, ticket, slot, keys, values).await {
+                    error!("[LsmStore][minor_compaction][error happen]: {:?}", err);
+                }
+                drop(permit);
+                sender.send(()).unwrap();
+                info!("[LsmStore][Compaction Drop][Time: {:?}]", start.elapsed());
+            });
+        }
+        Ok(())
     }
 
+    /// 获取当前待落盘的ImmutableMemTable数量
     #[inline]
-    pub fn wal_compaction_threshold(mut self, wal_compaction_threshold: u64) -> Self {
-        self.wal_compaction_threshold = wal_compaction_threshold;
-        self
+    pub fn pending_immutable_tables(&self) -> usize {
+        self.config.max_immutable_tables - self.immutable_permits.available_permits()
     }
 
+    /// 当前是否处于背压状态
+    ///
+    /// 处于背压状态时，触发落盘的写入会阻塞直至已有的ImmutableMemTable落盘完成
     #[inline]
-    pub fn sparse_index_interval_block_size(mut self, sparse_index_interval_block_size: u64) -> Self {
-        self.sparse_index_interval_block_size = sparse_index_interval_block_size;
-        self
+    pub fn is_backpressure(&self) -> bool {
+        self.immutable_permits.available_permits() == 0
     }
 
+    /// 同步持久化immutable_table为SSTable
+    ///
+    /// 落盘重试耗尽时返回错误，数据会被放回MemTable中
     #[inline]
-    pub fn sst_file_size(mut self, sst_file_size: usize) -> Self {
-        self.sst_file_size = sst_file_size;
-        self
+    pub async fn minor_compaction_sync(&self) -> Result<()> {
+        let (keys, values, ticket) = self.mem_table.table_swap(|| self.config.create_gen()).await;
+        Compactor::from_lsm_kv(self).minor_compaction_or_restore(&self.mem_table, ticket, None, keys, values).await
     }
 
+    /// 同步进行SSTable基于Level的层级压缩
     #[inline]
-    pub fn major_threshold_with_sst_size(mut self, major_threshold_with_sst_size: usize) -> Self {
-        self.major_threshold_with_sst_size = major_threshold_with_sst_size;
-        self
+    pub async fn major_compaction_sync(&self, level: usize) -> Result<()> {
+        Compactor::from_lsm_kv(self).major_compaction(level).await
     }
 
+    /// 批量导入数据
+    ///
+    /// 数据在内部以Key排序并去重，同一Key保留最后出现的value，与逐条set的覆盖语义一致，
+    /// 随后跳过MemTable与Wal直接生成有序的SSTable，放置于与数据范围不重叠的最深Level，
+    /// 避免大量无序数据逐条写入时在Level 0中产生大量重叠
+    /// 导入前会先将MemTable落盘并等待压缩结束，因此导入的数据总是新于此前写入的数据
     #[inline]
-    pub fn major_select_file_size(mut self, major_select_file_size: usize) -> Self {
-        self.major_select_file_size = major_select_file_size;
-        self
+    pub async fn bulk_load(&self, data: Vec<(Vec<u8>, Vec<u8>)>) -> Result<()> {
+        for (key, _) in data.iter() {
+            self.write_key_check(key)?;
+        }
+        if data.is_empty() {
+            return Ok(());
+        }
+        // 逆序后进行稳定排序，同一Key中最后出现的数据排在最前而被去重保留
+        let vec_cmd_data = data.into_iter()
+            .rev()
+            .sorted_by(|(key_a, _), (key_b, _)| key_a.cmp(key_b))
+            .dedup_by(|(key_a, _), (key_b, _)| key_a == key_b)
+            .map(|(key, value)| CommandData::Set { key, value })
+            .collect_vec();
+        self.flush_to_sst().await?;
+
+        let compactor = Compactor::from_lsm_kv(self);
+        let level = compactor.bulk_load(&self.mem_table, vec_cmd_data).await?;
+        // 导入后该Level可能超出阈值
+        compactor.major_compaction(level).await
     }
 
+    /// 将各Key范围[start, end)中的数据导出为dest目录下的SSTable，返回生成的SSTable Gen
+    ///
+    /// 每个范围以`Config::sst_file_size`切分为一个或多个有序的SSTable，不含数据的范围不生成SSTable，
+    /// 导出的数据已解析vLog指针与Merge且不含墓碑，因此dest可直接作为其他LsmStore的dir_path开启
+    /// 各范围互不相交且dest中原本不存在SSTable时，SSTable放置于最深的Level，否则放置于Level 0
+    /// 设置了`Config::cipher`时SSTable同样经过加密，开启dest时需使用相同的密钥
+    /// 各范围以快照锁定所涉及的SSTable后逐批归并，每批数据达到`Config::sst_file_size`时即写出，
+    /// 因此内存中至多同时持有约一个SSTable大小的数据(以及MemTable中处于该范围内的数据)
     #[inline]
-    pub fn node_id(mut self, node_id: i32) -> Self {
-        self.node_id = node_id;
-        self
+    pub async fn export_range_as_ssts(&self, ranges: Vec<(Vec<u8>, Vec<u8>)>, dest: &Path) -> Result<Vec<i64>> {
+        fs::create_dir_all(dest)?;
+        let is_disjoint = ranges.iter()
+            .filter(|(start, end)| start < end)
+            .sorted()
+            .tuple_windows()
+            .all(|((_, end), (start, _))| end <= start);
+        let level = if is_disjoint && sorted_gen_list(dest)?.is_empty() { MAX_LEVEL } else { LEVEL_0 };
+        let io_handler_factory = IOHandlerFactory::new(dest)
+            .buffer_size(self.config.io_buffer_size)
+            .cipher(self.config.cipher.clone());
+        self.wait_for_compression_down().await?;
+
+        let mut vec_gen = Vec::new();
+        for (start, end) in ranges {
+            let (start, end) = (Bound::Included(start.as_slice()), Bound::Excluded(end.as_slice()));
+            let manifest = self.manifest.read().await;
+            let vec_mem_data = self.mem_table.get_range_cmd_data(start, end).await;
+            let snapshot = manifest.range_snapshot(start, end)?;
+            drop(manifest);
+
+            let result = self.export_snapshot_in_batches(&vec_mem_data, &snapshot, start, end, level, &io_handler_factory).await;
+            drop(snapshot);
+            self.manifest.read().await
+                .clean_released()?;
+            vec_gen.append(&mut result?);
+        }
+        info!(level, gens = ?vec_gen, dest = ?dest, "[LsmStore][Export Range][finished]");
+
+        Ok(vec_gen)
     }
 
-    #[inline]
-    pub fn level_sst_magnification(mut self, level_sst_magnification: usize) -> Self {
-        self.level_sst_magnification = level_sst_magnification;
-        self
+    /// 对范围内的MemTable数据与SSTable快照逐批归并并写出为SSTable
+    ///
+    /// 每批归并至数据大小达到`Config::sst_file_size`时停止，写出后从该批最后一个Key之后继续归并
+    async fn export_snapshot_in_batches(&self, vec_mem_data: &[Vec<CommandData>], snapshot: &SsTableSnapshot, start: Bound<&[u8]>, end: Bound<&[u8]>, level: usize, io_handler_factory: &IOHandlerFactory) -> Result<Vec<i64>> {
+        let mut vec_gen = Vec::new();
+        let mut option_last_key: Option<Vec<u8>> = None;
+
+        loop {
+            let batch_start = option_last_key.as_deref()
+                .map_or(start, Bound::Excluded);
+            let vec_source = vec_mem_data.iter()
+                .map(|vec_cmd_data| RangeSource::from_vec_cmd_data(vec_cmd_data.iter()
+                    .filter(|cmd_data| is_after_start(cmd_data.get_key(), batch_start))
+                    .cloned()
+                    .collect_vec()))
+                .chain(snapshot.range_sources(batch_start, end))
+                .collect_vec();
+            let mut vec_cmd_data = Vec::new();
+            let mut batch_size = 0;
+            merge_range_sources_with(vec_source, &self.value_log, self.config.merge_operator, batch_start, end, |key, value| {
+                batch_size += key.len() + value.len();
+                vec_cmd_data.push(CommandData::Set { key, value });
+                Ok(batch_size < self.config.sst_file_size)
+            }).await?;
+            let is_last_batch = batch_size < self.config.sst_file_size;
+            option_last_key = vec_cmd_data.last()
+                .map(CommandData::get_key_clone);
+
+            for (gen, sharding) in data_sharding(vec_cmd_data, self.config.sst_file_size, &self.config, true).await {
+                let io_handler = io_handler_factory.create(gen)?;
+                let _ignore = SsTable::create_for_immutable_table(&self.config, io_handler, sharding, level, gen as u64).await?;
+                vec_gen.push(gen);
+            }
+            if is_last_batch {
+                break
+            }
+        }
+
+        Ok(vec_gen)
     }
 
+    /// 估算[start, end]范围内的数据在SSTable中占用的大小
+    ///
+    /// 以稀疏索引中的数据段为粒度进行估算而不会读取实际数据，因此范围越大估算值越大
+    /// 边界处仅部分处于范围内的数据段同样整段计入，因此估算值偏大：
+    /// 每个相交的SSTable至多多出两端的两个数据段，约为2 * `Config::block_size`，范围越小偏差的占比越高
+    /// MemTable中尚未持久化的数据不计入其中
     #[inline]
-    pub fn desired_error_prob(mut self, desired_error_prob: f64) -> Self {
-        self.desired_error_prob = desired_error_prob;
-        self
+    pub async fn estimate_range_size(&self, start: &[u8], end: &[u8]) -> Result<u64> {
+        Ok(self.manifest.read().await
+            .estimate_range_size(start, end))
     }
 
+    /// 创建检查点
+    ///
+    /// 先将MemTable中的数据持久化为SSTable并等待压缩结束，
+    /// 随后持有Manifest的读锁对level_slice进行快照，因此检查点中的各SSTable是同一时刻的状态
     #[inline]
-    pub fn cache_size(mut self, cache_size: usize) -> Self {
-        self.cache_size = cache_size;
-        self
+    pub async fn checkpoint(&self) -> Result<Checkpoint> {
+        self.flush().await?;
+
+        Ok(self.manifest.read().await
+            .checkpoint())
     }
 
+    /// 比对当前数据是否与检查点一致
+    ///
+    /// SSTable的组成发生变化，或任一SSTable的文件内容与其crc_code不符时返回false
     #[inline]
-    pub fn create_gen(&self) -> i64 {
-        SnowflakeIdBucket::new(self.node_id, self.buffer_i32
-            .fetch_add(1, Ordering::SeqCst)).get_id()
+    pub async fn verify_against(&self, checkpoint: &Checkpoint) -> Result<bool> {
+        self.wait_for_compression_down().await?;
+
+        let manifest = self.manifest.read().await;
+        if manifest.checkpoint() != *checkpoint {
+            return Ok(false);
+        }
+        for (gen, _) in checkpoint.vec_level.iter().flatten() {
+            match manifest.get_ss_table(gen) {
+                Some(ss_table) if ss_table.is_crc_match().await? => {}
+                _ => return Ok(false)
+            }
+        }
+
+        Ok(true)
     }
 
+    /// 增量备份
+    ///
+    /// 不会将MemTable落盘，而是暂停文件删除后持有Manifest的读锁，仅将since中不存在的SSTable拷贝至dest，
+    /// 并将Wal、vLog与ts记录目录中的文件全量拷贝至dest中的同名目录，返回当前的检查点作为下次增量备份的since
+    /// 持有读锁期间MemTable无法落盘，因此MemTable中尚未持久化的数据总是处于拷贝的Wal之中，
+    /// 而备份期间的写入则不一定包含于备份之中
     #[inline]
-    pub fn wal_enable(mut self, wal_enable: bool) -> Self {
-        self.wal_enable = wal_enable;
-        self
+    pub async fn incremental_backup(&self, since: Checkpoint, dest: &Path) -> Result<Checkpoint> {
+        self.flush_buffer().await?;
+        self.pause_file_deletion().await?;
+        let result = self.copy_live_files(&since, dest).await;
+        self.resume_file_deletion().await?;
+
+        result
     }
 
-    #[inline]
-    pub fn wal_async_put_enable(mut self, wal_async_put_enable: bool) -> Self {
-        self.wal_async_put_enable = wal_async_put_enable;
-        self
+    /// 拷贝since中不存在的SSTable，以及Wal、vLog与ts记录的所有文件
+    async fn copy_live_files(&self, since: &Checkpoint, dest: &Path) -> Result<Checkpoint> {
+        let manifest = self.manifest.read().await;
+        let checkpoint = manifest.checkpoint();
+        let since_gens = since.gens_with_crc()
+            .into_iter()
+            .map(|(gen, _)| gen)
+            .collect::<HashSet<_>>();
+
+        let extra_dir_paths = self.config.extra_dir_paths();
+
+        fs::create_dir_all(dest)?;
+        for (gen, _) in checkpoint.gens_with_crc() {
+            if !since_gens.contains(&gen) {
+                let source_path = locate_log_path(&self.config.dir_path, &extra_dir_paths, gen);
+                let _ignore = fs::copy(source_path, log_path(dest, gen))?;
+            }
+        }
+        for (dir_name, vec_file) in [
+            (DEFAULT_WAL_PATH, self.wal.live_files().await?),
+            (DEFAULT_VALUE_LOG_PATH, self.value_log.live_files().await?),
+            (DEFAULT_TS_PATH, self.ts_store.live_files().await?)
+        ] {
+            let dest_dir_path = dest.join(dir_name);
+
+            fs::create_dir_all(&dest_dir_path)?;
+            for file in vec_file {
+                if let Some(file_name) = file.path().file_name() {
+                    let _ignore = fs::copy(file.path(), dest_dir_path.join(file_name))?;
+                }
+            }
+        }
+
+        Ok(checkpoint)
     }
-}
 
-impl Default for Config {
+    /// 通过增量备份链恢复数据目录
+    ///
+    /// 按检查点中的SSTable，由新往旧在各备份目录中查找对应的文件并拷贝至dir_path，
+    /// 因此backup_paths需以备份的先后顺序排列，checkpoint则为最后一次备份所返回的检查点
+    /// Wal、vLog与ts记录的文件则由最后一次备份中拷贝，开启时通过Wal恢复备份时尚未落盘的数据
+    /// 检查点中的SSTable在各备份目录中均不存在时返回`KvsError::FileNotFound`
     #[inline]
-    fn default() -> Self {
-        Self {
-            dir_path: DEFAULT_WAL_PATH.into(),
-            minor_threshold_with_data_size: DEFAULT_MINOR_THRESHOLD_WITH_DATA_OCCUPIED,
-            wal_compaction_threshold: DEFAULT_WAL_COMPACTION_THRESHOLD,
-            sparse_index_interval_block_size: DEFAULT_SPARSE_INDEX_INTERVAL_BLOCK_SIZE,
-            sst_file_size: DEFAULT_SST_FILE_SIZE,
-            major_threshold_with_sst_size: DEFAULT_MAJOR_THRESHOLD_WITH_SST_SIZE,
-            major_select_file_size: DEFAULT_MAJOR_SELECT_FILE_SIZE,
-            node_id: DEFAULT_MACHINE_ID,
-            level_sst_magnification: DEFAULT_LEVEL_SST_MAGNIFICATION,
-            buffer_i32: AtomicI32::new(0),
-            desired_error_prob: DEFAULT_DESIRED_ERROR_PROB,
-            cache_size: DEFAULT_CACHE_SIZE,
-            wal_enable: true,
-            wal_async_put_enable: true,
+    pub fn restore_from_backups(checkpoint: &Checkpoint, backup_paths: &[PathBuf], dir_path: &Path) -> Result<()> {
+        fs::create_dir_all(dir_path)?;
+        for (gen, _) in checkpoint.gens_with_crc() {
+            let backup_path = backup_paths.iter()
+                .rev()
+                .map(|backup_path| log_path(backup_path, gen))
+                .find(|backup_path| backup_path.exists())
+                .ok_or(KvsError::FileNotFound)?;
+
+            let _ignore = fs::copy(backup_path, log_path(dir_path, gen))?;
         }
-    }
-}
+        if let Some(last_backup_path) = backup_paths.last() {
+            for dir_name in [DEFAULT_WAL_PATH, DEFAULT_VALUE_LOG_PATH, DEFAULT_TS_PATH] {
+                let backup_dir_path = last_backup_path.join(dir_name);
+                if !backup_dir_path.exists() {
+                    continue
+                }
+                let target_dir_path = dir_path.join(dir_name);
 
-/// 以Task类似的异步写数据，避免影响数据写入性能
-/// 当然，LevelDB的话虽然wal写入会提供是否同步的选项，此处先简化优先使用异步
-pub(crate) async fn wal_put(wal: &Arc<HashStore>, key: Vec<u8>, value: Vec<u8>, is_sync: bool) {
-    let wal = Arc::clone(wal);
+                fs::create_dir_all(&target_dir_path)?;
+                for entry in fs::read_dir(backup_dir_path)? {
+                    let entry = entry?;
+                    let _ignore = fs::copy(entry.path(), target_dir_path.join(entry.file_name()))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 获取指定Level中的所有数据，以Key由小到大排列且去重
+    ///
+    /// 仅包含该Level中SSTable的数据，不涉及MemTable与其他Level，因此已被删除的Key不会出现在结果中，
+    /// 但可能返回在其他Level中已被覆盖的旧数据，主要用于调试压缩
+    /// Level超出层数时返回`KvsError::LevelOver`
+    #[inline]
+    pub async fn scan_level(&self, level: usize) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        if level > MAX_LEVEL {
+            return Err(KvsError::LevelOver);
+        }
+        let manifest = self.manifest.read().await;
+        let mut vec_kv = Vec::new();
+
+        for cmd_data in manifest.get_level_data(level).await? {
+            match cmd_data {
+                CommandData::Set { key, value } => vec_kv.push((key, value)),
+                CommandData::SetPtr { key, ptr } => vec_kv.push((key, self.value_log.read(&ptr).await?)),
+                _ => {}
+            }
+        }
+
+        Ok(vec_kv)
+    }
+
+    /// Manifest一致性自检
+    ///
+    /// 校验level_slice与SSTable集合是否一致，以及Level 1-7内的SSTable是否有序且不重叠，
+    /// 不一致时返回`KvsError::ManifestInconsistent`
+    #[inline]
+    pub async fn check_consistency(&self) -> Result<()> {
+        self.manifest.read().await
+            .check_consistency()
+    }
+
+    /// 获取布隆过滤器所占用的内存大小(单位: 字节)
+    ///
+    /// 包括常驻的布隆过滤器，以及非常驻时缓存中的布隆过滤器
+    #[inline]
+    pub async fn bloom_memory_usage(&self) -> Result<u64> {
+        self.manifest.read().await
+            .bloom_memory_usage().await
+    }
+
+    /// 获取统计信息
+    ///
+    /// 聚合所有SSTable生成时记录的Key与value大小的直方图，MemTable中尚未持久化的数据不计入其中，
+    /// 并附带自开启以来的Major压缩统计
+    #[inline]
+    pub async fn stats(&self) -> Stats {
+        let mut stats = self.manifest.read().await
+            .stats();
+        stats.scheduler_decision = self.scheduler.as_ref()
+            .map(|scheduler| scheduler.decision());
+        stats.amplification.get_read_bytes = self.get_read_bytes.load(Ordering::Relaxed);
+        stats.amplification.get_value_bytes = self.get_value_bytes.load(Ordering::Relaxed);
+        stats.amplification.user_written_bytes = self.mem_table.written_bytes();
+        stats
+    }
+
+    /// 批量获取多个Key对应的值，返回值与keys一一对应
+    ///
+    /// 需要从SSTable中读取的Key会被排序去重后批量查询，同一SSTable中首尾相接的数据段合并为一次读取，
+    /// 因此获取大量相邻的Key时相比逐个get能够减少读盘与seek的次数
+    #[inline]
+    pub async fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>> {
+        let mut vec_option_value = Vec::with_capacity(keys.len());
+        let mut vec_pending = Vec::new();
+        for (index, key) in keys.iter().enumerate() {
+            key_check(key)?;
+            vec_option_value.push(match self.mem_table.get_cmd_data(key).await {
+                Some(cmd_data @ CommandData::Merge { .. }) => {
+                    vec_pending.push((index, *key, Some(cmd_data)));
+                    None
+                }
+                Some(cmd_data) => cmd_data.get_value_owner(),
+                None => {
+                    vec_pending.push((index, *key, None));
+                    None
+                }
+            });
+        }
+        if vec_pending.is_empty() {
+            return Ok(vec_option_value);
+        }
+        self.wait_for_compression_down().await?;
+
+        let mut vec_missing = Vec::new();
+        {
+            let manifest = self.manifest.read().await;
+            let merge_operator = self.config.merge_operator;
+            let vec_key = vec_pending.iter()
+                .map(|(_, key, _)| *key)
+                .sorted_unstable()
+                .dedup()
+                .collect_vec();
+            let vec_cmd_data = manifest.get_data_for_ss_tables_batch(&vec_key).await?;
+
+            for (index, key, option_merge) in vec_pending {
+                let option_older = vec_key.binary_search(&key).ok()
+                    .and_then(|i| vec_cmd_data[i].clone());
+                let option_cmd_data = match (option_merge, option_older) {
+                    (Some(newer), Some(older)) => Some(merge_cmd_data(newer, older, merge_operator)?),
+                    (Some(newer), None) => Some(resolve_merge(newer, merge_operator)?),
+                    (None, option_cmd_data) => option_cmd_data
+                };
+                match option_cmd_data {
+                    Some(cmd_data) => vec_option_value[index] = self.value_log.unpack(cmd_data).await?,
+                    None => vec_missing.push((index, key))
+                }
+            }
+        }
+        // 尝试从Wal获取数据
+        for (index, key) in vec_missing {
+            if let Some(vec_cmd_u8) = self.wal.get(key).await? {
+                let wal_cmd = self.decode_wal_cmd(&vec_cmd_u8)?;
+                warn!("[Command][reload_from_wal]{:?}", wal_cmd);
+                vec_option_value[index] = wal_cmd.get_value_clone();
+                self.append_cmd_data(wal_cmd, false).await?;
+            }
+        }
+
+        Ok(vec_option_value)
+    }
+
+    /// 获取以prefix为前缀的至多limit个键值对，以Key由小到大排列
+    ///
+    /// 设置了`Config::prefix_bloom_len`时，先以各SSTable的前缀布隆过滤器跳过必然不含该前缀的SSTable
+    #[inline]
+    pub async fn prefix_scan(&self, prefix: &[u8], limit: usize) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        key_check(prefix)?;
+        self.wait_for_compression_down().await?;
+
+        let manifest = self.manifest.read().await;
+        let end = match prefix_end(prefix) {
+            Some(end) => end,
+            // 前缀全为0xFF时不小于prefix的Key均以其为前缀，以现有数据中最大的Key作为上界
+            None => {
+                let option_max_key = self.max_key_with_manifest(&manifest)
+                    .filter(|max_key| max_key.as_slice() >= prefix);
+                match option_max_key {
+                    Some(mut max_key) => {
+                        max_key.push(0);
+                        max_key
+                    }
+                    None => return Ok(Vec::new())
+                }
+            }
+        };
+        let (start_bound, end_bound) = (Bound::Included(prefix), Bound::Excluded(end.as_slice()));
+        let vec_source = self.mem_table.get_range_cmd_data(start_bound, end_bound).await
+            .into_iter()
+            .map(RangeSource::from_vec_cmd_data)
+            .chain(manifest.get_prefix_range_sources(prefix, &end)?)
+            .collect_vec();
+
+        merge_range_sources(vec_source, &self.value_log, self.config.merge_operator, start_bound, end_bound, limit).await
+    }
+
+    /// 预热指定的Key，将其在SSTable中所处的数据段预先载入position_cache
+    ///
+    /// 与get的查找路径一致，因此预热后对这些Key的get无需读盘(vLog中的value除外)
+    /// 已位于MemTable中的Key无需预热
+    #[inline]
+    pub async fn warm_up(&self, keys: &[&[u8]]) -> Result<()> {
+        self.wait_for_compression_down().await?;
+
+        let manifest = self.manifest.read().await;
+        for key in keys {
+            key_check(key)?;
+            if self.mem_table.get_cmd_data(key).await.is_some() {
+                continue;
+            }
+            let _ignore = manifest.get_data_for_ss_tables(key).await?;
+        }
+
+        Ok(())
+    }
+
+    /// get的读取流程，并将从SSTable、vLog与Wal中实际读取的字节数累计至get_read_bytes
+    async fn get_and_record_read_bytes(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        key_check(key)?;
+        // 负缓存中的Key最近已确认不存在，且之后未被写入
+        if self.mem_table.is_negative(key) {
+            return Ok(None);
+        }
+        // 读修复与负缓存的记录以此判断读取后是否存在其他写入
+        let version = self.mem_table.version();
+        // MemTable中的墓碑数据表示该Key已被删除
+        // Merge则需与SSTables中更旧的数据折叠
+        let option_merge = match self.mem_table.get_cmd_data(key).await {
+            Some(cmd_data @ CommandData::Merge { .. }) => Some(cmd_data),
+            Some(cmd_data) => return Ok(cmd_data.get_value_owner()),
+            None => None
+        };
+        // 读取前等待压缩完毕
+        // 相对来说，消耗较小
+        // 当压缩时长高时，说明数据量非常大
+        // 此时直接去获取的话可能会既获取不到数据，也花费大量时间
+        self.wait_for_compression_down().await?;
+
+        if let Some(value) = self.get_value_for_ss_tables(key, option_merge, Some(&self.get_read_bytes), Some(version)).await? {
+            return Ok(Some(value));
+        }
+        // 尝试从Wal获取数据
+        if let Some(vec_cmd_u8) = self.wal.get(key).await? {
+            let _ignore = self.get_read_bytes.fetch_add(vec_cmd_u8.len() as u64, Ordering::Relaxed);
+            let wal_cmd = self.decode_wal_cmd(&vec_cmd_u8)?;
+            warn!("[Command][reload_from_wal]{:?}", wal_cmd);
+            let option_value = wal_cmd.get_value_clone();
+            self.append_cmd_data(wal_cmd, false).await?;
+            return Ok(option_value);
+        }
+        self.mem_table.record_negative(key, version);
+
+        Ok(None)
+    }
+
+    /// 使用Key从SSTables中获取对应的value
+    ///
+    /// option_merge为MemTable中该Key的Merge时，与SSTables中的数据折叠后返回
+    /// 数据为SetPtr时通过vLog读取value，期间持有Manifest读锁，避免对应的vLog文件被GC回收
+    /// read_bytes不为None时，将从SSTable与vLog中实际读取的字节数累计至其中
+    ///
+    /// repair_version为读取MemTable前获取的`MemTable::version`，`Config::read_repair`为`ReadRepair::Repair`
+    /// 且跳过了读取失败的SSTable时，将读到的value重新写入，MemTable中存在该Key的Merge时则不进行修复以免覆盖其操作数
+    /// 修复持有该Key的写入锁并确认此后MemTable的版本未变化，避免以旧数据覆盖期间的写入
+    /// 修复需获取该Key的写入锁，因此已持有写入锁时repair_version需为None
+    async fn get_value_for_ss_tables(&self, key: &[u8], option_merge: Option<CommandData>, read_bytes: Option<&AtomicU64>, repair_version: Option<u64>) -> Result<Option<Vec<u8>>> {
+        let merge_operator = self.config.merge_operator;
+        let is_repair_enable = self.config.read_repair == ReadRepair::Repair && option_merge.is_none();
+
+        let (option_value, vec_skipped_gen) = {
+            let manifest = self.manifest.read().await;
+            let (option_cmd_data, vec_skipped_gen) = manifest.get_data_for_ss_tables_with_repair(key, read_bytes).await?;
+
+            let option_cmd_data = match (option_merge, option_cmd_data) {
+                (Some(newer), Some(older)) => Some(merge_cmd_data(newer, older, merge_operator)?),
+                (Some(newer), None) => Some(resolve_merge(newer, merge_operator)?),
+                (None, option_cmd_data) => option_cmd_data
+            };
+            if let (Some(CommandData::SetPtr { ptr, .. }), Some(read_bytes)) = (option_cmd_data.as_ref(), read_bytes) {
+                let _ignore = read_bytes.fetch_add(ptr.get_len() as u64, Ordering::Relaxed);
+            }
+            let option_value = match option_cmd_data {
+                Some(cmd_data) => self.value_log.unpack(cmd_data).await?,
+                None => None
+            };
+            (option_value, vec_skipped_gen)
+        };
+        // 修复需在释放Manifest读锁后进行，写入可能触发落盘
+        if let (true, Some(version), Some(value)) = (is_repair_enable && !vec_skipped_gen.is_empty(), repair_version, option_value.as_ref()) {
+            let guard = self.key_locks.lock(key).await;
+            if self.mem_table.version() == version {
+                warn!("[LsmStore][Read Repair][Key: {:?}][Skipped SSTables: {:?}]", key, vec_skipped_gen);
+                let cmd_data = CommandData::set(key.to_vec(), value.clone());
+
+                self.wal_write(&cmd_data).await?;
+                self.mem_table.insert_data(key.to_vec(), cmd_data).await;
+            }
+            drop(guard);
+            self.minor_compaction_if_exceeded().await?;
+        }
+
+        Ok(option_value)
+    }
+
+    /// 通过`Config::merge_operator`将operand与Key已有的value合并
+    ///
+    /// MemTable中存在该Key的数据时直接与之折叠，否则以Merge写入，待读取或压缩时再与更旧的数据折叠，
+    /// 因此写入时无需读取SSTables
+    /// Wal中则写入与所有尚未落盘的数据(包括ImmutableMemTable中的Merge)折叠的结果，
+    /// 使其在ImmutableMemTable落盘前崩溃时仍保有全部的操作数，开启时由`LsmStore::reload_merge_for_wal`恢复
+    /// 未设置merge_operator时返回`KvsError::MergeOperatorNotSet`
+    #[inline]
+    pub async fn merge(&self, key: &[u8], operand: Vec<u8>) -> Result<()> {
+        self.write_key_check(key)?;
+        let merge_operator = self.config.merge_operator;
+        if merge_operator.is_none() {
+            return Err(KvsError::MergeOperatorNotSet);
+        }
+        // 持有该Key的写入锁，使读取MemTable中的数据与写入折叠结果之间不会插入该Key的其他写入
+        let guard = self.key_locks.lock(key).await;
+
+        let operand_cmd = CommandData::Merge { key: key.to_vec(), operands: vec![operand] };
+        let wal_cmd = match self.mem_table.get_unflushed_cmd_data(key, merge_operator)? {
+            Some(older) => merge_cmd_data(operand_cmd.clone(), older, merge_operator)?,
+            None => operand_cmd.clone()
+        };
+        let cmd = match self.mem_table.get_cmd_data(key).await {
+            Some(older) => merge_cmd_data(operand_cmd, older, merge_operator)?,
+            None => operand_cmd
+        };
+        self.wal_write(&wal_cmd).await?;
+        self.mem_table.insert_data(key.to_vec(), cmd).await;
+        drop(guard);
+
+        self.minor_compaction_if_exceeded().await?;
+
+        Ok(())
+    }
+
+    /// 同步进行vLog的垃圾回收
+    #[inline]
+    pub async fn value_log_gc_sync(&self) -> Result<()> {
+        Compactor::from_lsm_kv(self).value_log_gc(&self.mem_table).await
+    }
+
+    /// 通过CommandData的引用解包并克隆出value值
+    #[allow(dead_code)]
+    fn value_unpack(cmd_data: &CommandData) -> Option<Vec<u8>> {
+        cmd_data.get_value_clone()
+    }
+
+    /// 通过CommandData的所有权直接返回value值的所有权
+    #[allow(dead_code)]
+    fn value_unpack_with_owner(cmd_data: CommandData) -> Option<Vec<u8>> {
+        cmd_data.get_value_owner()
+    }
+    pub(crate) fn manifest(&self) -> &Arc<RwLock<Manifest>> {
+        &self.manifest
+    }
+    pub(crate) fn config(&self) -> &Arc<Config> {
+        &self.config
+    }
+    pub(crate) fn io_handler_factory(&self) -> &Arc<IOHandlerFactory> {
+        &self.io_handler_factory
+    }
+    pub(crate) fn wal(&self) -> &Arc<HashStore> {
+        &self.wal
+    }
+    pub(crate) fn wal_in_flight(&self) -> &Arc<RwLock<()>> {
+        &self.wal_in_flight
+    }
+    pub(crate) fn value_log(&self) -> &Arc<ValueLog> {
+        &self.value_log
+    }
+
+    /// 存活标记
+    /// 返回一个Sender用于存活结束通知
+    pub(crate) async fn live_tag(&self) -> Sender<()> {
+        let (sender, receiver) = oneshot::channel();
+
+        self.vec_rev.lock()
+            .await
+            .push(receiver);
+
+        sender
+    }
+
+    /// 在已持有该Key的写入锁时获取Key对应的值
+    ///
+    /// 不进行读修复与Wal的重新加载，避免再次获取该Key的写入锁
+    async fn get_with_key_guard(&self, _guard: &KeyGuard<'_>, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(match self.mem_table.get_cmd_data(key).await {
+            Some(cmd_data @ CommandData::Merge { .. }) => {
+                self.wait_for_compression_down().await?;
+                self.get_value_for_ss_tables(key, Some(cmd_data), None, None).await?
+            }
+            Some(cmd_data) => cmd_data.get_value_owner(),
+            None => {
+                self.wait_for_compression_down().await?;
+                match self.get_value_for_ss_tables(key, None, None, None).await? {
+                    Some(value) => Some(value),
+                    None => self.wal.get(key).await?
+                        .map(|vec_cmd_u8| self.decode_wal_cmd(&vec_cmd_u8))
+                        .transpose()?
+                        .and_then(CommandData::get_value_owner)
+                }
+            }
+        })
+    }
+
+    /// 解析Wal中的数据
+    ///
+    /// 读取Wal时SSTables中不存在该Key的数据，因此其中尚未落盘的Merge以None折叠为Set
+    fn decode_wal_cmd(&self, vec_cmd_u8: &[u8]) -> Result<CommandData> {
+        resolve_merge(CommandPackage::decode(vec_cmd_u8)?, self.config.merge_operator)
+    }
+
+    /// 写入前的Key校验
+    /// 在空Key校验之后调用`Config::validate_key`所注入的校验器
+    fn write_key_check(&self, key: &[u8]) -> Result<()> {
+        key_check(key)?;
+        match self.config.validate_key {
+            Some(validate_key) => validate_key(key),
+            None => Ok(())
+        }
+    }
+
+    /// 由旧往新合并SSTable与MemTable中的Key，使每个Key仅保留最新的墓碑标记
+    async fn merge_keys(&self, option_scope: Option<&Scope>) -> Result<BTreeMap<Vec<u8>, bool>> {
+        let mut merge_map = BTreeMap::new();
+        let vec_ss_table_key = self.manifest.read().await
+            .get_keys_for_ss_tables(option_scope).await?;
+        let vec_mem_table_key = self.mem_table.get_all_cmd_data().await
+            .iter()
+            .map(key_with_tombstone)
+            .collect_vec();
+
+        for (key, is_tombstone) in vec_ss_table_key.into_iter().chain(vec_mem_table_key) {
+            let _ignore = merge_map.insert(key, is_tombstone);
+        }
+
+        Ok(merge_map)
+    }
+
+    /// 获取MemTable与Manifest中各SSTable里最大的Key
+    fn max_key_with_manifest(&self, manifest: &Manifest) -> Option<Vec<u8>> {
+        self.mem_table.max_key()
+            .into_iter()
+            .chain(manifest.max_key().map(<[u8]>::to_vec))
+            .max()
+    }
+
+    /// 等待所有压缩结束
+    async fn wait_for_compression_down(&self) -> Result<()> {
+        // 监听异步任务是否执行完毕
+        let mut vec_rev = self.vec_rev.lock().await;
+        while let Some(rev) = vec_rev.pop() {
+            rev.await?
+        }
+
+        Ok(())
+    }
+}
+
+/// LsmStore的只读快照
+/// 由`LsmStore::open_snapshot`开启，Drop时会清除对应的快照目录
+#[derive(Debug)]
+pub struct LsmSnapshot {
+    inner: LsmStore,
+    snapshot_path: PathBuf
+}
+
+impl LsmSnapshot {
+    #[inline]
+    pub async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.inner.get(key).await
+    }
+
+    #[inline]
+    pub async fn for_each<F>(&self, f: F) -> Result<()>
+        where F: FnMut(&[u8], &[u8]) -> Result<()> + Send
+    {
+        self.inner.for_each(f).await
+    }
+
+    #[inline]
+    pub async fn scan(&self, start: &[u8], end: &[u8], limit: usize) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.inner.scan(start, end, limit).await
+    }
+
+    #[inline]
+    pub async fn len(&self) -> Result<usize> {
+        self.inner.len().await
+    }
+
+    #[inline]
+    pub async fn is_empty(&self) -> bool {
+        self.inner.is_empty().await
+    }
+}
+
+impl Drop for LsmSnapshot {
+    #[inline]
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_dir_all(&self.snapshot_path) {
+            error!("[LsmSnapshot][drop][error happen]: {:?}", err);
+        }
+    }
+}
+
+/// 只读跟随者
+///
+/// 由`LsmStore::open_follower`开启，通过`refresh`重新扫描目录以跟随主进程的写入
+/// 注意：仅能读取主进程已持久化为SSTable的数据，MemTable与Wal中的数据不可见；
+/// 主进程压缩期间新旧SSTable可能同时可见，但两者中同一Key的数据一致
+#[derive(Debug)]
+pub struct LsmFollower {
+    manifest: RwLock<Manifest>,
+    config: Config,
+    io_handler_factory: IOHandlerFactory,
+    value_log: ValueLog
+}
+
+impl LsmFollower {
+    /// 重新扫描dir_path与各Level目录刷新视图
+    ///
+    /// 已加载的SSTable直接复用，已被删除的SSTable被移除，
+    /// 无法完整加载的SSTable(如主进程仍在写入中)会被跳过，待之后的refresh再次尝试
+    /// 待删除列表中的SSTable已被压缩取代，仅因暂停文件删除或快照而保留，同样被移除
+    #[inline]
+    pub async fn refresh(&self) -> Result<()> {
+        let vec_pending_gen = read_pending_delete(&self.config.dir_path)?;
+        let vec_gen = self.config.sorted_gen_list_with_levels()?
+            .into_iter()
+            .filter(|gen| !vec_pending_gen.contains(gen))
+            .collect_vec();
+        let mut manifest = self.manifest.write().await;
+        let mut ss_tables = manifest.take_ss_tables();
+
+        ss_tables.retain(|gen, _| vec_gen.contains(gen));
+        for gen in vec_gen {
+            if !ss_tables.contains_key(&gen) {
+                if let Some(ss_table) = self.load_ss_table(gen).await {
+                    let _ignore = ss_tables.insert(gen, Arc::new(ss_table));
+                }
+            }
+        }
+        *manifest = Manifest::new(ss_tables, Arc::new(self.config.dir_path.clone()), &self.config)?;
+        drop(manifest);
+
+        self.value_log.refresh().await
+    }
+
+    /// 加载并校验SSTable，文件已被删除或尚未写入完成时返回None
+    async fn load_ss_table(&self, gen: i64) -> Option<SsTable> {
+        let io_handler = self.io_handler_factory.create(gen).ok()?;
+
+        match SsTable::restore_from_file_with_verify(io_handler, self.config.bloom_resident, true).await {
+            Ok(ss_table) => Some(ss_table),
+            Err(err) => {
+                warn!("[LsmFollower][Skip SSTable: {gen}]: {err:?}");
+                None
+            }
+        }
+    }
+
+    #[inline]
+    pub async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        key_check(key)?;
+
+        match self.manifest.read().await.get_data_for_ss_tables(key).await? {
+            Some(cmd_data) => self.value_log.unpack(cmd_data).await,
+            None => Ok(None)
+        }
+    }
+
+    #[inline]
+    pub async fn scan(&self, start: &[u8], end: &[u8], limit: usize) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        key_check(start)?;
+        let manifest = self.manifest.read().await;
+
+        let (start, end) = (Bound::Included(start), Bound::Excluded(end));
+
+        merge_range_sources(manifest.get_range_sources(start, end)?, &self.value_log, self.config.merge_operator, start, end, limit).await
+    }
+
+    /// 获取当前视图中SSTable的数量
+    #[inline]
+    pub async fn ss_table_count(&self) -> usize {
+        self.manifest.read().await
+            .ss_tables_len()
+    }
+}
+
+/// 检查点
+/// 由`LsmStore::checkpoint`创建，以Level由低到高记录当时所有SSTable的Gen与crc_code
+/// 默认值为不包含任何SSTable的空检查点，可作为首次增量备份的since
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub(crate) vec_level: Vec<Vec<(i64, u64)>>
+}
+
+impl Checkpoint {
+    /// 获取检查点中所有SSTable的Gen与crc_code
+    #[inline]
+    pub fn gens_with_crc(&self) -> Vec<(i64, u64)> {
+        self.vec_level.iter()
+            .flatten()
+            .copied()
+            .collect_vec()
+    }
+}
+
+/// 以2的幂次划分区间的大小直方图
+/// 索引为i(i > 0)的桶统计大小处于[2^(i-1), 2^i)的数量，索引0的桶则统计大小为0的数量
+///
+/// 仅保留至最后一个非空的桶，以减小其在SSTable中的序列化长度
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Histogram {
+    pub(crate) buckets: Vec<u64>
+}
+
+impl Histogram {
+    /// 记录一个大小
+    pub(crate) fn record(&mut self, size: usize) {
+        let index = Self::bucket_index(size);
+        if self.buckets.len() <= index {
+            self.buckets.resize(index + 1, 0);
+        }
+        self.buckets[index] += 1;
+    }
+
+    /// 合并另一个直方图
+    pub(crate) fn merge(&mut self, other: &Histogram) {
+        if self.buckets.len() < other.buckets.len() {
+            self.buckets.resize(other.buckets.len(), 0);
+        }
+        for (count, other_count) in self.buckets.iter_mut().zip(other.buckets.iter()) {
+            *count += other_count;
+        }
+    }
+
+    /// 获取各桶的数量
+    #[inline]
+    pub fn buckets(&self) -> &[u64] {
+        &self.buckets
+    }
+
+    /// 获取size所处桶的数量
+    #[inline]
+    pub fn count_with_size(&self, size: usize) -> u64 {
+        self.buckets.get(Self::bucket_index(size)).copied().unwrap_or(0)
+    }
+
+    /// 获取记录的总数量
+    #[inline]
+    pub fn total(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    /// size所处桶的索引，即size的二进制位数
+    fn bucket_index(size: usize) -> usize {
+        (usize::BITS - size.leading_zeros()) as usize
+    }
+}
+
+/// 统计信息
+/// 由`LsmStore::stats`创建，聚合当前所有SSTable中的统计数据
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stats {
+    pub(crate) key_size_histogram: Histogram,
+    pub(crate) value_size_histogram: Histogram,
+    pub(crate) compaction_stats: CompactionStats,
+    /// 各Level的SSTable大小之和，与下一Level中与之范围重叠的SSTable大小之和
+    pub(crate) vec_level_overlap: Vec<(u64, u64)>,
+    /// 各Level中墓碑数据的数量，与数据的总数量
+    pub(crate) vec_level_tombstone: Vec<(usize, usize)>,
+    pub(crate) scheduler_decision: Option<SchedulerDecision>,
+    pub(crate) amplification: Amplification
+}
+
+/// 自适应压缩调度器的决策
+/// 由调度器在每次采样写入速率后更新
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SchedulerDecision {
+    pub(crate) write_rate: u64,
+    pub(crate) backlog: usize,
+    pub(crate) minor_threshold: u64,
+    pub(crate) concurrency: usize
+}
+
+impl SchedulerDecision {
+    /// 近期的写入速率(单位: 字节/秒)
+    #[inline]
+    pub fn write_rate(&self) -> u64 {
+        self.write_rate
+    }
+
+    /// 待压缩的积压数量，即待落盘的ImmutableMemTable数量与Level 0中等待Major压缩的SSTable数量之和
+    #[inline]
+    pub fn backlog(&self) -> usize {
+        self.backlog
+    }
+
+    /// 当前的MemTable落盘阈值(单位: 字节)
+    #[inline]
+    pub fn minor_threshold(&self) -> u64 {
+        self.minor_threshold
+    }
+
+    /// 当前允许同时进行的落盘数量
+    #[inline]
+    pub fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+}
+
+/// 读写放大的累计统计
+/// 由`LsmStore::stats`获取，各数据量均为自开启以来的累计值(单位: 字节)
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Amplification {
+    pub(crate) get_read_bytes: u64,
+    pub(crate) get_value_bytes: u64,
+    pub(crate) user_written_bytes: u64,
+    pub(crate) compaction_written_bytes: u64
+}
+
+impl Amplification {
+    /// get从SSTable、vLog与Wal中实际读取的字节数
+    ///
+    /// 命中MemTable或position_cache的读取不计入其中
+    #[inline]
+    pub fn get_read_bytes(&self) -> u64 {
+        self.get_read_bytes
+    }
+
+    /// get返回的value字节数
+    #[inline]
+    pub fn get_value_bytes(&self) -> u64 {
+        self.get_value_bytes
+    }
+
+    /// 用户写入MemTable的数据大小，即各次写入的数据以`CommandData::get_data_len_for_rmp`估算的大小之和
+    #[inline]
+    pub fn user_written_bytes(&self) -> u64 {
+        self.user_written_bytes
+    }
+
+    /// MemTable落盘、Major压缩与vLog GC写入SSTable的字节数
+    ///
+    /// Key-Value分离时写入vLog的value不计入其中
+    #[inline]
+    pub fn compaction_written_bytes(&self) -> u64 {
+        self.compaction_written_bytes
+    }
+
+    /// 读放大，即get实际读取的字节数与返回的value字节数之比，尚未返回任何value时为0
+    #[inline]
+    pub fn read_amplification(&self) -> f64 {
+        amplification_ratio(self.get_read_bytes, self.get_value_bytes)
+    }
+
+    /// 写放大，即每写入1字节用户数据时压缩实际写入SSTable的字节数，尚未写入任何数据时为0
+    #[inline]
+    pub fn write_amplification(&self) -> f64 {
+        amplification_ratio(self.compaction_written_bytes, self.user_written_bytes)
+    }
+}
+
+/// 以字节数计算放大倍数，base_bytes为0时为0
+#[allow(clippy::float_arithmetic)]
+fn amplification_ratio(bytes: u64, base_bytes: u64) -> f64 {
+    if base_bytes == 0 {
+        return 0.0;
+    }
+    bytes as f64 / base_bytes as f64
+}
+
+impl Stats {
+    /// 获取Key大小的直方图
+    #[inline]
+    pub fn key_size_histogram(&self) -> &Histogram {
+        &self.key_size_histogram
+    }
+
+    /// 获取value大小的直方图
+    ///
+    /// 墓碑数据不计入其中，Key-Value分离的数据以其在vLog中的数据长度计入
+    #[inline]
+    pub fn value_size_histogram(&self) -> &Histogram {
+        &self.value_size_histogram
+    }
+
+    /// 获取压缩的统计，其中压缩次数与回收量仅统计Major压缩，回收量为参与压缩的SSTable与压缩生成的SSTable的大小之差，
+    /// Minor压缩次数为MemTable落盘生成SSTable的次数
+    #[inline]
+    pub fn compaction_stats(&self) -> CompactionStats {
+        self.compaction_stats
+    }
+
+    /// 获取该Level与下一Level的重叠比例
+    ///
+    /// 即下一Level中与该Level范围重叠的SSTable大小之和与该Level的SSTable大小之和的比值，
+    /// 比例越高该Level压缩时的写放大越大；该Level为空或不存在时为0
+    #[inline]
+    pub fn overlap_ratio(&self, level: usize) -> f64 {
+        self.vec_level_overlap.get(level)
+            .map_or(0.0, |(level_bytes, overlap_bytes)| overlap_ratio(*level_bytes, *overlap_bytes))
+    }
+
+    /// 获取该Level中墓碑数据的数量，Level不存在时为0
+    ///
+    /// 由各SSTable落盘时记录的墓碑数量聚合得到，不记录墓碑数量的旧SSTable视为不含墓碑
+    #[inline]
+    pub fn tombstone_count(&self, level: usize) -> usize {
+        self.vec_level_tombstone.get(level)
+            .map_or(0, |(tombstone_count, _)| *tombstone_count)
+    }
+
+    /// 获取该Level中墓碑数据占数据总数量的比例，该Level为空或不存在时为0
+    #[inline]
+    pub fn tombstone_ratio(&self, level: usize) -> f64 {
+        self.vec_level_tombstone.get(level)
+            .map_or(0.0, |(tombstone_count, len)| tombstone_ratio(*tombstone_count, *len))
+    }
+
+    /// 获取自适应压缩调度器的当前决策，未开启`Config::adaptive_compaction`时为None
+    #[inline]
+    pub fn scheduler_decision(&self) -> Option<SchedulerDecision> {
+        self.scheduler_decision
+    }
+
+    /// 获取读写放大的累计统计
+    #[inline]
+    pub fn amplification(&self) -> Amplification {
+        self.amplification
+    }
+}
+
+pub(crate) struct CommandCodec;
+
+impl CommandCodec {
+    pub(crate) fn encode_gen(gen: i64) -> Result<Vec<u8>> {
+        Ok(bincode::serialize(&gen)?)
+    }
+
+    #[allow(dead_code)]
+    pub(crate) fn decode_gen(key: &[u8]) -> Result<i64> {
+        Ok(bincode::deserialize(key)?)
+    }
+
+    pub(crate) fn encode_keys(value: &Vec<Vec<u8>>) -> Result<Vec<u8>> {
+        Ok(bincode::serialize(value)?)
+    }
+
+    pub(crate) fn decode_keys(vec_u8: &[u8]) -> Result<Vec<Vec<u8>>> {
+        Ok(bincode::deserialize(vec_u8)?)
+    }
+}
+/// SSTable间数据新旧的判断依据
+///
+/// SSTable的数据版本号为其所含数据中最新的版本：MemTable落盘时取新生成的Gen，
+/// Major压缩时则沿用参与压缩的SSTable中最大的数据版本号，因此不随压缩生成的Gen而变大
+/// 旧的SSTable不存在数据版本号，视为0并以Gen判断
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum VersionOrder {
+    /// 以数据版本号判断，数据版本号相同时以Gen判断
+    Version,
+    /// 仅以Gen判断
+    Gen
+}
+
+/// 查询时单个SSTable读取失败(如crc校验失败、数据无法解析)的处理方式
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ReadRepair {
+    /// 直接返回该SSTable的错误
+    Disabled,
+    /// 跳过该SSTable并继续查询其他候选SSTable，均未读到数据时仍返回该错误
+    /// 注意：被跳过的SSTable中可能存在更新的数据，因此读到的可能为旧版本的数据
+    Fallback,
+    /// 在Fallback的基础上将读到的数据重新写入，随下次落盘生成更新的SSTable，覆盖损坏的SSTable中该Key的数据
+    Repair
+}
+
+/// Major压缩的触发策略
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CompactionStrategy {
+    /// Leveled
+    /// Level中SSTable的数量超过`major_threshold_with_sst_size ^ level * level_sst_magnification`时触发
+    Leveled,
+    /// Size-Tiered
+    /// Level中SSTable的总大小超过size_threshold(单位: 字节)，且其中大小相近的SSTable达到min_tables个时触发
+    /// 相比Leveled合并次数更少、写放大更低，但同一Level中堆积的SSTable更多、读放大更高
+    SizeTiered { size_threshold: u64, min_tables: usize }
+}
+
+/// Major压缩的输入选择器
+///
+/// 从该Level的SSTable Gen(Level 0中由新往旧排列，其余Level中由Key的小到大排列)中选择作为压缩起点的SSTable，
+/// 压缩时会以其Key范围补充两级之间相交的SSTable，因此实际参与压缩的SSTable可能多于所选择的SSTable
+/// 返回空或含有不属于该Level的Gen时放弃本次压缩
+pub trait CompactionSelector: Debug + Send + Sync {
+    fn select(&self, level: usize, level_gens: &[i64], config: &Config) -> Vec<i64>;
+
+    /// 参考各SSTable的墓碑占比进行选择，tombstone_ratios与level_gens一一对应
+    ///
+    /// 压缩时实际调用该方法，默认忽略墓碑占比并以`CompactionSelector::select`选择
+    #[inline]
+    fn select_with_tombstone_ratios(&self, level: usize, level_gens: &[i64], _tombstone_ratios: &[f64], config: &Config) -> Vec<i64> {
+        self.select(level, level_gens, config)
+    }
+}
+
+/// 默认的选择器，选择Level开头`Config::major_select_file_size`个SSTable
+///
+/// 存在墓碑占比不低于`Config::tombstone_ratio_threshold`的SSTable时，
+/// 则按墓碑占比由高至低优先选择这些SSTable，使压缩尽早清除墓碑
+#[derive(Debug, Copy, Clone, Default)]
+#[non_exhaustive]
+pub struct DefaultCompactionSelector;
+
+impl CompactionSelector for DefaultCompactionSelector {
+    #[inline]
+    fn select(&self, _level: usize, level_gens: &[i64], config: &Config) -> Vec<i64> {
+        level_gens.iter()
+            .take(config.major_select_file_size)
+            .copied()
+            .collect_vec()
+    }
+
+    #[inline]
+    fn select_with_tombstone_ratios(&self, level: usize, level_gens: &[i64], tombstone_ratios: &[f64], config: &Config) -> Vec<i64> {
+        let vec_gen = level_gens.iter()
+            .zip(tombstone_ratios)
+            .filter(|(_, ratio)| **ratio >= config.tombstone_ratio_threshold)
+            .sorted_by(|(_, ratio_a), (_, ratio_b)| ratio_b.total_cmp(ratio_a))
+            .take(config.major_select_file_size)
+            .map(|(gen, _)| *gen)
+            .collect_vec();
+
+        if vec_gen.is_empty() {
+            self.select(level, level_gens, config)
+        } else {
+            vec_gen
+        }
+    }
+}
+
+/// 固定位置的选择器
+///
+/// 选择各Level中指定位置的SSTable，超出范围的位置会被忽略
+/// 压缩的输入不受写入顺序以外的运行时状态影响，用于编写压缩结果可预测的测试
+#[derive(Debug, Clone)]
+pub struct FixedCompactionSelector {
+    positions: Vec<usize>
+}
+
+impl FixedCompactionSelector {
+    #[inline]
+    pub fn new(positions: Vec<usize>) -> Self {
+        FixedCompactionSelector { positions }
+    }
+}
+
+impl CompactionSelector for FixedCompactionSelector {
+    #[inline]
+    fn select(&self, _level: usize, level_gens: &[i64], _config: &Config) -> Vec<i64> {
+        self.positions.iter()
+            .filter_map(|position| level_gens.get(*position))
+            .copied()
+            .collect_vec()
+    }
+}
+
+#[derive(Debug)]
+pub struct Config {
+    /// 数据目录地址
+    pub(crate) dir_path: PathBuf,
+    /// WAL持久化阈值
+    pub(crate) wal_compaction_threshold: u64,
+    /// WAL写入触发压缩的冷却时间
+    pub(crate) wal_compaction_cooldown: Duration,
+    /// SSTable中每个data block的大小(单位: 字节)
+    /// 每个block对应一条稀疏索引，点查时以block为单位读取
+    /// 较小的block能够降低随机读时的读放大，但会增大稀疏索引的体积，仅影响之后生成的SSTable
+    pub(crate) block_size: usize,
+    /// 截断稀疏索引Key
+    /// 开启后各数据段的索引Key仅保留足以与上一段数据区分的最短前缀，Key较长时能够显著减小索引体积
+    /// 仅影响之后生成的SSTable，默认关闭
+    pub(crate) sparse_index_truncate: bool,
+    /// SSTable文件大小
+    pub(crate) sst_file_size: usize,
+    /// 持久化阈值(单位: 字节)
+    /// 可通过`LsmStore::reconfigure`热更新
+    pub(crate) minor_threshold_with_data_size: AtomicU64,
+    /// Major压缩触发阈值
+    /// 可通过`LsmStore::reconfigure`热更新
+    pub(crate) major_threshold_with_sst_size: AtomicUsize,
+    /// Major压缩选定文件数
+    /// Major压缩时通过选定个别SSTable(即该配置项)进行下一级的SSTable选定，
+    /// 并将确定范围的下一级SSTable再次对当前等级的SSTable进行范围判定，
+    /// 找到最合理的上下级数据范围并压缩
+    pub(crate) major_select_file_size: usize,
+    /// Major压缩的输入选择器
+    /// 默认为`DefaultCompactionSelector`，即按`major_select_file_size`选择Level开头的SSTable，并优先选择墓碑占比高的SSTable
+    pub(crate) compaction_selector: Arc<dyn CompactionSelector>,
+    /// 墓碑占比阈值
+    /// MemTable落盘或Major压缩后Level的墓碑占比超过该值时输出告警并建议进行压缩，告警每分钟至多一次，
+    /// `DefaultCompactionSelector`也会优先选择墓碑占比不低于该值的SSTable
+    pub(crate) tombstone_ratio_threshold: f64,
+    /// 节点Id
+    pub(crate) node_id: i32,
+    /// 每级SSTable数量倍率
+    pub(crate) level_sst_magnification: usize,
+    /// Major压缩触发策略
+    /// 默认为Leveled
+    pub(crate) compaction_strategy: CompactionStrategy,
+    /// 用于ID生成的原子缓冲
+    /// 避免极端情况下，SSTable创建重复问题并保持时间有序性
+    pub(crate) buffer_i32: AtomicI32,
+    /// 布隆过滤器 期望的错误概率
+    pub(crate) desired_error_prob: f64,
+    /// 数据库全局Position段数据缓存的数量
+    /// 一个size大约为4kb(可能更少)
+    pub(crate) cache_size: usize,
+    /// 负缓存的容量
+    /// 负缓存以LRU记录最近经由get确认不存在的Key，再次get时直接返回None而无需查询布隆过滤器与SSTable，
+    /// 对该Key的任何写入都会使其失效
+    /// 默认为0，即不开启
+    pub(crate) negative_cache_size: usize,
+    /// 布隆过滤器常驻内存
+    /// 关闭后SSTable的布隆过滤器不常驻内存，查询时按需从文件中读取并放入LRU缓存，
+    /// 以IO换取内存，适用于SSTable数量较多而内存紧张的场景
+    /// 默认开启
+    pub(crate) bloom_resident: bool,
+    /// 布隆过滤器非常驻时，缓存的布隆过滤器数量
+    pub(crate) bloom_cache_size: usize,
+    /// Level 0中多个SSTable存在同一Key时判断数据新旧的依据
+    /// 默认以SSTable的数据版本号判断，不受Gen的生成顺序影响
+    pub(crate) version_order: VersionOrder,
+    /// 读修复
+    /// 查询时单个SSTable读取失败的处理方式，默认为`ReadRepair::Disabled`
+    pub(crate) read_repair: ReadRepair,
+    /// 开启wal日志写入
+    /// 在开启状态时，会在SSTable文件读取失败时生效，避免数据丢失
+    /// 不过在设备IO容易成为瓶颈，或使用多节点冗余写入时，建议关闭以提高写入性能
+    pub(crate) wal_enable: bool,
+    /// wal写入时开启异步写入
+    /// 可以提高写入响应速度，但可能会导致wal日志在某种情况下并落盘慢于LSM内核而导致该条wal日志无效
+    pub(crate) wal_async_put_enable: bool,
+    /// wal组提交的时间窗口
+    /// 设置后窗口内并发的多个写入会合并为一次批量写盘，写入者会等待写盘完成
+    /// 默认不开启
+    pub(crate) group_commit_interval: Option<Duration>,
+    /// 待落盘的ImmutableMemTable数量上限
+    /// 超过上限时触发落盘的写入会阻塞，直至落盘跟上写入速度，避免内存无限堆积
+    pub(crate) max_immutable_tables: usize,
+    /// ImmutableMemTable落盘失败(如磁盘错误)时的重试次数
+    /// 重试耗尽后数据会放回MemTable中等待下一次落盘，不会因落盘失败而丢失
+    pub(crate) minor_compaction_retries: usize,
+    /// ImmutableMemTable落盘失败后到下一次重试的间隔
+    pub(crate) minor_compaction_retry_interval: Duration,
+    /// 开启自适应压缩调度
+    /// 开启后根据近期的写入速率与压缩积压动态调整MemTable的落盘阈值与落盘的并发度，
+    /// 以平滑突发写入时的延迟，当前决策可通过`Stats::scheduler_decision`获取
+    /// 默认不开启
+    pub(crate) adaptive_compaction: bool,
+    /// 自适应压缩调度时落盘阈值相对于`minor_threshold_with_data_size`的最大倍数
+    pub(crate) adaptive_compaction_max_factor: u64,
+    /// 开启Key-Value分离
+    /// 开启后MemTable落盘时，较大的value会写入独立的vLog，SSTable中仅存储Key与value的指针，
+    /// 以此降低大value负载下Major压缩的写入量
+    /// 默认不开启
+    pub(crate) kv_separation_enable: bool,
+    /// Key-Value分离的value大小阈值(单位: 字节)
+    /// 不小于该值的value才会被写入vLog，较小的value则直接内联存储于SSTable中，
+    /// 短value的指针所占空间与其本身相差无几，且读取时需额外读取一次vLog
+    pub(crate) kv_separation_threshold: usize,
+    /// vLog文件大小
+    /// 当前vLog文件超过该大小时会切换至新文件，并对旧文件进行GC
+    pub(crate) value_log_file_size: u64,
+    /// vLog GC触发比例
+    /// vLog文件中失效数据占比达到该值时才会被回收
+    pub(crate) value_log_gc_ratio: f64,
+    /// value压缩阈值(单位: 字节)
+    /// 设置后落盘至SSTable时，超过该大小的value会使用lz4压缩，读取时解压
+    /// 小value压缩收益低，因此不进行压缩；压缩后未变小的value同样保持原样
+    /// 默认不开启
+    pub(crate) value_compression_threshold: Option<usize>,
+    /// 前缀布隆过滤器的前缀长度(单位: 字节)
+    /// 设置后SSTable额外以各Key的前缀构建布隆过滤器，前缀扫描时以其跳过不含该前缀的SSTable
+    /// 仅对长度不小于该值的前缀生效，默认不开启
+    pub(crate) prefix_bloom_len: Option<usize>,
+    /// 开启时进行格式迁移
+    /// 开启后会将旧格式版本的SSTable与Wal日志重写为当前格式版本，迁移过程中断不会损坏原有数据
+    /// vLog中的数据位置被SSTable所引用，因此不参与迁移，仍以原有格式版本读取
+    /// 默认不开启
+    pub(crate) migrate_on_open: bool,
+    /// SSTable与vLog文件的读写缓冲大小(单位: 字节)
+    /// 较大的缓冲能够减少压缩与范围扫描等顺序读写时的系统调用次数
+    pub(crate) io_buffer_size: usize,
+    /// SSTable文件只读句柄池的上限
+    /// 按gen复用已打开的只读句柄以减少压缩等频繁创建IOHandler时的open次数，为0时不复用
+    pub(crate) io_reader_pool_size: usize,
+    /// 生成SSTable时并行序列化data block的数量
+    /// MemTable落盘与压缩时各data block在阻塞线程池中并行序列化后再按顺序写盘，
+    /// 内存中至多同时持有该数量的data block的序列化数据，为1时逐个序列化
+    pub(crate) serialize_parallelism: usize,
+    /// 严格删除
+    /// 开启时删除不存在的Key返回`KvsError::KeyNotFound`，与其他内核的行为一致；
+    /// 关闭时则视为删除成功，使删除操作幂等，write_batch_atomic中的Remove同理
+    /// 默认开启
+    pub(crate) strict_remove: bool,
+    /// Key校验器
+    /// 设置后set、remove等写入操作会在写入前以此校验Key，校验失败时直接返回其错误而不写入任何数据
+    /// 读取操作不进行校验
+    pub(crate) validate_key: Option<fn(&[u8]) -> Result<()>>,
+    /// 合并函数
+    /// 设置后可通过`LsmStore::merge`写入操作数，读取与压缩时将同一Key的多个操作数与已有的value折叠，
+    /// 适用于计数器、集合合并等增量更新的场景
+    /// 注意：Merge不支持与Key-Value分离同时使用
+    pub(crate) merge_operator: Option<MergeOperator>,
+    /// 后台校验
+    /// 开启时open跳过SSTable的crc校验并立即返回，之后由后台任务逐个校验，
+    /// 校验失败的SSTable会被隔离，对其数据的查询返回`KvsError::CorruptedFile`
+    /// 默认关闭
+    pub(crate) background_verify: bool,
+    /// 后台校验发现SSTable损坏时的回调，参数为损坏的SSTable的Gen
+    pub(crate) on_corrupted: Option<fn(i64)>,
+    /// 校验和清单
+    /// 开启时在目录中维护记录各SSTable文件整体crc的`MANIFEST.crc`，并随SSTable的增删同步更新；
+    /// open时以其校验所有已记录的SSTable，存在被外部修改或删除的SSTable时返回`KvsError::ChecksumManifestMismatch`
+    /// 校验需读取所有SSTable，适用于只读分发等需要检测整个目录是否被篡改的场景
+    /// 默认关闭
+    pub(crate) checksum_manifest: bool,
+    /// 开启时若检测到其他进程仍在写入该目录(心跳文件未过期)，是否拒绝开启并返回`KvsError::ActiveWriter`
+    /// 默认仅进行警告
+    pub(crate) reject_active_writer: bool,
+    /// 开启后是否在持有目录锁期间定期更新目录中的心跳文件，使其他进程开启该目录时能够检测到该写入者
+    /// 文件锁可靠时无需开启；同一进程中开启心跳的所有目录共用一个更新线程
+    /// 默认关闭
+    pub(crate) heartbeat: bool,
+    /// 各Level的SSTable所存放的目录
+    /// 以Level为Key，该Level及更高的Level(直至下一个设置了目录的Level)的SSTable存放于对应目录，未覆盖的Level存放于dir_path
+    /// 可用于冷热分层，如将较低Level的热数据存放于SSD，较高Level的冷数据存放于HDD
+    /// Wal、vLog与各元数据文件始终存放于dir_path
+    pub(crate) level_dir_paths: BTreeMap<usize, PathBuf>,
+    /// 加密器
+    /// 设置后SSTable、Wal与vLog中的数据在写盘前加密、读取后解密，开启已有的目录时需使用相同的密钥，
+    /// 密钥错误时open返回`KvsError::CipherError`
+    /// 每条数据的nonce随机生成并与密文一同存储，SSTable的MetaInfo保持明文且格式不变；
+    /// crc基于加密后的数据计算，因此无需密钥即可校验文件完整性，解密时则由GCM的认证标签校验数据未被篡改
+    /// 注意：`LsmStore::open_snapshot`与`LsmStore::open_follower`需以`_with_config`的形式传入相同的密钥，且加密后value无法流式读取
+    pub(crate) cipher: Option<Arc<Cipher>>
+}
+
+impl Config {
+
+    #[inline]
+    pub fn dir_path(mut self, dir_path: PathBuf) -> Self {
+        self.dir_path = dir_path;
+        self
+    }
+
+    /// 设置Level及更高Level的SSTable所存放的目录，直至下一个设置了目录的Level为止
+    ///
+    /// 例如设置`level_dir_path(2, hdd)`时，Level 0-1的SSTable存放于dir_path，Level 2-6则存放于hdd
+    /// 压缩将数据推至更高的Level时会写入对应的目录；开启时会扫描所有目录中的SSTable，
+    /// 因此调整配置后原有的SSTable仍能读取，并随之后的压缩逐渐迁移至新的目录
+    #[inline]
+    pub fn level_dir_path(mut self, level: usize, dir_path: impl Into<PathBuf>) -> Self {
+        let _ignore = self.level_dir_paths.insert(level, dir_path.into());
+        self
+    }
+
+    /// 获取该Level的SSTable所存放的目录
+    pub(crate) fn dir_path_with_level(&self, level: usize) -> &PathBuf {
+        self.level_dir_paths.range(..=level)
+            .next_back()
+            .map_or(&self.dir_path, |(_, dir_path)| dir_path)
+    }
+
+    /// 获取dir_path之外存放SSTable的所有目录
+    pub(crate) fn extra_dir_paths(&self) -> Vec<PathBuf> {
+        self.level_dir_paths.values()
+            .filter(|dir_path| *dir_path != &self.dir_path)
+            .unique()
+            .cloned()
+            .collect_vec()
+    }
+
+    /// 获取所有存放SSTable的目录中现有的Gen，由小到大排列
+    pub(crate) fn sorted_gen_list_with_levels(&self) -> Result<Vec<i64>> {
+        let mut vec_gen = sorted_gen_list(&self.dir_path)?;
+
+        for extra_dir_path in self.extra_dir_paths() {
+            vec_gen.append(&mut sorted_gen_list(&extra_dir_path)?);
+        }
+        vec_gen.sort_unstable();
+        vec_gen.dedup();
+
+        Ok(vec_gen)
+    }
+
+    #[inline]
+    pub fn minor_threshold_with_data_size(mut self, minor_threshold_with_data_size: u64) -> Self {
+        self.minor_threshold_with_data_size = AtomicU64::new(minor_threshold_with_data_size);
+        self
+    }
+
+    #[inline]
+    pub fn wal_compaction_threshold(mut self, wal_compaction_threshold: u64) -> Self {
+        self.wal_compaction_threshold = wal_compaction_threshold;
+        self
+    }
+
+    #[inline]
+    pub fn wal_compaction_cooldown(mut self, wal_compaction_cooldown: Duration) -> Self {
+        self.wal_compaction_cooldown = wal_compaction_cooldown;
+        self
+    }
+
+    #[inline]
+    pub fn block_size(mut self, block_size: usize) -> Self {
+        self.block_size = block_size.max(1);
+        self
+    }
+
+    /// 以4K字节为单位设置`Config::block_size`
+    #[inline]
+    pub fn sparse_index_interval_block_size(self, sparse_index_interval_block_size: u64) -> Self {
+        self.block_size(ALIGNMENT_4K * sparse_index_interval_block_size as usize)
+    }
+
+    #[inline]
+    pub fn sparse_index_truncate(mut self, sparse_index_truncate: bool) -> Self {
+        self.sparse_index_truncate = sparse_index_truncate;
+        self
+    }
+
+    #[inline]
+    pub fn sst_file_size(mut self, sst_file_size: usize) -> Self {
+        self.sst_file_size = sst_file_size;
+        self
+    }
+
+    #[inline]
+    pub fn major_threshold_with_sst_size(mut self, major_threshold_with_sst_size: usize) -> Self {
+        self.major_threshold_with_sst_size = AtomicUsize::new(major_threshold_with_sst_size);
+        self
+    }
+
+    #[inline]
+    pub fn major_select_file_size(mut self, major_select_file_size: usize) -> Self {
+        self.major_select_file_size = major_select_file_size;
+        self
+    }
+
+    #[inline]
+    pub fn compaction_selector(mut self, compaction_selector: impl CompactionSelector + 'static) -> Self {
+        self.compaction_selector = Arc::new(compaction_selector);
+        self
+    }
+
+    #[inline]
+    pub fn tombstone_ratio_threshold(mut self, tombstone_ratio_threshold: f64) -> Self {
+        self.tombstone_ratio_threshold = tombstone_ratio_threshold;
+        self
+    }
+
+    #[inline]
+    pub fn node_id(mut self, node_id: i32) -> Self {
+        self.node_id = node_id;
+        self
+    }
+
+    #[inline]
+    pub fn level_sst_magnification(mut self, level_sst_magnification: usize) -> Self {
+        self.level_sst_magnification = level_sst_magnification;
+        self
+    }
+
+    #[inline]
+    pub fn compaction_strategy(mut self, compaction_strategy: CompactionStrategy) -> Self {
+        self.compaction_strategy = compaction_strategy;
+        self
+    }
+
+    #[inline]
+    pub fn desired_error_prob(mut self, desired_error_prob: f64) -> Self {
+        self.desired_error_prob = desired_error_prob;
+        self
+    }
+
+    #[inline]
+    pub fn cache_size(mut self, cache_size: usize) -> Self {
+        self.cache_size = cache_size;
+        self
+    }
+
+    #[inline]
+    pub fn negative_cache_size(mut self, negative_cache_size: usize) -> Self {
+        self.negative_cache_size = negative_cache_size;
+        self
+    }
+
+    #[inline]
+    pub fn bloom_resident(mut self, bloom_resident: bool) -> Self {
+        self.bloom_resident = bloom_resident;
+        self
+    }
+
+    #[inline]
+    pub fn bloom_cache_size(mut self, bloom_cache_size: usize) -> Self {
+        self.bloom_cache_size = bloom_cache_size;
+        self
+    }
+
+    #[inline]
+    pub fn version_order(mut self, version_order: VersionOrder) -> Self {
+        self.version_order = version_order;
+        self
+    }
+
+    #[inline]
+    pub fn read_repair(mut self, read_repair: ReadRepair) -> Self {
+        self.read_repair = read_repair;
+        self
+    }
+
+    #[inline]
+    pub fn create_gen(&self) -> i64 {
+        SnowflakeIdBucket::new(self.node_id, self.buffer_i32
+            .fetch_add(1, Ordering::SeqCst)).get_id()
+    }
+
+    #[inline]
+    pub fn wal_enable(mut self, wal_enable: bool) -> Self {
+        self.wal_enable = wal_enable;
+        self
+    }
+
+    #[inline]
+    pub fn wal_async_put_enable(mut self, wal_async_put_enable: bool) -> Self {
+        self.wal_async_put_enable = wal_async_put_enable;
+        self
+    }
+
+    #[inline]
+    pub fn group_commit_interval(mut self, group_commit_interval: Duration) -> Self {
+        self.group_commit_interval = Some(group_commit_interval);
+        self
+    }
+
+    /// 上限至少为1
+    #[inline]
+    pub fn max_immutable_tables(mut self, max_immutable_tables: usize) -> Self {
+        self.max_immutable_tables = max_immutable_tables.max(1);
+        self
+    }
+
+    #[inline]
+    pub fn minor_compaction_retries(mut self, minor_compaction_retries: usize) -> Self {
+        self.minor_compaction_retries = minor_compaction_retries;
+        self
+    }
+
+    #[inline]
+    pub fn minor_compaction_retry_interval(mut self, minor_compaction_retry_interval: Duration) -> Self {
+        self.minor_compaction_retry_interval = minor_compaction_retry_interval;
+        self
+    }
+
+    #[inline]
+    pub fn adaptive_compaction(mut self, adaptive_compaction: bool) -> Self {
+        self.adaptive_compaction = adaptive_compaction;
+        self
+    }
+
+    #[inline]
+    pub fn adaptive_compaction_max_factor(mut self, adaptive_compaction_max_factor: u64) -> Self {
+        self.adaptive_compaction_max_factor = adaptive_compaction_max_factor.max(1);
+        self
+    }
+
+    #[inline]
+    pub fn kv_separation_enable(mut self, kv_separation_enable: bool) -> Self {
+        self.kv_separation_enable = kv_separation_enable;
+        self
+    }
+
+    #[inline]
+    pub fn kv_separation_threshold(mut self, kv_separation_threshold: usize) -> Self {
+        self.kv_separation_threshold = kv_separation_threshold;
+        self
+    }
+
+    #[inline]
+    pub fn value_log_file_size(mut self, value_log_file_size: u64) -> Self {
+        self.value_log_file_size = value_log_file_size;
+        self
+    }
+
+    #[inline]
+    pub fn value_log_gc_ratio(mut self, value_log_gc_ratio: f64) -> Self {
+        self.value_log_gc_ratio = value_log_gc_ratio;
+        self
+    }
+
+    #[inline]
+    pub fn value_compression_threshold(mut self, value_compression_threshold: usize) -> Self {
+        self.value_compression_threshold = Some(value_compression_threshold);
+        self
+    }
+
+    #[inline]
+    pub fn prefix_bloom_len(mut self, prefix_bloom_len: usize) -> Self {
+        self.prefix_bloom_len = Some(prefix_bloom_len);
+        self
+    }
+
+    #[inline]
+    pub fn migrate_on_open(mut self, migrate_on_open: bool) -> Self {
+        self.migrate_on_open = migrate_on_open;
+        self
+    }
+
+    #[inline]
+    pub fn io_buffer_size(mut self, io_buffer_size: usize) -> Self {
+        self.io_buffer_size = io_buffer_size;
+        self
+    }
+
+    #[inline]
+    pub fn io_reader_pool_size(mut self, io_reader_pool_size: usize) -> Self {
+        self.io_reader_pool_size = io_reader_pool_size;
+        self
+    }
+
+    #[inline]
+    pub fn serialize_parallelism(mut self, serialize_parallelism: usize) -> Self {
+        self.serialize_parallelism = serialize_parallelism;
+        self
+    }
+
+    #[inline]
+    pub fn strict_remove(mut self, strict_remove: bool) -> Self {
+        self.strict_remove = strict_remove;
+        self
+    }
+
+    #[inline]
+    pub fn validate_key(mut self, validate_key: fn(&[u8]) -> Result<()>) -> Self {
+        self.validate_key = Some(validate_key);
+        self
+    }
+
+    #[inline]
+    pub fn merge_operator(mut self, merge_operator: MergeOperator) -> Self {
+        self.merge_operator = Some(merge_operator);
+        self
+    }
+
+    #[inline]
+    pub fn background_verify(mut self, background_verify: bool) -> Self {
+        self.background_verify = background_verify;
+        self
+    }
+
+    #[inline]
+    pub fn on_corrupted(mut self, on_corrupted: fn(i64)) -> Self {
+        self.on_corrupted = Some(on_corrupted);
+        self
+    }
+
+    #[inline]
+    pub fn checksum_manifest(mut self, checksum_manifest: bool) -> Self {
+        self.checksum_manifest = checksum_manifest;
+        self
+    }
+
+    #[inline]
+    pub fn reject_active_writer(mut self, reject_active_writer: bool) -> Self {
+        self.reject_active_writer = reject_active_writer;
+        self
+    }
+
+    #[inline]
+    pub fn heartbeat(mut self, heartbeat: bool) -> Self {
+        self.heartbeat = heartbeat;
+        self
+    }
+
+    #[inline]
+    pub fn cipher(mut self, cipher: Cipher) -> Self {
+        self.cipher = Some(Arc::new(cipher));
+        self
+    }
+}
+
+impl Default for Config {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            dir_path: DEFAULT_WAL_PATH.into(),
+            minor_threshold_with_data_size: AtomicU64::new(DEFAULT_MINOR_THRESHOLD_WITH_DATA_OCCUPIED),
+            wal_compaction_threshold: DEFAULT_WAL_COMPACTION_THRESHOLD,
+            wal_compaction_cooldown: DEFAULT_WAL_COMPACTION_COOLDOWN,
+            block_size: DEFAULT_BLOCK_SIZE,
+            sparse_index_truncate: false,
+            sst_file_size: DEFAULT_SST_FILE_SIZE,
+            major_threshold_with_sst_size: AtomicUsize::new(DEFAULT_MAJOR_THRESHOLD_WITH_SST_SIZE),
+            major_select_file_size: DEFAULT_MAJOR_SELECT_FILE_SIZE,
+            compaction_selector: Arc::new(DefaultCompactionSelector),
+            tombstone_ratio_threshold: DEFAULT_TOMBSTONE_RATIO_THRESHOLD,
+            node_id: DEFAULT_MACHINE_ID,
+            level_sst_magnification: DEFAULT_LEVEL_SST_MAGNIFICATION,
+            compaction_strategy: CompactionStrategy::Leveled,
+            buffer_i32: AtomicI32::new(0),
+            desired_error_prob: DEFAULT_DESIRED_ERROR_PROB,
+            cache_size: DEFAULT_CACHE_SIZE,
+            negative_cache_size: 0,
+            bloom_resident: true,
+            bloom_cache_size: DEFAULT_BLOOM_CACHE_SIZE,
+            version_order: VersionOrder::Version,
+            read_repair: ReadRepair::Disabled,
+            wal_enable: true,
+            wal_async_put_enable: true,
+            group_commit_interval: None,
+            max_immutable_tables: DEFAULT_MAX_IMMUTABLE_TABLES,
+            minor_compaction_retries: DEFAULT_MINOR_COMPACTION_RETRIES,
+            minor_compaction_retry_interval: DEFAULT_MINOR_COMPACTION_RETRY_INTERVAL,
+            adaptive_compaction: false,
+            adaptive_compaction_max_factor: DEFAULT_ADAPTIVE_COMPACTION_MAX_FACTOR,
+            kv_separation_enable: false,
+            kv_separation_threshold: DEFAULT_KV_SEPARATION_THRESHOLD,
+            value_log_file_size: DEFAULT_VALUE_LOG_FILE_SIZE,
+            value_log_gc_ratio: DEFAULT_VALUE_LOG_GC_RATIO,
+            value_compression_threshold: None,
+            prefix_bloom_len: None,
+            migrate_on_open: false,
+            io_buffer_size: DEFAULT_IO_BUFFER_SIZE,
+            io_reader_pool_size: DEFAULT_IO_READER_POOL_SIZE,
+            serialize_parallelism: DEFAULT_SERIALIZE_PARALLELISM,
+            strict_remove: true,
+            validate_key: None,
+            merge_operator: None,
+            background_verify: false,
+            on_corrupted: None,
+            checksum_manifest: false,
+            reject_active_writer: false,
+            heartbeat: false,
+            level_dir_paths: BTreeMap::new(),
+            cipher: None
+        }
+    }
+}
+
+/// 运行时热更新的配置项，由`LsmStore::reconfigure`应用，未设置的配置项保持不变
+///
+/// 可热更新：
+/// - cache_size: 数据段缓存的容量，缩小时淘汰最久未使用的数据段
+/// - minor_threshold_with_data_size: 持久化阈值，开启自适应压缩调度时作为其落盘阈值的基准
+/// - major_threshold_with_sst_size: Major压缩触发阈值
+/// - wal_compaction_threshold: Wal的压缩阈值
+///
+/// 不可热更新(设置后返回`KvsError::ConfigNotReloadable`)：
+/// - level_sst_magnification: 决定各Level的容量，已有的SSTable按其分布于各Level，运行时变更会使层级布局失衡
+/// - node_id: Gen的生成依赖于节点Id，运行时变更可能与其他节点生成的Gen重复
+#[derive(Debug, Clone, Default)]
+pub struct ConfigPatch {
+    pub(crate) cache_size: Option<usize>,
+    pub(crate) minor_threshold_with_data_size: Option<u64>,
+    pub(crate) major_threshold_with_sst_size: Option<usize>,
+    pub(crate) wal_compaction_threshold: Option<u64>,
+    pub(crate) level_sst_magnification: Option<usize>,
+    pub(crate) node_id: Option<i32>
+}
+
+impl ConfigPatch {
+    #[inline]
+    pub fn cache_size(mut self, cache_size: usize) -> Self {
+        self.cache_size = Some(cache_size);
+        self
+    }
+
+    #[inline]
+    pub fn minor_threshold_with_data_size(mut self, minor_threshold_with_data_size: u64) -> Self {
+        self.minor_threshold_with_data_size = Some(minor_threshold_with_data_size);
+        self
+    }
+
+    #[inline]
+    pub fn major_threshold_with_sst_size(mut self, major_threshold_with_sst_size: usize) -> Self {
+        self.major_threshold_with_sst_size = Some(major_threshold_with_sst_size);
+        self
+    }
+
+    #[inline]
+    pub fn wal_compaction_threshold(mut self, wal_compaction_threshold: u64) -> Self {
+        self.wal_compaction_threshold = Some(wal_compaction_threshold);
+        self
+    }
+
+    #[inline]
+    pub fn level_sst_magnification(mut self, level_sst_magnification: usize) -> Self {
+        self.level_sst_magnification = Some(level_sst_magnification);
+        self
+    }
+
+    #[inline]
+    pub fn node_id(mut self, node_id: i32) -> Self {
+        self.node_id = Some(node_id);
+        self
+    }
+
+    /// 校验是否含有不可热更新或不合法的配置项
+    fn check(&self) -> Result<()> {
+        if self.level_sst_magnification.is_some() {
+            return Err(KvsError::ConfigNotReloadable("level_sst_magnification".to_owned()));
+        }
+        if self.node_id.is_some() {
+            return Err(KvsError::ConfigNotReloadable("node_id".to_owned()));
+        }
+        if self.cache_size == Some(0) {
+            return Err(KvsError::CacheSizeOverFlow);
+        }
+        Ok(())
+    }
+}
+
+/// 以Task类似的异步写数据，避免影响数据写入性能
+/// 当然，LevelDB的话虽然wal写入会提供是否同步的选项，此处先简化优先使用异步
+///
+/// 写入期间持有in_flight的读锁，`LsmStore::flush_buffer`以此等待未完成的写入
+pub(crate) async fn wal_put(wal: &Arc<HashStore>, in_flight: &Arc<RwLock<()>>, key: Vec<u8>, value: Vec<u8>, is_sync: bool) {
+    let wal = Arc::clone(wal);
+    let guard = Arc::clone(in_flight).read_owned().await;
     let wal_closure = async move {
         if let Err(err) = wal.set(&key, value).await {
             error!("[LsmStore][wal_put][error happen]: {:?}", err);
         }
-    };
-    if is_sync {
-        wal_closure.await;
-    } else {
-        let _ignore = tokio::spawn(wal_closure);
+        drop(guard);
+    };
+    if is_sync {
+        wal_closure.await;
+    } else {
+        let _ignore = tokio::spawn(wal_closure);
+    }
+
+}
+
+/// 以Task类似的异步批量写数据，Wal中以单条SetBatch整体写入
+pub(crate) async fn wal_put_batch(wal: &Arc<HashStore>, in_flight: &Arc<RwLock<()>>, pairs: Vec<(Vec<u8>, Vec<u8>)>, is_sync: bool) {
+    let wal = Arc::clone(wal);
+    let guard = Arc::clone(in_flight).read_owned().await;
+    let wal_closure = async move {
+        if let Err(err) = wal.set_batch(pairs).await {
+            error!("[LsmStore][wal_put_batch][error happen]: {:?}", err);
+        }
+        drop(guard);
+    };
+    if is_sync {
+        wal_closure.await;
+    } else {
+        let _ignore = tokio::spawn(wal_closure);
+    }
+}
+
+#[test]
+fn test_lsm_major_compactor() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let config = Config::default()
+            .dir_path(temp_dir.into_path())
+            .wal_enable(false);
+        let kv_store = LsmStore::open_with_config(config).await?;
+        let mut vec_key: Vec<Vec<u8>> = Vec::new();
+
+        let start = Instant::now();
+        for i in 0..300000 {
+            let vec_u8 = rmp_serde::to_vec(&i).unwrap();
+            kv_store.set(&vec_u8, vec_u8.clone()).await?;
+            vec_key.push(vec_u8);
+        }
+        kv_store.set(&vec_key[0], vec![b'k']).await?;
+        println!("[set_for][Time: {:?}]", start.elapsed());
+
+        let start = Instant::now();
+        let _ignore = kv_store.get(&rmp_serde::to_vec(&0).unwrap()).await?.unwrap();
+        println!("[compaction_for][Time: {:?}]", start.elapsed());
+
+        kv_store.flush().await?;
+        let start = Instant::now();
+        assert_eq!(kv_store.get(&vec_key[0]).await?, Some(vec![b'k']));
+        for i in 1..300000 {
+            assert_eq!(kv_store.get(&vec_key[i]).await?, Some(vec_key[i].clone()));
+        }
+        println!("[get_for][Time: {:?}]", start.elapsed());
+        kv_store.flush().await?;
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_check_consistency() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let config = Config::default()
+            .dir_path(temp_dir.into_path())
+            .minor_threshold_with_data_size(1024)
+            .sst_file_size(4 * 1024)
+            .level_sst_magnification(1)
+            .wal_enable(false);
+        let kv_store = LsmStore::open_with_config(config).await?;
+
+        for i in 0..2000 {
+            let vec_u8 = rmp_serde::to_vec(&i).unwrap();
+            kv_store.set(&vec_u8, vec_u8.clone()).await?;
+        }
+        kv_store.flush().await?;
+        kv_store.major_compaction_sync(0).await?;
+        kv_store.check_consistency().await?;
+
+        let level = {
+            let manifest = kv_store.manifest.read().await;
+            (1..7).find(|level| manifest.get_level_vec(*level).len() > 1).unwrap()
+        };
+
+        // level_slice中存在ss_tables_map中不存在的gen
+        kv_store.manifest.write().await.level_slice[level].push(-1);
+        assert!(matches!(kv_store.check_consistency().await, Err(KvsError::ManifestInconsistent(_))));
+        let _ignore = kv_store.manifest.write().await.level_slice[level].pop();
+        kv_store.check_consistency().await?;
+
+        // ss_tables_map中的SSTable不处于任何Level
+        let gen = kv_store.manifest.read().await.get_level_vec(level)[0];
+        kv_store.manifest.write().await.level_slice[level].retain(|level_gen| gen != *level_gen);
+        assert!(matches!(kv_store.check_consistency().await, Err(KvsError::ManifestInconsistent(_))));
+
+        // scope最小的SSTable被放置于末尾导致无序
+        kv_store.manifest.write().await.level_slice[level].push(gen);
+        assert!(matches!(kv_store.check_consistency().await, Err(KvsError::ManifestInconsistent(_))));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_scan_level() -> Result<()> {
+    use tempfile::TempDir;
+    use crate::kernel::lsm::compactor::LEVEL_COUNT;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let config = Config::default()
+            .dir_path(temp_dir.into_path())
+            .minor_threshold_with_data_size(1024)
+            .sst_file_size(4 * 1024)
+            .level_sst_magnification(1)
+            .wal_enable(false);
+        let kv_store = LsmStore::open_with_config(config).await?;
+
+        // 重复写入同一批Key，使Level 0中的SSTable之间存在重复数据
+        for round in 0..2_u8 {
+            for i in (0..1000_u32).rev() {
+                kv_store.set(&i.to_be_bytes(), vec![round]).await?;
+            }
+            kv_store.minor_compaction_sync().await?;
+        }
+        kv_store.flush().await?;
+        kv_store.major_compaction_sync(0).await?;
+
+        let vec_kv = kv_store.scan_level(1).await?;
+        assert!(!vec_kv.is_empty());
+        assert!(vec_kv.iter().tuple_windows().all(|((key_a, _), (key_b, _))| key_a < key_b));
+        // Level 0为磁盘中最新的数据，重复的Key应当取Gen最大的SSTable中的数据
+        let vec_kv = kv_store.scan_level(0).await?;
+        assert!(vec_kv.iter().tuple_windows().all(|((key_a, _), (key_b, _))| key_a < key_b));
+        for (key, value) in vec_kv {
+            assert_eq!(kv_store.get(&key).await?, Some(value));
+        }
+        assert!(matches!(kv_store.scan_level(LEVEL_COUNT).await, Err(KvsError::LevelOver)));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_scan_with_limit() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let config = Config::default()
+            .dir_path(temp_dir.into_path())
+            .wal_enable(false);
+        let kv_store = LsmStore::open_with_config(config).await?;
+
+        for i in 0..10000 {
+            kv_store.set(format!("key{:05}", i).as_bytes(), vec![b'v'; 100]).await?;
+        }
+        kv_store.flush().await?;
+        // 以范围外的数据替换ImmutableMemTable，使范围内的数据只能从SSTable读取
+        kv_store.set(b"other", vec![b'v']).await?;
+        kv_store.flush().await?;
+
+        let read_count = kv_store.io_handler_factory().read_count();
+        let vec_kv = kv_store.scan(b"key00000", b"key99999", 10).await?;
+        let limit_read_count = kv_store.io_handler_factory().read_count() - read_count;
+        assert_eq!(vec_kv.len(), 10);
+        for (i, (key, _)) in vec_kv.iter().enumerate() {
+            assert_eq!(key, format!("key{:05}", i).as_bytes());
+        }
+
+        let read_count = kv_store.io_handler_factory().read_count();
+        let vec_kv = kv_store.scan(b"key00000", b"key99999", usize::MAX).await?;
+        let full_read_count = kv_store.io_handler_factory().read_count() - read_count;
+        assert_eq!(vec_kv.len(), 10000);
+
+        // limit较小时仅需读取第一个数据段
+        assert_eq!(limit_read_count, 1);
+        assert!(limit_read_count < full_read_count);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_for_each_streaming() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let config = Config::default()
+            .dir_path(temp_dir.into_path())
+            .wal_enable(false);
+        let kv_store = LsmStore::open_with_config(config).await?;
+
+        for i in 0..10000 {
+            kv_store.set(format!("key{:05}", i).as_bytes(), vec![b'v'; 100]).await?;
+        }
+        kv_store.flush().await?;
+        kv_store.set(b"other", vec![b'v']).await?;
+        kv_store.flush().await?;
+        // MemTable中较新的数据覆盖SSTable中的数据，墓碑则被跳过
+        kv_store.set(b"key00001", b"new".to_vec()).await?;
+        kv_store.remove(b"key00002").await?;
+
+        let mut vec_key = Vec::new();
+        kv_store.for_each(|key, value| {
+            if key == b"key00001" {
+                assert_eq!(value, b"new");
+            }
+            vec_key.push(key.to_vec());
+            Ok(())
+        }).await?;
+        assert_eq!(vec_key.len(), 10000);
+        assert!(vec_key.windows(2).all(|keys| keys[0] < keys[1]));
+        assert!(!vec_key.contains(&b"key00002".to_vec()));
+
+        // 回调中止遍历时仅读取了第一个数据段
+        let read_count = kv_store.io_handler_factory().read_count();
+        let mut count = 0;
+        let result = kv_store.for_each(|_, _| {
+            count += 1;
+            Err(KvsError::KeyNotFound)
+        }).await;
+        assert!(matches!(result, Err(KvsError::KeyNotFound)));
+        assert_eq!(count, 1);
+        assert_eq!(kv_store.io_handler_factory().read_count() - read_count, 1);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_keys_only() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let config = Config::default()
+            .dir_path(temp_dir.into_path())
+            .wal_enable(false);
+        let kv_store = LsmStore::open_with_config(config).await?;
+
+        for i in 0..10000 {
+            kv_store.set(format!("key{:05}", i).as_bytes(), vec![b'v'; 100]).await?;
+        }
+        for i in 0..100 {
+            kv_store.remove(format!("key{:05}", i).as_bytes()).await?;
+        }
+        kv_store.flush().await?;
+        // 以范围外的数据替换ImmutableMemTable，使范围内的Key只能从SSTable读取
+        kv_store.set(b"other", vec![b'v']).await?;
+        kv_store.flush().await?;
+        kv_store.wait_for_compression_down().await?;
+        let ss_table_count = kv_store.manifest.read().await
+            .ss_tables_map.values()
+            .filter(|ss_table| ss_table.get_scope().meet(&Scope::from_range(b"key00000", b"key99999")))
+            .count();
+
+        let read_count = kv_store.io_handler_factory().read_count();
+        let vec_key = kv_store.scan_keys(b"key00000", b"key99999", usize::MAX).await?;
+        let keys_read_count = kv_store.io_handler_factory().read_count() - read_count;
+
+        let read_count = kv_store.io_handler_factory().read_count();
+        let vec_kv = kv_store.scan(b"key00000", b"key99999", usize::MAX).await?;
+        let full_read_count = kv_store.io_handler_factory().read_count() - read_count;
+
+        assert_eq!(vec_key.len(), 9900);
+        assert_eq!(vec_key, vec_kv.into_iter().map(|(key, _)| key).collect_vec());
+        // 每个SSTable仅读取一次Key块，不触发数据段的读取
+        assert_eq!(keys_read_count, ss_table_count as u64);
+        assert!(keys_read_count < full_read_count);
+
+        let read_count = kv_store.io_handler_factory().read_count();
+        let mut count = 0;
+        let mut option_prev_key: Option<Vec<u8>> = None;
+        kv_store.for_each_key(|key| {
+            assert_ne!(key, b"key00000");
+            // 归并各SSTable后Key由小到大且不重复
+            assert!(option_prev_key.as_deref() < Some(key));
+            option_prev_key = Some(key.to_vec());
+            count += 1;
+            Ok(())
+        }).await?;
+        assert_eq!(count, 9901);
+        assert_eq!(kv_store.io_handler_factory().read_count() - read_count, kv_store.manifest.read().await.ss_tables_map.len() as u64);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_get_with_bloom_filter() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        // 缓存仅保留一个数据段，避免读盘次数被缓存掩盖
+        let config = Config::default()
+            .dir_path(temp_dir.into_path())
+            .desired_error_prob(0.001)
+            .cache_size(1)
+            .wal_enable(false);
+        let kv_store = LsmStore::open_with_config(config).await?;
+
+        for i in 0..10000 {
+            kv_store.set(format!("key{:05}", i * 2).as_bytes(), vec![b'v'; 100]).await?;
+        }
+        kv_store.flush().await?;
+        // 以范围外的数据替换ImmutableMemTable，使范围内的数据只能从SSTable读取
+        kv_store.set(b"other", vec![b'v']).await?;
+        kv_store.flush().await?;
+
+        // 不存在的Key处于SSTable的Scope之中，仅由布隆过滤器过滤
+        let read_count = kv_store.io_handler_factory().read_count();
+        for i in 0..10000 {
+            assert_eq!(kv_store.get(format!("key{:05}", i * 2 + 1).as_bytes()).await?, None);
+        }
+        let miss_read_count = kv_store.io_handler_factory().read_count() - read_count;
+        assert!(miss_read_count < 100);
+
+        assert_eq!(kv_store.get(b"key00000").await?, Some(vec![b'v'; 100]));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_bloom_not_resident() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let mut vec_memory_usage = Vec::new();
+        for bloom_resident in [true, false] {
+            let dir_path = temp_dir.path().join(bloom_resident.to_string());
+            let config = Config::default()
+                .dir_path(dir_path.clone())
+                .minor_threshold_with_data_size(64 * 1024)
+                .bloom_resident(bloom_resident)
+                .bloom_cache_size(2)
+                .wal_enable(false);
+            let kv_store = LsmStore::open_with_config(config).await?;
+            for i in 0..5000 {
+                kv_store.set(format!("key{:05}", i * 2).as_bytes(), vec![b'v'; 100]).await?;
+            }
+            kv_store.flush().await?;
+            kv_store.set(b"other", vec![b'v']).await?;
+            kv_store.flush().await?;
+            assert!(kv_store.manifest().read().await.ss_tables_map.len() > 2);
+
+            // 非常驻时布隆过滤器按需读取，查询结果与常驻时一致
+            for i in 0..5000 {
+                assert_eq!(kv_store.get(format!("key{:05}", i * 2).as_bytes()).await?, Some(vec![b'v'; 100]));
+                assert_eq!(kv_store.get(format!("key{:05}", i * 2 + 1).as_bytes()).await?, None);
+            }
+            vec_memory_usage.push(kv_store.bloom_memory_usage().await?);
+            drop(kv_store);
+
+            // 重启后以相同的模式恢复SSTable
+            let kv_store = LsmStore::open_with_config(Config::default()
+                .dir_path(dir_path)
+                .bloom_resident(bloom_resident)
+                .wal_enable(false)
+            ).await?;
+            assert_eq!(kv_store.bloom_memory_usage().await? == 0, !bloom_resident);
+            assert_eq!(kv_store.get(b"key00000").await?, Some(vec![b'v'; 100]));
+        }
+        // 非常驻时仅缓存中的布隆过滤器占用内存
+        assert!(vec_memory_usage[1] < vec_memory_usage[0]);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_backpressure() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        // 极小的落盘阈值使写入速度远超落盘速度
+        let config = Config::default()
+            .dir_path(temp_dir.into_path())
+            .minor_threshold_with_data_size(1024)
+            .max_immutable_tables(2)
+            .wal_enable(false);
+        let kv_store = LsmStore::open_with_config(config).await?;
+
+        let mut max_pending = 0;
+        for i in 0..2000 {
+            kv_store.set(format!("key{:05}", i).as_bytes(), vec![b'v'; 100]).await?;
+            max_pending = max_pending.max(kv_store.pending_immutable_tables());
+        }
+        // 待落盘的ImmutableMemTable达到上限后写入被阻塞，而不会超出上限
+        assert_eq!(max_pending, 2);
+
+        kv_store.flush().await?;
+        assert_eq!(kv_store.pending_immutable_tables(), 0);
+        assert!(!kv_store.is_backpressure());
+        for i in 0..2000 {
+            assert_eq!(kv_store.get(format!("key{:05}", i).as_bytes()).await?, Some(vec![b'v'; 100]));
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_kv_separation() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let config = Config::default()
+            .dir_path(temp_dir.path().join("separation"))
+            .kv_separation_enable(true)
+            .value_log_file_size(64 * 1024)
+            .wal_enable(false);
+        let kv_store = LsmStore::open_with_config(config).await?;
+
+        for i in 0..200 {
+            kv_store.set(format!("key{:05}", i).as_bytes(), vec![b'1'; 4096]).await?;
+        }
+        kv_store.flush().await?;
+        // 覆盖写入后旧vLog文件中的数据全部失效，vLog切换文件时会被回收
+        for i in 0..200 {
+            kv_store.set(format!("key{:05}", i).as_bytes(), vec![b'2'; 4096]).await?;
+        }
+        kv_store.flush().await?;
+        assert!(kv_store.value_log().size_of_disk().await? < 2 * 200 * 4096);
+        // 覆盖一半的数据，GC时剩余有效的value会被重写
+        for i in 0..100 {
+            kv_store.set(format!("key{:05}", i).as_bytes(), vec![b'3'; 4096]).await?;
+        }
+        kv_store.flush().await?;
+        kv_store.value_log_gc_sync().await?;
+        assert!(kv_store.value_log().size_of_disk().await? < 2 * 200 * 4096);
+
+        let check = |kv_store: LsmStore| async move {
+            for i in 0..200 {
+                let value = if i < 100 { vec![b'3'; 4096] } else { vec![b'2'; 4096] };
+                assert_eq!(kv_store.get(format!("key{:05}", i).as_bytes()).await?, Some(value));
+            }
+            let vec_kv = kv_store.scan(b"key00000", b"key99999", usize::MAX).await?;
+            assert_eq!(vec_kv.len(), 200);
+            assert_eq!(vec_kv[199].1, vec![b'2'; 4096]);
+            Ok::<LsmStore, KvsError>(kv_store)
+        };
+        drop(check(kv_store).await?);
+        // 重启后仍可通过vLog读取value
+        let kv_store = LsmStore::open_with_config(Config::default()
+            .dir_path(temp_dir.path().join("separation"))
+            .wal_enable(false)
+        ).await?;
+        let _ignore = check(kv_store).await?;
+
+        // 大value负载下，Key-Value分离使SSTable中的数据量，即压缩时的写入量大幅下降
+        let mut vec_sst_size = Vec::new();
+        for (dir_name, kv_separation_enable) in [("with_separation", true), ("without_separation", false)] {
+            let kv_store = LsmStore::open_with_config(Config::default()
+                .dir_path(temp_dir.path().join(dir_name))
+                .kv_separation_enable(kv_separation_enable)
+                .minor_threshold_with_data_size(256 * 1024)
+                .level_sst_magnification(2)
+                .wal_enable(false)
+            ).await?;
+            for i in 0..1000 {
+                kv_store.set(format!("key{:05}", i).as_bytes(), vec![b'v'; 4096]).await?;
+            }
+            kv_store.flush().await?;
+            vec_sst_size.push(kv_store.manifest().read().await
+                .ss_tables_map.values()
+                .map(|ss_table| ss_table.get_size_of_disk())
+                .sum::<u64>());
+        }
+        assert!(vec_sst_size[0] * 10 < vec_sst_size[1]);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_value_compression() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let value = |i: usize, value_len: usize| format!("{:05}", i).repeat(value_len / 5).into_bytes();
+
+        // 分别以大value与小value写入，对比开启压缩与否时SSTable的大小
+        for value_len in [4096, 100] {
+            let mut vec_sst_size = Vec::new();
+            for option_threshold in [Some(1024), None] {
+                let dir_path = temp_dir.path().join(format!("{value_len}_{}", option_threshold.is_some()));
+                let mut config = Config::default()
+                    .dir_path(dir_path.clone())
+                    .wal_enable(false);
+                if let Some(threshold) = option_threshold {
+                    config = config.value_compression_threshold(threshold);
+                }
+                let kv_store = LsmStore::open_with_config(config).await?;
+                for i in 0..500 {
+                    kv_store.set(format!("key{:05}", i).as_bytes(), value(i, value_len)).await?;
+                }
+                kv_store.flush().await?;
+                vec_sst_size.push(kv_store.manifest().read().await
+                    .ss_tables_map.values()
+                    .map(|ss_table| ss_table.get_size_of_disk())
+                    .sum::<u64>());
+                drop(kv_store);
+
+                // 重启后从SSTable中读取的数据不变
+                let kv_store = LsmStore::open_with_config(Config::default()
+                    .dir_path(dir_path)
+                    .wal_enable(false)
+                ).await?;
+                for i in 0..500 {
+                    assert_eq!(kv_store.get(format!("key{:05}", i).as_bytes()).await?, Some(value(i, value_len)));
+                }
+                let vec_kv = kv_store.scan(b"key00000", b"key99999", usize::MAX).await?;
+                assert_eq!(vec_kv.len(), 500);
+                assert_eq!(vec_kv[499].1, value(499, value_len));
+            }
+            if value_len > 1024 {
+                assert!(vec_sst_size[0] * 4 < vec_sst_size[1]);
+            } else {
+                assert_eq!(vec_sst_size[0], vec_sst_size[1]);
+            }
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_open_with_corrupted_ss_table() -> Result<()> {
+    use std::fs::OpenOptions;
+    use std::io::{Seek, SeekFrom, Write};
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        // 关闭wal使损坏的SSTable无法恢复
+        let config = Config::default()
+            .dir_path(temp_dir.path().to_path_buf())
+            .wal_enable(false);
+        let kv_store = LsmStore::open_with_config(config).await?;
+        for i in 0..100 {
+            kv_store.set(format!("key{:05}", i).as_bytes(), vec![b'v'; 100]).await?;
+        }
+        kv_store.flush().await?;
+        drop(kv_store);
+
+        let gen = sorted_gen_list(temp_dir.path())?[0];
+        let mut file = OpenOptions::new()
+            .write(true)
+            .open(log_path(temp_dir.path(), gen))?;
+        let _ignore = file.seek(SeekFrom::Start(10))?;
+        file.write_all(&[0; 8])?;
+        file.flush()?;
+
+        let result = LsmStore::open_with_config(Config::default()
+            .dir_path(temp_dir.path().to_path_buf())
+            .wal_enable(false)
+        ).await;
+        match result {
+            Err(KvsError::CorruptedFile { gen: corrupted_gen, .. }) => assert_eq!(corrupted_gen, gen),
+            other => panic!("unexpected result: {:?}", other.map(|_| ()))
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_migrate_on_open() -> Result<()> {
+    use tempfile::TempDir;
+    use crate::kernel::FormatVersion;
+    use crate::kernel::migrator::MIGRATING_DIR_NAME;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let path = temp_dir.path().to_path_buf();
+        let config = Config::default().dir_path(path.clone());
+
+        // 以旧格式构建Level 0与Level 1的SSTable，Level 0中的数据覆盖Level 1中的偶数Key
+        let io_handler_factory = IOHandlerFactory::new_with_format_version(path.clone(), FormatVersion::V0);
+        for (level, round) in [(1, 0_u8), (0, 1)] {
+            let vec_cmd_data = (0..100_u32)
+                .filter(|i| level == 1 || i % 2 == 0)
+                .map(|i| CommandData::set(i.to_be_bytes().to_vec(), vec![round; 100]))
+                .collect_vec();
+            let gen = config.create_gen();
+            let io_handler = io_handler_factory.create(gen)?;
+            let _ignore = SsTable::create_for_immutable_table(&config, io_handler, vec_cmd_data, level, gen as u64).await?;
+        }
+        // 以旧格式写入Wal日志
+        let wal_path = path.join(DEFAULT_WAL_PATH);
+        fs::create_dir_all(&wal_path)?;
+        let wal_io_handler = IOHandlerFactory::new_with_format_version(wal_path.clone(), FormatVersion::V0).create(1)?;
+        let _ignore = CommandPackage::write(&wal_io_handler, &CommandData::set(b"wal".to_vec(), b"value".to_vec())).await?;
+        wal_io_handler.flush().await?;
+
+        let kv_store = LsmStore::open_with_config(Config::default()
+            .dir_path(path.clone())
+            .migrate_on_open(true)
+        ).await?;
+        {
+            let manifest = kv_store.manifest.read().await;
+            assert_eq!(manifest.get_vec_ss_table_with_level(0).len(), 1);
+            assert_eq!(manifest.get_vec_ss_table_with_level(1).len(), 1);
+            for level in 0..2 {
+                assert!(manifest.get_vec_ss_table_with_level(level).iter()
+                    .all(|ss_table| ss_table.get_version() == FormatVersion::CURRENT.as_u64()));
+            }
+        }
+        for i in 0..100_u32 {
+            let round = u8::from(i % 2 == 0);
+            assert_eq!(kv_store.get(&i.to_be_bytes()).await?, Some(vec![round; 100]));
+        }
+        assert_eq!(kv_store.wal.get(b"wal").await?, Some(b"value".to_vec()));
+        assert_eq!(FormatVersion::load_or_init(&wal_path)?, FormatVersion::CURRENT);
+        assert!(!path.join(MIGRATING_DIR_NAME).exists());
+        assert!(!wal_path.join(MIGRATING_DIR_NAME).exists());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_warm_up() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let config = Config::default()
+            .dir_path(temp_dir.into_path())
+            .wal_enable(false);
+        let kv_store = LsmStore::open_with_config(config).await?;
+
+        for i in 0..10000 {
+            kv_store.set(format!("key{:05}", i).as_bytes(), vec![b'v'; 100]).await?;
+        }
+        kv_store.flush().await?;
+        // 以其他数据替换ImmutableMemTable，使Key只能从SSTable读取
+        kv_store.set(b"other", vec![b'v']).await?;
+        kv_store.flush().await?;
+        kv_store.wait_for_compression_down().await?;
+
+        let vec_key = (0..10000).step_by(500)
+            .map(|i| format!("key{:05}", i).into_bytes())
+            .collect_vec();
+        kv_store.warm_up(&vec_key.iter().map(Vec::as_slice).collect_vec()).await?;
+
+        // 预热后的get命中缓存，不产生读盘
+        let read_count = kv_store.io_handler_factory().read_count();
+        for key in vec_key.iter() {
+            assert_eq!(kv_store.get(key).await?, Some(vec![b'v'; 100]));
+        }
+        assert_eq!(kv_store.io_handler_factory().read_count(), read_count);
+
+        // 未预热的Key仍需读盘
+        assert_eq!(kv_store.get(b"key09999").await?, Some(vec![b'v'; 100]));
+        assert!(kv_store.io_handler_factory().read_count() > read_count);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_stats() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let config = Config::default()
+            .dir_path(temp_dir.into_path())
+            .minor_threshold_with_data_size(1024)
+            .sst_file_size(16 * 1024)
+            .level_sst_magnification(1)
+            .wal_enable(false);
+        let kv_store = LsmStore::open_with_config(config).await?;
+
+        // Key长度均为8，value长度一半为100，一半为1000
+        for i in 0..1000 {
+            let value_len = if i % 2 == 0 { 100 } else { 1000 };
+            kv_store.set(format!("key{:05}", i).as_bytes(), vec![b'v'; value_len]).await?;
+        }
+        kv_store.flush().await?;
+        kv_store.major_compaction_sync(0).await?;
+        assert!(!kv_store.manifest.read().await.get_vec_ss_table_with_level(1).is_empty());
+
+        let stats = kv_store.stats().await;
+        let key_size_histogram = stats.key_size_histogram();
+        let value_size_histogram = stats.value_size_histogram();
+
+        assert_eq!(key_size_histogram.total(), 1000);
+        assert_eq!(key_size_histogram.count_with_size(8), 1000);
+        assert_eq!(value_size_histogram.total(), 1000);
+        // 100处于[64, 128)，1000处于[512, 1024)
+        assert_eq!(value_size_histogram.count_with_size(100), 500);
+        assert_eq!(value_size_histogram.count_with_size(64), 500);
+        assert_eq!(value_size_histogram.count_with_size(1000), 500);
+        assert_eq!(value_size_histogram.buckets().len(), 11);
+        // 直方图仅保留至最后一个非空的桶，序列化后体积很小
+        assert!(rmp_serde::to_vec(value_size_histogram)?.len() < 32);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_compaction_stats() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let config = Config::default()
+            .dir_path(temp_dir.path().to_path_buf())
+            .level_sst_magnification(1)
+            .wal_enable(false);
+        let kv_store = LsmStore::open_with_config(config).await?;
+
+        // 第一次落盘后Level 0仅有一个SSTable，未达到Major压缩阈值
+        for i in (0..1000).filter(|i| i % 2 == 0) {
+            kv_store.set(format!("key{:05}", i).as_bytes(), vec![b'v'; 100]).await?;
+        }
+        kv_store.flush().await?;
+        let compaction_stats = kv_store.stats().await.compaction_stats();
+        assert_eq!(compaction_stats.minor_count(), 1);
+        assert_eq!(compaction_stats.count(), 0);
+        assert_eq!(compaction_stats.reclaimed_bytes(), 0);
+
+        // 第二次落盘后Level 0的两个SSTable被压缩为Level 1的一个SSTable
+        for i in (0..1000).filter(|i| i % 2 == 1) {
+            kv_store.set(format!("key{:05}", i).as_bytes(), vec![b'v'; 100]).await?;
+        }
+        kv_store.flush().await?;
+        let compaction_stats = kv_store.stats().await.compaction_stats();
+        assert_eq!(compaction_stats.minor_count(), 2);
+        assert_eq!(compaction_stats.count(), 1);
+
+        // 无需压缩的Level不计入压缩次数
+        kv_store.major_compaction_sync(1).await?;
+        let compaction_stats = kv_store.stats().await.compaction_stats();
+        assert_eq!(compaction_stats.minor_count(), 2);
+        assert_eq!(compaction_stats.count(), 1);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_amplification() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let config = Config::default()
+            .dir_path(temp_dir.path().to_path_buf())
+            .minor_threshold_with_data_size(64 * 1024)
+            .level_sst_magnification(1)
+            .wal_enable(false);
+        let kv_store = LsmStore::open_with_config(config).await?;
+
+        let amplification = kv_store.stats().await.amplification();
+        assert_eq!(amplification, Amplification::default());
+        assert!(amplification.read_amplification() < f64::EPSILON);
+        assert!(amplification.write_amplification() < f64::EPSILON);
+
+        // 1000个Key长度为8、value长度为1000的键值对，写入期间多次落盘并触发Major压缩
+        for i in 0..1000 {
+            kv_store.set(format!("key{:05}", i).as_bytes(), vec![b'v'; 1000]).await?;
+        }
+        kv_store.minor_compaction_sync().await?;
+        kv_store.major_compaction_sync(0).await?;
+
+        let amplification = kv_store.stats().await.amplification();
+        // 每条Set的估算大小为Key与value的长度之和再加上命令的编码开销
+        assert_eq!(amplification.user_written_bytes(), 1000 * (1008 + 10));
+        // 每个字节至少落盘一次，且SSTable的索引与编码开销有限
+        let write_amplification = amplification.write_amplification();
+        assert!((1.0..10.0).contains(&write_amplification), "write amplification: {write_amplification}");
+
+        // 顺序读取时每个数据段仅从文件中读取一次，读取量略大于value总量
+        for i in 0..1000 {
+            assert_eq!(kv_store.get(format!("key{:05}", i).as_bytes()).await?, Some(vec![b'v'; 1000]));
+        }
+        let amplification = kv_store.stats().await.amplification();
+        assert_eq!(amplification.get_value_bytes(), 1000 * 1000);
+        let read_amplification = amplification.read_amplification();
+        assert!((1.0..1.5).contains(&read_amplification), "read amplification: {read_amplification}");
+
+        // 再次读取时命中position_cache，不产生新的读取
+        for i in 0..1000 {
+            let _ignore = kv_store.get(format!("key{:05}", i).as_bytes()).await?;
+        }
+        let cached_amplification = kv_store.stats().await.amplification();
+        assert_eq!(cached_amplification.get_read_bytes(), amplification.get_read_bytes());
+        assert_eq!(cached_amplification.get_value_bytes(), 2 * 1000 * 1000);
+        assert!(cached_amplification.read_amplification() < read_amplification);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_cipher() -> Result<()> {
+    use tempfile::TempDir;
+    use tokio::io::AsyncReadExt;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let path = temp_dir.path().to_path_buf();
+    let config_with_key = |key: [u8; 32]| Config::default()
+        .dir_path(path.clone())
+        .kv_separation_enable(true)
+        .kv_separation_threshold(512)
+        .cipher(Cipher::new(&key));
+    let value = |i: usize| format!("plaintext_value_{i:04}").repeat(if i % 2 == 0 { 1 } else { 64 }).into_bytes();
+
+    tokio_test::block_on(async {
+        {
+            let kv_store = LsmStore::open_with_config(config_with_key([1; 32])).await?;
+            // 前半部分落盘为SSTable与vLog，后半部分仅存在于Wal中
+            for i in 0..100 {
+                kv_store.set(format!("plaintext_key_{i:04}").as_bytes(), value(i)).await?;
+            }
+            kv_store.minor_compaction_sync().await?;
+            for i in 100..200 {
+                kv_store.set(format!("plaintext_key_{i:04}").as_bytes(), value(i)).await?;
+            }
+            kv_store.flush().await?;
+        }
+
+        // 目录中的所有文件均不包含明文的Key与value
+        let mut vec_dir = vec![path.clone()];
+        let mut file_count = 0;
+        while let Some(dir) = vec_dir.pop() {
+            for entry in fs::read_dir(dir)? {
+                let entry_path = entry?.path();
+                if entry_path.is_dir() {
+                    vec_dir.push(entry_path);
+                } else {
+                    let bytes = fs::read(&entry_path)?;
+                    assert!(!bytes.windows(9).any(|window| window == b"plaintext"), "plaintext found in {entry_path:?}");
+                    file_count += 1;
+                }
+            }
+        }
+        assert!(file_count > 0);
+
+        // 密钥错误或未设置密钥时无法开启，且不会删除数据
+        assert!(matches!(LsmStore::open_with_config(config_with_key([2; 32])).await, Err(KvsError::CipherError)));
+        let config_without_key = Config::default()
+            .dir_path(path.clone())
+            .kv_separation_enable(true)
+            .kv_separation_threshold(512);
+        assert!(matches!(LsmStore::open_with_config(config_without_key).await, Err(KvsError::CipherError)));
+
+        let kv_store = LsmStore::open_with_config(config_with_key([1; 32])).await?;
+        for i in 0..200 {
+            assert_eq!(kv_store.get(format!("plaintext_key_{i:04}").as_bytes()).await?, Some(value(i)));
+        }
+        // 加密后的value无法流式读取，退化为完整读取
+        let mut reader = kv_store.get_reader(b"plaintext_key_0001").await?
+            .expect("key not found");
+        let mut vec_value = Vec::new();
+        let _ignore = reader.read_to_end(&mut vec_value).await?;
+        assert_eq!(vec_value, value(1));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_open_locked() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let kv_store = LsmStore::open(temp_dir.path()).await?;
+        kv_store.set(b"key", b"value".to_vec()).await?;
+
+        // 目录被占用时第二次开启被拒绝
+        assert!(matches!(LsmStore::open(temp_dir.path()).await, Err(KvsError::AlreadyOpen)));
+        // 快照位于独立的目录，不受其影响
+        let snapshot = LsmStore::open_snapshot(temp_dir.path()).await?;
+        drop(snapshot);
+
+        // Drop后锁被释放，可再次开启
+        kv_store.flush().await?;
+        drop(kv_store);
+        let kv_store = LsmStore::open(temp_dir.path()).await?;
+        assert_eq!(kv_store.get(b"key").await?, Some(b"value".to_vec()));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_open_snapshot_with_in_flight_minor_compaction() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let kv_store = LsmStore::open(temp_dir.path()).await?;
+        for i in 0..100 {
+            let key = format!("key{i:03}").into_bytes();
+            kv_store.set(&key, key.clone()).await?;
+        }
+        kv_store.flush().await?;
+
+        // 以已完成SSTable的前半部分模拟Minor压缩进行中、尚未写完的SSTable
+        let complete_gen = sorted_gen_list(temp_dir.path())?[0];
+        let bytes = fs::read(log_path(temp_dir.path(), complete_gen))?;
+        let in_flight_gen = Config::default().create_gen();
+        fs::write(log_path(temp_dir.path(), in_flight_gen), &bytes[..bytes.len() / 2])?;
+
+        let snapshot = LsmStore::open_snapshot(temp_dir.path()).await?;
+        let snapshot_gens = sorted_gen_list(&snapshot.snapshot_path)?;
+        assert!(snapshot_gens.contains(&complete_gen));
+        assert!(!snapshot_gens.contains(&in_flight_gen));
+        for i in 0..100 {
+            let key = format!("key{i:03}").into_bytes();
+            assert_eq!(snapshot.get(&key).await?, Some(key));
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_open_snapshot_with_config() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let config = || Config::default()
+        .dir_path(temp_dir.path().to_path_buf())
+        .level_dir_path(1, temp_dir.path().join("level_1"))
+        .level_sst_magnification(1)
+        .cipher(Cipher::new(&[7; 32]));
+
+    tokio_test::block_on(async move {
+        let kv_store = LsmStore::open_with_config(config()).await?;
+        // 暂停文件删除使压缩过期的SSTable保留于待删除列表中
+        kv_store.pause_file_deletion().await?;
+        for round in 0..2_u8 {
+            for i in 0..100 {
+                kv_store.set(format!("key{i:03}").as_bytes(), vec![round; 100]).await?;
+            }
+            kv_store.flush().await?;
+            kv_store.major_compaction_sync(LEVEL_0).await?;
+        }
+        let vec_live_gen = kv_store.manifest.read().await
+            .ss_tables_map.keys()
+            .copied()
+            .sorted()
+            .collect_vec();
+        assert!(!sorted_gen_list(&temp_dir.path().join("level_1"))?.is_empty());
+
+        // 快照仅包含各Level目录中现存的SSTable
+        let snapshot = LsmStore::open_snapshot_with_config(config()).await?;
+        assert_eq!(sorted_gen_list(&snapshot.snapshot_path)?, vec_live_gen);
+        for i in 0..100 {
+            assert_eq!(snapshot.get(format!("key{i:03}").as_bytes()).await?, Some(vec![1; 100]));
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_open_with_active_writer() -> Result<()> {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use tempfile::TempDir;
+    use crate::kernel::dir_lock::{active_writer, DEFAULT_HEARTBEAT_EXPIRE, HEARTBEAT_FILE_NAME};
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let heartbeat_path = temp_dir.path().join(HEARTBEAT_FILE_NAME);
+        let now_millis = SystemTime::now().duration_since(UNIX_EPOCH)
+            .expect("system time before unix epoch")
+            .as_millis() as u64;
+        fs::create_dir_all(temp_dir.path())?;
+
+        // 模拟不占用LOCK文件但仍在更新心跳的写入者
+        fs::write(&heartbeat_path, format!("{} {}", u32::MAX, now_millis))?;
+        assert_eq!(active_writer(temp_dir.path(), DEFAULT_HEARTBEAT_EXPIRE)?, Some(u32::MAX));
+        // 设置拒绝时开启失败
+        assert!(matches!(
+            LsmStore::open_with_config(Config::default().dir_path(temp_dir.path().to_path_buf()).reject_active_writer(true)).await,
+            Err(KvsError::ActiveWriter(u32::MAX))
+        ));
+        // 默认仅警告，开启心跳时由自身接管心跳
+        let kv_store = LsmStore::open_with_config(Config::default().dir_path(temp_dir.path().to_path_buf()).heartbeat(true)).await?;
+        assert_eq!(active_writer(temp_dir.path(), DEFAULT_HEARTBEAT_EXPIRE)?, Some(std::process::id()));
+        // Wal等内部的目录不更新心跳
+        assert!(!temp_dir.path().join(DEFAULT_WAL_PATH).join(HEARTBEAT_FILE_NAME).exists());
+        kv_store.set(b"key", b"value".to_vec()).await?;
+        kv_store.flush().await?;
+        // 正常关闭时删除心跳文件
+        drop(kv_store);
+        assert!(!heartbeat_path.exists());
+
+        // 过期的心跳文件视为崩溃后的残留，不影响开启
+        let expire_millis = DEFAULT_HEARTBEAT_EXPIRE.as_millis() as u64;
+        fs::write(&heartbeat_path, format!("{} {}", u32::MAX, now_millis - 2 * expire_millis))?;
+        assert_eq!(active_writer(temp_dir.path(), DEFAULT_HEARTBEAT_EXPIRE)?, None);
+        let kv_store = LsmStore::open_with_config(Config::default().dir_path(temp_dir.path().to_path_buf()).reject_active_writer(true)).await?;
+        assert_eq!(kv_store.get(b"key").await?, Some(b"value".to_vec()));
+        // 默认不开启心跳
+        assert_eq!(active_writer(temp_dir.path(), DEFAULT_HEARTBEAT_EXPIRE)?, None);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_strict_remove() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        for strict_remove in [true, false] {
+            let kv_store = LsmStore::open_with_config(Config::default()
+                .dir_path(temp_dir.path().join(format!("strict_{strict_remove}")))
+                .strict_remove(strict_remove)
+            ).await?;
+            kv_store.set(b"key1", b"value1".to_vec()).await?;
+
+            let result = kv_store.remove(b"key2").await;
+            let batch_result = kv_store.write_batch_atomic(vec![
+                CommandData::set(b"key3".to_vec(), b"value3".to_vec()),
+                CommandData::remove(b"key2".to_vec()),
+            ]).await;
+            if strict_remove {
+                assert!(matches!(result, Err(KvsError::KeyNotFound)));
+                assert!(matches!(batch_result, Err(KvsError::KeyNotFound)));
+                assert_eq!(kv_store.get(b"key3").await?, None);
+            } else {
+                // 删除不存在的Key幂等成功，且不影响其余数据
+                assert!(result.is_ok());
+                assert!(batch_result.is_ok());
+                kv_store.remove(b"key2").await?;
+                assert_eq!(kv_store.get(b"key2").await?, None);
+                assert_eq!(kv_store.get(b"key3").await?, Some(b"value3".to_vec()));
+            }
+            // 存在的Key总是可以被删除，空Key总是返回错误
+            kv_store.remove(b"key1").await?;
+            assert_eq!(kv_store.get(b"key1").await?, None);
+            assert!(matches!(kv_store.remove(&[]).await, Err(KvsError::DataEmpty)));
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_validate_key() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let kv_store = LsmStore::open_with_config(Config::default()
+            .dir_path(temp_dir.into_path())
+            .validate_key(|key| {
+                if key.starts_with(b"user:") {
+                    Ok(())
+                } else {
+                    Err(KvsError::InvalidKey(String::from_utf8_lossy(key).into_owned()))
+                }
+            })
+        ).await?;
+
+        kv_store.set(b"user:1", b"value".to_vec()).await?;
+        assert!(matches!(kv_store.set(b"1", b"value".to_vec()).await, Err(KvsError::InvalidKey(key)) if key == "1"));
+        assert!(matches!(
+            kv_store.set_batch(vec![(b"user:2".to_vec(), b"value".to_vec()), (b"2".to_vec(), b"value".to_vec())]).await,
+            Err(KvsError::InvalidKey(_))
+        ));
+        assert!(matches!(kv_store.remove(b"1").await, Err(KvsError::InvalidKey(_))));
+        assert!(matches!(
+            kv_store.write_batch_atomic(vec![CommandData::set(b"user:3".to_vec(), b"value".to_vec()), CommandData::remove(b"3".to_vec())]).await,
+            Err(KvsError::InvalidKey(_))
+        ));
+        // 空Key仍优先返回DataEmpty
+        assert!(matches!(kv_store.set(&[], b"value".to_vec()).await, Err(KvsError::DataEmpty)));
+
+        // 校验失败时不写入任何数据
+        assert_eq!(kv_store.get(b"user:1").await?, Some(b"value".to_vec()));
+        assert_eq!(kv_store.get(b"user:2").await?, None);
+        assert_eq!(kv_store.get(b"user:3").await?, None);
+        assert_eq!(kv_store.get(b"1").await?, None);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_prefix_bloom() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let mut vec_read_count = Vec::new();
+        for (index, config) in [Config::default(), Config::default().prefix_bloom_len(2)].into_iter().enumerate() {
+            let config = config
+                .dir_path(temp_dir.path().join(index.to_string()))
+                .wal_enable(false);
+            let kv_store = LsmStore::open_with_config(config).await?;
+
+            // 该SSTable的scope覆盖"b:"前缀，但不含有以其为前缀的Key
+            for i in 0..1000 {
+                kv_store.set(format!("a:{:04}", i).as_bytes(), vec![b'a'; 100]).await?;
+                kv_store.set(format!("c:{:04}", i).as_bytes(), vec![b'c'; 100]).await?;
+            }
+            kv_store.flush().await?;
+            for i in 0..1000 {
+                kv_store.set(format!("b:{:04}", i).as_bytes(), vec![b'b'; 100]).await?;
+            }
+            kv_store.flush().await?;
+            // 以其他数据替换ImmutableMemTable，使数据只能从SSTable读取
+            kv_store.set(b"other", vec![b'v']).await?;
+            kv_store.flush().await?;
+            kv_store.wait_for_compression_down().await?;
+
+            let read_count = kv_store.io_handler_factory().read_count();
+            let vec_kv = kv_store.prefix_scan(b"b:", 2000).await?;
+            vec_read_count.push(kv_store.io_handler_factory().read_count() - read_count);
+
+            assert_eq!(vec_kv, (0..1000)
+                .map(|i| (format!("b:{:04}", i).into_bytes(), vec![b'b'; 100]))
+                .collect_vec());
+            assert_eq!(kv_store.prefix_scan(b"b:00", 2000).await?.len(), 100);
+            assert!(kv_store.prefix_scan(b"d:", 2000).await?.is_empty());
+
+            kv_store.set(b"\xff\xffkey", vec![b'v']).await?;
+            assert_eq!(kv_store.prefix_scan(b"\xff", 10).await?, vec![(b"\xff\xffkey".to_vec(), vec![b'v'])]);
+        }
+        // 前缀布隆过滤器跳过了不相关的SSTable，读盘次数更少
+        assert!(vec_read_count[1] < vec_read_count[0]);
+
+        // 前缀末尾的0xFF被跳过，全为0xFF时不存在上界
+        assert_eq!(prefix_end(b"ab\xff"), Some(b"ac".to_vec()));
+        assert_eq!(prefix_end(b"\xff\xff"), None);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_clean_orphan_ss_tables() -> Result<()> {
+    use tempfile::TempDir;
+    use crate::kernel::lsm::{PENDING_DELETE_FILE_NAME, record_pending_delete};
+
+    async fn write_round(kv_store: &LsmStore, round: u8) -> Result<()> {
+        for i in 0..100 {
+            kv_store.set(format!("key{}", i).as_bytes(), vec![round; 100]).await?;
+        }
+        kv_store.flush().await?;
+        kv_store.major_compaction_sync(0).await
+    }
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let path = temp_dir.path().to_path_buf();
+        let config = || Config::default()
+            .dir_path(path.clone())
+            .level_sst_magnification(1)
+            .wal_enable(false);
+        let vec_expired_gen = {
+            let kv_store = LsmStore::open_with_config(config()).await?;
+            for round in 0..3 {
+                write_round(&kv_store, round).await?;
+            }
+            // 保存压缩前所有SSTable的文件，压缩后被删除的文件作为删除中途崩溃而残留的孤儿文件
+            let vec_gen_with_bytes = kv_store.manifest.read().await
+                .ss_tables_map.keys()
+                .map(|gen| Ok((*gen, fs::read(log_path(&path, *gen))?)))
+                .collect::<Result<Vec<_>>>()?;
+            write_round(&kv_store, 3).await?;
+            let vec_expired_gen_with_bytes = {
+                let manifest = kv_store.manifest.read().await;
+                vec_gen_with_bytes.into_iter()
+                    .filter(|(gen, _)| !manifest.ss_tables_map.contains_key(gen))
+                    .collect_vec()
+            };
+            assert!(!vec_expired_gen_with_bytes.is_empty());
+            assert!(!path.join(PENDING_DELETE_FILE_NAME).exists());
+
+            // 待删除列表已落盘而文件尚未删除
+            let vec_expired_gen = vec_expired_gen_with_bytes.iter()
+                .map(|(gen, _)| *gen)
+                .collect_vec();
+            record_pending_delete(&path, &vec_expired_gen)?;
+            for (gen, bytes) in vec_expired_gen_with_bytes {
+                fs::write(log_path(&path, gen), bytes)?;
+            }
+            vec_expired_gen
+        };
+
+        // 重新开启时清除孤儿文件，不作为有效数据恢复
+        let kv_store = LsmStore::open_with_config(config()).await?;
+        assert!(vec_expired_gen.iter().all(|gen| !log_path(&path, *gen).exists()));
+        assert!(!path.join(PENDING_DELETE_FILE_NAME).exists());
+        kv_store.check_consistency().await?;
+        for i in 0..100 {
+            assert_eq!(kv_store.get(format!("key{}", i).as_bytes()).await?, Some(vec![3; 100]));
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_background_verify() -> Result<()> {
+    use std::fs::OpenOptions;
+    use std::io::{Seek, SeekFrom, Write};
+    use std::sync::atomic::AtomicI64;
+    use tempfile::TempDir;
+
+    static CORRUPTED_GEN: AtomicI64 = AtomicI64::new(0);
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let config = || Config::default()
+            .dir_path(temp_dir.path().to_path_buf())
+            .wal_enable(false);
+        let kv_store = LsmStore::open_with_config(config()).await?;
+        for i in 0..100 {
+            kv_store.set(format!("key{:05}", i).as_bytes(), vec![b'v'; 100]).await?;
+        }
+        kv_store.flush().await?;
+        drop(kv_store);
+
+        let gen = sorted_gen_list(temp_dir.path())?[0];
+        let mut file = OpenOptions::new()
+            .write(true)
+            .open(log_path(temp_dir.path(), gen))?;
+        let _ignore = file.seek(SeekFrom::Start(10))?;
+        file.write_all(&[0; 8])?;
+        file.flush()?;
+
+        // 开启时跳过校验，立即可用
+        let kv_store = LsmStore::open_with_config(config()
+            .background_verify(true)
+            .on_corrupted(|gen| CORRUPTED_GEN.store(gen, Ordering::SeqCst))
+        ).await?;
+        // 后台校验完成前SSTable不会被压缩选中
+        assert!(!kv_store.manifest.read().await.try_select_for_compaction(&[gen]));
+
+        // 等待后台校验上报损坏
+        for _ in 0..500 {
+            if CORRUPTED_GEN.load(Ordering::SeqCst) != 0 {
+                break
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(CORRUPTED_GEN.load(Ordering::SeqCst), gen);
+        assert_eq!(kv_store.corrupted_gens().await, vec![gen]);
+
+        // 查询已隔离的SSTable返回明确的错误
+        assert!(matches!(
+            kv_store.get(b"key00000").await,
+            Err(KvsError::CorruptedFile { gen: corrupted_gen, .. }) if corrupted_gen == gen
+        ));
+        assert!(matches!(
+            kv_store.scan(b"key00000", b"key00100", 10).await,
+            Err(KvsError::CorruptedFile { gen: corrupted_gen, .. }) if corrupted_gen == gen
+        ));
+        // 不在其范围内的Key不受影响
+        assert_eq!(kv_store.get(b"other").await?, None);
+
+        // 已隔离的SSTable不会被压缩合并
+        kv_store.major_compaction_sync(0).await?;
+        assert_eq!(kv_store.manifest.read().await.get_level_vec(0), &[gen]);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_read_repair() -> Result<()> {
+    use std::fs::OpenOptions;
+    use std::io::{Seek, SeekFrom, Write};
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let config = || Config::default()
+            .dir_path(temp_dir.path().to_path_buf())
+            .wal_enable(false);
+        let kv_store = LsmStore::open_with_config(config()).await?;
+        // 旧数据下压至Level 1，新数据位于Level 0，其中key00100仅存在于Level 0
+        for i in 0..100 {
+            kv_store.set(format!("key{:05}", i).as_bytes(), vec![b'o'; 100]).await?;
+        }
+        kv_store.flush().await?;
+        kv_store.major_compaction_sync(0).await?;
+        for i in 0..=100 {
+            kv_store.set(format!("key{:05}", i).as_bytes(), vec![b'n'; 100]).await?;
+        }
+        kv_store.flush().await?;
+        let level_0_gen = kv_store.manifest.read().await
+            .get_level_vec(0)[0];
+        drop(kv_store);
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .open(log_path(temp_dir.path(), level_0_gen))?;
+        let _ignore = file.seek(SeekFrom::Start(10))?;
+        file.write_all(&[0; 8])?;
+        file.flush()?;
+
+        let kv_store = LsmStore::open_with_config(config()
+            .background_verify(true)
+            .read_repair(ReadRepair::Repair)
+        ).await?;
+        for _ in 0..500 {
+            if !kv_store.corrupted_gens().await.is_empty() {
+                break
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(kv_store.corrupted_gens().await, vec![level_0_gen]);
+
+        // 跳过损坏的Level 0，从Level 1读到旧数据
+        assert_eq!(kv_store.get(b"key00000").await?, Some(vec![b'o'; 100]));
+        // 读取后存在其他写入时放弃修复，不会以旧数据覆盖新写入的数据
+        let version = kv_store.mem_table.version();
+        kv_store.set(b"key00002", vec![b'w'; 100]).await?;
+        assert_eq!(kv_store.get_value_for_ss_tables(b"key00002", None, None, Some(version)).await?, Some(vec![b'o'; 100]));
+        assert_eq!(kv_store.get(b"key00002").await?, Some(vec![b'w'; 100]));
+        // 仅存在于损坏的SSTable中的Key无法回退，仍返回错误
+        assert!(matches!(
+            kv_store.get(b"key00100").await,
+            Err(KvsError::CorruptedFile { gen, .. }) if gen == level_0_gen
+        ));
+
+        // 修复的数据落盘后位于比损坏的SSTable更新的SSTable中，不再需要跳过
+        kv_store.flush().await?;
+        let manifest = kv_store.manifest.read().await;
+        assert_eq!(manifest.get_data_for_ss_tables_with_repair(b"key00000", None).await?,
+                   (Some(CommandData::set(b"key00000".to_vec(), vec![b'o'; 100])), vec![]));
+        assert_eq!(manifest.get_data_for_ss_tables_with_repair(b"key00001", None).await?,
+                   (Some(CommandData::set(b"key00001".to_vec(), vec![b'o'; 100])), vec![level_0_gen]));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_merge_operator() -> Result<()> {
+    use tempfile::TempDir;
+
+    // 计数器：将所有操作数累加至已有的value
+    fn add(_key: &[u8], existing: Option<&[u8]>, operands: &[Vec<u8>]) -> Vec<u8> {
+        let to_u64 = |bytes: &[u8]| <[u8; 8]>::try_from(bytes).map_or(0, u64::from_le_bytes);
+
+        operands.iter()
+            .fold(existing.map_or(0, to_u64), |sum, operand| sum + to_u64(operand))
+            .to_le_bytes()
+            .to_vec()
+    }
+
+    async fn merge_round(kv_store: &LsmStore, vec_merge: &[(&str, u64)]) -> Result<()> {
+        for (key, operand) in vec_merge {
+            kv_store.merge(key.as_bytes(), operand.to_le_bytes().to_vec()).await?;
+        }
+        kv_store.flush().await?;
+        kv_store.major_compaction_sync(0).await
+    }
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let path = temp_dir.path().to_path_buf();
+        let config = || Config::default()
+            .dir_path(path.clone())
+            .level_sst_magnification(1)
+            .wal_enable(false)
+            .merge_operator(add);
+        let count = |value: u64| Some(value.to_le_bytes().to_vec());
+        {
+            let kv_store = LsmStore::open_with_config(config()).await?;
+
+            // MemTable中存在旧值时直接折叠
+            kv_store.set(b"counter", 10_u64.to_le_bytes().to_vec()).await?;
+            merge_round(&kv_store, &[("counter", 1)]).await?;
+            assert_eq!(kv_store.get(b"counter").await?, count(11));
+
+            // 多个Merge在压缩时与旧值折叠，不存在旧值时以None合并
+            merge_round(&kv_store, &[("counter", 2), ("counter", 3), ("only_merge", 5)]).await?;
+            assert!(kv_store.scan_level(0).await?.is_empty());
+            assert_eq!(kv_store.scan_level(1).await?, vec![
+                (b"counter".to_vec(), 16_u64.to_le_bytes().to_vec()),
+                (b"only_merge".to_vec(), 5_u64.to_le_bytes().to_vec())
+            ]);
+
+            // 尚未压缩的Merge在读取时与SSTable中的旧值折叠
+            merge_round(&kv_store, &[("counter", 4), ("only_merge", 1)]).await?;
+            assert_eq!(kv_store.get(b"counter").await?, count(20));
+            assert_eq!(kv_store.scan(b"a", b"z", 10).await?, vec![
+                (b"counter".to_vec(), 20_u64.to_le_bytes().to_vec()),
+                (b"only_merge".to_vec(), 6_u64.to_le_bytes().to_vec())
+            ]);
+            kv_store.merge(b"counter", 5_u64.to_le_bytes().to_vec()).await?;
+            assert_eq!(kv_store.get(b"counter").await?, count(25));
+
+            merge_round(&kv_store, &[]).await?;
+            assert!(kv_store.scan_level(0).await?.is_empty());
+            assert_eq!(kv_store.scan_level(1).await?, vec![
+                (b"counter".to_vec(), 25_u64.to_le_bytes().to_vec()),
+                (b"only_merge".to_vec(), 6_u64.to_le_bytes().to_vec())
+            ]);
+        }
+
+        let kv_store = LsmStore::open_with_config(config()).await?;
+        assert_eq!(kv_store.get(b"counter").await?, count(25));
+        assert_eq!(kv_store.get(b"only_merge").await?, count(6));
+        drop(kv_store);
+
+        // 未设置merge_operator时无法写入Merge
+        let kv_store = LsmStore::open_with_config(Config::default()
+            .dir_path(path.clone())
+            .wal_enable(false)
+        ).await?;
+        assert!(matches!(kv_store.merge(b"counter", vec![1]).await, Err(KvsError::MergeOperatorNotSet)));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_merge_operator_with_wal() -> Result<()> {
+    use tempfile::TempDir;
+
+    fn add(_key: &[u8], existing: Option<&[u8]>, operands: &[Vec<u8>]) -> Vec<u8> {
+        let to_u64 = |bytes: &[u8]| <[u8; 8]>::try_from(bytes).map_or(0, u64::from_le_bytes);
+
+        operands.iter()
+            .fold(existing.map_or(0, to_u64), |sum, operand| sum + to_u64(operand))
+            .to_le_bytes()
+            .to_vec()
+    }
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let path = temp_dir.path().to_path_buf();
+        let config = || Config::default()
+            .dir_path(path.clone())
+            .merge_operator(add);
+        let count = |value: u64| Some(value.to_le_bytes().to_vec());
+        {
+            let kv_store = LsmStore::open_with_config(config()).await?;
+            // SSTables中的最新数据为墓碑
+            kv_store.set(b"removed", 100_u64.to_le_bytes().to_vec()).await?;
+            kv_store.remove(b"removed").await?;
+            kv_store.minor_compaction_sync().await?;
+
+            for operand in [1_u64, 2, 3] {
+                kv_store.merge(b"counter", operand.to_le_bytes().to_vec()).await?;
+            }
+            kv_store.merge(b"removed", 7_u64.to_le_bytes().to_vec()).await?;
+        }
+        {
+            // 未落盘的Merge于开启时由Wal恢复，恢复前再次Merge也不会丢失此前的操作数
+            let kv_store = LsmStore::open_with_config(config()).await?;
+            kv_store.merge(b"counter", 4_u64.to_le_bytes().to_vec()).await?;
+            assert_eq!(kv_store.get(b"counter").await?, count(10));
+            assert_eq!(kv_store.get(b"removed").await?, count(7));
+        }
+
+        // Merge无法与Key-Value分离同时开启
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let result = LsmStore::open_with_config(Config::default()
+            .dir_path(temp_dir.path().to_path_buf())
+            .merge_operator(add)
+            .kv_separation_enable(true)
+        ).await;
+        assert!(matches!(result, Err(KvsError::ConfigConflict(_))));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_compaction_strategy() -> Result<()> {
+    use tempfile::TempDir;
+
+    async fn compaction_count(compaction_strategy: CompactionStrategy) -> Result<u64> {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let config = Config::default()
+            .dir_path(temp_dir.path().to_path_buf())
+            .minor_threshold_with_data_size(1024)
+            .sst_file_size(16 * 1024)
+            .level_sst_magnification(1)
+            .compaction_strategy(compaction_strategy)
+            .wal_enable(false);
+        let kv_store = LsmStore::open_with_config(config).await?;
+
+        for i in 0..1000 {
+            kv_store.set(format!("key{:05}", i).as_bytes(), vec![b'v'; 100]).await?;
+        }
+        kv_store.flush().await?;
+
+        // 两种策略下数据均完整可读
+        for i in 0..1000 {
+            assert_eq!(kv_store.get(format!("key{:05}", i).as_bytes()).await?, Some(vec![b'v'; 100]));
+        }
+
+        Ok(kv_store.stats().await.compaction_stats().count())
+    }
+
+    tokio_test::block_on(async move {
+        let leveled_count = compaction_count(CompactionStrategy::Leveled).await?;
+        let size_tiered_count = compaction_count(CompactionStrategy::SizeTiered {
+            size_threshold: 4 * 1024,
+            min_tables: 4
+        }).await?;
+
+        // Size-Tiered需累积多个大小相近的SSTable才合并，因此压缩次数更少
+        assert!(size_tiered_count > 0);
+        assert!(size_tiered_count < leveled_count);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_multi_get() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let config = Config::default()
+            .dir_path(temp_dir.into_path())
+            .wal_enable(false);
+        let kv_store = LsmStore::open_with_config(config).await?;
+
+        for i in 0..10000 {
+            kv_store.set(format!("key{:05}", i).as_bytes(), format!("value{}", i).into_bytes()).await?;
+        }
+        kv_store.flush().await?;
+        // 以范围外的数据替换ImmutableMemTable，使范围内的数据只能从SSTable读取
+        kv_store.set(b"other", vec![b'v']).await?;
+        kv_store.flush().await?;
+        kv_store.set(b"in_mem", vec![b'm']).await?;
+
+        // 乱序且含重复的Key，Key之间相邻而分布于多个数据段之中
+        let vec_key = (0..5000)
+            .map(|i| format!("key{:05}", (i * 7919) % 5000))
+            .chain(["key00001".to_string(), "key99999".to_string(), "in_mem".to_string()])
+            .collect_vec();
+        let keys = vec_key.iter()
+            .map(String::as_bytes)
+            .collect_vec();
+
+        let read_count = kv_store.io_handler_factory().read_count();
+        let vec_option_value = kv_store.multi_get(&keys).await?;
+        let multi_get_read_count = kv_store.io_handler_factory().read_count() - read_count;
+
+        assert_eq!(vec_option_value.len(), keys.len());
+        for (key, option_value) in keys.iter().zip(vec_option_value) {
+            assert_eq!(option_value, kv_store.get(key).await?);
+        }
+        assert_eq!(kv_store.multi_get(&[b"key00002".as_slice(), b"key99999".as_slice()]).await?, vec![Some(b"value2".to_vec()), None]);
+
+        // 逐个get另一半相同数量的相邻Key
+        let read_count = kv_store.io_handler_factory().read_count();
+        for i in 5000..10000 {
+            assert!(kv_store.get(format!("key{:05}", i).as_bytes()).await?.is_some());
+        }
+        let get_read_count = kv_store.io_handler_factory().read_count() - read_count;
+
+        // 相邻的数据段合并为一次读取
+        assert_eq!(multi_get_read_count, 1);
+        assert!(multi_get_read_count < get_read_count);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_overlap_ratio() -> Result<()> {
+    use tempfile::TempDir;
+    use crate::kernel::lsm::compactor::LEVEL_0;
+
+    // 将覆盖整个范围的数据压缩至Level 1后，再以vec_key生成一个Level 0的SSTable
+    async fn level_0_overlap_ratio(vec_key: Vec<String>) -> Result<f64> {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let config = Config::default()
+            .dir_path(temp_dir.path().to_path_buf())
+            .level_sst_magnification(1)
+            .wal_enable(false);
+        let kv_store = LsmStore::open_with_config(config).await?;
+
+        for remainder in 0..2 {
+            for i in (0..10000).filter(|i| i % 2 == remainder) {
+                kv_store.set(format!("key{:05}", i).as_bytes(), vec![b'v'; 100]).await?;
+            }
+            kv_store.flush().await?;
+        }
+        assert!(kv_store.manifest.read().await.get_level_vec(LEVEL_0).is_empty());
+
+        for key in vec_key {
+            kv_store.set(key.as_bytes(), vec![b'v'; 100]).await?;
+        }
+        kv_store.flush().await?;
+        assert_eq!(kv_store.manifest.read().await.get_level_vec(LEVEL_0).len(), 1);
+
+        let ratio = kv_store.stats().await.overlap_ratio(LEVEL_0);
+        assert_eq!(kv_store.manifest.read().await.overlap_ratio(LEVEL_0).to_bits(), ratio.to_bits());
+        Ok(ratio)
+    }
+
+    tokio_test::block_on(async move {
+        // 与Level 1完全不相交
+        let disjoint_ratio = level_0_overlap_ratio((0..100)
+            .map(|i| format!("other{:05}", i))
+            .collect_vec()).await?;
+        // 少量数据覆盖了Level 1的整个范围
+        let full_overlap_ratio = level_0_overlap_ratio((0..100)
+            .map(|i| format!("key{:05}", i * 100))
+            .collect_vec()).await?;
+
+        assert!(disjoint_ratio <= 0.0);
+        assert!(full_overlap_ratio > 1.0);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_tombstone_stats() -> Result<()> {
+    use tempfile::TempDir;
+    use crate::kernel::lsm::compactor::LEVEL_0;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let open_config = || Config::default()
+        .dir_path(temp_dir.path().to_path_buf())
+        .level_sst_magnification(1)
+        .wal_enable(false);
+
+    tokio_test::block_on(async move {
+        let kv_store = LsmStore::open_with_config(open_config()).await?;
+
+        for i in 0..1000 {
+            kv_store.set(format!("key{:05}", i).as_bytes(), vec![b'v'; 100]).await?;
+        }
+        kv_store.minor_compaction_sync().await?;
+        assert!(kv_store.manifest.read().await.last_tombstone_warn.is_none());
+        for i in 0..800 {
+            kv_store.remove(format!("key{:05}", i).as_bytes()).await?;
+        }
+        kv_store.minor_compaction_sync().await?;
+        assert_eq!(kv_store.manifest.read().await.get_level_vec(LEVEL_0).len(), 2);
+        // 落盘后墓碑占比超过阈值时告警，且间隔内不再重复告警
+        let option_last_warn = kv_store.manifest.read().await.last_tombstone_warn;
+        assert!(option_last_warn.is_some());
+        let mut manifest = kv_store.manifest.write().await;
+        manifest.warn_tombstone_ratio(kv_store.config.tombstone_ratio_threshold);
+        assert_eq!(manifest.last_tombstone_warn, option_last_warn);
+        drop(manifest);
+
+        let stats = kv_store.stats().await;
+        assert_eq!(stats.tombstone_count(LEVEL_0), 800);
+        assert_eq!(stats.tombstone_ratio(LEVEL_0).to_bits(), tombstone_ratio(800, 1800).to_bits());
+        assert_eq!(stats.tombstone_count(1), 0);
+        assert!(stats.tombstone_ratio(1) <= 0.0);
+        assert_eq!(stats.tombstone_count(7), 0);
+        drop(kv_store);
+
+        // 墓碑数量随SSTable持久化，重新开启后保持不变
+        let kv_store = LsmStore::open_with_config(open_config()).await?;
+        assert_eq!(kv_store.stats().await.tombstone_count(LEVEL_0), 800);
+
+        // 下层不存在数据时，压缩会丢弃墓碑
+        kv_store.major_compaction_sync(LEVEL_0).await?;
+        let stats = kv_store.stats().await;
+        assert_eq!((0..7).map(|level| stats.tombstone_count(level)).sum::<usize>(), 0);
+        assert!(stats.tombstone_ratio(LEVEL_0) <= 0.0);
+        assert_eq!(kv_store.len().await?, 200);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_default_compaction_selector_with_tombstone() {
+    let config = Config::default()
+        .major_select_file_size(2)
+        .tombstone_ratio_threshold(0.3);
+    let selector = DefaultCompactionSelector;
+
+    // 优先按墓碑占比由高至低选择超过阈值的SSTable
+    assert_eq!(selector.select_with_tombstone_ratios(1, &[1, 2, 3, 4], &[0.0, 0.5, 0.2, 0.9], &config), vec![4, 2]);
+    assert_eq!(selector.select_with_tombstone_ratios(1, &[1, 2, 3, 4], &[0.0, 0.5, 0.2, 0.1], &config), vec![2]);
+    // 不存在超过阈值的SSTable时选择Level开头的SSTable
+    assert_eq!(selector.select_with_tombstone_ratios(1, &[1, 2, 3, 4], &[0.0, 0.1, 0.2, 0.1], &config), vec![1, 2]);
+    // 自定义选择器默认忽略墓碑占比
+    assert_eq!(FixedCompactionSelector::new(vec![0]).select_with_tombstone_ratios(1, &[1, 2], &[0.0, 0.9], &config), vec![1]);
+}
+
+#[test]
+fn test_lsm_checksum_manifest() -> Result<()> {
+    use std::fs::OpenOptions;
+    use std::io::{Read, Seek, SeekFrom, Write};
+    use tempfile::TempDir;
+    use crate::kernel::lsm::CHECKSUM_MANIFEST_FILE_NAME;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let config = || Config::default()
+            .dir_path(temp_dir.path().to_path_buf())
+            .level_sst_magnification(1)
+            .checksum_manifest(true)
+            .wal_enable(false);
+        {
+            let kv_store = LsmStore::open_with_config(config()).await?;
+            // 两次落盘触发Major压缩，清单随SSTable的生成与删除增量更新
+            for remainder in 0..2 {
+                for i in (0..1000).filter(|i| i % 2 == remainder) {
+                    kv_store.set(format!("key{:05}", i).as_bytes(), vec![b'v'; 100]).await?;
+                }
+                kv_store.flush().await?;
+            }
+            assert!(kv_store.stats().await.compaction_stats().count() > 0);
+            assert!(temp_dir.path().join(CHECKSUM_MANIFEST_FILE_NAME).exists());
+        }
+        drop(LsmStore::open_with_config(config()).await?);
+
+        // 外部修改SSTable后开启时报告该SSTable
+        let gen = sorted_gen_list(temp_dir.path())?[0];
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(log_path(temp_dir.path(), gen))?;
+        let mut original = [0; 1];
+        let _ignore = file.seek(SeekFrom::Start(10))?;
+        file.read_exact(&mut original)?;
+        let _ignore = file.seek(SeekFrom::Start(10))?;
+        file.write_all(&[!original[0]])?;
+        file.flush()?;
+        assert!(matches!(
+            LsmStore::open_with_config(config()).await,
+            Err(KvsError::ChecksumManifestMismatch(vec_gen)) if vec_gen == vec![gen]
+        ));
+        // 恢复后校验通过，删除后同样被报告
+        let _ignore = file.seek(SeekFrom::Start(10))?;
+        file.write_all(&original)?;
+        file.flush()?;
+        drop(file);
+        let kv_store = LsmStore::open_with_config(config()).await?;
+        assert_eq!(kv_store.get(b"key00001").await?, Some(vec![b'v'; 100]));
+        drop(kv_store);
+
+        fs::remove_file(log_path(temp_dir.path(), gen))?;
+        assert!(matches!(
+            LsmStore::open_with_config(config()).await,
+            Err(KvsError::ChecksumManifestMismatch(vec_gen)) if vec_gen == vec![gen]
+        ));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_follower() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    let config = || Config::default()
+        .dir_path(temp_dir.path().to_path_buf())
+        .level_dir_path(1, temp_dir.path().join("level_1"))
+        .level_sst_magnification(1)
+        .cipher(Cipher::new(&[7; 32]));
+
+    tokio_test::block_on(async move {
+        let kv_store = LsmStore::open_with_config(config().wal_enable(false)).await?;
+        for i in (0..1000).filter(|i| i % 2 == 0) {
+            kv_store.set(format!("key{:05}", i).as_bytes(), vec![b'v'; 100]).await?;
+        }
+        kv_store.flush().await?;
+
+        // 主进程持有目录锁时仍可开启跟随者，且不会向其目录写入任何文件
+        let version_path = temp_dir.path().join(DEFAULT_VALUE_LOG_PATH).join(FORMAT_VERSION_FILE_NAME);
+        fs::remove_file(&version_path)?;
+        let follower = LsmStore::open_follower_with_config(config()).await?;
+        assert!(!version_path.exists());
+        assert_eq!(follower.get(b"key00000").await?, Some(vec![b'v'; 100]));
+        assert_eq!(follower.get(b"key00001").await?, None);
+
+        // 主进程的新写入在refresh之前不可见
+        // 暂停文件删除使压缩过期的SSTable保留于待删除列表中
+        kv_store.pause_file_deletion().await?;
+        for i in (0..1000).filter(|i| i % 2 == 1) {
+            kv_store.set(format!("key{:05}", i).as_bytes(), vec![b'w'; 100]).await?;
+        }
+        kv_store.remove(b"key00000").await?;
+        kv_store.flush().await?;
+        assert_eq!(follower.get(b"key00001").await?, None);
+
+        // 尚未写入完成的SSTable被跳过
+        fs::write(log_path(temp_dir.path(), 1), [0_u8; 64])?;
+
+        // 压缩后被删除或待删除的SSTable随refresh移除，Level目录中的SSTable被加载
+        assert!(kv_store.stats().await.compaction_stats().count() > 0);
+        assert!(!sorted_gen_list(&temp_dir.path().join("level_1"))?.is_empty());
+        follower.refresh().await?;
+        assert_eq!(follower.ss_table_count().await, kv_store.manifest.read().await.ss_tables_len());
+        assert_eq!(follower.get(b"key00000").await?, None);
+        assert_eq!(follower.get(b"key00001").await?, Some(vec![b'w'; 100]));
+        assert_eq!(follower.get(b"key00002").await?, Some(vec![b'v'; 100]));
+        assert_eq!(follower.scan(b"key00000", b"key00010", 100).await?.len(), 9);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_minor_compaction_retry() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let dir_path = temp_dir.path().join("lsm");
+    let moved_path = temp_dir.path().join("moved");
+
+    tokio_test::block_on(async move {
+        let config = || Config::default()
+            .dir_path(dir_path.clone())
+            .wal_enable(false)
+            .minor_compaction_retries(2)
+            .minor_compaction_retry_interval(Duration::from_millis(10));
+        {
+            let kv_store = LsmStore::open_with_config(config()).await?;
+            for i in 0..100 {
+                kv_store.set(format!("key{:05}", i).as_bytes(), vec![b'v'; 100]).await?;
+            }
+
+            // 移走目录模拟磁盘错误，重试耗尽后返回错误
+            fs::rename(&dir_path, &moved_path)?;
+            assert!(kv_store.minor_compaction_sync().await.is_err());
+            assert_eq!(kv_store.manifest.read().await.ss_tables_len(), 0);
+
+            // 数据被放回MemTable，且失败后的写入较之更新
+            kv_store.set(b"key00000", vec![b'w'; 100]).await?;
+            assert_eq!(kv_store.mem_table.mem_table_len().await, 100);
+            assert_eq!(kv_store.get(b"key00000").await?, Some(vec![b'w'; 100]));
+
+            // 再次交换并落盘失败后数据仍在内存中
+            assert!(kv_store.minor_compaction_sync().await.is_err());
+            assert_eq!(kv_store.mem_table.mem_table_len().await, 100);
+            assert_eq!(kv_store.get(b"key00000").await?, Some(vec![b'w'; 100]));
+            assert_eq!(kv_store.get(b"key00099").await?, Some(vec![b'v'; 100]));
+
+            // 恢复后能够落盘
+            fs::rename(&moved_path, &dir_path)?;
+            kv_store.flush().await?;
+            assert!(kv_store.mem_table.mem_table_is_empty().await);
+            assert_eq!(kv_store.manifest.read().await.ss_tables_len(), 1);
+        }
+
+        let kv_store = LsmStore::open_with_config(config()).await?;
+        assert_eq!(kv_store.get(b"key00000").await?, Some(vec![b'w'; 100]));
+        for i in 1..100 {
+            assert_eq!(kv_store.get(format!("key{:05}", i).as_bytes()).await?, Some(vec![b'v'; 100]));
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_conditional_write_during_failed_minor_compaction() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let dir_path = temp_dir.path().join("lsm");
+    let moved_path = temp_dir.path().join("moved");
+
+    tokio_test::block_on(async move {
+        let kv_store = LsmStore::open_with_config(Config::default()
+            .dir_path(dir_path.clone())
+            .wal_enable(false)
+            .minor_compaction_retries(2)
+            .minor_compaction_retry_interval(Duration::from_millis(10))
+        ).await?;
+        for i in 0..100 {
+            kv_store.set(format!("key{:05}", i).as_bytes(), vec![b'v'; 100]).await?;
+        }
+
+        // 移走目录使后台落盘失败，重试耗尽后需获取MemTable的写入锁将数据放回
+        fs::rename(&dir_path, &moved_path)?;
+        kv_store.minor_compaction().await?;
+        // 判断Key是否存在时等待该落盘结束，期间不能持有MemTable的写入锁
+        let result = tokio::time::timeout(
+            Duration::from_secs(10),
+            kv_store.write_batch_atomic(vec![CommandData::remove(b"absent".to_vec())])
+        ).await;
+        assert!(matches!(result, Ok(Err(KvsError::KeyNotFound))));
+        assert_eq!(kv_store.mem_table.mem_table_len().await, 100);
+        kv_store.minor_compaction().await?;
+        let result = tokio::time::timeout(Duration::from_secs(10), kv_store.remove_and_get(b"absent")).await;
+        assert!(matches!(result, Ok(Ok(None))));
+        kv_store.minor_compaction().await?;
+        let result = tokio::time::timeout(Duration::from_secs(10), kv_store.set_if_absent(b"absent", b"value".to_vec())).await;
+        assert!(matches!(result, Ok(Ok(true))));
+        kv_store.minor_compaction().await?;
+        let result = tokio::time::timeout(Duration::from_secs(10), kv_store.remove_batch(&[b"absent".as_slice(), b"key00001".as_slice()])).await;
+        assert!(matches!(result, Ok(Ok(2))));
+
+        fs::rename(&moved_path, &dir_path)?;
+        kv_store.write_batch_atomic(vec![CommandData::remove(b"key00000".to_vec())]).await?;
+        assert_eq!(kv_store.get(b"key00000").await?, None);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_level_dir_path() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let hot_path = temp_dir.path().join("hot");
+    let cold_path = temp_dir.path().join("cold");
+
+    tokio_test::block_on(async move {
+        let config = || Config::default()
+            .dir_path(hot_path.clone())
+            .level_sst_magnification(1)
+            .level_dir_path(1, cold_path.clone())
+            .wal_enable(false);
+        {
+            let kv_store = LsmStore::open_with_config(config()).await?;
+            // 多次落盘触发Major压缩，将数据推至Level 1
+            for remainder in 0..3 {
+                for i in (0..1000).filter(|i| i % 3 == remainder) {
+                    kv_store.set(format!("key{:05}", i).as_bytes(), vec![b'v'; 100]).await?;
+                }
+                kv_store.flush().await?;
+            }
+
+            let manifest = kv_store.manifest.read().await;
+            assert!(!manifest.get_level_vec(1).is_empty());
+            for level in 0..7 {
+                let dir_path = if level == 0 { &hot_path } else { &cold_path };
+                for gen in manifest.get_level_vec(level) {
+                    assert!(log_path(dir_path, *gen).exists());
+                }
+            }
+            // 被压缩的SSTable已从所在目录中删除
+            assert_eq!(config().sorted_gen_list_with_levels()?.len(), manifest.checkpoint().gens_with_crc().len());
+        }
+
+        // 重新开启时扫描所有目录
+        let kv_store = LsmStore::open_with_config(config()).await?;
+        assert!(!sorted_gen_list(&cold_path)?.is_empty());
+        for i in 0..1000 {
+            assert_eq!(kv_store.get(format!("key{:05}", i).as_bytes()).await?, Some(vec![b'v'; 100]));
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_negative_cache() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        // Position缓存仅保留一个数据段，使重复查询时需要再次读盘
+        let kv_store = LsmStore::open_with_config(Config::default()
+            .dir_path(temp_dir.path().to_path_buf())
+            .cache_size(1)
+            .negative_cache_size(16)
+            .wal_enable(false)).await?;
+        kv_store.set(b"removed", vec![b'v']).await?;
+        kv_store.flush().await?;
+        kv_store.remove(b"removed").await?;
+        kv_store.flush().await?;
+        kv_store.set(b"other", vec![b'o']).await?;
+        kv_store.flush().await?;
+
+        // 首次查询读取SSTable中的墓碑并确认不存在
+        let read_count = kv_store.io_handler_factory.read_count();
+        assert_eq!(kv_store.get(b"removed").await?, None);
+        assert!(kv_store.io_handler_factory.read_count() > read_count);
+        assert_eq!(kv_store.get(b"other").await?, Some(vec![b'o']));
+
+        // 再次查询命中负缓存，不再读盘
+        let read_count = kv_store.io_handler_factory.read_count();
+        assert_eq!(kv_store.get(b"removed").await?, None);
+        assert_eq!(kv_store.io_handler_factory.read_count(), read_count);
+
+        // 写入使负缓存失效
+        kv_store.set(b"removed", vec![b'n']).await?;
+        assert_eq!(kv_store.get(b"removed").await?, Some(vec![b'n']));
+        kv_store.flush().await?;
+        assert_eq!(kv_store.get(b"removed").await?, Some(vec![b'n']));
+        assert_eq!(kv_store.get(b"batch").await?, None);
+        kv_store.set_batch(vec![(b"batch".to_vec(), vec![b'b'])]).await?;
+        assert_eq!(kv_store.get(b"batch").await?, Some(vec![b'b']));
+
+        // 确认不存在后、记录前该Key被写入并落盘时，写入版本已变化，不会被记录
+        let version = kv_store.mem_table.version();
+        kv_store.set(b"racing", vec![b'r']).await?;
+        kv_store.flush().await?;
+        kv_store.mem_table.record_negative(b"racing", version);
+        assert!(!kv_store.mem_table.is_negative(b"racing"));
+        assert_eq!(kv_store.get(b"racing").await?, Some(vec![b'r']));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_flush_buffer() -> Result<()> {
+    use tempfile::TempDir;
+
+    /// 复制此刻目录中的文件，模拟进程崩溃时硬盘上遗留的数据
+    fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let path = entry?.path();
+            if let Some(file_name) = path.file_name() {
+                if path.is_dir() {
+                    copy_dir(&path, &to.join(file_name))?;
+                } else {
+                    let _ignore = fs::copy(&path, to.join(file_name))?;
+                }
+            }
+        }
+        Ok(())
     }
 
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let crash_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let kv_store = LsmStore::open_with_config(Config::default()
+            .dir_path(temp_dir.path().to_path_buf())).await?;
+        for i in 0..100_u32 {
+            kv_store.set(&i.to_be_bytes(), i.to_le_bytes().to_vec()).await?;
+        }
+        kv_store.set_batch((100..200_u32)
+            .map(|i| (i.to_be_bytes().to_vec(), i.to_le_bytes().to_vec()))
+            .collect_vec()).await?;
+
+        // flush_buffer不会生成SSTable
+        kv_store.flush_buffer().await?;
+        assert_eq!(kv_store.manifest.read().await.ss_tables_len(), 0);
+        copy_dir(temp_dir.path(), crash_dir.path())?;
+
+        // 崩溃后重新开启时数据可通过Wal恢复
+        let recovered = LsmStore::open_with_config(Config::default()
+            .dir_path(crash_dir.path().to_path_buf())).await?;
+        for i in 0..200_u32 {
+            assert_eq!(recovered.get(&i.to_be_bytes()).await?, Some(i.to_le_bytes().to_vec()));
+        }
+
+        // set_sync仅fsync Wal，同样不会生成SSTable
+        kv_store.set_sync(b"sync_key", b"sync_value".to_vec()).await?;
+        assert_eq!(kv_store.manifest.read().await.ss_tables_len(), 0);
+        let sync_crash_dir = TempDir::new().expect("unable to create temporary working directory");
+        copy_dir(temp_dir.path(), sync_crash_dir.path())?;
+        let recovered = LsmStore::open_with_config(Config::default()
+            .dir_path(sync_crash_dir.path().to_path_buf())).await?;
+        assert_eq!(recovered.get(b"sync_key").await?, Some(b"sync_value".to_vec()));
+
+        // flush_to_sst则将MemTable持久化为SSTable
+        kv_store.flush_to_sst().await?;
+        assert!(kv_store.mem_table.mem_table_is_empty().await);
+        assert!(kv_store.manifest.read().await.ss_tables_len() > 0);
+
+        Ok(())
+    })
 }
 
 #[test]
-fn test_lsm_major_compactor() -> Result<()> {
+fn test_lsm_adaptive_compaction() -> Result<()> {
+    use tempfile::TempDir;
+    use crate::kernel::lsm::scheduler::DEFAULT_SCHEDULER_SAMPLE_INTERVAL;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let kv_store = LsmStore::open_with_config(Config::default()
+            .dir_path(temp_dir.path().to_path_buf())
+            .minor_threshold_with_data_size(64 * 1024)
+            .adaptive_compaction(true)
+            .adaptive_compaction_max_factor(64)
+            .wal_enable(false)).await?;
+        let decision = kv_store.stats().await.scheduler_decision().unwrap();
+        assert_eq!(decision.minor_threshold(), 64 * 1024);
+        assert_eq!(decision.backlog(), 0);
+
+        // 突发写入后调度器增大落盘阈值
+        for round in 0..3_u32 {
+            for i in 0..500_u32 {
+                kv_store.set(&(round * 500 + i).to_be_bytes(), vec![b'v'; 1024]).await?;
+            }
+            tokio::time::sleep(DEFAULT_SCHEDULER_SAMPLE_INTERVAL).await;
+        }
+        kv_store.set(b"last", vec![b'v']).await?;
+        let decision = kv_store.stats().await.scheduler_decision().unwrap();
+        assert!(decision.write_rate() > 0);
+        assert!(decision.minor_threshold() > 64 * 1024);
+        assert!(decision.minor_threshold() <= 64 * 64 * 1024);
+        assert!(decision.concurrency() >= 1);
+
+        kv_store.flush().await?;
+        for i in 0..1500_u32 {
+            assert_eq!(kv_store.get(&i.to_be_bytes()).await?, Some(vec![b'v'; 1024]));
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_pause_file_deletion() -> Result<()> {
+    use tempfile::TempDir;
+    use crate::kernel::lsm::compactor::LEVEL_0;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let kv_store = LsmStore::open_with_config(Config::default()
+            .dir_path(temp_dir.path().to_path_buf())
+            .wal_enable(false)).await?;
+        for round in 0..3_u32 {
+            for i in 0..100_u32 {
+                kv_store.set(&(round * 100 + i).to_be_bytes(), vec![b'v'; 128]).await?;
+            }
+            kv_store.minor_compaction_sync().await?;
+        }
+        let vec_gen = kv_store.manifest.read().await
+            .get_level_vec(LEVEL_0).clone();
+        assert_eq!(vec_gen.len(), 3);
+
+        kv_store.pause_file_deletion().await?;
+        let vec_file = kv_store.live_files().await?;
+        let vec_ss_table_file = vec_file.iter()
+            .filter(|file| file.kind() == FileKind::SsTable)
+            .collect_vec();
+        assert_eq!(vec_ss_table_file.len(), 3);
+        for file in &vec_ss_table_file {
+            assert!(vec_gen.contains(&file.gen()));
+            assert!(file.size() > 0);
+        }
+        // 同时包括Wal、vLog与ts记录目录的格式版本文件
+        assert_eq!(vec_file.iter().filter(|file| file.kind() == FileKind::FormatVersion).count(), 3);
+
+        // 暂停期间压缩不会删除任何已列出的文件
+        kv_store.major_compaction_sync(LEVEL_0).await?;
+        assert!(kv_store.manifest.read().await.get_level_vec(LEVEL_0).is_empty());
+        for file in &vec_file {
+            assert!(file.path().exists());
+        }
+
+        // 恢复后删除暂停期间过期的SSTable，并移除句柄池中其只读句柄
+        assert!(vec_gen.iter().all(|gen| kv_store.io_handler_factory.is_pooled(*gen)));
+        kv_store.resume_file_deletion().await?;
+        for file in &vec_ss_table_file {
+            assert!(!file.path().exists());
+            assert!(!kv_store.io_handler_factory.is_pooled(file.gen()));
+        }
+        for i in 0..300_u32 {
+            assert_eq!(kv_store.get(&i.to_be_bytes()).await?, Some(vec![b'v'; 128]));
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_get_reader_from_value_log() -> Result<()> {
+    use tokio::io::AsyncReadExt;
     use tempfile::TempDir;
+    use crate::kernel::value_reader::DEFAULT_VALUE_READER_BUFFER_SIZE;
 
     let temp_dir = TempDir::new().expect("unable to create temporary working directory");
 
     tokio_test::block_on(async move {
         let config = Config::default()
-            .dir_path(temp_dir.into_path())
+            .dir_path(temp_dir.path().to_path_buf())
+            .kv_separation_enable(true)
             .wal_enable(false);
         let kv_store = LsmStore::open_with_config(config).await?;
-        let mut vec_key: Vec<Vec<u8>> = Vec::new();
+        let value = (0..4 * 1024 * 1024_usize).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+        kv_store.set(b"big", value.clone()).await?;
+        kv_store.set(b"small", b"value".to_vec()).await?;
 
-        let start = Instant::now();
-        for i in 0..300000 {
-            let vec_u8 = rmp_serde::to_vec(&i).unwrap();
-            kv_store.set(&vec_u8, vec_u8.clone()).await?;
-            vec_key.push(vec_u8);
+        // 位于MemTable时value已在内存中
+        assert_eq!(kv_store.get_reader(b"big").await?.map(|reader| reader.buffer_len()), Some(value.len()));
+        kv_store.flush().await?;
+
+        // 被分离至vLog后分块读取
+        let mut reader = kv_store.get_reader(b"big").await?
+            .expect("value not found");
+        assert_eq!(reader.buffer_len(), DEFAULT_VALUE_READER_BUFFER_SIZE);
+        let mut vec_value = Vec::new();
+        let _ignore = reader.read_to_end(&mut vec_value).await?;
+        assert_eq!(vec_value, value);
+
+        let mut vec_value = Vec::new();
+        let _ignore = kv_store.get_reader(b"small").await?
+            .expect("value not found")
+            .read_to_end(&mut vec_value).await?;
+        assert_eq!(vec_value, b"value");
+        kv_store.remove(b"big").await?;
+        assert!(kv_store.get_reader(b"big").await?.is_none());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_reconfigure() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let config = Config::default()
+            .dir_path(temp_dir.path().to_path_buf())
+            .cache_size(1)
+            .wal_enable(false);
+        let kv_store = LsmStore::open_with_config(config).await?;
+
+        for i in 0..200 {
+            kv_store.set(format!("key{:05}", i).as_bytes(), vec![b'v'; 1000]).await?;
         }
-        kv_store.set(&vec_key[0], vec![b'k']).await?;
-        println!("[set_for][Time: {:?}]", start.elapsed());
+        kv_store.minor_compaction_sync().await?;
 
-        let start = Instant::now();
-        let _ignore = kv_store.get(&rmp_serde::to_vec(&0).unwrap()).await?.unwrap();
-        println!("[compaction_for][Time: {:?}]", start.elapsed());
+        let read_all = || async {
+            for i in 0..200 {
+                assert_eq!(kv_store.get(format!("key{:05}", i).as_bytes()).await?, Some(vec![b'v'; 1000]));
+            }
+            Ok::<u64, KvsError>(kv_store.stats().await.amplification().get_read_bytes())
+        };
 
-        kv_store.flush().await?;
-        let start = Instant::now();
-        assert_eq!(kv_store.get(&vec_key[0]).await?, Some(vec![b'k']));
-        for i in 1..300000 {
-            assert_eq!(kv_store.get(&vec_key[i]).await?, Some(vec_key[i].clone()));
+        // 缓存仅能容纳一个数据段，再次读取时仍需从文件中读取
+        let read_bytes = read_all().await?;
+        assert!(read_all().await? > read_bytes);
+
+        // 扩大缓存后预热一次，之后的读取全部命中缓存
+        kv_store.reconfigure(ConfigPatch::default().cache_size(1024)).await?;
+        let read_bytes = read_all().await?;
+        assert_eq!(read_all().await?, read_bytes);
+
+        kv_store.reconfigure(ConfigPatch::default()
+            .minor_threshold_with_data_size(4096)
+            .major_threshold_with_sst_size(8)
+            .wal_compaction_threshold(4096)
+        ).await?;
+        assert_eq!(kv_store.config.minor_threshold_with_data_size.load(Ordering::Relaxed), 4096);
+        assert_eq!(kv_store.config.major_threshold_with_sst_size.load(Ordering::Relaxed), 8);
+
+        // 不可热更新或不合法的配置项返回错误，且不应用其中任何配置项
+        assert!(matches!(
+            kv_store.reconfigure(ConfigPatch::default().cache_size(1).level_sst_magnification(4)).await,
+            Err(KvsError::ConfigNotReloadable(field)) if field == "level_sst_magnification"
+        ));
+        assert!(matches!(
+            kv_store.reconfigure(ConfigPatch::default().node_id(1)).await,
+            Err(KvsError::ConfigNotReloadable(field)) if field == "node_id"
+        ));
+        assert!(matches!(
+            kv_store.reconfigure(ConfigPatch::default().cache_size(0)).await,
+            Err(KvsError::CacheSizeOverFlow)
+        ));
+        assert_eq!(read_all().await?, read_bytes);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_scan_with_compaction() -> Result<()> {
+    use tempfile::TempDir;
+    use crate::kernel::lsm::compactor::LEVEL_0;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let kv_store = LsmStore::open_with_config(Config::default()
+            .dir_path(temp_dir.path().to_path_buf())
+            .wal_enable(false)).await?;
+        for round in 0..3_u32 {
+            for i in 0..100_u32 {
+                kv_store.set(&(round * 100 + i).to_be_bytes(), vec![b'v'; 128]).await?;
+            }
+            kv_store.minor_compaction_sync().await?;
         }
-        println!("[get_for][Time: {:?}]", start.elapsed());
-        kv_store.flush().await?;
+        let vec_ss_table_file = kv_store.live_files().await?
+            .into_iter()
+            .filter(|file| file.kind() == FileKind::SsTable)
+            .collect_vec();
+        assert_eq!(vec_ss_table_file.len(), 3);
+
+        // 模拟进行中的扫描：获取快照并读取各数据源的首段数据后释放读锁，随后的压缩使快照中的SSTable全部过期
+        let (start, end) = (Bound::Unbounded, Bound::Unbounded);
+        let snapshot = kv_store.manifest.read().await
+            .range_snapshot(start, end)?;
+        let mut vec_source = snapshot.range_sources(start, end);
+        for source in vec_source.iter_mut() {
+            source.load(start, end).await?;
+        }
+        kv_store.major_compaction_sync(LEVEL_0).await?;
+        assert!(kv_store.manifest.read().await.get_level_vec(LEVEL_0).is_empty());
+
+        // 快照中的SSTable文件不会被删除，扫描仍能完整地迭代
+        for file in &vec_ss_table_file {
+            assert!(file.path().exists());
+        }
+        let vec_kv = merge_range_sources(vec_source, &kv_store.value_log, None, start, end, usize::MAX).await?;
+        assert_eq!(vec_kv.len(), 300);
+        for ((key, value), i) in vec_kv.into_iter().zip(0..300_u32) {
+            assert_eq!(key, i.to_be_bytes());
+            assert_eq!(value, vec![b'v'; 128]);
+        }
+
+        // 快照释放后删除其间过期的SSTable文件
+        drop(snapshot);
+        for file in &vec_ss_table_file {
+            assert!(file.path().exists());
+        }
+        kv_store.manifest.read().await
+            .clean_released()?;
+        for file in &vec_ss_table_file {
+            assert!(!file.path().exists());
+        }
+        assert_eq!(kv_store.scan_with_bound(start, end, usize::MAX).await?.len(), 300);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_kv_separation_with_mixed_value() -> Result<()> {
+    use tempfile::TempDir;
+    use crate::kernel::lsm::compactor::LEVEL_0;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let config = || Config::default()
+        .dir_path(temp_dir.path().to_path_buf())
+        .kv_separation_enable(true)
+        .kv_separation_threshold(16)
+        .wal_enable(false);
+    // 偶数Key为短于阈值的小value，奇数Key为大value，恰为阈值长度的value同样写入vLog
+    let value = |i: usize| match i % 4 {
+        0 | 2 => format!("v{i:05}").into_bytes(),
+        1 => format!("{i:05}").repeat(200).into_bytes(),
+        _ => format!("value{i:011}").into_bytes()
+    };
+
+    tokio_test::block_on(async {
+        let check = |kv_store: LsmStore| async move {
+            for i in 0..400 {
+                assert_eq!(kv_store.get(format!("key{:05}", i).as_bytes()).await?, Some(value(i)));
+            }
+            let vec_kv = kv_store.scan(b"key00000", b"key99999", usize::MAX).await?;
+            assert_eq!(vec_kv.len(), 400);
+            for (i, (key, value_i)) in vec_kv.into_iter().enumerate() {
+                assert_eq!(key, format!("key{:05}", i).into_bytes());
+                assert_eq!(value_i, value(i));
+            }
+            Ok::<LsmStore, KvsError>(kv_store)
+        };
+
+        let kv_store = LsmStore::open_with_config(config()).await?;
+        for round in 0..2 {
+            for i in (round..400).step_by(2) {
+                kv_store.set(format!("key{:05}", i).as_bytes(), value(i)).await?;
+            }
+            kv_store.minor_compaction_sync().await?;
+        }
+
+        // 小value内联于SSTable中，大value在SSTable中仅存储指针
+        for i in 0..400 {
+            let cmd_data = kv_store.manifest.read().await
+                .get_data_for_ss_tables(format!("key{:05}", i).as_bytes()).await?;
+            match cmd_data {
+                Some(CommandData::Set { value: inline_value, .. }) => {
+                    assert!(inline_value.len() < 16);
+                    assert_eq!(inline_value, value(i));
+                }
+                Some(CommandData::SetPtr { .. }) => assert!(value(i).len() >= 16),
+                _ => panic!("key{:05} not found in SSTable", i)
+            }
+        }
+        let separated_len = (0..400)
+            .map(value)
+            .filter(|value| value.len() >= 16)
+            .map(|value| value.len() as u64)
+            .sum::<u64>();
+        assert!(kv_store.value_log().size_of_disk().await? >= separated_len);
+        let kv_store = check(kv_store).await?;
+
+        // Major压缩后内联与分离的value均保持不变
+        kv_store.major_compaction_sync(LEVEL_0).await?;
+        drop(check(kv_store).await?);
+
+        // 重启后仍能读取
+        let _ignore = check(LsmStore::open_with_config(config()).await?).await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_bulk_load() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let config = || Config::default()
+        .dir_path(temp_dir.path().to_path_buf())
+        .sst_file_size(4 * 1024)
+        .wal_enable(false)
+        .negative_cache_size(16);
+    let key = |i: usize| format!("key{:05}", i).into_bytes();
+
+    tokio_test::block_on(async {
+        let kv_store = LsmStore::open_with_config(config()).await?;
+        // 导入前确认不存在的Key进入负缓存，导入后需失效
+        assert_eq!(kv_store.get(&key(0)).await?, None);
+        assert!(kv_store.mem_table.is_negative(&key(0)));
+        // 无序且含有重复Key的数据，重复的Key以最后出现的value为准
+        let mut data = (0..2000)
+            .map(|i| (i * 7919) % 2000)
+            .map(|i| (key(i), b"first".to_vec()))
+            .collect_vec();
+        data.extend((0..100).map(|i| (key(i), b"dup".to_vec())));
+        kv_store.bulk_load(data).await?;
+
+        // 不存在重叠时直接放置于最深的Level，且各SSTable有序且互不重叠
+        {
+            let manifest = kv_store.manifest.read().await;
+            manifest.check_consistency()?;
+            assert!(manifest.get_level_vec(6).len() > 1);
+            assert_eq!(manifest.get_level_vec(6).len(), manifest.ss_tables_len());
+        }
+        let check = |kv_store: LsmStore, vec_overwrite: Vec<(usize, &'static str)>| async move {
+            let expect_value = |i: usize| vec_overwrite.iter()
+                .rev()
+                .find(|(overwrite_i, _)| *overwrite_i == i)
+                .map_or(if i < 100 { b"dup".to_vec() } else { b"first".to_vec() }, |(_, value)| value.as_bytes().to_vec());
+            for i in 0..2000 {
+                assert_eq!(kv_store.get(&key(i)).await?, Some(expect_value(i)));
+            }
+            let vec_kv = kv_store.scan(b"key00000", b"key99999", usize::MAX).await?;
+            assert_eq!(vec_kv.len(), 2000);
+            for (i, (key_i, value)) in vec_kv.into_iter().enumerate() {
+                assert_eq!(key_i, key(i));
+                assert_eq!(value, expect_value(i));
+            }
+            Ok::<LsmStore, KvsError>(kv_store)
+        };
+        let kv_store = check(kv_store, vec![]).await?;
+
+        // 与Level 6重叠时放置于其上的Level 5，导入的数据遮盖更旧的数据
+        kv_store.bulk_load(vec![(key(500), b"level_5".to_vec()), (key(1500), b"level_5".to_vec())]).await?;
+        assert_eq!(kv_store.manifest.read().await.get_level_vec(5).len(), 1);
+        let kv_store = check(kv_store, vec![(500, "level_5"), (1500, "level_5")]).await?;
+
+        // MemTable中的数据落盘至Level 0后与之重叠时放置于Level 0，导入的数据仍为最新
+        kv_store.set(&key(1000), b"set".to_vec()).await?;
+        kv_store.set(&key(1001), b"set".to_vec()).await?;
+        kv_store.bulk_load(vec![(key(1000), b"level_0".to_vec())]).await?;
+        let vec_overwrite = vec![(500, "level_5"), (1500, "level_5"), (1001, "set"), (1000, "level_0")];
+        let kv_store = check(kv_store, vec_overwrite.clone()).await?;
+        kv_store.manifest.read().await
+            .check_consistency()?;
+
+        // 重启后读取不变
+        drop(kv_store);
+        let _ignore = check(LsmStore::open_with_config(config()).await?, vec_overwrite).await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_export_range_as_ssts() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let dest_dir = TempDir::new().expect("unable to create temporary working directory");
+    let overlap_dir = TempDir::new().expect("unable to create temporary working directory");
+    let key = |i: usize| format!("key{:05}", i).into_bytes();
+    let value = |i: usize| vec![(i % 128) as u8; if i % 2 == 0 { 16 } else { 2048 }];
+
+    tokio_test::block_on(async {
+        let config = Config::default()
+            .dir_path(temp_dir.path().to_path_buf())
+            .sst_file_size(4 * 1024)
+            .kv_separation_enable(true)
+            .wal_enable(false);
+        let kv_store = LsmStore::open_with_config(config).await?;
+        for i in 0..1000 {
+            kv_store.set(&key(i), value(i)).await?;
+        }
+        kv_store.flush_to_sst().await?;
+        // 部分数据仍处于MemTable中，且含有墓碑
+        kv_store.remove(&key(150)).await?;
+        kv_store.set(&key(151), b"new".to_vec()).await?;
+
+        let ranges = vec![(key(100), key(200)), (key(600), key(900)), (key(2000), key(3000))];
+        let vec_gen = kv_store.export_range_as_ssts(ranges, dest_dir.path()).await?;
+        assert!(vec_gen.len() > 2);
+        assert_eq!(sorted_gen_list(dest_dir.path())?, vec_gen.iter().copied().sorted().collect_vec());
+
+        let exported_store = LsmStore::open(dest_dir.path()).await?;
+        {
+            let manifest = exported_store.manifest.read().await;
+            manifest.check_consistency()?;
+            assert_eq!(manifest.get_level_vec(MAX_LEVEL).len(), vec_gen.len());
+        }
+        for i in 0..1000 {
+            let expect_value = match i {
+                150 => None,
+                151 => Some(b"new".to_vec()),
+                100..=199 | 600..=899 => Some(value(i)),
+                _ => None
+            };
+            assert_eq!(exported_store.get(&key(i)).await?, expect_value);
+        }
+        assert_eq!(exported_store.scan(&key(0), &key(1000), usize::MAX).await?.len(), 399);
+
+        // 范围相交时放置于Level 0，相交部分的数据在各SSTable中一致
+        let ranges = vec![(key(100), key(300)), (key(200), key(400))];
+        let vec_gen = kv_store.export_range_as_ssts(ranges, overlap_dir.path()).await?;
+        let overlap_store = LsmStore::open(overlap_dir.path()).await?;
+        assert_eq!(overlap_store.manifest.read().await.get_level_vec(LEVEL_0).len(), vec_gen.len());
+        let vec_kv = overlap_store.scan(&key(0), &key(1000), usize::MAX).await?;
+        assert_eq!(vec_kv.len(), 299);
+        assert!(vec_kv.iter().all(|(key, _)| key.as_slice() >= b"key00100".as_slice() && key.as_slice() < b"key00400".as_slice()));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lsm_apply_versioned() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let config = || Config::default()
+        .dir_path(temp_dir.path().to_path_buf());
+    let set = |key: &[u8], value: &[u8], ts: u64| VersionedCommand::new(CommandData::set(key.to_vec(), value.to_vec()), ts);
+    let remove = |key: &[u8], ts: u64| VersionedCommand::new(CommandData::remove(key.to_vec()), ts);
+
+    tokio_test::block_on(async {
+        {
+            let kv_store = LsmStore::open_with_config(config()).await?;
+            // 按ts选出最新的数据，而非最后写入的数据
+            assert!(kv_store.apply_versioned(set(b"key", b"ts10", 10)).await?);
+            assert!(!kv_store.apply_versioned(set(b"key", b"ts5", 5)).await?);
+            assert_eq!(kv_store.get(b"key").await?, Some(b"ts10".to_vec()));
+            assert!(!kv_store.apply_versioned(remove(b"key", 3)).await?);
+            assert!(kv_store.apply_versioned(remove(b"key", 11)).await?);
+            assert_eq!(kv_store.get(b"key").await?, None);
+
+            // 墓碑落盘后同样使ts更小的Set不再生效，Key不存在时的Remove同样保留墓碑
+            kv_store.flush().await?;
+            assert!(!kv_store.apply_versioned(set(b"key", b"ts10", 10)).await?);
+            assert!(kv_store.apply_versioned(remove(b"absent", 5)).await?);
+            assert!(!kv_store.apply_versioned(set(b"absent", b"ts4", 4)).await?);
+            assert_eq!(kv_store.get(b"absent").await?, None);
+        }
+
+        // 重新开启后墓碑仍被保留
+        let kv_store = LsmStore::open_with_config(config()).await?;
+        assert!(!kv_store.apply_versioned(set(b"key", b"ts10", 10)).await?);
+        assert!(!kv_store.apply_versioned(set(b"absent", b"ts4", 4)).await?);
+        assert!(kv_store.apply_versioned(set(b"key", b"ts12", 12)).await?);
+        assert_eq!(kv_store.get(b"key").await?, Some(b"ts12".to_vec()));
+
         Ok(())
     })
-}
\ No newline at end of file
+}
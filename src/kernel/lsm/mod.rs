@@ -1,32 +1,66 @@
-use std::cmp::Ordering;
-use std::collections::HashSet;
-use std::fs;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::collections::hash_map::Entry;
+use std::{fs, io};
 use std::num::NonZeroUsize;
-use std::path::PathBuf;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use arc_swap::ArcSwap;
+use futures::future;
 use growable_bloom_filter::GrowableBloom;
+use im::OrdMap;
 use itertools::Itertools;
 use lru::LruCache;
 use serde::{Deserialize, Serialize};
-use skiplist::SkipMap;
-use tokio::sync::RwLock;
-use crate::kernel::{CommandData, log_path, Result};
-use crate::kernel::io_handler::IOHandler;
-use crate::kernel::lsm::compactor::MergeShardingVec;
-use crate::kernel::lsm::lsm_kv::{Config, LevelSlice, SsTableMap};
-use crate::kernel::lsm::ss_table::{Scope, SsTable};
+use serde::de::IgnoredAny;
+use tokio::sync::{MutexGuard, oneshot};
+use tracing::warn;
+use crate::kernel::{CommandData, CompactionStats, FileKind, FileRef, log_path, Result};
+use crate::kernel::io_handler::{IOHandler, IOHandlerFactory};
+use crate::kernel::lsm::compactor::{LEVEL_0, LEVEL_COUNT, MergeShardingVec};
+use crate::kernel::lsm::lsm_kv::{Amplification, Checkpoint, CompactionStrategy, Config, Histogram, LevelSlice, MergeOperator, ReadRepair, SsTableMap, Stats, VersionOrder};
+use crate::kernel::lsm::ss_table::{PrefixFilter, RangeSource, Scope, SsTable};
+use crate::kernel::lsm::value_log::ValueLog;
+use crate::kernel::migrator::write_and_sync;
 use crate::KvsError;
 
 pub(crate) mod ss_table;
 pub mod lsm_kv;
 mod compactor;
+mod group_commit;
+mod scheduler;
+pub mod value_log;
 
-pub(crate) type MemMap = SkipMap<Vec<u8>, CommandData>;
+pub(crate) type MemMap = OrdMap<Vec<u8>, CommandData>;
+
+/// 被SSTable快照锁定的Gen及其引用计数
+type PinnedGens = Arc<Mutex<HashMap<i64, usize>>>;
+
+/// MemTable切片
+/// 索引0为MemTable，索引1为ImmutableMemTable，各自附带其数据占用大小
+pub(crate) type MemTableSlice = [(MemMap, u64); 2];
 
 /// MetaInfo序列化长度定长
 /// 注意MetaInfo序列化时，需要使用类似BinCode这样的定长序列化框架，否则若类似Rmp的话会导致MetaInfo在不同数据时，长度不一致
 const TABLE_META_INFO_SIZE: usize = 40;
 
+/// 待删除SSTable列表文件名
+/// 删除过期SSTable前记录其Gen，全部删除后移除；开启时存在则说明上次删除中断，据此清除残留的孤儿文件
+const PENDING_DELETE_FILE_NAME: &str = "PENDING_DELETE";
+
+/// 校验和清单文件名
+/// 记录各SSTable文件整体的crc，用于在开启时检测文件是否被外部修改
+const CHECKSUM_MANIFEST_FILE_NAME: &str = "MANIFEST.crc";
+
+/// Size-Tiered策略下视为大小相近的SSTable间的大小倍率
+const SIZE_TIERED_BUCKET_RATIO: f64 = 1.5;
+
+/// 墓碑占比告警的最小间隔
+const TOMBSTONE_WARN_INTERVAL: Duration = Duration::from_secs(60);
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
 struct MetaInfo {
     level: u64,
@@ -42,17 +76,104 @@ struct ExtraInfo {
     scope: Scope,
     filter: GrowableBloom,
     size_of_data: usize,
+    /// 旧的SSTable中不存在数据版本号，视为0
+    #[serde(default)]
+    data_version: u64,
+    /// 旧的SSTable中不存在直方图，视为空
+    #[serde(default)]
+    key_size_histogram: Histogram,
+    #[serde(default)]
+    value_size_histogram: Histogram,
+    /// 旧的SSTable与未设置前缀长度时不存在前缀布隆过滤器
+    #[serde(default)]
+    prefix_filter: Option<PrefixFilter>,
+    /// 旧的SSTable中不存在墓碑数量，视为0
+    #[serde(default)]
+    tombstone_count: usize,
+}
+
+/// 仅解析ExtraInfo中的布隆过滤器，其余字段直接跳过
+/// 用于布隆过滤器非常驻时按需读取
+#[derive(Deserialize)]
+struct ExtraInfoFilter {
+    #[serde(rename = "vec_index")]
+    _vec_index: IgnoredAny,
+    #[serde(rename = "scope")]
+    _scope: IgnoredAny,
+    filter: GrowableBloom,
+    #[serde(rename = "size_of_data")]
+    _size_of_data: IgnoredAny,
+    #[serde(default, rename = "data_version")]
+    _data_version: IgnoredAny,
+    #[serde(default, rename = "key_size_histogram")]
+    _key_size_histogram: IgnoredAny,
+    #[serde(default, rename = "value_size_histogram")]
+    _value_size_histogram: IgnoredAny,
+    #[serde(default, rename = "prefix_filter")]
+    _prefix_filter: IgnoredAny,
+    #[serde(default, rename = "tombstone_count")]
+    _tombstone_count: IgnoredAny,
+}
+
+/// 布隆过滤器非常驻时，按需读取的布隆过滤器的LRU缓存，以SSTable的Gen为Key
+pub(crate) type FilterCache = tokio::sync::Mutex<LruCache<i64, GrowableBloom>>;
+
+/// 最近确认不存在的Key的LRU缓存
+type NegativeCache = Mutex<LruCache<Vec<u8>, ()>>;
+
+/// Key写入锁的分段数量
+pub(crate) const DEFAULT_KEY_LOCK_STRIPES: usize = 64;
+
+/// 持有的Key写入锁，Drop时释放
+pub(crate) type KeyGuard<'a> = Vec<MutexGuard<'a, ()>>;
+
+/// 以Key的哈希分段的写入锁
+///
+/// 同一Key的写入持有同一分段的锁完成Wal与MemTable的双写，使该Key在两者中的写入顺序一致；
+/// 条件写入持有该锁完成存在判断与写入，期间等待压缩不会占用MemTable的写入锁，
+/// 因此落盘失败时`MemTable::restore_immutable`不会被阻塞
+#[derive(Debug)]
+pub(crate) struct KeyLocks {
+    vec_lock: Vec<tokio::sync::Mutex<()>>
 }
 
+/// Level 0 SSTable的写入凭证
+///
+/// 由`MemTable::flush_ticket`按领取顺序发放并分配Gen，因此较早领取的凭证总是持有较小的Gen；
+/// SSTable的生成可以并行进行，而写入Manifest(或落盘失败时放回MemTable)前需通过`FlushTicket::wait_prev`
+/// 等待前一个凭证的持有者完成，使Level 0的SSTable按领取顺序写入，Drop时即视为完成
 #[derive(Debug)]
-struct MemTable {
+pub(crate) struct FlushTicket {
+    gen: i64,
+    prev: Option<oneshot::Receiver<()>>,
+    _done: Option<oneshot::Sender<()>>
+}
+
+#[derive(Debug)]
+pub(crate) struct MemTable {
+    // 写入锁，保证写入时新切片的生成是串行的
+    write_lock: tokio::sync::Mutex<()>,
     // MemTable切片，管理MemTable和ImmutableMemTable
-    mem_table_slice: RwLock<[(MemMap, u64); 2]>
+    // 读取时直接获取当前切片，不会与写入争用锁
+    mem_table_slice: ArcSwap<MemTableSlice>,
+    // 负缓存，为None时不开启
+    // 写入时在生成新切片前移除对应的Key，并持有其锁直至新切片生效且写入版本递增，
+    // 而记录时持有其锁并确认读取前的写入版本未变化，以此保证写入后的Key不会残留于负缓存之中
+    negative_cache: Option<NegativeCache>,
+    // 累计写入的数据大小，用于统计写入速率
+    written_bytes: AtomicU64,
+    // 写入版本，每次写入、放回、交换生效与负缓存失效后递增
+    // 读取前获取版本，之后版本未变化即表示期间MemTable未发生任何写入
+    version: AtomicU64,
+    // 最后发放的落盘凭证完成时的通知
+    last_flush: Mutex<Option<oneshot::Receiver<()>>>
 }
 
 #[derive(Debug)]
 pub(crate) struct Manifest {
     _path: Arc<PathBuf>,
+    /// `_path`之外存放SSTable的目录，即`Config::level_dir_path`所设置的目录
+    extra_dir_paths: Vec<PathBuf>,
     /// SSTable有序存储集合
     ss_tables_map: SsTableMap,
     /// Level层级Vec
@@ -61,10 +182,56 @@ pub(crate) struct Manifest {
     /// SSTable集合占有磁盘大小
     size_of_disk: u64,
     /// 用于防止SSTable重合的同步Buffer
-    /// 内部会存储SSTable的Gen，
-    /// 判定meet成功时移除对应Gen，避免收集重复SSTable
+    /// 内部会存储尚未被压缩选中的SSTable的Gen，
+    /// 压缩选中时一次性移除对应的所有Gen，避免同一SSTable被多个压缩重复合并
     sync_buffer_of_meet: Mutex<HashSet<i64>>,
-    position_cache: tokio::sync::Mutex<LruCache<(i64, Position), Vec<CommandData>>>
+    position_cache: tokio::sync::Mutex<LruCache<(i64, Position), Vec<CommandData>>>,
+    /// 布隆过滤器非常驻时按需读取的布隆过滤器缓存
+    filter_cache: FilterCache,
+    /// Level 0中判断SSTable数据新旧的依据
+    version_order: VersionOrder,
+    /// Major压缩与Minor压缩的统计
+    compaction_stats: CompactionStats,
+    /// MemTable落盘、Major压缩与vLog GC累计写入SSTable的大小(单位: 字节)，用于统计写放大
+    compaction_written_bytes: u64,
+    /// 后台校验发现损坏而被隔离的SSTable的Gen
+    /// 被隔离的SSTable不再被压缩选中，对其数据的查询返回`KvsError::CorruptedFile`
+    corrupted_gens: HashSet<i64>,
+    /// 查询时用于折叠Merge数据
+    merge_operator: Option<MergeOperator>,
+    /// 查询时单个SSTable读取失败的处理方式
+    read_repair: ReadRepair,
+    /// 各SSTable文件的crc，开启`Config::checksum_manifest`时随SSTable的增删同步更新至校验和清单
+    checksums: Option<BTreeMap<i64, u32>>,
+    /// 暂停文件删除的次数
+    /// 大于0时过期的SSTable仅记录至待删除列表，待全部恢复后再删除，且不进行vLog的垃圾回收
+    deletion_paused: usize,
+    /// 被SSTable快照锁定的Gen
+    /// 被锁定的过期SSTable仅记录至待删除列表，待快照释放后再删除，存在快照时不进行vLog的垃圾回收
+    pinned_gens: PinnedGens,
+    /// 打开SSTable的IOHandlerFactory，删除过期的SSTable时一并移除其句柄池中的只读句柄
+    io_handler_factory: Option<Arc<IOHandlerFactory>>,
+    /// 上次输出墓碑占比告警的时间，用于限制告警频率
+    last_tombstone_warn: Option<Instant>
+}
+
+/// SSTable快照
+///
+/// 持有范围扫描所涉及的SSTable并锁定其Gen，期间压缩不会删除这些SSTable的文件，
+/// 使范围扫描能够在释放Manifest的读锁后继续读取，既不阻塞压缩也不会读取到已删除的文件
+/// Drop时解除锁定，其间过期的文件由`Manifest::clean_released`或之后的压缩删除
+#[derive(Debug)]
+pub(crate) struct SsTableSnapshot {
+    vec_ss_table: Vec<Arc<SsTable>>,
+    pinned_gens: PinnedGens
+}
+
+/// 依次读取一组SSTable的Key块，同时仅在内存中保留其中一个SSTable的Key
+///
+/// 组内SSTable间的Key需互不重叠且以Key由小到大排列，使读出的Key整体有序
+pub(crate) struct KeyCursor<'a> {
+    vec_ss_table: VecDeque<&'a SsTable>,
+    keys: VecDeque<(Vec<u8>, bool)>
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Hash, PartialOrd, Eq)]
@@ -73,6 +240,63 @@ pub(crate) struct Position {
     len: usize
 }
 
+impl KeyLocks {
+    pub(crate) fn new(stripes: usize) -> Self {
+        KeyLocks {
+            vec_lock: (0..stripes.max(1))
+                .map(|_| tokio::sync::Mutex::new(()))
+                .collect_vec()
+        }
+    }
+
+    fn stripe(&self, key: &[u8]) -> usize {
+        crc32fast::hash(key) as usize % self.vec_lock.len()
+    }
+
+    /// 获取Key所在分段的写入锁
+    pub(crate) async fn lock(&self, key: &[u8]) -> KeyGuard<'_> {
+        vec![self.vec_lock[self.stripe(key)].lock().await]
+    }
+
+    /// 获取一组Key所在分段的写入锁
+    ///
+    /// 以分段序号由小到大获取，避免并发的批量写入相互等待而死锁
+    pub(crate) async fn lock_batch<K: AsRef<[u8]>>(&self, keys: impl IntoIterator<Item = K>) -> KeyGuard<'_> {
+        let vec_stripe = keys.into_iter()
+            .map(|key| self.stripe(key.as_ref()))
+            .sorted_unstable()
+            .dedup()
+            .collect_vec();
+        let mut guard = Vec::with_capacity(vec_stripe.len());
+
+        for stripe in vec_stripe {
+            guard.push(self.vec_lock[stripe].lock().await);
+        }
+        guard
+    }
+}
+
+impl FlushTicket {
+    /// 不参与排序的凭证，用于不写入Level 0的场景
+    fn detached(gen: i64) -> Self {
+        FlushTicket { gen, prev: None, _done: None }
+    }
+
+    pub(crate) fn gen(&self) -> i64 {
+        self.gen
+    }
+
+    /// 等待前一个凭证的持有者完成
+    pub(crate) async fn wait_prev(&mut self) {
+        // 等待被取消时保留Receiver，使再次等待时仍需前一个凭证完成
+        if let Some(prev) = self.prev.as_mut() {
+            // 前一个凭证Drop时Sender随之释放，此处的Err即表示完成
+            let _ignore = prev.await;
+            self.prev = None;
+        }
+    }
+}
+
 impl MemTable {
     pub(crate) fn new(mem_map: MemMap) -> Self {
         let mem_occupied = mem_map.iter()
@@ -80,59 +304,275 @@ impl MemTable {
                 (key.len() + value.get_data_len_for_rmp()) as u64
             })
             .sum();
-        MemTable { mem_table_slice: RwLock::new([(mem_map, mem_occupied), (MemMap::new(), 0)]) }
+        MemTable {
+            write_lock: tokio::sync::Mutex::new(()),
+            mem_table_slice: ArcSwap::from_pointee([(mem_map, mem_occupied), (MemMap::new(), 0)]),
+            negative_cache: None,
+            written_bytes: AtomicU64::new(0),
+            version: AtomicU64::new(0),
+            last_flush: Mutex::new(None)
+        }
+    }
+
+    /// 获取累计写入的数据大小(单位: 字节)
+    pub(crate) fn written_bytes(&self) -> u64 {
+        self.written_bytes.load(Ordering::Relaxed)
+    }
+
+    /// 获取当前的写入版本
+    ///
+    /// 版本在新切片生效后递增，因此读取MemTable前获取的版本未变化时，读取到的即为最新的数据
+    pub(crate) fn version(&self) -> u64 {
+        self.version.load(Ordering::SeqCst)
+    }
+
+    /// 开启负缓存，容量为0时不开启
+    pub(crate) fn negative_cache_size(mut self, negative_cache_size: usize) -> Self {
+        self.negative_cache = NonZeroUsize::new(negative_cache_size)
+            .map(|cap| Mutex::new(LruCache::new(cap)));
+        self
+    }
+
+    /// Key是否处于负缓存之中，即最近已确认不存在
+    #[allow(clippy::unwrap_used)]
+    pub(crate) fn is_negative(&self, key: &[u8]) -> bool {
+        self.negative_cache.as_ref()
+            .map_or(false, |cache| cache.lock().unwrap().get(key).is_some())
+    }
+
+    /// 将确认不存在的Key记录至负缓存
+    ///
+    /// version为读取MemTable前获取的`MemTable::version`，确认期间该Key可能已被写入并落盘，
+    /// 因此持有负缓存的锁确认写入版本未变化后才进行记录
+    #[allow(clippy::unwrap_used)]
+    pub(crate) fn record_negative(&self, key: &[u8], version: u64) {
+        if let Some(cache) = &self.negative_cache {
+            let mut cache = cache.lock().unwrap();
+
+            if self.version() == version {
+                let _ignore = cache.put(key.to_vec(), ());
+            }
+        }
+    }
+
+    /// 将未经MemTable写入的Key由负缓存中移除，如直接导入SSTable的Key
+    ///
+    /// 同时递增写入版本，使移除前已完成确认的查询不再将其记录至负缓存
+    pub(crate) fn invalidate_negative(&self, vec_key: &[Vec<u8>]) {
+        let mut negative_cache = self.lock_negative_cache();
+
+        if let Some(cache) = negative_cache.as_mut() {
+            for key in vec_key {
+                let _ignore = cache.pop(key);
+            }
+        }
+        let _ignore = self.version.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[allow(clippy::unwrap_used)]
+    fn lock_negative_cache(&self) -> Option<std::sync::MutexGuard<'_, LruCache<Vec<u8>, ()>>> {
+        self.negative_cache.as_ref()
+            .map(|cache| cache.lock().unwrap())
     }
 
     pub(crate) async fn insert_data(&self, key: Vec<u8>, value: CommandData) {
-        let mut mem_table_slice = self.mem_table_slice.write().await;
+        let _guard = self.write_lock.lock().await;
+        let mut mem_table_slice = self.clone_slice();
+        let mut negative_cache = self.lock_negative_cache();
 
-        mem_table_slice[0].1 += (key.len() + value.get_data_len_for_rmp()) as u64;
+        if let Some(cache) = negative_cache.as_mut() {
+            let _ignore = cache.pop(&key);
+        }
+
+        let data_len = (key.len() + value.get_data_len_for_rmp()) as u64;
+        mem_table_slice[0].1 += data_len;
         let _ignore = mem_table_slice[0].0.insert(key, value);
+        self.mem_table_slice.store(Arc::new(mem_table_slice));
+        let _ignore = self.version.fetch_add(1, Ordering::SeqCst);
+        let _ignore = self.written_bytes.fetch_add(data_len, Ordering::Relaxed);
     }
 
-    pub(crate) async fn mem_table_is_empty(&self) -> bool {
-        let mem_table_slice = self.mem_table_slice.read().await;
+    /// 批量插入数据，整批仅生成一次新切片
+    pub(crate) async fn insert_data_batch(&self, vec_data: Vec<(Vec<u8>, CommandData)>) {
+        let _guard = self.write_lock.lock().await;
+        let mut mem_table_slice = self.clone_slice();
+        let mut negative_cache = self.lock_negative_cache();
 
-        mem_table_slice[0].0.is_empty()
+        for (key, value) in vec_data {
+            if let Some(cache) = negative_cache.as_mut() {
+                let _ignore = cache.pop(&key);
+            }
+            let data_len = (key.len() + value.get_data_len_for_rmp()) as u64;
+            mem_table_slice[0].1 += data_len;
+            let _ignore = mem_table_slice[0].0.insert(key, value);
+            let _ignore = self.written_bytes.fetch_add(data_len, Ordering::Relaxed);
+        }
+        self.mem_table_slice.store(Arc::new(mem_table_slice));
+        let _ignore = self.version.fetch_add(1, Ordering::SeqCst);
     }
 
-    pub(crate) async fn mem_table_len(&self) -> usize {
-        let mem_table_slice = self.mem_table_slice.read().await;
+    /// 将落盘失败的ImmutableMemTable数据放回MemTable，等待下一次落盘
+    ///
+    /// MemTable中已存在的Key较之更新，因此跳过这些Key，但其中的Merge需要以放回的数据为基础进行合并；
+    /// 落盘重试期间可能已发生新的交换，此时当前ImmutableMemTable中value不同的Key同样较之更新
+    pub(crate) async fn restore_immutable(&self, vec_keys: Vec<Vec<u8>>, vec_values: Vec<CommandData>, merge_operator: Option<MergeOperator>) -> Result<()> {
+        let _guard = self.write_lock.lock().await;
+        let mut mem_table_slice = self.clone_slice();
+        let mut negative_cache = self.lock_negative_cache();
+
+        for (key, older) in vec_keys.into_iter().zip(vec_values) {
+            if let Some(cache) = negative_cache.as_mut() {
+                let _ignore = cache.pop(&key);
+            }
+            let cmd_data = match mem_table_slice[0].0.get(&key) {
+                Some(newer) if is_merge(newer) => merge_cmd_data(newer.clone(), older, merge_operator)?,
+                Some(_) => continue,
+                None if mem_table_slice[1].0.get(&key).map_or(false, |immutable| immutable != &older) => continue,
+                None => older
+            };
+            mem_table_slice[0].1 += (key.len() + cmd_data.get_data_len_for_rmp()) as u64;
+            let _ignore = mem_table_slice[0].0.insert(key, cmd_data);
+        }
+        self.mem_table_slice.store(Arc::new(mem_table_slice));
+        let _ignore = self.version.fetch_add(1, Ordering::SeqCst);
+
+        Ok(())
+    }
 
-        mem_table_slice[0].0.len()
+    pub(crate) async fn mem_table_is_empty(&self) -> bool {
+        self.mem_table_slice.load()[0].0.is_empty()
+    }
+
+    pub(crate) async fn mem_table_len(&self) -> usize {
+        self.mem_table_slice.load()[0].0.len()
     }
 
     async fn is_threshold_exceeded_minor(&self, threshold_size_with_mem_table: u64) -> bool {
-        self.mem_table_slice.read()
-            .await[0].1 > threshold_size_with_mem_table
+        self.mem_table_slice.load()[0].1 > threshold_size_with_mem_table
     }
 
-    /// MemTable交换并分解
-    async fn table_swap(&self) -> (Vec<Vec<u8>>, Vec<CommandData>){
-        let mut mem_table_slice = self.mem_table_slice.write().await;
+    /// MemTable交换并分解，同时领取ImmutableMemTable落盘的写入凭证
+    ///
+    /// 凭证在交换时领取，因此较早交换的ImmutableMemTable总是持有较小的Gen，并先于之后交换的写入Level 0
+    /// ImmutableMemTable为空时不写入Level 0，其凭证不参与排序
+    async fn table_swap(&self, create_gen: impl FnOnce() -> i64) -> (Vec<Vec<u8>>, Vec<CommandData>, FlushTicket) {
+        let _guard = self.write_lock.lock().await;
+        let mut mem_table_slice = self.clone_slice();
 
         mem_table_slice.swap(0, 1);
         mem_table_slice[0] = (MemMap::new(), 0);
-        mem_table_slice[1].0
+        let (vec_keys, vec_values): (Vec<Vec<u8>>, Vec<CommandData>) = mem_table_slice[1].0
             .iter()
             .map(|(key, value)| (key.clone(), value.clone()))
-            .unzip()
+            .unzip();
+        self.mem_table_slice.store(Arc::new(mem_table_slice));
+        let _ignore = self.version.fetch_add(1, Ordering::SeqCst);
+        let ticket = if vec_keys.is_empty() {
+            FlushTicket::detached(create_gen())
+        } else {
+            self.flush_ticket(create_gen)
+        };
+
+        (vec_keys, vec_values, ticket)
     }
 
+    /// 领取Level 0 SSTable的写入凭证，并以create_gen分配其Gen
+    ///
+    /// 领取与Gen的分配在同一锁内完成，使凭证的顺序与Gen的大小一致
+    #[allow(clippy::unwrap_used)]
+    pub(crate) fn flush_ticket(&self, create_gen: impl FnOnce() -> i64) -> FlushTicket {
+        let mut last_flush = self.last_flush.lock().unwrap();
+        let (done, receiver) = oneshot::channel();
+
+        FlushTicket { gen: create_gen(), prev: last_flush.replace(receiver), _done: Some(done) }
+    }
+
+    /// 获取Key在MemTable中的数据
+    ///
+    /// ImmutableMemTable在落盘后仍会保留至下一次交换，其中的Merge与落盘后的SSTable重复折叠会导致操作数被重复合并，
+    /// 因此ImmutableMemTable中的Merge不参与读取，等待压缩完毕后由SSTables中读取
     async fn get_cmd_data(&self, key: &[u8]) -> Option<CommandData> {
-        let mem_table_slice = self.mem_table_slice.read().await;
+        let mem_table_slice = self.snapshot();
 
         mem_table_slice[0].0.get(key)
-            .or_else(|| mem_table_slice[1].0.get(key))
+            .or_else(|| mem_table_slice[1].0.get(key)
+                .filter(|cmd_data| !is_merge(cmd_data)))
             .map(CommandData::clone)
     }
+
+    /// 获取Key在MemTable与ImmutableMemTable中尚未与SSTables折叠的数据
+    ///
+    /// 与`MemTable::get_cmd_data`不同，ImmutableMemTable中的Merge同样参与折叠，用于写入Wal的数据
+    pub(crate) fn get_unflushed_cmd_data(&self, key: &[u8], merge_operator: Option<MergeOperator>) -> Result<Option<CommandData>> {
+        let mem_table_slice = self.snapshot();
+
+        Ok(match (mem_table_slice[0].0.get(key), mem_table_slice[1].0.get(key)) {
+            (Some(newer), Some(older)) => Some(merge_cmd_data(newer.clone(), older.clone(), merge_operator)?),
+            (Some(cmd_data), None) | (None, Some(cmd_data)) => Some(cmd_data.clone()),
+            (None, None) => None
+        })
+    }
+
+    /// 获取ImmutableMemTable与MemTable中的所有数据，由旧往新
+    ///
+    /// 与`MemTable::get_cmd_data`相同，ImmutableMemTable中的Merge不参与读取
+    async fn get_all_cmd_data(&self) -> Vec<CommandData> {
+        let mem_table_slice = self.snapshot();
+
+        mem_table_slice[1].0.iter()
+            .filter(|(_, cmd_data)| !is_merge(cmd_data))
+            .chain(mem_table_slice[0].0.iter())
+            .map(|(_, cmd_data)| cmd_data.clone())
+            .collect_vec()
+    }
+
+    /// 获取MemTable与ImmutableMemTable中处于start与end边界之间的数据，由新往旧
+    ///
+    /// 与`MemTable::get_cmd_data`相同，ImmutableMemTable中的Merge不参与读取
+    async fn get_range_cmd_data(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<Vec<CommandData>> {
+        let mem_table_slice = self.snapshot();
+
+        mem_table_slice.iter()
+            .enumerate()
+            .map(|(i, (mem_map, _))| mem_map.iter()
+                .skip_while(|(key, _)| !is_after_start(key, start))
+                .take_while(|(key, _)| is_before_end(key, end))
+                .filter(|(_, cmd_data)| i == 0 || !is_merge(cmd_data))
+                .map(|(_, cmd_data)| cmd_data.clone())
+                .collect_vec())
+            .collect_vec()
+    }
+
+    /// 获取MemTable与ImmutableMemTable中最大的Key
+    fn max_key(&self) -> Option<Vec<u8>> {
+        self.snapshot().iter()
+            .filter_map(|(mem_map, _)| mem_map.get_max())
+            .map(|(key, _)| key.clone())
+            .max()
+    }
+
+    /// 获取当前MemTable切片的只读快照
+    ///
+    /// 快照与之后的写入相互隔离，持有快照期间不会阻塞写入
+    pub(crate) fn snapshot(&self) -> Arc<MemTableSlice> {
+        self.mem_table_slice.load_full()
+    }
+
+    /// 克隆当前的MemTable切片用于写入
+    /// MemMap为持久化数据结构，克隆时共享内部节点
+    fn clone_slice(&self) -> MemTableSlice {
+        (*self.mem_table_slice.load_full()).clone()
+    }
 }
 
 impl MetaInfo {
     /// 将MetaInfo自身写入对应的IOHandler之中
-    async fn write_to_file_and_flush(&self, io_handler: &IOHandler) -> Result<()> {
+    ///
+    /// MetaInfo为SSTable最后写入的部分，写入后对整个文件进行fsync，
+    /// 使flush返回时MemTable中的数据已落盘
+    async fn write_to_file_and_sync(&self, io_handler: &IOHandler) -> Result<()> {
         let _ignore = io_handler.write(bincode::serialize(&self)?).await?;
-        io_handler.flush().await?;
+        io_handler.sync().await?;
         Ok(())
     }
 
@@ -146,7 +586,7 @@ impl MetaInfo {
 }
 
 impl Manifest {
-    pub(crate) fn new(mut ss_tables_map: SsTableMap, path: Arc<PathBuf>, cache_size: usize) -> Result<Self> {
+    pub(crate) fn new(mut ss_tables_map: SsTableMap, path: Arc<PathBuf>, config: &Config) -> Result<Self> {
         // 获取ss_table分级Vec
         let level_slice = Self::level_layered(&mut ss_tables_map);
 
@@ -155,13 +595,81 @@ impl Manifest {
             .collect());
 
         let size_of_disk = ss_tables_map.values()
-            .map(SsTable::get_size_of_disk)
+            .map(|ss_table| ss_table.get_size_of_disk())
             .sum();
 
-        let position_cache = tokio::sync::Mutex::new(LruCache::new(NonZeroUsize::new(cache_size)
+        let position_cache = tokio::sync::Mutex::new(LruCache::new(NonZeroUsize::new(config.cache_size)
+            .ok_or(KvsError::CacheSizeOverFlow)?));
+        let filter_cache = tokio::sync::Mutex::new(LruCache::new(NonZeroUsize::new(config.bloom_cache_size)
             .ok_or(KvsError::CacheSizeOverFlow)?));
 
-        Ok(Self { _path: path, ss_tables_map, level_slice, size_of_disk, sync_buffer_of_meet, position_cache })
+        Ok(Self {
+            _path: path,
+            extra_dir_paths: config.extra_dir_paths(),
+            ss_tables_map,
+            level_slice,
+            size_of_disk,
+            sync_buffer_of_meet,
+            position_cache,
+            filter_cache,
+            version_order: config.version_order,
+            compaction_stats: CompactionStats::default(),
+            compaction_written_bytes: 0,
+            corrupted_gens: HashSet::new(),
+            merge_operator: config.merge_operator,
+            read_repair: config.read_repair,
+            checksums: None,
+            deletion_paused: 0,
+            pinned_gens: PinnedGens::default(),
+            io_handler_factory: None,
+            last_tombstone_warn: None
+        })
+    }
+
+    /// 设置打开SSTable的IOHandlerFactory，之后过期的SSTable经由其删除
+    pub(crate) fn set_io_handler_factory(&mut self, io_handler_factory: Arc<IOHandlerFactory>) {
+        self.io_handler_factory = Some(io_handler_factory);
+    }
+
+    /// 取出所有的SSTable，用于只读跟随者以目录中现有的SSTable重建Manifest
+    pub(crate) fn take_ss_tables(&mut self) -> SsTableMap {
+        std::mem::take(&mut self.ss_tables_map)
+    }
+
+    /// 获取SSTable的数量
+    pub(crate) fn ss_tables_len(&self) -> usize {
+        self.ss_tables_map.len()
+    }
+
+    /// 开启校验和清单的维护
+    ///
+    /// 沿用开启时已校验的crc，仅计算未记录或已被迁移重写的SSTable的crc，并移除已不存在的SSTable后覆盖写入清单
+    pub(crate) fn enable_checksums(&mut self, mut checksums: BTreeMap<i64, u32>, is_rewritten: bool) -> Result<()> {
+        checksums.retain(|gen, _| self.ss_tables_map.contains_key(gen));
+        for gen in self.ss_tables_map.keys() {
+            if is_rewritten || !checksums.contains_key(gen) {
+                let _ignore = checksums.insert(*gen, file_crc(&self._path, &self.extra_dir_paths, *gen)?);
+            }
+        }
+        write_checksum_manifest(&self._path, &checksums)?;
+        self.checksums = Some(checksums);
+
+        Ok(())
+    }
+
+    /// 将新增与过期的SSTable更新至校验和清单，未开启时不进行任何操作
+    fn update_checksums(&mut self, vec_new_gen: &[i64], vec_expired_gen: &[i64]) -> Result<()> {
+        if let Some(checksums) = &mut self.checksums {
+            for gen in vec_new_gen {
+                let _ignore = checksums.insert(*gen, file_crc(&self._path, &self.extra_dir_paths, *gen)?);
+            }
+            for gen in vec_expired_gen {
+                let _ignore = checksums.remove(gen);
+            }
+            write_checksum_manifest(&self._path, checksums)?;
+        }
+
+        Ok(())
     }
 
     /// 使用ss_tables返回LevelVec
@@ -172,53 +680,73 @@ impl Manifest {
             let level = ss_table.get_level();
             level_slice[level].push(ss_table.get_gen());
         }
+        for vec_gen in level_slice.iter_mut().skip(1) {
+            Self::sort_by_scope(vec_gen, ss_tables);
+        }
         level_slice
     }
 
+    /// Level 1-7中的SSTable以scope由小到大排列
+    fn sort_by_scope(vec_gen: &mut [i64], ss_tables_map: &SsTableMap) {
+        let get_start = |gen: &i64| ss_tables_map.get(gen)
+            .map(|ss_table| ss_table.get_scope().get_start());
+
+        vec_gen.sort_by(|gen_a, gen_b| get_start(gen_a).cmp(&get_start(gen_b)));
+    }
+
     #[allow(clippy::unwrap_used)]
-    pub(crate) async fn insert_ss_table_with_index(&mut self, ss_table: SsTable, index: usize) {
+    pub(crate) async fn insert_ss_table_with_index(&mut self, ss_table: SsTable, index: usize) -> Result<()> {
         let gen = ss_table.get_gen();
         let level = ss_table.get_level();
 
         self.size_of_disk += ss_table.get_size_of_disk();
-        let _ignore = self.ss_tables_map.insert(gen, ss_table);
+        let _ignore = self.ss_tables_map.insert(gen, Arc::new(ss_table));
         self.level_slice[level].insert(index, gen);
         let _ignore1 = self.sync_buffer_of_meet.lock().unwrap()
             .insert(gen);
+
+        self.update_checksums(&[gen], &[])
     }
 
     #[allow(clippy::unwrap_used)]
-    pub(crate) async fn insert_ss_table_with_index_batch(&mut self, ss_tables: Vec<SsTable>, index: usize) {
+    pub(crate) async fn insert_ss_table_with_index_batch(&mut self, ss_tables: Vec<SsTable>, index: usize) -> Result<()> {
         let vec_gen = ss_tables.into_iter()
             .map(|ss_table| {
                 let gen = ss_table.get_gen();
                 let level = ss_table.get_level();
 
                 self.size_of_disk += ss_table.get_size_of_disk();
-                let _ignore = self.ss_tables_map.insert(gen, ss_table);
+                let _ignore = self.ss_tables_map.insert(gen, Arc::new(ss_table));
                 self.level_slice[level].insert(index, gen);
+                if level > 0 {
+                    Self::sort_by_scope(&mut self.level_slice[level], &self.ss_tables_map);
+                }
                 gen
             })
             .collect_vec();
 
-        let mut sync_buffer_of_meet = self.sync_buffer_of_meet.lock().unwrap();
+        self.sync_buffer_of_meet.lock().unwrap()
+            .extend(vec_gen.iter().copied());
 
-        sync_buffer_of_meet.extend(vec_gen);
+        self.update_checksums(&vec_gen, &[])
     }
 
     /// 删除指定的过期gen
+    ///
+    /// 先将过期gen记录至待删除列表并落盘，再更新内存结构，最后删除文件；
+    /// 删除中途失败或崩溃时，残留的孤儿文件会在下次开启时通过`clean_pending_delete`清除
+    /// 校验和清单先于待删除列表更新，使被清除的孤儿文件不会残留于清单之中
     #[allow(clippy::unwrap_used)]
     pub(crate) async fn retain_with_vec_gen_and_level(&mut self, vec_expired_gen: &[i64]) -> Result<()> {
+        self.update_checksums(&[], vec_expired_gen)?;
+        record_pending_delete(&self._path, vec_expired_gen)?;
+
         self.size_of_disk -= vec_expired_gen.iter()
             .map(|gen| self.get_ss_table(gen).map(SsTable::get_size_of_disk).unwrap_or(0))
             .sum::<u64>();
-
-        // 遍历过期Vec对数据进行旧文件删除
         for expired_gen in vec_expired_gen.iter() {
             let _ignore = self.ss_tables_map.remove(expired_gen);
-            fs::remove_file(log_path(&self._path, *expired_gen))?;
         }
-
         // 将存储的Level表中含有该gen的SSTable一并删除
         for vec_level in &mut self.level_slice {
             vec_level.retain(|gen| !vec_expired_gen.contains(gen));
@@ -226,9 +754,71 @@ impl Manifest {
         self.sync_buffer_of_meet.lock().unwrap()
             .retain(|gen| !vec_expired_gen.contains(gen));
 
+        // 内存结构更新后再删除文件
+        if !self.is_deletion_paused() {
+            self.clean_unpinned()?;
+        }
+
         Ok(())
     }
 
+    /// 暂停文件删除
+    pub(crate) fn pause_deletion(&mut self) {
+        self.deletion_paused += 1;
+    }
+
+    /// 恢复文件删除，全部恢复后删除暂停期间积压的过期SSTable
+    pub(crate) fn resume_deletion(&mut self) -> Result<()> {
+        self.deletion_paused = self.deletion_paused.saturating_sub(1);
+        if !self.is_deletion_paused() {
+            self.clean_unpinned()?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn is_deletion_paused(&self) -> bool {
+        self.deletion_paused > 0
+    }
+
+    /// 判断是否存在尚未释放的SSTable快照
+    #[allow(clippy::unwrap_used)]
+    pub(crate) fn is_snapshot_pinned(&self) -> bool {
+        !self.pinned_gens.lock().unwrap().is_empty()
+    }
+
+    /// 删除待删除列表中未被快照锁定的SSTable文件
+    ///
+    /// 删除期间持有锁定计数的锁，避免多个快照同时释放时并发改写待删除列表
+    #[allow(clippy::unwrap_used)]
+    fn clean_unpinned(&self) -> Result<()> {
+        let pinned_gens = self.pinned_gens.lock().unwrap();
+        let _ignore = clean_pending_delete_except(
+            &self._path,
+            &self.extra_dir_paths,
+            self.io_handler_factory.as_deref(),
+            |gen| pinned_gens.contains_key(&gen)
+        )?;
+
+        Ok(())
+    }
+
+    /// 删除SSTable快照释放后不再被锁定的过期SSTable文件，暂停文件删除期间不进行删除
+    pub(crate) fn clean_released(&self) -> Result<()> {
+        if !self.is_deletion_paused() {
+            self.clean_unpinned()?;
+        }
+
+        Ok(())
+    }
+
+    /// 获取所有SSTable的文件
+    pub(crate) fn ss_table_files(&self) -> Result<Vec<FileRef>> {
+        self.ss_tables_map.values()
+            .map(|ss_table| ss_table.file_ref())
+            .collect()
+    }
+
     pub(crate) fn get_level_vec(&self, level: usize) -> &Vec<i64> {
         &self.level_slice[level]
     }
@@ -236,40 +826,476 @@ impl Manifest {
     pub(crate) fn get_vec_ss_table_with_level(&self, level: usize) -> Vec<&SsTable> {
         self.level_slice[level]
             .iter()
-            .filter_map(|gen| self.ss_tables_map.get(gen))
+            .filter_map(|gen| self.get_ss_table(gen))
             .collect_vec()
     }
 
     pub(crate) fn get_ss_table(&self, gen: &i64) -> Option<&SsTable> {
         self.ss_tables_map.get(gen)
+            .map(Arc::as_ref)
     }
 
-    fn is_threshold_exceeded_major(&self, sst_size: usize, level: usize, sst_magnification: usize) -> bool {
-        self.level_slice[level].len() > (sst_size.pow(level as u32) * sst_magnification)
+    /// 判断该Level是否达到`Config::compaction_strategy`的Major压缩触发条件
+    pub(crate) fn is_threshold_exceeded_major(&self, config: &Config, level: usize) -> bool {
+        match config.compaction_strategy {
+            CompactionStrategy::Leveled => {
+                self.level_slice[level].len() > (config.major_threshold_with_sst_size.load(Ordering::Relaxed).pow(level as u32) * config.level_sst_magnification)
+            }
+            CompactionStrategy::SizeTiered { size_threshold, min_tables } => {
+                let vec_size = self.level_slice[level].iter()
+                    .filter_map(|gen| self.get_ss_table(gen))
+                    .map(SsTable::get_size_of_disk)
+                    .collect_vec();
+
+                vec_size.iter().sum::<u64>() > size_threshold
+                    && similar_size_count(vec_size) >= min_tables
+            }
+        }
     }
 
-    /// 使用Key从现有SSTables中获取对应的数据
-    pub(crate) async fn get_data_for_ss_tables(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
-        // Level 0的SSTable是无序且SSTable间的数据是可能重复的
-        for ss_table in self.get_vec_ss_table_with_level(0).iter().rev() {
-            if let Some(cmd_data) = ss_table.query_with_key(key, &self.position_cache).await? {
-                return Ok(cmd_data.get_value_owner());
+    /// Level 0中等待Major压缩的SSTable积压数量
+    ///
+    /// Leveled时为超出触发阈值的SSTable数量，SizeTiered时达到触发条件则为该Level的SSTable数量
+    pub(crate) fn level_0_backlog(&self, config: &Config) -> usize {
+        let level_0_len = self.level_slice[LEVEL_0].len();
+
+        match config.compaction_strategy {
+            CompactionStrategy::Leveled => level_0_len.saturating_sub(config.level_sst_magnification),
+            CompactionStrategy::SizeTiered { .. } => {
+                if self.is_threshold_exceeded_major(config, LEVEL_0) { level_0_len } else { 0 }
             }
         }
-        // Level 1-7的数据排布有序且唯一，因此在每一个等级可以直接找到唯一一个Key可能在范围内的SSTable
+    }
+
+    /// 使用Key从现有SSTables中获取对应的数据
+    ///
+    /// 布隆过滤器负命中的SSTable会被直接跳过，不读取数据段
+    /// 各候选SSTable按由新往旧的顺序逐个查询，取第一个命中的数据，命中后不再查询更旧的SSTable
+    /// 命中的数据为Merge时继续与更旧的数据折叠，因此返回的数据不会为Merge
+    /// Key-Value分离时返回的数据可能为SetPtr，需通过vLog获取value
+    pub(crate) async fn get_data_for_ss_tables(&self, key: &[u8]) -> Result<Option<CommandData>> {
+        self.get_data_for_ss_tables_with_read_bytes(key, None).await
+    }
+
+    /// 与get_data_for_ss_tables一致，read_bytes不为None时将从SSTable中实际读取的数据段大小累计至其中
+    pub(crate) async fn get_data_for_ss_tables_with_read_bytes(&self, key: &[u8], read_bytes: Option<&AtomicU64>) -> Result<Option<CommandData>> {
+        Ok(self.get_data_for_ss_tables_with_repair(key, read_bytes).await?.0)
+    }
+
+    /// 与get_data_for_ss_tables_with_read_bytes一致，并返回因读取失败而被跳过的SSTable的Gen
+    ///
+    /// `Config::read_repair`不为`ReadRepair::Disabled`时，单个SSTable读取失败(包括已被隔离)不会使查询整体失败，
+    /// 而是跳过该SSTable并继续查询其他候选；所有候选均未读到数据时返回首个读取失败的错误，
+    /// 以免将可能位于损坏SSTable中的数据视为不存在
+    pub(crate) async fn get_data_for_ss_tables_with_repair(&self, key: &[u8], read_bytes: Option<&AtomicU64>) -> Result<(Option<CommandData>, Vec<i64>)> {
         let key_scope = Scope::from_key(key);
+        // Level 0的SSTable是无序且SSTable间的数据是可能重复的，因此由新往旧查找
+        // Level 1-7的数据排布有序且唯一，因此在每一个等级可以直接找到唯一一个Key可能在范围内的SSTable
+        let vec_ss_table = self.get_vec_ss_table_with_level_0_from_new_to_old()
+            .into_iter()
+            .chain((1..7).filter_map(|level| self.get_vec_ss_table_with_level(level)
+                .into_iter()
+                .rfind(|ss_table| ss_table.get_scope().meet(&key_scope))));
+
+        // 按候选由新往旧的顺序逐个查询，命中非Merge的数据后即返回，不再读取更旧的SSTable
+        let mut option_merge = None;
+        let mut vec_skipped_gen = Vec::new();
+        let mut option_first_err = None;
+        for ss_table in vec_ss_table {
+            let gen = ss_table.get_gen();
+            // Key可能位于已损坏的SSTable中时直接返回错误，而不读取其数据
+            let result = match ss_table.get_scope().meet(&key_scope) {
+                true => self.check_corrupted(gen),
+                false => Ok(())
+            };
+            let result = match result {
+                Ok(()) => ss_table.query_with_key(key, &self.position_cache, &self.filter_cache, read_bytes).await,
+                Err(err) => Err(err)
+            };
+            let option_cmd_data = match result {
+                Ok(option_cmd_data) => option_cmd_data,
+                Err(err) if self.read_repair != ReadRepair::Disabled => {
+                    warn!("[SsTable: {gen}][Query Failed, Skipped]: {err:?}");
+                    vec_skipped_gen.push(gen);
+                    let _ignore = option_first_err.get_or_insert(err);
+                    continue
+                }
+                Err(err) => return Err(err)
+            };
+            if let Some(cmd_data) = option_cmd_data {
+                let cmd_data = match option_merge.take() {
+                    Some(newer) => merge_cmd_data(newer, cmd_data, self.merge_operator)?,
+                    None => cmd_data
+                };
+                if !is_merge(&cmd_data) {
+                    return Ok((Some(cmd_data), vec_skipped_gen));
+                }
+                option_merge = Some(cmd_data);
+            }
+        }
+        if option_merge.is_none() {
+            if let Some(err) = option_first_err {
+                return Err(err);
+            }
+        }
+
+        Ok((option_merge.map(|cmd_data| resolve_merge(cmd_data, self.merge_operator))
+            .transpose()?, vec_skipped_gen))
+    }
+
+    /// 使用多个Key从现有SSTables中批量获取对应的数据，返回值与keys一一对应
+    ///
+    /// 由新往旧依次查询，每个SSTable仅批量查询其中尚未得到最终数据的Key，
+    /// 以此合并同一SSTable中相邻数据段的读取；数据的折叠与get_data_for_ss_tables一致
+    pub(crate) async fn get_data_for_ss_tables_batch(&self, keys: &[&[u8]]) -> Result<Vec<Option<CommandData>>> {
+        let mut vec_option_cmd_data: Vec<Option<CommandData>> = keys.iter()
+            .map(|_| None)
+            .collect_vec();
+        let is_pending = |option_cmd_data: &Option<CommandData>| option_cmd_data.as_ref()
+            .map_or(true, is_merge);
+
+        // Level 0的SSTable间数据可能重复，因此由新往旧逐个查询
+        let mut vec_query: Vec<Vec<(&SsTable, Vec<usize>)>> = Vec::new();
+        for ss_table in self.get_vec_ss_table_with_level_0_from_new_to_old() {
+            let vec_index = (0..keys.len())
+                .filter(|index| ss_table.get_scope().contains(keys[*index]))
+                .collect_vec();
+            vec_query.push(vec![(ss_table, vec_index)]);
+        }
+        // Level 1-7中每个Key在每一个等级至多对应一个SSTable，因此同一等级的SSTable可以并发查询
         for level in 1..7 {
-            if let Some(ss_table) = self.get_vec_ss_table_with_level(level)
-                .iter()
-                .rfind(|ss_table| ss_table.get_scope().meet(&key_scope))
-            {
-                if let Some(cmd_data) = ss_table.query_with_key(key, &self.position_cache).await? {
-                    return Ok(cmd_data.get_value_owner());
+            let vec_ss_table = self.get_vec_ss_table_with_level(level);
+            let mut map_index: HashMap<i64, Vec<usize>> = HashMap::new();
+            for (index, key) in keys.iter().enumerate() {
+                if let Some(ss_table) = vec_ss_table.iter()
+                    .rfind(|ss_table| ss_table.get_scope().contains(key))
+                {
+                    map_index.entry(ss_table.get_gen())
+                        .or_default()
+                        .push(index);
+                }
+            }
+            vec_query.push(vec_ss_table.into_iter()
+                .filter_map(|ss_table| map_index.remove(&ss_table.get_gen())
+                    .map(|vec_index| (ss_table, vec_index)))
+                .collect_vec());
+        }
+
+        for vec_ss_table_query in vec_query {
+            let queries = vec_ss_table_query.into_iter()
+                .filter_map(|(ss_table, vec_index)| {
+                    let vec_index = vec_index.into_iter()
+                        .filter(|index| is_pending(&vec_option_cmd_data[*index]))
+                        .collect_vec();
+
+                    (!vec_index.is_empty()).then_some((ss_table, vec_index))
+                })
+                .map(|(ss_table, vec_index)| async move {
+                    // Key可能位于已损坏的SSTable中时直接返回错误，而不读取其数据
+                    self.check_corrupted(ss_table.get_gen())?;
+                    let vec_key = vec_index.iter()
+                        .map(|index| keys[*index])
+                        .collect_vec();
+                    let vec_result = ss_table.query_with_keys(&vec_key, &self.position_cache, &self.filter_cache).await?;
+
+                    Ok::<_, KvsError>(vec_index.into_iter().zip(vec_result).collect_vec())
+                });
+
+            let vec_result = future::try_join_all(queries).await?;
+            for (index, option_cmd_data) in vec_result.into_iter().flatten() {
+                if let Some(cmd_data) = option_cmd_data {
+                    vec_option_cmd_data[index] = Some(match vec_option_cmd_data[index].take() {
+                        Some(newer) => merge_cmd_data(newer, cmd_data, self.merge_operator)?,
+                        None => cmd_data
+                    });
                 }
             }
         }
 
-        Ok(None)
+        vec_option_cmd_data.into_iter()
+            .map(|option_cmd_data| option_cmd_data
+                .map(|cmd_data| resolve_merge(cmd_data, self.merge_operator))
+                .transpose())
+            .collect()
+    }
+
+    /// 获取SSTable中所有的Key及其是否为墓碑，由旧往新
+    ///
+    /// 仅读取各SSTable的Key块，存在scope时跳过与之不相交的SSTable
+    pub(crate) async fn get_keys_for_ss_tables(&self, option_scope: Option<&Scope>) -> Result<Vec<(Vec<u8>, bool)>> {
+        let map_futures = self.get_vec_ss_table_from_old_to_new()
+            .into_iter()
+            .filter(|ss_table| option_scope
+                .map_or(true, |scope| ss_table.get_scope().meet(scope)))
+            .map(SsTable::get_all_keys);
+
+        Ok(future::try_join_all(map_futures)
+            .await?
+            .into_iter()
+            .flatten()
+            .collect_vec())
+    }
+
+    /// 获取所有SSTable，由旧往新：Level由高至低，Level 0中由旧往新
+    fn get_vec_ss_table_from_old_to_new(&self) -> Vec<&SsTable> {
+        (1..7).rev()
+            .flat_map(|level| self.get_vec_ss_table_with_level(level))
+            .chain(self.get_vec_ss_table_with_level_0_from_new_to_old()
+                .into_iter()
+                .rev())
+            .collect_vec()
+    }
+
+    /// 获取Level 0中的SSTable，以`Config::version_order`由新往旧排列
+    ///
+    /// Level 0中的SSTable间数据可能重复，需显式比较新旧而不依赖level_slice中的插入顺序
+    fn get_vec_ss_table_with_level_0_from_new_to_old(&self) -> Vec<&SsTable> {
+        self.get_vec_ss_table_with_level(0)
+            .into_iter()
+            .sorted_by_key(|ss_table| Reverse(ss_table.freshness(self.version_order)))
+            .collect_vec()
+    }
+
+    /// 获取指定Level中所有SSTable的数据，以Key由小到大排列且去重
+    ///
+    /// Level 0的SSTable间数据可能重复，因此由旧往新覆盖，保留最新的数据
+    pub(crate) async fn get_level_data(&self, level: usize) -> Result<Vec<CommandData>> {
+        let map_futures = self.get_vec_ss_table_with_level(level)
+            .into_iter()
+            .sorted_by_key(|ss_table| ss_table.freshness(self.version_order))
+            .map(SsTable::get_all_data);
+        let mut map_data = BTreeMap::new();
+
+        for cmd_data in future::try_join_all(map_futures).await?.into_iter().flatten() {
+            let _ignore = map_data.insert(cmd_data.get_key_clone(), cmd_data);
+        }
+
+        Ok(map_data.into_values().collect_vec())
+    }
+
+    /// 获取所有SSTable中处于start与end边界之间的数据源，由新往旧
+    ///
+    /// 范围涉及已损坏的SSTable时返回`KvsError::CorruptedFile`
+    pub(crate) fn get_range_sources(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<Vec<RangeSource<'_>>> {
+        self.get_range_sources_with_filter(start, end, |_| true)
+    }
+
+    /// 获取可能含有以prefix为前缀的Key的SSTable中处于[prefix, end)范围内的数据源，由新往旧
+    ///
+    /// 前缀布隆过滤器负命中的SSTable直接跳过，不读取其数据段
+    pub(crate) fn get_prefix_range_sources(&self, prefix: &[u8], end: &[u8]) -> Result<Vec<RangeSource<'_>>> {
+        self.get_range_sources_with_filter(Bound::Included(prefix), Bound::Excluded(end), |ss_table| ss_table.may_contain_prefix(prefix))
+    }
+
+    /// 获取与start与end边界相交的SSTable快照，由新往旧
+    ///
+    /// 快照释放前其中的SSTable即使因压缩过期，文件也不会被删除
+    /// 范围涉及已损坏的SSTable时返回`KvsError::CorruptedFile`
+    #[allow(clippy::unwrap_used)]
+    pub(crate) fn range_snapshot(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<SsTableSnapshot> {
+        let vec_ss_table = self.get_vec_ss_table_with_level_0_from_new_to_old()
+            .into_iter()
+            .chain((1..7).flat_map(|level| self.get_vec_ss_table_with_level(level)))
+            .filter(|ss_table| ss_table.range_source(start, end).lower_bound().is_some())
+            .filter_map(|ss_table| self.ss_tables_map.get(&ss_table.get_gen()))
+            .map(|ss_table| {
+                self.check_corrupted(ss_table.get_gen())?;
+                Ok(Arc::clone(ss_table))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut pinned_gens = self.pinned_gens.lock().unwrap();
+        for ss_table in &vec_ss_table {
+            *pinned_gens.entry(ss_table.get_gen()).or_insert(0) += 1;
+        }
+
+        Ok(SsTableSnapshot { vec_ss_table, pinned_gens: Arc::clone(&self.pinned_gens) })
+    }
+
+    fn get_range_sources_with_filter<F>(&self, start: Bound<&[u8]>, end: Bound<&[u8]>, filter: F) -> Result<Vec<RangeSource<'_>>>
+        where F: Fn(&SsTable) -> bool
+    {
+        self.get_vec_ss_table_with_level_0_from_new_to_old()
+            .into_iter()
+            .chain((1..7).flat_map(|level| self.get_vec_ss_table_with_level(level)))
+            .filter(|ss_table| filter(ss_table))
+            .map(|ss_table| (ss_table.get_gen(), ss_table.range_source(start, end)))
+            .filter(|(_, source)| source.lower_bound().is_some())
+            .map(|(gen, source)| {
+                self.check_corrupted(gen)?;
+                Ok(source)
+            })
+            .collect()
+    }
+
+    /// 调整数据段缓存的容量，容量缩小时淘汰最久未使用的数据段
+    pub(crate) async fn resize_position_cache(&self, cache_size: usize) -> Result<()> {
+        self.position_cache.lock().await
+            .resize(NonZeroUsize::new(cache_size).ok_or(KvsError::CacheSizeOverFlow)?);
+        Ok(())
+    }
+
+    /// 隔离已损坏的SSTable
+    ///
+    /// 将其从同步Buffer中移除，使其不再被压缩选中
+    #[allow(clippy::unwrap_used)]
+    pub(crate) fn quarantine(&mut self, gen: i64) {
+        let _ignore = self.corrupted_gens.insert(gen);
+        let _ignore1 = self.sync_buffer_of_meet.lock().unwrap()
+            .remove(&gen);
+    }
+
+    /// 获取已被隔离的SSTable的Gen，由小到大
+    pub(crate) fn corrupted_gens(&self) -> Vec<i64> {
+        self.corrupted_gens.iter()
+            .copied()
+            .sorted()
+            .collect_vec()
+    }
+
+    /// 该Gen的SSTable已被隔离时返回`KvsError::CorruptedFile`
+    fn check_corrupted(&self, gen: i64) -> Result<()> {
+        if self.corrupted_gens.contains(&gen) {
+            return Err(KvsError::CorruptedFile { kind: FileKind::SsTable, gen, offset: 0, reason: KvsError::CrcMisMatch.to_string() });
+        }
+        Ok(())
+    }
+
+    /// 获取所有SSTable中最大的Key
+    pub(crate) fn max_key(&self) -> Option<&[u8]> {
+        self.ss_tables_map.values()
+            .map(|ss_table| ss_table.get_scope())
+            .filter(|scope| !scope.is_empty())
+            .map(Scope::get_end)
+            .max()
+    }
+
+    /// 估算所有SSTable中处于[start, end]范围内的数据大小
+    pub(crate) fn estimate_range_size(&self, start: &[u8], end: &[u8]) -> u64 {
+        self.ss_tables_map.values()
+            .map(|ss_table| ss_table.estimate_range_size(start, end))
+            .sum()
+    }
+
+    /// 以当前的level_slice生成检查点
+    pub(crate) fn checkpoint(&self) -> Checkpoint {
+        let vec_level = self.level_slice.iter()
+            .map(|vec_gen| vec_gen.iter()
+                .filter_map(|gen| self.get_ss_table(gen))
+                .map(|ss_table| (ss_table.get_gen(), ss_table.get_crc_code()))
+                .collect_vec())
+            .collect_vec();
+
+        Checkpoint { vec_level }
+    }
+
+    pub(crate) fn get_size_of_disk(&self) -> u64 {
+        self.size_of_disk
+    }
+
+    /// 记录一次完成的Major压缩
+    pub(crate) fn record_compaction(&mut self, reclaimed_bytes: u64) {
+        self.compaction_stats.record(reclaimed_bytes);
+    }
+
+    /// 记录一次完成的Minor压缩
+    pub(crate) fn record_minor_compaction(&mut self) {
+        self.compaction_stats.record_minor();
+    }
+
+    /// 记录MemTable落盘、Major压缩或vLog GC所写入的SSTable大小
+    pub(crate) fn record_compaction_written(&mut self, written_bytes: u64) {
+        self.compaction_written_bytes += written_bytes;
+    }
+
+    /// 聚合所有SSTable的统计信息
+    pub(crate) fn stats(&self) -> Stats {
+        let mut key_size_histogram = Histogram::default();
+        let mut value_size_histogram = Histogram::default();
+
+        for ss_table in self.ss_tables_map.values() {
+            key_size_histogram.merge(ss_table.get_key_size_histogram());
+            value_size_histogram.merge(ss_table.get_value_size_histogram());
+        }
+
+        let vec_level_overlap = (0..7)
+            .map(|level| self.overlap_bytes(level))
+            .collect_vec();
+        let vec_level_tombstone = (0..7)
+            .map(|level| self.tombstone_count(level))
+            .collect_vec();
+
+        let amplification = Amplification {
+            compaction_written_bytes: self.compaction_written_bytes,
+            ..Amplification::default()
+        };
+
+        Stats { key_size_histogram, value_size_histogram, compaction_stats: self.compaction_stats, vec_level_overlap, vec_level_tombstone, scheduler_decision: None, amplification }
+    }
+
+    /// 获取该Level与下一Level的重叠比例
+    ///
+    /// 即下一Level中与该Level的SSTable范围重叠(`Scope::meet`)的SSTable大小之和与该Level的SSTable大小之和的比值，
+    /// 约为该Level的数据全部压缩至下一Level时每字节需要重写的下一Level数据量，比例越高写放大越大
+    /// 该Level为空或为最后一层时为0
+    #[allow(dead_code)]
+    pub(crate) fn overlap_ratio(&self, level: usize) -> f64 {
+        let (level_bytes, overlap_bytes) = self.overlap_bytes(level);
+        overlap_ratio(level_bytes, overlap_bytes)
+    }
+
+    /// 获取该Level中墓碑数据的数量与数据的总数量
+    fn tombstone_count(&self, level: usize) -> (usize, usize) {
+        self.get_vec_ss_table_with_level(level)
+            .iter()
+            .fold((0, 0), |(tombstone_count, len), ss_table| {
+                (tombstone_count + ss_table.get_tombstone_count(), len + ss_table.len())
+            })
+    }
+
+    /// 墓碑占比超过threshold的Level输出告警并建议进行压缩
+    ///
+    /// 于MemTable落盘与Major压缩写入Manifest后调用，每`TOMBSTONE_WARN_INTERVAL`至多告警一次
+    pub(crate) fn warn_tombstone_ratio(&mut self, threshold: f64) {
+        if self.last_tombstone_warn.map_or(false, |instant| instant.elapsed() < TOMBSTONE_WARN_INTERVAL) {
+            return;
+        }
+        let vec_level_ratio = (0..LEVEL_COUNT)
+            .map(|level| {
+                let (tombstone_count, len) = self.tombstone_count(level);
+                (level, tombstone_ratio(tombstone_count, len))
+            })
+            .filter(|(_, ratio)| *ratio > threshold)
+            .collect_vec();
+        if vec_level_ratio.is_empty() {
+            return;
+        }
+
+        for (level, ratio) in vec_level_ratio {
+            warn!(
+                "[LsmStore][Compaction][Level: {}]: tombstone ratio {:.2} exceeds {:.2}, compaction is suggested",
+                level, ratio, threshold
+            );
+        }
+        self.last_tombstone_warn = Some(Instant::now());
+    }
+
+    /// 获取该Level的SSTable大小之和，与下一Level中与之范围重叠的SSTable大小之和
+    fn overlap_bytes(&self, level: usize) -> (u64, u64) {
+        if level >= 6 {
+            return (0, 0);
+        }
+        let vec_ss_table = self.get_vec_ss_table_with_level(level);
+        let level_bytes = vec_ss_table.iter()
+            .map(|ss_table| ss_table.get_size_of_disk())
+            .sum();
+        let overlap_bytes = self.get_vec_ss_table_with_level(level + 1).into_iter()
+            .filter(|next_ss_table| vec_ss_table.iter()
+                .any(|ss_table| ss_table.get_scope().meet(next_ss_table.get_scope())))
+            .map(SsTable::get_size_of_disk)
+            .sum();
+
+        (level_bytes, overlap_bytes)
     }
 
     pub(crate) fn get_ss_table_batch(&self, vec_gen: &[i64]) -> Option<Vec<&SsTable>> {
@@ -278,15 +1304,94 @@ impl Manifest {
             .collect::<Option<Vec<&SsTable>>>()
     }
 
-    #[allow(clippy::unwrap_used)]
     pub(crate) fn get_meet_scope_ss_tables(&self, level: usize, scope: &Scope) -> Vec<&SsTable> {
         self.get_level_vec(level).iter()
             .filter_map(|gen| self.get_ss_table(gen))
-            .filter(|ss_table| ss_table.get_scope().meet(scope) &&
-                self.sync_buffer_of_meet.lock().unwrap().remove(&ss_table.get_gen()))
+            .filter(|ss_table| ss_table.get_scope().meet(scope))
             .collect_vec()
     }
 
+    /// 选中一组SSTable用于压缩
+    ///
+    /// 在同一次加锁中检查并移除所有Gen，仅当其中的SSTable均未被其他压缩选中时成功，
+    /// 否则不选中任何SSTable并返回false，以此保证同一SSTable同时只被一个压缩选中
+    #[allow(clippy::unwrap_used)]
+    pub(crate) fn try_select_for_compaction(&self, vec_gen: &[i64]) -> bool {
+        let mut sync_buffer_of_meet = self.sync_buffer_of_meet.lock().unwrap();
+
+        if vec_gen.iter().all(|gen| sync_buffer_of_meet.contains(gen)) {
+            for gen in vec_gen {
+                let _ignore = sync_buffer_of_meet.remove(gen);
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// 压缩失败时释放已选中的SSTable，使其能够再次被选中
+    #[allow(clippy::unwrap_used)]
+    pub(crate) fn release_for_compaction(&self, vec_gen: &[i64]) {
+        self.sync_buffer_of_meet.lock().unwrap()
+            .extend(vec_gen.iter().filter(|gen| self.ss_tables_map.contains_key(gen) && !self.corrupted_gens.contains(gen)));
+    }
+
+    /// 获取布隆过滤器所占用的内存大小，包括常驻的布隆过滤器与缓存中的布隆过滤器
+    /// 以布隆过滤器的序列化大小进行估算
+    pub(crate) async fn bloom_memory_usage(&self) -> Result<u64> {
+        let mut memory_usage = 0;
+
+        for filter in self.ss_tables_map.values()
+            .filter_map(|ss_table| ss_table.resident_filter())
+        {
+            memory_usage += bincode::serialized_size(filter)?;
+        }
+        for (_, filter) in self.filter_cache.lock().await.iter() {
+            memory_usage += bincode::serialized_size(filter)?;
+        }
+
+        Ok(memory_usage)
+    }
+
+    /// 校验level_slice与ss_tables_map的一致性
+    ///
+    /// level_slice中的每个gen都应存在于ss_tables_map，且ss_tables_map中的每个SSTable都应处于其所属的Level，
+    /// Level 1-7中的SSTable的scope则应由小到大排列且互不重叠
+    pub(crate) fn check_consistency(&self) -> Result<()> {
+        let inconsistent = |reason: String| Err(KvsError::ManifestInconsistent(reason));
+
+        for (level, vec_gen) in self.level_slice.iter().enumerate() {
+            for gen in vec_gen {
+                match self.get_ss_table(gen) {
+                    None => return inconsistent(format!("gen {} in level {} not found in ss_tables_map", gen, level)),
+                    Some(ss_table) if ss_table.get_level() != level => {
+                        return inconsistent(format!("gen {} in level {} but SSTable level is {}", gen, level, ss_table.get_level()));
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+        for (gen, ss_table) in self.ss_tables_map.iter() {
+            let count = self.level_slice.iter()
+                .flatten()
+                .filter(|level_gen| gen.eq(*level_gen))
+                .count();
+            if count != 1 {
+                return inconsistent(format!("gen {} appears {} times in level_slice, level {}", gen, count, ss_table.get_level()));
+            }
+        }
+        for level in 1..7 {
+            for (first, second) in self.get_vec_ss_table_with_level(level).into_iter().tuple_windows() {
+                if !first.get_scope().is_before(second.get_scope()) {
+                    return inconsistent(format!("scope of gen {} and gen {} in level {} overlap or disorder",
+                                                first.get_gen(), second.get_gen(), level));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     pub(crate) fn get_index(&self, level: usize, source_gen: i64) -> Option<usize> {
         self.level_slice[level].iter()
             .enumerate()
@@ -297,14 +1402,264 @@ impl Manifest {
 
 impl Position {
     /// 通过稀疏索引与指定Key进行获取对应Position
-    pub(crate) fn from_sparse_index_with_key<'a>(sparse_index: &'a SkipMap<Vec<u8>, Position>, key: &'a [u8]) -> Option<&'a Self> {
-        sparse_index.into_iter()
-            .rev()
-            .find(|(key_item, _)| !key.cmp(key_item).eq(&Ordering::Less))
+    ///
+    /// 稀疏索引以Key由小到大排列，二分查找不大于该Key的最大索引项
+    pub(crate) fn from_sparse_index_with_key<'a>(sparse_index: &'a [(Vec<u8>, Position)], key: &[u8]) -> Option<&'a Self> {
+        sparse_index.partition_point(|(key_item, _)| key_item.as_slice() <= key)
+            .checked_sub(1)
+            .and_then(|index| sparse_index.get(index))
             .map(|(_, value_item)| value_item)
     }
 }
 
+impl SsTableSnapshot {
+    /// 获取快照中各SSTable的KeyCursor，由新往旧
+    ///
+    /// Level 0中的SSTable间Key可能重叠，各自作为一个KeyCursor；Level 1及以上同层的SSTable则共用一个KeyCursor
+    pub(crate) fn key_cursors(&self) -> Vec<KeyCursor<'_>> {
+        let mut vec_cursor: Vec<KeyCursor<'_>> = Vec::new();
+        let mut option_level = None;
+
+        for ss_table in self.vec_ss_table.iter().map(Arc::as_ref) {
+            let level = ss_table.get_level();
+            let is_same_level = level != LEVEL_0 && option_level == Some(level);
+            match vec_cursor.last_mut() {
+                Some(cursor) if is_same_level => cursor.vec_ss_table.push_back(ss_table),
+                _ => vec_cursor.push(KeyCursor::new(vec![ss_table]))
+            }
+            option_level = Some(level);
+        }
+
+        vec_cursor
+    }
+
+    /// 获取快照中处于start与end边界之间的数据源，由新往旧
+    pub(crate) fn range_sources(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<RangeSource<'_>> {
+        self.vec_ss_table.iter()
+            .map(|ss_table| ss_table.range_source(start, end))
+            .filter(|source| source.lower_bound().is_some())
+            .collect()
+    }
+}
+
+impl<'a> KeyCursor<'a> {
+    fn new(vec_ss_table: Vec<&'a SsTable>) -> Self {
+        KeyCursor { vec_ss_table: vec_ss_table.into(), keys: VecDeque::new() }
+    }
+
+    /// 以已有序的Key及其是否为墓碑构建
+    pub(crate) fn from_keys(keys: Vec<(Vec<u8>, bool)>) -> Self {
+        KeyCursor { vec_ss_table: VecDeque::new(), keys: keys.into() }
+    }
+
+    /// 当前的Key读尽时读取下一个SSTable的Key块
+    async fn fill(&mut self) -> Result<()> {
+        while self.keys.is_empty() {
+            match self.vec_ss_table.pop_front() {
+                Some(ss_table) => self.keys = ss_table.get_all_keys().await?.into(),
+                None => break
+            }
+        }
+
+        Ok(())
+    }
+
+    fn peek(&self) -> Option<&[u8]> {
+        self.keys.front()
+            .map(|(key, _)| key.as_slice())
+    }
+}
+
+impl Drop for SsTableSnapshot {
+    #[allow(clippy::unwrap_used)]
+    fn drop(&mut self) {
+        let mut pinned_gens = self.pinned_gens.lock().unwrap();
+
+        for ss_table in &self.vec_ss_table {
+            if let Entry::Occupied(mut entry) = pinned_gens.entry(ss_table.get_gen()) {
+                *entry.get_mut() -= 1;
+                if *entry.get() == 0 {
+                    let _ignore = entry.remove();
+                }
+            }
+        }
+    }
+}
+
+/// 将过期gen追加至待删除列表并落盘
+///
+/// 先写入临时文件再重命名，避免写入中断时损坏原有列表
+fn record_pending_delete(dir_path: &Path, vec_expired_gen: &[i64]) -> Result<()> {
+    let mut vec_pending_gen = read_pending_delete(dir_path)?;
+    vec_pending_gen.extend_from_slice(vec_expired_gen);
+
+    write_pending_delete(dir_path, &vec_pending_gen)
+}
+
+/// 以临时文件原子地覆写待删除列表
+fn write_pending_delete(dir_path: &Path, vec_pending_gen: &[i64]) -> Result<()> {
+    let pending_path = dir_path.join(PENDING_DELETE_FILE_NAME);
+    let temp_path = pending_path.with_extension("tmp");
+    write_and_sync(&temp_path, &bincode::serialize(vec_pending_gen)?)?;
+    fs::rename(temp_path, pending_path)?;
+
+    Ok(())
+}
+
+fn read_pending_delete(dir_path: &Path) -> Result<Vec<i64>> {
+    let pending_path = dir_path.join(PENDING_DELETE_FILE_NAME);
+
+    if !pending_path.exists() {
+        return Ok(Vec::new());
+    }
+    Ok(bincode::deserialize(&fs::read(pending_path)?)?)
+}
+
+/// 删除待删除列表中的SSTable文件，全部删除后移除该列表，返回删除的文件数量
+///
+/// 已不存在的文件视为已删除
+/// 用于开启时清理上次残留的文件，此时尚无IOHandler打开这些文件
+pub(crate) fn clean_pending_delete(dir_path: &Path, extra_dir_paths: &[PathBuf]) -> Result<usize> {
+    clean_pending_delete_except(dir_path, extra_dir_paths, None, |_| false)
+}
+
+/// 删除待删除列表中is_retained不成立的SSTable文件，返回删除的文件数量
+///
+/// 被保留的gen仍留于待删除列表中，待之后的清理再删除，列表为空时移除该列表
+/// io_handler_factory不为None时经由`IOHandlerFactory::clean`删除，一并移除句柄池中该gen的只读句柄
+fn clean_pending_delete_except<F>(dir_path: &Path, extra_dir_paths: &[PathBuf], io_handler_factory: Option<&IOHandlerFactory>, is_retained: F) -> Result<usize>
+    where F: Fn(i64) -> bool
+{
+    let mut count = 0;
+    let (vec_retained_gen, vec_expired_gen): (Vec<i64>, Vec<i64>) = read_pending_delete(dir_path)?
+        .into_iter()
+        .partition(|gen| is_retained(*gen));
+
+    for gen in vec_expired_gen {
+        let result = match io_handler_factory {
+            Some(io_handler_factory) => io_handler_factory.clean(gen),
+            None => fs::remove_file(locate_log_path(dir_path, extra_dir_paths, gen))
+                .map_err(KvsError::from)
+        };
+        match result {
+            Ok(()) => count += 1,
+            Err(KvsError::Io(err)) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err)
+        }
+    }
+    let pending_path = dir_path.join(PENDING_DELETE_FILE_NAME);
+    if !vec_retained_gen.is_empty() {
+        write_pending_delete(dir_path, &vec_retained_gen)?;
+    } else if pending_path.exists() {
+        fs::remove_file(pending_path)?;
+    }
+
+    Ok(count)
+}
+
+/// 查找SSTable文件的路径
+///
+/// 依次在dir_path与extra_dir_paths中查找，均不存在时为dir_path中的路径
+pub(crate) fn locate_log_path(dir_path: &Path, extra_dir_paths: &[PathBuf], gen: i64) -> PathBuf {
+    let path = log_path(dir_path, gen);
+
+    if path.exists() {
+        return path;
+    }
+    extra_dir_paths.iter()
+        .map(|extra_dir_path| log_path(extra_dir_path, gen))
+        .find(|extra_path| extra_path.exists())
+        .unwrap_or(path)
+}
+
+/// 计算SSTable文件整体的crc
+fn file_crc(dir_path: &Path, extra_dir_paths: &[PathBuf], gen: i64) -> Result<u32> {
+    Ok(crc32fast::hash(&fs::read(locate_log_path(dir_path, extra_dir_paths, gen))?))
+}
+
+/// 读取校验和清单，不存在时返回None
+fn read_checksum_manifest(dir_path: &Path) -> Result<Option<BTreeMap<i64, u32>>> {
+    let checksum_path = dir_path.join(CHECKSUM_MANIFEST_FILE_NAME);
+
+    if !checksum_path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(bincode::deserialize(&fs::read(checksum_path)?)?))
+}
+
+/// 覆盖写入校验和清单
+///
+/// 先写入临时文件再重命名，避免写入中断时损坏原有清单
+fn write_checksum_manifest(dir_path: &Path, checksums: &BTreeMap<i64, u32>) -> Result<()> {
+    let checksum_path = dir_path.join(CHECKSUM_MANIFEST_FILE_NAME);
+    let temp_path = checksum_path.with_extension("tmp");
+    write_and_sync(&temp_path, &bincode::serialize(checksums)?)?;
+    fs::rename(temp_path, checksum_path)?;
+
+    Ok(())
+}
+
+/// 以校验和清单校验目录中的SSTable，返回校验通过的各SSTable的crc
+///
+/// 清单中记录的SSTable被修改或删除时返回`KvsError::ChecksumManifestMismatch`，并附带所有不一致的Gen；
+/// 未记录于清单中的SSTable视为尚未记录的新文件(如生成后未及更新清单即崩溃)，不视为不一致
+/// 清单不存在时直接返回空集
+pub(crate) fn verify_checksum_manifest(dir_path: &Path, extra_dir_paths: &[PathBuf]) -> Result<BTreeMap<i64, u32>> {
+    let checksums = match read_checksum_manifest(dir_path)? {
+        Some(checksums) => checksums,
+        None => return Ok(BTreeMap::new())
+    };
+    let mut vec_changed_gen = Vec::new();
+
+    for (gen, crc) in checksums.iter() {
+        match file_crc(dir_path, extra_dir_paths, *gen) {
+            Ok(file_crc) if file_crc == *crc => (),
+            Ok(_) => vec_changed_gen.push(*gen),
+            Err(KvsError::Io(err)) if err.kind() == io::ErrorKind::NotFound => vec_changed_gen.push(*gen),
+            Err(err) => return Err(err)
+        }
+    }
+    if !vec_changed_gen.is_empty() {
+        return Err(KvsError::ChecksumManifestMismatch(vec_changed_gen));
+    }
+
+    Ok(checksums)
+}
+
+/// 获取数据的Key及其是否为墓碑，即是否为CommandData::Remove
+pub(crate) fn key_with_tombstone(cmd_data: &CommandData) -> (Vec<u8>, bool) {
+    (cmd_data.get_key_clone(), matches!(cmd_data, CommandData::Remove { .. }))
+}
+
+/// 以Key由小到大归并一组由新往旧排列的KeyCursor，相同的Key仅以最新的墓碑标记回调一次
+///
+/// 每个KeyCursor同时仅加载一个SSTable的Key块，回调返回Err时中止归并并返回该Err
+pub(crate) async fn for_each_merged_key<F>(mut vec_cursor: Vec<KeyCursor<'_>>, mut f: F) -> Result<()>
+    where F: FnMut(&[u8], bool) -> Result<()> + Send
+{
+    loop {
+        for cursor in vec_cursor.iter_mut() {
+            cursor.fill().await?;
+        }
+        let min_key = match vec_cursor.iter().filter_map(KeyCursor::peek).min() {
+            Some(min_key) => min_key.to_vec(),
+            None => return Ok(())
+        };
+
+        let mut option_is_tombstone = None;
+        for cursor in vec_cursor.iter_mut() {
+            if cursor.peek() == Some(min_key.as_slice()) {
+                if let Some((_, is_tombstone)) = cursor.keys.pop_front() {
+                    let _ignore = option_is_tombstone.get_or_insert(is_tombstone);
+                }
+            }
+        }
+        if let Some(is_tombstone) = option_is_tombstone {
+            f(&min_key, is_tombstone)?;
+        }
+    }
+}
+
 /// CommandData数据分片，尽可能将数据按给定的分片大小：file_size，填满一片（可能会溢出一些）
 /// 保持原有数据的顺序进行分片，所有第一片分片中最后的值肯定会比其他分片开始的值Key排序较前（如果vec_data是以Key从小到大排序的话）
 async fn data_sharding(mut vec_data: Vec<CommandData>, file_size: usize, config: &Config, with_gen: bool) -> MergeShardingVec {
@@ -338,6 +1693,169 @@ async fn data_sharding(mut vec_data: Vec<CommandData>, file_size: usize, config:
     vec_sharding
 }
 
+/// 以字节数计算重叠比例，level_bytes为0时为0
+#[allow(clippy::float_arithmetic)]
+pub(crate) fn overlap_ratio(level_bytes: u64, overlap_bytes: u64) -> f64 {
+    if level_bytes == 0 {
+        return 0.0;
+    }
+    overlap_bytes as f64 / level_bytes as f64
+}
+
+/// 以数据数量计算墓碑占比，len为0时为0
+#[allow(clippy::float_arithmetic)]
+pub(crate) fn tombstone_ratio(tombstone_count: usize, len: usize) -> f64 {
+    if len == 0 {
+        return 0.0;
+    }
+    tombstone_count as f64 / len as f64
+}
+
+/// 获取大小相近的SSTable的最大数量
+///
+/// 将大小由小到大排列，大小不超过其中最小者`SIZE_TIERED_BUCKET_RATIO`倍的SSTable视为大小相近
+#[allow(clippy::float_arithmetic)]
+fn similar_size_count(mut vec_size: Vec<u64>) -> usize {
+    vec_size.sort_unstable();
+
+    let mut max_count = 0;
+    let mut start = 0;
+    for (end, size) in vec_size.iter().enumerate() {
+        while (*size as f64) > (vec_size[start] as f64) * SIZE_TIERED_BUCKET_RATIO {
+            start += 1;
+        }
+        max_count = max_count.max(end - start + 1);
+    }
+    max_count
+}
+
+/// 将较新的数据与较旧的数据折叠
+///
+/// 较新的数据不为Merge时直接覆盖较旧的数据；较旧的数据为Merge时拼接两者的操作数，
+/// 为Set或墓碑时则通过merge_operator合并为Set
+pub(crate) fn merge_cmd_data(newer: CommandData, older: CommandData, merge_operator: Option<MergeOperator>) -> Result<CommandData> {
+    let (key, operands) = match newer {
+        CommandData::Merge { key, operands } => (key, operands),
+        newer => return Ok(newer)
+    };
+
+    match older.decompress()? {
+        CommandData::Merge { operands: mut older_operands, .. } => {
+            older_operands.extend(operands);
+            Ok(CommandData::Merge { key, operands: older_operands })
+        }
+        CommandData::Set { value, .. } => apply_merge(key, Some(value.as_slice()), &operands, merge_operator),
+        CommandData::Remove { .. } => apply_merge(key, None, &operands, merge_operator),
+        // Key-Value分离时value位于vLog之中，不支持与Merge折叠
+        _ => Err(KvsError::UnexpectedCommandType)
+    }
+}
+
+fn is_merge(cmd_data: &CommandData) -> bool {
+    matches!(cmd_data, CommandData::Merge { .. })
+}
+
+/// 不存在更旧的数据时，以existing为None完成Merge，其余数据原样返回
+pub(crate) fn resolve_merge(cmd_data: CommandData, merge_operator: Option<MergeOperator>) -> Result<CommandData> {
+    match cmd_data {
+        CommandData::Merge { key, operands } => apply_merge(key, None, &operands, merge_operator),
+        cmd_data => Ok(cmd_data)
+    }
+}
+
+fn apply_merge(key: Vec<u8>, existing: Option<&[u8]>, operands: &[Vec<u8>], merge_operator: Option<MergeOperator>) -> Result<CommandData> {
+    let merge_operator = merge_operator.ok_or(KvsError::MergeOperatorNotSet)?;
+    let value = merge_operator(&key, existing, operands);
+
+    Ok(CommandData::Set { key, value })
+}
+
+/// 判断key是否处于start边界之后，start为Unbounded时恒为true
+pub(crate) fn is_after_start(key: &[u8], start: Bound<&[u8]>) -> bool {
+    match start {
+        Bound::Included(start) => key >= start,
+        Bound::Excluded(start) => key > start,
+        Bound::Unbounded => true
+    }
+}
+
+/// 判断key是否处于end边界之前，end为Unbounded时恒为true
+pub(crate) fn is_before_end(key: &[u8], end: Bound<&[u8]>) -> bool {
+    match end {
+        Bound::Included(end) => key <= end,
+        Bound::Excluded(end) => key < end,
+        Bound::Unbounded => true
+    }
+}
+
+/// 判断key是否处于start与end边界之间
+pub(crate) fn is_in_bound(key: &[u8], start: Bound<&[u8]>, end: Bound<&[u8]>) -> bool {
+    is_after_start(key, start) && is_before_end(key, end)
+}
+
+/// 对由新往旧排列的数据源进行多路归并，获取处于start与end边界之间的至多limit个有效数据
+///
+/// 达到limit时立即停止归并与读盘
+async fn merge_range_sources(vec_source: Vec<RangeSource<'_>>, value_log: &ValueLog, merge_operator: Option<MergeOperator>, start: Bound<&[u8]>, end: Bound<&[u8]>, limit: usize) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    let mut vec_kv = Vec::new();
+
+    if limit > 0 {
+        merge_range_sources_with(vec_source, value_log, merge_operator, start, end, |key, value| {
+            vec_kv.push((key, value));
+            Ok(vec_kv.len() < limit)
+        }).await?;
+    }
+
+    Ok(vec_kv)
+}
+
+/// 对由新往旧排列的数据源进行多路归并，将处于start与end边界之间的有效数据以Key由小到大逐个交由f处理
+///
+/// 同一Key仅取最新数据源中的数据，最新的数据为Merge时则与更旧数据源中的数据折叠，墓碑数据则被跳过
+/// SSTable的数据段仅在归并至该段时才读取，因此内存中至多同时持有各数据源的一段数据
+/// f返回false时立即停止归并与读盘
+/// SetPtr的value会通过vLog读取
+async fn merge_range_sources_with<F>(mut vec_source: Vec<RangeSource<'_>>, value_log: &ValueLog, merge_operator: Option<MergeOperator>, start: Bound<&[u8]>, end: Bound<&[u8]>, mut f: F) -> Result<()>
+    where F: FnMut(Vec<u8>, Vec<u8>) -> Result<bool>
+{
+    loop {
+        let min_key = match vec_source.iter()
+            .filter_map(RangeSource::lower_bound)
+            .min()
+        {
+            Some(key) => key.to_vec(),
+            None => break
+        };
+        // 可能存在该Key的数据段尚未读取时，先读取后再重新判断
+        if let Some(source) = vec_source.iter_mut()
+            .find(|source| !source.is_loaded() && source.lower_bound() == Some(min_key.as_slice()))
+        {
+            source.load(start, end).await?;
+            continue
+        }
+        // 数据源由新往旧排列，第一个取出的即为最新数据
+        let mut option_newest = None;
+        for source in vec_source.iter_mut() {
+            if let Some(cmd_data) = source.pop_with_key(&min_key) {
+                option_newest = Some(match option_newest {
+                    Some(newer) => merge_cmd_data(newer, cmd_data, merge_operator)?,
+                    None => cmd_data
+                });
+            }
+        }
+        let is_continue = match option_newest.map(|cmd_data| resolve_merge(cmd_data, merge_operator)).transpose()? {
+            Some(CommandData::Set { key, value }) => f(key, value)?,
+            Some(CommandData::SetPtr { key, ptr }) => f(key, value_log.read(&ptr).await?)?,
+            _ => true
+        };
+        if !is_continue {
+            break
+        }
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_meta_info() -> Result<()> {
     let info = MetaInfo {
@@ -353,4 +1871,117 @@ fn test_meta_info() -> Result<()> {
     assert_eq!(vec_u8.len(), TABLE_META_INFO_SIZE);
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_mem_table_snapshot() {
+    tokio_test::block_on(async move {
+        let mem_table = MemTable::new(MemMap::new());
+        let key = vec![b'k'];
+
+        mem_table.insert_data(key.clone(), CommandData::set(key.clone(), vec![b'1'])).await;
+        let snapshot = mem_table.snapshot();
+        mem_table.insert_data(key.clone(), CommandData::set(key.clone(), vec![b'2'])).await;
+
+        // 快照不受之后写入的影响
+        assert_eq!(snapshot[0].0.get(&key), Some(&CommandData::set(key.clone(), vec![b'1'])));
+        assert_eq!(mem_table.get_cmd_data(&key).await, Some(CommandData::set(key, vec![b'2'])));
+    })
+}
+
+#[test]
+fn test_mem_table_flush_ticket() {
+    tokio_test::block_on(async move {
+        let mem_table = MemTable::new(MemMap::new());
+        let key = vec![b'k'];
+        let gen = AtomicU64::new(0);
+        let create_gen = || gen.fetch_add(1, Ordering::SeqCst) as i64;
+
+        // 空MemTable的凭证不参与排序
+        let (_, _, empty_ticket) = mem_table.table_swap(create_gen).await;
+        mem_table.insert_data(key.clone(), CommandData::set(key.clone(), vec![b'1'])).await;
+        let (_, _, mut first) = mem_table.table_swap(create_gen).await;
+        mem_table.insert_data(key.clone(), CommandData::set(key.clone(), vec![b'2'])).await;
+        let (_, _, mut second) = mem_table.table_swap(create_gen).await;
+        let mut third = mem_table.flush_ticket(create_gen);
+        drop(empty_ticket);
+
+        // 凭证的Gen随领取顺序递增
+        assert!(first.gen() < second.gen() && second.gen() < third.gen());
+        first.wait_prev().await;
+        // 前一个凭证完成前需等待
+        assert!(tokio::time::timeout(Duration::from_millis(10), second.wait_prev()).await.is_err());
+        drop(first);
+        second.wait_prev().await;
+        drop(second);
+        third.wait_prev().await;
+    })
+}
+
+#[test]
+fn test_position_from_sparse_index() {
+    let sparse_index = (0..100000)
+        .map(|i| (format!("key{:06}", i * 2).into_bytes(), Position { start: i, len: 1 }))
+        .collect_vec();
+    // 逐项线性扫描的查找方式，用于语义的对比
+    let linear_search = |key: &[u8]| sparse_index.iter()
+        .rev()
+        .find(|(key_item, _)| key_item.as_slice() <= key)
+        .map(|(_, position)| position);
+
+    let vec_key = (0..1000)
+        .map(|i| format!("key{:06}", i * 199).into_bytes())
+        .chain(vec![b"a".to_vec(), b"key".to_vec(), b"z".to_vec()])
+        .collect_vec();
+    for key in vec_key.iter() {
+        assert_eq!(Position::from_sparse_index_with_key(&sparse_index, key), linear_search(key));
+    }
+    assert_eq!(Position::from_sparse_index_with_key(&sparse_index, b"a"), None);
+    assert_eq!(Position::from_sparse_index_with_key(&sparse_index, b"key000001"), Some(&Position { start: 0, len: 1 }));
+    assert_eq!(Position::from_sparse_index_with_key(&sparse_index, b"z"), Some(&Position { start: 99999, len: 1 }));
+}
+
+#[test]
+fn test_manifest_version_order() -> Result<()> {
+    use tempfile::TempDir;
+    use crate::kernel::io_handler::IOHandlerFactory;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let path = temp_dir.path().to_path_buf();
+        let config = Config::default().dir_path(path.clone());
+        let factory = IOHandlerFactory::new(&path);
+        // 构造乱序的Gen：新数据位于Gen较小的SSTable之中
+        let new_gen = config.create_gen();
+        let old_gen = config.create_gen();
+        for (gen, data_version, value) in [(old_gen, 1, b"old"), (new_gen, 2, b"new")] {
+            let vec_data = vec![CommandData::set(b"key".to_vec(), value.to_vec())];
+            let _ignore = SsTable::create_for_immutable_table(&config, factory.create(gen)?, vec_data, 0, data_version).await?;
+        }
+        let cmd_data_new = CommandData::set(b"key".to_vec(), b"new".to_vec());
+        let cmd_data_old = CommandData::set(b"key".to_vec(), b"old".to_vec());
+
+        for version_order in [VersionOrder::Version, VersionOrder::Gen] {
+            let config = Config::default()
+                .dir_path(path.clone())
+                .version_order(version_order);
+            let mut ss_tables_map = SsTableMap::new();
+            for gen in [old_gen, new_gen] {
+                let _ignore = ss_tables_map.insert(gen, Arc::new(SsTable::restore_from_file(factory.create(gen)?, true).await?));
+            }
+            let manifest = Manifest::new(ss_tables_map, Arc::new(path.clone()), &config)?;
+
+            if version_order == VersionOrder::Version {
+                assert_eq!(manifest.get_data_for_ss_tables(b"key").await?, Some(cmd_data_new.clone()));
+                assert_eq!(manifest.get_level_data(0).await?, vec![cmd_data_new.clone()]);
+                assert_eq!(manifest.get_vec_ss_table_from_old_to_new().last().map(|ss_table| ss_table.get_gen()), Some(new_gen));
+            } else {
+                // 仅以Gen判断时取到Gen较大的旧值
+                assert_eq!(manifest.get_data_for_ss_tables(b"key").await?, Some(cmd_data_old.clone()));
+            }
+        }
+
+        Ok(())
+    })
+}
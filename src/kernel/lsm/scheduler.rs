@@ -0,0 +1,188 @@
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use arc_swap::ArcSwap;
+use tokio::sync::Notify;
+use crate::kernel::lsm::lsm_kv::{Config, SchedulerDecision};
+
+/// 写入速率的采样间隔
+pub(crate) const DEFAULT_SCHEDULER_SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 期望的最短落盘间隔
+/// 写入速率较高时增大落盘阈值，使两次落盘之间的间隔尽量不短于此
+pub(crate) const DEFAULT_SCHEDULER_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 自适应压缩调度器
+///
+/// 以MemTable累计写入的字节数采样近期的写入速率，并结合待压缩的积压决定落盘阈值与并发度：
+/// 1. 突发写入时增大落盘阈值(至多为`Config::minor_threshold_with_data_size`的`Config::adaptive_compaction_max_factor`倍)，
+/// 避免频繁落盘与随之触发的压缩
+/// 2. 存在积压时按积压数量缩小落盘阈值，避免内存堆积，并提高落盘的并发度以尽快消化积压
+#[derive(Debug)]
+pub(crate) struct CompactionScheduler {
+    base_threshold: AtomicU64,
+    max_threshold: AtomicU64,
+    max_factor: u64,
+    max_concurrency: usize,
+    sample: Mutex<WriteSample>,
+    decision: ArcSwap<SchedulerDecision>,
+    /// 正在进行的落盘数量
+    running: AtomicUsize,
+    slot_notify: Notify
+}
+
+/// 写入速率的采样状态
+#[derive(Debug)]
+struct WriteSample {
+    start: Instant,
+    written_bytes: u64,
+    /// 平滑后的写入速率(单位: 字节/秒)
+    write_rate: u64
+}
+
+/// 落盘的执行许可，Drop时归还
+#[derive(Debug)]
+pub(crate) struct CompactionSlot {
+    scheduler: Arc<CompactionScheduler>
+}
+
+impl CompactionScheduler {
+    pub(crate) fn new(config: &Config) -> Self {
+        let base_threshold = config.minor_threshold_with_data_size.load(Ordering::Relaxed);
+        let max_factor = config.adaptive_compaction_max_factor.max(1);
+        let max_concurrency = config.max_immutable_tables.max(1);
+
+        CompactionScheduler {
+            base_threshold: AtomicU64::new(base_threshold),
+            max_threshold: AtomicU64::new(base_threshold.saturating_mul(max_factor)),
+            max_factor,
+            max_concurrency,
+            sample: Mutex::new(WriteSample { start: Instant::now(), written_bytes: 0, write_rate: 0 }),
+            decision: ArcSwap::from_pointee(SchedulerDecision {
+                write_rate: 0,
+                backlog: 0,
+                minor_threshold: base_threshold,
+                concurrency: 1
+            }),
+            running: AtomicUsize::new(0),
+            slot_notify: Notify::new()
+        }
+    }
+
+    /// 以MemTable累计写入的字节数进行采样
+    ///
+    /// 距上次采样超过`DEFAULT_SCHEDULER_SAMPLE_INTERVAL`时更新写入速率并返回true，此时应调用`decide`重新决策
+    /// 写入速率为本次采样速率与上次速率的平均值，以平滑单次采样的波动
+    #[allow(clippy::unwrap_used)]
+    pub(crate) fn sample(&self, written_bytes: u64) -> bool {
+        let mut sample = self.sample.lock().unwrap();
+        let elapsed = sample.start.elapsed();
+
+        if elapsed < DEFAULT_SCHEDULER_SAMPLE_INTERVAL {
+            return false;
+        }
+        let elapsed_millis = (elapsed.as_millis() as u64).max(1);
+        let rate = written_bytes.saturating_sub(sample.written_bytes)
+            .saturating_mul(1000) / elapsed_millis;
+
+        sample.write_rate = rate / 2 + sample.write_rate / 2;
+        sample.written_bytes = written_bytes;
+        sample.start = Instant::now();
+        true
+    }
+
+    /// 更新落盘阈值的基准，于下次决策时生效
+    pub(crate) fn set_base_threshold(&self, base_threshold: u64) {
+        self.base_threshold.store(base_threshold, Ordering::Relaxed);
+        self.max_threshold.store(base_threshold.saturating_mul(self.max_factor), Ordering::Relaxed);
+    }
+
+    /// 根据当前的写入速率与积压重新决策
+    #[allow(clippy::unwrap_used)]
+    pub(crate) fn decide(&self, backlog: usize) -> SchedulerDecision {
+        let write_rate = self.sample.lock().unwrap().write_rate;
+        let flush_interval_millis = DEFAULT_SCHEDULER_FLUSH_INTERVAL.as_millis() as u64;
+        let target_threshold = write_rate.saturating_mul(flush_interval_millis) / 1000;
+        let base_threshold = self.base_threshold.load(Ordering::Relaxed);
+        let max_threshold = self.max_threshold.load(Ordering::Relaxed).max(base_threshold);
+        let minor_threshold = (target_threshold.clamp(base_threshold, max_threshold)
+            / (backlog as u64 + 1))
+            .max(base_threshold);
+        let concurrency = (backlog + 1).min(self.max_concurrency);
+
+        let decision = SchedulerDecision { write_rate, backlog, minor_threshold, concurrency };
+        let old_decision = self.decision.swap(Arc::new(decision));
+        // 并发度提高时唤醒等待中的落盘
+        if concurrency > old_decision.concurrency {
+            self.slot_notify.notify_waiters();
+        }
+        decision
+    }
+
+    /// 获取当前的决策
+    pub(crate) fn decision(&self) -> SchedulerDecision {
+        **self.decision.load()
+    }
+
+    /// 获取当前的落盘阈值
+    pub(crate) fn minor_threshold(&self) -> u64 {
+        self.decision.load().minor_threshold
+    }
+
+    /// 获取落盘的执行许可
+    ///
+    /// 正在生成SSTable的落盘数量达到当前的并发度时等待
+    /// 许可的获取不保证先后顺序，落盘写入Level 0的顺序由交换MemTable时领取的`FlushTicket`保证
+    pub(crate) async fn acquire_slot(self: &Arc<Self>) -> CompactionSlot {
+        loop {
+            // 先创建Notified再进行判断，避免判断后与等待前之间的唤醒丢失
+            let notified = self.slot_notify.notified();
+            let running = self.running.load(Ordering::Acquire);
+
+            if running < self.decision().concurrency && self.running
+                .compare_exchange(running, running + 1, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+            {
+                return CompactionSlot { scheduler: Arc::clone(self) };
+            }
+            notified.await;
+        }
+    }
+}
+
+impl Drop for CompactionSlot {
+    fn drop(&mut self) {
+        let _ignore = self.scheduler.running.fetch_sub(1, Ordering::AcqRel);
+        self.scheduler.slot_notify.notify_waiters();
+    }
+}
+
+#[test]
+fn test_compaction_scheduler_decide() {
+    let config = Config::default()
+        .minor_threshold_with_data_size(1024)
+        .adaptive_compaction_max_factor(4)
+        .max_immutable_tables(3);
+    let scheduler = CompactionScheduler::new(&config);
+    assert_eq!(scheduler.minor_threshold(), 1024);
+
+    // 未到采样间隔时不更新
+    assert!(!scheduler.sample(1024 * 1024));
+    std::thread::sleep(DEFAULT_SCHEDULER_SAMPLE_INTERVAL);
+    assert!(scheduler.sample(1024 * 1024));
+
+    // 写入速率较高且无积压时增大阈值，但不超过上限
+    let decision = scheduler.decide(0);
+    assert!(decision.write_rate > 0);
+    assert_eq!(decision.minor_threshold, 4 * 1024);
+    assert_eq!(decision.concurrency, 1);
+    assert_eq!(scheduler.decision(), decision);
+
+    // 积压时缩小阈值并提高并发度
+    let decision = scheduler.decide(1);
+    assert_eq!(decision.minor_threshold, 2 * 1024);
+    assert_eq!(decision.concurrency, 2);
+    let decision = scheduler.decide(10);
+    assert_eq!(decision.minor_threshold, 1024);
+    assert_eq!(decision.concurrency, 3);
+}
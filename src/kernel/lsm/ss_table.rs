@@ -1,19 +1,24 @@
 use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use async_trait::async_trait;
 use growable_bloom_filter::GrowableBloom;
 use itertools::Itertools;
 use lru::LruCache;
 use tokio::sync::Mutex;
 use serde::{Deserialize, Serialize};
-use skiplist::SkipMap;
 use tracing::info;
-use crate::kernel::{CommandData, CommandPackage};
-use crate::kernel::io_handler::IOHandler;
-use crate::kernel::lsm::{data_sharding, ExtraInfo, Manifest, MetaInfo, Position};
-use crate::kernel::lsm::lsm_kv::Config;
+use crate::kernel::{CommandData, CommandPackage, FileKind, FileRef, FormatVersion, ShardingWriter, sorted_gen_list};
+use crate::kernel::io_handler::{IOHandler, IOHandlerFactory};
+use crate::kernel::migrator::Migrator;
+use crate::kernel::lsm::{data_sharding, ExtraInfo, is_after_start, is_before_end, is_in_bound, key_with_tombstone, tombstone_ratio, ExtraInfoFilter, FilterCache, Manifest, MetaInfo, Position, TABLE_META_INFO_SIZE};
+use crate::kernel::lsm::lsm_kv::{Config, Histogram, VersionOrder};
 use crate::kernel::Result;
 use crate::KvsError;
 
-const ALIGNMENT_4K: usize = 4096;
+pub(crate) const ALIGNMENT_4K: usize = 4096;
 
 /// SSTable
 #[derive(Debug)]
@@ -21,7 +26,8 @@ pub(crate) struct SsTable {
     // 表索引信息
     meta_info: MetaInfo,
     // 字段稀疏索引
-    sparse_index: SkipMap<Vec<u8>, Position>,
+    // 以Key由小到大排列，可进行二分查找
+    sparse_index: Vec<(Vec<u8>, Position)>,
     // 文件IO操作器
     io_handler: IOHandler,
     // 该SSTable的唯一编号(时间递增)
@@ -29,22 +35,60 @@ pub(crate) struct SsTable {
     // 数据范围索引
     scope: Scope,
     // 过滤器
-    filter: GrowableBloom,
+    // 非常驻时为None，查询时按需从文件中读取
+    filter: Option<GrowableBloom>,
     // 硬盘占有大小
     size_of_disk: u64,
     // 数据数量
     size_of_data: usize,
+    // 数据版本号，即所含数据中最新的版本
+    data_version: u64,
+    // Key大小的直方图
+    key_size_histogram: Histogram,
+    // value大小的直方图
+    value_size_histogram: Histogram,
+    // Key前缀的布隆过滤器，未设置`Config::prefix_bloom_len`时为None
+    // 体积远小于整Key的布隆过滤器，因此始终常驻内存
+    prefix_filter: Option<PrefixFilter>,
+    // 墓碑数据的数量
+    tombstone_count: usize,
+    // 数据段长度头的格式版本，由MetaInfo中的version得出
+    format_version: FormatVersion,
 }
 
 /// 数据范围索引
 /// 用于缓存SSTable中所有数据的第一个和最后一个数据的Key
 /// 标明数据的范围以做到快速区域定位
+///
+/// 序列化格式固定为start与end两个字段，参与SSTable元数据的持久化，不可随意调整
+/// 空Key不合法，因此以start与end均为空表示不包含任何Key的空范围
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub(crate) struct Scope {
     start: Vec<u8>,
     end: Vec<u8>
 }
 
+/// Key前缀的布隆过滤器
+/// 以各Key的前len个字节构建，用于前缀扫描时判断SSTable是否可能含有该前缀的Key
+/// 短于len的Key不参与构建，而其也必然不以长度不小于len的前缀开头
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct PrefixFilter {
+    len: usize,
+    filter: GrowableBloom
+}
+
+/// 范围数据源
+/// 以Key由小到大提供处于范围内的数据
+/// 来源为SSTable时以稀疏索引的数据段为单位，仅在需要时读取
+#[derive(Debug)]
+pub(crate) struct RangeSource<'a> {
+    ss_table: Option<&'a SsTable>,
+    // 尚未读取的数据段，附带其索引Key
+    vec_position: VecDeque<(&'a Vec<u8>, &'a Position)>,
+    // 已读取且处于范围内的数据
+    buffer: VecDeque<CommandData>
+}
+
 impl PartialEq<Self> for SsTable {
     fn eq(&self, other: &Self) -> bool {
         self.meta_info.eq(&other.meta_info)
@@ -57,8 +101,54 @@ impl PartialOrd<Self> for SsTable {
     }
 }
 
+impl PrefixFilter {
+
+    /// 以一组数据的Key构建前缀长度为len的布隆过滤器
+    pub(crate) fn from_vec_cmd_data(len: usize, vec_cmd_data: &[CommandData], desired_error_prob: f64) -> Self {
+        let mut filter = GrowableBloom::new(desired_error_prob, vec_cmd_data.len());
+
+        for key in vec_cmd_data.iter()
+            .map(CommandData::get_key)
+            .filter(|key| key.len() >= len)
+        {
+            let _ignore = filter.insert(&key[..len]);
+        }
+        PrefixFilter { len, filter }
+    }
+
+    /// 判断是否可能存在以prefix为前缀的Key
+    ///
+    /// prefix短于构建时的前缀长度时无法判断，视为可能存在
+    pub(crate) fn may_contain(&self, prefix: &[u8]) -> bool {
+        prefix.len() < self.len || self.filter.contains(&prefix[..self.len])
+    }
+}
+
 impl Scope {
 
+    /// 不包含任何Key的空scope
+    pub(crate) fn empty() -> Self {
+        Scope {
+            start: Vec::new(),
+            end: Vec::new()
+        }
+    }
+
+    /// 判断scope是否不包含任何Key
+    ///
+    /// 除Scope::empty外，start大于end的scope同样视为空
+    pub(crate) fn is_empty(&self) -> bool {
+        self.end.is_empty() || self.start > self.end
+    }
+
+    /// 由[start, end]范围构成scope
+    pub(crate) fn from_range(start: &[u8], end: &[u8]) -> Self {
+        Scope {
+            start: start.to_vec(),
+            end: end.to_vec()
+        }
+    }
+
     /// 由CommandData组成的Key构成scope
     pub(crate) fn from_cmd_data(first: &CommandData, last: &CommandData) -> Self {
         Scope {
@@ -76,7 +166,12 @@ impl Scope {
     }
 
     /// 将多个scope重组融合成一个scope
+    ///
+    /// 空scope不参与融合，不存在非空scope时返回空scope
     pub(crate) fn fusion(vec_scope :Vec<&Scope>) -> Result<Self> {
+        let vec_scope = vec_scope.into_iter()
+            .filter(|scope| !scope.is_empty())
+            .collect_vec();
         if !vec_scope.is_empty() {
             let start = vec_scope.iter()
                 .map(|scope| &scope.start)
@@ -89,14 +184,36 @@ impl Scope {
 
             Ok(Scope { start, end })
         } else {
-            Err(KvsError::DataEmpty)
+            Ok(Scope::empty())
         }
     }
 
     /// 判断scope之间是否相交
+    ///
+    /// 空scope与任何scope均不相交
     pub(crate) fn meet(&self, target: &Scope) -> bool {
-        (self.start.le(&target.start) && self.end.ge(&target.start)) ||
-            (self.start.le(&target.end) && self.end.ge(&target.end))
+        !self.is_empty() && !target.is_empty()
+            && self.start.le(&target.end) && target.start.le(&self.end)
+    }
+
+    /// 判断scope是否完全处于target之前且不相交
+    pub(crate) fn is_before(&self, target: &Scope) -> bool {
+        self.end.lt(&target.start)
+    }
+
+    pub(crate) fn get_start(&self) -> &[u8] {
+        &self.start
+    }
+
+    pub(crate) fn get_end(&self) -> &[u8] {
+        &self.end
+    }
+
+    /// 判断Key是否处于scope之中
+    ///
+    /// 空scope不包含任何Key
+    pub(crate) fn contains(&self, key: &[u8]) -> bool {
+        !self.is_empty() && self.start.as_slice() <= key && key <= self.end.as_slice()
     }
 
     /// 由一组Command组成一个scope
@@ -129,59 +246,95 @@ impl SsTable {
     /// 通过已经存在的文件构建SSTable
     ///
     /// 使用原有的路径与分区大小恢复出一个有内容的SSTable
-    pub(crate) async fn restore_from_file(io_handler: IOHandler) -> Result<Self>{
+    ///
+    /// bloom_resident为false时布隆过滤器不常驻内存
+    ///
+    /// 文件损坏时返回带有Gen与损坏位置偏移量的`KvsError::CorruptedFile`
+    pub(crate) async fn restore_from_file(io_handler: IOHandler, bloom_resident: bool) -> Result<Self>{
+        Self::restore_from_file_with_verify(io_handler, bloom_resident, true).await
+    }
+
+    /// 通过已经存在的文件构建SSTable
+    ///
+    /// verify_crc为false时跳过数据区、稀疏索引区与Key块的crc校验，需另行通过`SsTable::is_crc_match`校验
+    pub(crate) async fn restore_from_file_with_verify(io_handler: IOHandler, bloom_resident: bool, verify_crc: bool) -> Result<Self>{
         let gen = io_handler.get_gen();
+        let corrupted = |offset: u64, err: KvsError| KvsError::CorruptedFile { kind: FileKind::SsTable, gen, offset, reason: err.to_string() };
 
-        let meta_info = MetaInfo::read_to_file(&io_handler).await?;
         let size_of_disk = io_handler.file_size().await?;
+        let meta_info_pos = size_of_disk.checked_sub(TABLE_META_INFO_SIZE as u64)
+            .ok_or_else(|| corrupted(0, KvsError::DataEmpty))?;
+        let meta_info = MetaInfo::read_to_file(&io_handler).await
+            .map_err(|err| corrupted(meta_info_pos, err))?;
         info!("[SsTable: {}][restore_from_file][TableMetaInfo]: {:?}, Size of Disk: {}", gen, meta_info, &size_of_disk);
+        let format_version = FormatVersion::from_u64(meta_info.version)
+            .map_err(|err| corrupted(meta_info_pos, err))?;
 
         let index_pos = meta_info.data_part_len;
         let index_len = meta_info.index_len as usize;
 
-        let buffer = io_handler.read_with_pos(0, index_len + index_pos as usize).await?;
-        let crc_code_verification = crc32fast::hash(buffer.as_slice()) as u64;
+        // 先校验数据区、稀疏索引区与Key块，避免被篡改的数据参与稀疏索引的解析
+        if verify_crc && Self::crc_code_with_size(&io_handler, size_of_disk).await? != meta_info.crc_code {
+            return Err(corrupted(0, KvsError::CrcMisMatch));
+        }
 
         if let Some(extra_info_cmd) = CommandPackage::from_pos_unpack(&io_handler, index_pos, index_len).await? {
             match extra_info_cmd {
                 CommandData::Get { key: extra_info_bytes } => {
-                    let ExtraInfo { vec_index, scope, filter , size_of_data }
-                        = rmp_serde::from_slice::<ExtraInfo>(&extra_info_bytes)?;
-                    if crc_code_verification.eq(&meta_info.crc_code) {
-                        Ok(SsTable {
-                            meta_info,
-                            sparse_index: SkipMap::from_iter(vec_index),
-                            gen,
-                            io_handler,
-                            scope,
-                            filter,
-                            size_of_disk,
-                            size_of_data,
-                        })
-                    } else {
-                        Err(KvsError::CrcMisMatch)
-                    }
+                    let ExtraInfo { vec_index, scope, filter , size_of_data, data_version, key_size_histogram, value_size_histogram, prefix_filter, tombstone_count }
+                        = rmp_serde::from_slice::<ExtraInfo>(&extra_info_bytes)
+                            .map_err(|err| corrupted(index_pos, err.into()))?;
+                    Ok(SsTable {
+                        meta_info,
+                        sparse_index: vec_index,
+                        gen,
+                        io_handler,
+                        scope,
+                        filter: bloom_resident.then_some(filter),
+                        size_of_disk,
+                        size_of_data,
+                        data_version,
+                        key_size_histogram,
+                        value_size_histogram,
+                        prefix_filter,
+                        tombstone_count,
+                        format_version,
+                    })
+                }
+                CommandData::Set{ .. } | CommandData::Remove{ .. } | CommandData::SetBatch{ .. } | CommandData::SetPtr{ .. } | CommandData::SetCompressed{ .. } | CommandData::Merge{ .. } => {
+                    Err(corrupted(index_pos, KvsError::NotMatchCmd))
                 }
-                CommandData::Set{ .. } | CommandData::Remove{ .. } => Err(KvsError::NotMatchCmd)
             }
         } else {
-            Err(KvsError::KeyNotFound)
+            Err(corrupted(index_pos, KvsError::KeyNotFound))
         }
     }
 
     /// 写入CommandData数据段
+    ///
+    /// is_truncate为true时，除第一段外各段的索引Key截断为大于上一段最后一个Key的最短前缀
     #[allow(clippy::pattern_type_mismatch)]
-    async fn write_data_batch(vec_cmd_data: Vec<Vec<CommandData>>, io_handler: &IOHandler) -> Result<Vec<(Vec<u8>, Position)>> {
-        let (start_pos, batch_len, vec_sharding_len) =
-            CommandPackage::write_batch_first_pos_with_sharding(io_handler, &vec_cmd_data).await?;
-        info!("[SSTable][write_data_batch][data_zone]: start_pos: {}, batch_len: {}, vec_sharding_len: {:?}", start_pos, batch_len, vec_sharding_len);
-
-        let keys = vec_cmd_data.into_iter()
-            .filter_map(|sharding| sharding
-                .first()
-                .map(CommandData::get_key_clone))
+    async fn write_data_batch(vec_cmd_data: Vec<Vec<CommandData>>, io_handler: &IOHandler, is_truncate: bool, parallelism: usize) -> Result<Vec<(Vec<u8>, Position)>> {
+        let mut option_last_key: Option<&[u8]> = None;
+        let keys = vec_cmd_data.iter()
+            .filter_map(|sharding| {
+                let first_key = sharding.first()?.get_key();
+                let index_key = match option_last_key {
+                    Some(last_key) if is_truncate => shortest_separator(last_key, first_key),
+                    _ => first_key.clone()
+                };
+                option_last_key = sharding.last()
+                    .map(|cmd_data| cmd_data.get_key().as_slice());
+                Some(index_key)
+            })
             .collect_vec();
 
+        let mut writer = ShardingWriter::new(io_handler);
+        writer.write_shardings_parallel(vec_cmd_data, parallelism).await?;
+        let max_sharding_len = writer.max_sharding_len();
+        let (start_pos, batch_len, vec_sharding_len) = writer.finish().await?;
+        info!("[SSTable][write_data_batch][data_zone]: start_pos: {}, batch_len: {}, max_sharding_len: {}, vec_sharding_len: {:?}", start_pos, batch_len, max_sharding_len, vec_sharding_len);
+
         let mut start_len = 0;
 
         let vec_position = vec_sharding_len.into_iter()
@@ -213,10 +366,81 @@ impl SsTable {
         self.gen
     }
 
+    pub(crate) fn file_ref(&self) -> Result<FileRef> {
+        self.io_handler.file_ref(FileKind::SsTable)
+    }
+
+    pub(crate) fn get_data_version(&self) -> u64 {
+        self.data_version
+    }
+
+    pub(crate) fn get_key_size_histogram(&self) -> &Histogram {
+        &self.key_size_histogram
+    }
+
+    pub(crate) fn get_value_size_histogram(&self) -> &Histogram {
+        &self.value_size_histogram
+    }
+
+    pub(crate) fn get_tombstone_count(&self) -> usize {
+        self.tombstone_count
+    }
+
+    /// 墓碑数据占该SSTable数据数量的比例，无数据时为0
+    pub(crate) fn tombstone_ratio(&self) -> f64 {
+        tombstone_ratio(self.tombstone_count, self.size_of_data)
+    }
+
+    /// 统计数据的Key大小与value大小的直方图
+    ///
+    /// 墓碑数据不计入value的直方图，SetPtr则以其在vLog中的数据长度计入
+    fn size_histograms(vec_cmd_data: &[CommandData]) -> (Histogram, Histogram) {
+        let mut key_size_histogram = Histogram::default();
+        let mut value_size_histogram = Histogram::default();
+
+        for cmd_data in vec_cmd_data {
+            key_size_histogram.record(cmd_data.get_key().len());
+            match cmd_data {
+                CommandData::Set { value, .. } | CommandData::SetCompressed { value, .. } => value_size_histogram.record(value.len()),
+                CommandData::SetPtr { ptr, .. } => value_size_histogram.record(ptr.get_len()),
+                CommandData::Remove { .. } | CommandData::Get { .. } | CommandData::SetBatch { .. } | CommandData::Merge { .. } => {}
+            }
+        }
+
+        (key_size_histogram, value_size_histogram)
+    }
+
+    /// 获取用于判断数据新旧的排序依据，越大越新
+    pub(crate) fn freshness(&self, version_order: VersionOrder) -> (u64, i64) {
+        match version_order {
+            VersionOrder::Version => (self.data_version, self.gen),
+            VersionOrder::Gen => (0, self.gen)
+        }
+    }
+
     pub(crate) fn get_scope(&self) -> &Scope {
         &self.scope
     }
 
+    pub(crate) fn get_crc_code(&self) -> u64 {
+        self.meta_info.crc_code
+    }
+
+    /// 重新读取数据段、稀疏索引与Key块并校验其crc_code是否与MetaInfo中记录的一致
+    pub(crate) async fn is_crc_match(&self) -> Result<bool> {
+        Ok(Self::crc_code_with_size(&self.io_handler, self.size_of_disk).await? == self.meta_info.crc_code)
+    }
+
+    /// 计算MetaInfo之前所有内容的crc_code，即数据段、稀疏索引与Key块
+    ///
+    /// 不存在Key块的旧SSTable中MetaInfo紧随稀疏索引之后，因此同样适用
+    /// 与写入时一致，使用crc32计算后再扩展为MetaInfo中的u64
+    async fn crc_code_with_size(io_handler: &IOHandler, size_of_disk: u64) -> Result<u64> {
+        let len = size_of_disk.saturating_sub(TABLE_META_INFO_SIZE as u64);
+
+        Ok(io_handler.get_crc_code_with_len(len).await? as u64)
+    }
+
     pub(crate) fn get_size_of_disk(&self) -> u64 {
         self.size_of_disk
     }
@@ -225,38 +449,244 @@ impl SsTable {
         self.size_of_data
     }
 
-    /// 从该sstable中获取指定key对应数据可能存在的CommandData段
-    #[allow(clippy::expect_used)]
-    pub(crate) async fn query_with_key(&self, key: &[u8], position_cache: &Mutex<LruCache<(i64, Position), Vec<CommandData>>>) -> Result<Option<CommandData>> {
-        if self.filter.contains(key) {
-            let mut cache = position_cache.lock().await;
+    /// 估算该SSTable中处于[start, end]范围内的数据大小
+    ///
+    /// 以稀疏索引的Position为粒度，累加与范围相交的数据段长度
+    /// 范围仅覆盖边界数据段的一部分时同样计入整个数据段，因此每个SSTable至多多估算两端的两个数据段
+    pub(crate) fn estimate_range_size(&self, start: &[u8], end: &[u8]) -> u64 {
+        if self.scope.start.as_slice() > end || self.scope.end.as_slice() < start {
+            return 0;
+        }
+        let vec_index = &self.sparse_index;
+
+        vec_index.iter()
+            .enumerate()
+            .filter(|(i, (key, _))| {
+                // 每段数据的范围为其索引Key至下一段索引Key之前
+                let block_end = vec_index.get(i + 1)
+                    .map_or(self.scope.end.as_slice(), |(next_key, _)| next_key.as_slice());
+                key.as_slice() <= end && block_end >= start
+            })
+            .map(|(_, (_, position))| position.len as u64)
+            .sum()
+    }
 
+    /// 获取该SSTable中处于start与end边界之间的数据源
+    pub(crate) fn range_source(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> RangeSource<'_> {
+        let mut vec_position = VecDeque::new();
+
+        if is_before_end(&self.scope.start, end) && is_after_start(&self.scope.end, start) {
+            let vec_index = &self.sparse_index;
+
+            for (i, (key, position)) in vec_index.iter().enumerate() {
+                if !is_before_end(key, end) {
+                    break
+                }
+                // 下一段数据的索引Key不大于start时，该段数据均小于start
+                if let (Some((next_key, _)), Bound::Included(start) | Bound::Excluded(start)) = (vec_index.get(i + 1), start) {
+                    if next_key.as_slice() <= start {
+                        continue
+                    }
+                }
+                vec_position.push_back((key, position));
+            }
+        }
+
+        RangeSource {
+            ss_table: Some(self),
+            vec_position,
+            buffer: VecDeque::new(),
+        }
+    }
+
+    /// 判断该SSTable中是否可能存在该Key
+    ///
+    /// Key不处于Scope中或布隆过滤器负命中时必然不存在，无需读取数据段
+    /// 布隆过滤器非常驻时从filter_cache中获取，缺失时从文件中读取并放入filter_cache
+    pub(crate) async fn may_contain(&self, key: &[u8], filter_cache: &FilterCache) -> Result<bool> {
+        if !self.scope.contains(key) {
+            return Ok(false);
+        }
+        if let Some(filter) = self.filter.as_ref() {
+            return Ok(filter.contains(key));
+        }
+
+        let mut cache = filter_cache.lock().await;
+        if !cache.contains(&self.gen) {
+            let _ignore = cache.put(self.gen, self.read_filter().await?);
+        }
+        Ok(cache.get(&self.gen)
+            .map_or(true, |filter| filter.contains(key)))
+    }
+
+    /// 从文件的稀疏索引区中仅读取布隆过滤器
+    async fn read_filter(&self) -> Result<GrowableBloom> {
+        let index_pos = self.meta_info.data_part_len;
+        let index_len = self.meta_info.index_len as usize;
+
+        match CommandPackage::from_pos_unpack(&self.io_handler, index_pos, index_len).await? {
+            Some(CommandData::Get { key: extra_info_bytes }) => {
+                Ok(rmp_serde::from_slice::<ExtraInfoFilter>(&extra_info_bytes)?.filter)
+            }
+            _ => Err(KvsError::NotMatchCmd)
+        }
+    }
+
+    /// 判断该SSTable中是否可能存在以prefix为前缀的Key
+    ///
+    /// 未构建前缀布隆过滤器时无法判断，视为可能存在
+    pub(crate) fn may_contain_prefix(&self, prefix: &[u8]) -> bool {
+        self.prefix_filter.as_ref()
+            .map_or(true, |prefix_filter| prefix_filter.may_contain(prefix))
+    }
+
+    /// 获取常驻内存的布隆过滤器
+    pub(crate) fn resident_filter(&self) -> Option<&GrowableBloom> {
+        self.filter.as_ref()
+    }
+
+    /// 从该sstable中获取指定key对应数据可能存在的CommandData段
+    ///
+    /// 读取数据段时不持有position_cache的锁，以免多个SSTable的并发查询互相阻塞
+    /// read_bytes不为None时，将未命中position_cache而从文件中读取的数据段大小累计至其中
+    pub(crate) async fn query_with_key(&self, key: &[u8], position_cache: &Mutex<LruCache<(i64, Position), Vec<CommandData>>>, filter_cache: &FilterCache, read_bytes: Option<&AtomicU64>) -> Result<Option<CommandData>> {
+        if self.may_contain(key, filter_cache).await? {
             if let Some(position) = Position::from_sparse_index_with_key(&self.sparse_index, key) {
                 info!("[SsTable: {}][query_with_key][data_zone]: {:?}", self.gen, position);
                 let key_position = (self.gen, position.clone());
-                // !!!Async closure cannot be used in get_or_insert
-                if !cache.contains(&key_position) {
-                    let bytes = self.io_handler.read_with_pos(position.start, position.len).await?;
-                    let _ignore = cache.put(key_position.clone(), CommandPackage::from_bytes_to_unpack_vec(&bytes)?);
-                }
-
-                return Ok(cache.get(&key_position)
-                    .expect("When an error occurs here, it means that the empty data is not filled")
-                    .iter()
+                let find_with_key = |vec_cmd_data: &Vec<CommandData>| vec_cmd_data.iter()
                     .find(|cmd_data| cmd_data.get_key() == key)
-                    .map(CommandData::clone));
+                    .cloned();
+
+                let cached = position_cache.lock().await
+                    .get(&key_position)
+                    .map(find_with_key);
+                let option_cmd_data = match cached {
+                    Some(option_cmd_data) => option_cmd_data,
+                    None => {
+                        let bytes = self.io_handler.read_with_pos(position.start, position.len).await?;
+                        if let Some(read_bytes) = read_bytes {
+                            let _ignore = read_bytes.fetch_add(bytes.len() as u64, AtomicOrdering::Relaxed);
+                        }
+                        let vec_cmd_data = CommandPackage::from_bytes_to_unpack_vec(&bytes, self.format_version, self.io_handler.cipher())?;
+                        let option_cmd_data = find_with_key(&vec_cmd_data);
+                        let _ignore = position_cache.lock().await
+                            .put(key_position, vec_cmd_data);
+                        option_cmd_data
+                    }
+                };
+
+                // 缓存中保留压缩后的数据，仅对命中的数据进行解压
+                return option_cmd_data
+                    .map(CommandData::decompress)
+                    .transpose();
             }
         }
         Ok(None)
     }
 
+    /// 从该sstable中批量获取多个key对应的数据，返回值与keys一一对应
+    ///
+    /// 各Key所在的数据段按其位置排序去重后，将文件中相邻且未被缓存的数据段合并为一次读取，
+    /// 因此Key较为集中时相比逐个query_with_key能够减少读盘与seek的次数
+    pub(crate) async fn query_with_keys(&self, keys: &[&[u8]], position_cache: &Mutex<LruCache<(i64, Position), Vec<CommandData>>>, filter_cache: &FilterCache) -> Result<Vec<Option<CommandData>>> {
+        let mut vec_key_position = Vec::with_capacity(keys.len());
+        for key in keys {
+            vec_key_position.push(match self.may_contain(key, filter_cache).await? {
+                true => Position::from_sparse_index_with_key(&self.sparse_index, key),
+                false => None
+            });
+        }
+
+        // 先取出已缓存的数据段，剩余的数据段按位置由前往后排列
+        let mut map_segment: HashMap<&Position, Vec<CommandData>> = HashMap::new();
+        let mut vec_uncached = Vec::new();
+        {
+            let mut position_cache = position_cache.lock().await;
+            for position in vec_key_position.iter()
+                .flatten()
+                .sorted_unstable_by_key(|position| position.start)
+                .dedup()
+            {
+                match position_cache.get(&(self.gen, (*position).clone())) {
+                    Some(vec_cmd_data) => { let _ignore = map_segment.insert(*position, vec_cmd_data.clone()); }
+                    None => vec_uncached.push(*position)
+                }
+            }
+        }
+
+        // 首尾相接的数据段合并为一次读取，再按各数据段的长度切分
+        let mut vec_read = Vec::new();
+        for position in vec_uncached {
+            match vec_read.last_mut() {
+                Some((start, len, vec_position)) if *start + *len as u64 == position.start => {
+                    *len += position.len;
+                    vec_position.push(position);
+                }
+                _ => vec_read.push((position.start, position.len, vec![position]))
+            }
+        }
+        for (start, len, vec_position) in vec_read {
+            info!("[SsTable: {}][query_with_keys][data_zone]: start: {}, len: {}", self.gen, start, len);
+            let bytes = self.io_handler.read_with_pos(start, len).await?;
+
+            let mut offset = 0;
+            for position in vec_position {
+                let vec_cmd_data = CommandPackage::from_bytes_to_unpack_vec(&bytes[offset..offset + position.len], self.format_version, self.io_handler.cipher())?;
+                offset += position.len;
+                let _ignore = position_cache.lock().await
+                    .put((self.gen, position.clone()), vec_cmd_data.clone());
+                let _ignore = map_segment.insert(position, vec_cmd_data);
+            }
+        }
+
+        // 缓存中保留压缩后的数据，仅对命中的数据进行解压
+        keys.iter()
+            .zip(vec_key_position)
+            .map(|(key, option_position)| option_position
+                .and_then(|position| map_segment.get(position))
+                .and_then(|vec_cmd_data| vec_cmd_data.iter()
+                    .find(|cmd_data| cmd_data.get_key().as_slice() == *key)
+                    .cloned())
+                .map(CommandData::decompress)
+                .transpose())
+            .collect()
+    }
+
     /// 获取SsTable内所有的正常数据
     pub(crate) async fn get_all_data(&self) -> Result<Vec<CommandData>> {
         let info = &self.meta_info;
         let data_len = info.data_part_len;
 
         let all_data_u8 = self.io_handler.read_with_pos(0, data_len as usize).await?;
-        CommandPackage::from_bytes_to_unpack_vec(all_data_u8.as_slice())
+        CommandPackage::from_bytes_to_unpack_vec(all_data_u8.as_slice(), self.format_version, self.io_handler.cipher())?
+            .into_iter()
+            .map(CommandData::decompress)
+            .collect()
+    }
+
+    /// 获取SsTable内所有的Key及其是否为墓碑
+    ///
+    /// 仅读取位于稀疏索引与MetaInfo之间的Key块，不读取数据段
+    /// 不存在Key块的旧SSTable则退化为读取全部数据
+    pub(crate) async fn get_all_keys(&self) -> Result<Vec<(Vec<u8>, bool)>> {
+        let info = &self.meta_info;
+        let key_block_start = info.data_part_len + info.index_len;
+        let key_block_len = self.size_of_disk
+            .saturating_sub(TABLE_META_INFO_SIZE as u64)
+            .saturating_sub(key_block_start);
+
+        if key_block_len == 0 {
+            return Ok(self.get_all_data().await?
+                .iter()
+                .map(key_with_tombstone)
+                .collect());
+        }
+        let key_block_u8 = self.io_handler.read_with_pos(key_block_start, key_block_len as usize).await?;
+        match CommandPackage::from_bytes_to_unpack_vec(&key_block_u8, self.format_version, self.io_handler.cipher())?.pop() {
+            Some(CommandData::Get { key }) => Ok(rmp_serde::from_slice(&key)?),
+            _ => Err(KvsError::NotMatchCmd)
+        }
     }
 
     /// 通过一组SSTable收集对应的Gen
@@ -280,34 +710,70 @@ impl SsTable {
     /// 通过内存表构建持久化并构建SSTable
     ///
     /// 使用目标路径与文件大小，分块大小构建一个有内容的SSTable
-    pub(crate) async fn create_for_immutable_table(config: &Config, io_handler: IOHandler, vec_mem_data: Vec<CommandData>, level: usize) -> Result<Self> {
+    ///
+    /// 设置了`Config::value_compression_threshold`时，超过该大小的value会经lz4压缩后写入
+    ///
+    /// data_version为该SSTable的数据版本号，用于Level 0中判断数据的新旧
+    ///
+    /// 同时统计数据的Key大小与value大小的直方图及墓碑数量并写入ExtraInfo，供`LsmStore::stats`聚合
+    pub(crate) async fn create_for_immutable_table(config: &Config, io_handler: IOHandler, vec_mem_data: Vec<CommandData>, level: usize, data_version: u64) -> Result<Self> {
+        // 以压缩前的数据统计直方图
+        let (key_size_histogram, value_size_histogram) = Self::size_histograms(&vec_mem_data);
+        let vec_mem_data = match config.value_compression_threshold {
+            Some(threshold) => vec_mem_data.into_iter()
+                .map(|cmd_data| cmd_data.compress(threshold))
+                .collect_vec(),
+            None => vec_mem_data
+        };
         // 获取数据的Key涵盖范围
         let scope = Scope::from_vec_cmd_data(&vec_mem_data)?;
-        // 获取地址
-        let interval_block_size = config.sparse_index_interval_block_size;
         let gen = io_handler.get_gen();
         let mut filter = GrowableBloom::new(config.desired_error_prob, vec_mem_data.len());
 
         for data in vec_mem_data.iter() {
             let _ignore = filter.insert(data.get_key());
         }
+        let prefix_filter = config.prefix_bloom_len
+            .map(|len| PrefixFilter::from_vec_cmd_data(len, &vec_mem_data, config.desired_error_prob));
         let size_of_data = vec_mem_data.len();
+        let tombstone_count = vec_mem_data.iter()
+            .filter(|cmd_data| matches!(cmd_data, CommandData::Remove { .. }))
+            .count();
+        let format_version = io_handler.format_version();
+        // 收集所有Key及其是否为墓碑，供仅遍历Key时使用
+        let vec_key = vec_mem_data.iter()
+            .map(key_with_tombstone)
+            .collect_vec();
+        // 以数据的序列化长度与长度头预分配文件空间
+        let pre_allocate_len = vec_mem_data.iter()
+            .map(|cmd_data| {
+                let len = cmd_data.get_data_len_for_rmp();
+                len + CommandPackage::len_head(len, format_version).len()
+            })
+            .sum::<usize>() as u64;
+        io_handler.pre_allocate(pre_allocate_len).await?;
+        // 按block_size切分为data block，每个block对应一条稀疏索引
         let vec_sharding = data_sharding(
             vec_mem_data,
-            ALIGNMENT_4K * interval_block_size as usize,
+            config.block_size,
             config,
             false
         ).await
             .into_iter()
             .map(|(_, sharding)| sharding)
             .collect();
-        let vec_index = Self::write_data_batch(vec_sharding, &io_handler).await?;
+        let vec_index = Self::write_data_batch(vec_sharding, &io_handler, config.sparse_index_truncate, config.serialize_parallelism).await?;
 
         let extra_info = ExtraInfo {
             vec_index,
             scope,
             filter,
-            size_of_data
+            size_of_data,
+            data_version,
+            key_size_histogram,
+            value_size_histogram,
+            prefix_filter,
+            tombstone_count
         };
 
         // 开始对稀疏索引进行伪装并断点处理
@@ -316,36 +782,348 @@ impl SsTable {
         let cmd_sparse_index = CommandData::Get { key: rmp_serde::to_vec(&extra_info)?};
         // 将稀疏索引伪装成CommandData，使最后的MetaInfo位置能够被顺利找到
         let (data_part_len, sparse_index_len) = CommandPackage::write(&io_handler, &cmd_sparse_index).await?;
+        // Key块紧随稀疏索引之后，同样伪装为CommandData
+        let cmd_key_block = CommandData::Get { key: rmp_serde::to_vec(&vec_key)? };
+        let (key_block_pos, key_block_len) = CommandPackage::write(&io_handler, &cmd_key_block).await?;
 
-        // 数据刷入以获取crc_code
-        io_handler.flush().await?;
+        // 数据刷入并截断预分配的多余空间以获取crc_code
+        io_handler.truncate().await?;
 
-        let crc_code = io_handler.get_crc_code().await? as u64;
+        // crc_code覆盖MetaInfo之前的数据段、稀疏索引与Key块
+        let crc_code = io_handler.get_crc_code_with_len(key_block_pos + key_block_len as u64).await? as u64;
 
         // 将以上持久化信息封装为MetaInfo
         let meta_info = MetaInfo{
             level: level as u64,
-            version: 0,
+            version: format_version.as_u64(),
             data_part_len,
             index_len: sparse_index_len as u64,
             crc_code
         };
-        meta_info.write_to_file_and_flush(&io_handler).await?;
+        meta_info.write_to_file_and_sync(&io_handler).await?;
 
         let size_of_disk = io_handler.file_size().await?;
 
         info!("[SsTable: {}][create_form_index][TableMetaInfo]: {:?}", gen, meta_info);
-        let ExtraInfo { vec_index, scope, filter, size_of_data } = extra_info;
+        let ExtraInfo { vec_index, scope, filter, size_of_data, data_version, key_size_histogram, value_size_histogram, prefix_filter, tombstone_count } = extra_info;
         Ok(SsTable {
             meta_info,
-            sparse_index: SkipMap::from_iter(vec_index),
+            sparse_index: vec_index,
             io_handler,
             gen,
             scope,
-            filter,
+            filter: config.bloom_resident.then_some(filter),
             size_of_disk,
             size_of_data,
+            data_version,
+            key_size_histogram,
+            value_size_histogram,
+            prefix_filter,
+            tombstone_count,
+            format_version,
         })
 
     }
-}
\ No newline at end of file
+}
+
+/// 获取不大于key且大于prev_key的最短Key，要求prev_key < key
+///
+/// 即key中比prev_key的公共前缀多一个字节的前缀
+/// 以此作为稀疏索引Key时，上一段的数据均小于该索引Key，而该段的数据均不小于该索引Key，
+/// 因此查找不大于目标Key的最大索引项时，定位到的数据段与使用完整Key时一致
+fn shortest_separator(prev_key: &[u8], key: &[u8]) -> Vec<u8> {
+    let common_len = prev_key.iter()
+        .zip(key)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    key[..(common_len + 1).min(key.len())].to_vec()
+}
+
+/// SSTable的格式迁移器
+///
+/// SSTable的格式版本记录于各自的MetaInfo之中，因此仅迁移旧格式的SSTable
+/// 迁移时读取原SSTable的全部数据，以原有的Gen与Level重新构建
+#[derive(Debug)]
+pub(crate) struct SsTableMigrator<'a> {
+    config: &'a Config
+}
+
+impl<'a> SsTableMigrator<'a> {
+    pub(crate) fn new(config: &'a Config) -> Self {
+        SsTableMigrator { config }
+    }
+}
+
+#[async_trait]
+impl Migrator for SsTableMigrator<'_> {
+    async fn outdated_gens(&self, dir_path: &Path) -> Result<Vec<(i64, FormatVersion)>> {
+        let io_handler_factory = IOHandlerFactory::new(dir_path);
+        let mut vec_outdated = Vec::new();
+
+        for gen in sorted_gen_list(dir_path)? {
+            let io_handler = io_handler_factory.create(gen)?;
+            // 损坏的SSTable不进行迁移，交由开启时的Wal恢复处理
+            if io_handler.file_size().await? < TABLE_META_INFO_SIZE as u64 {
+                continue
+            }
+            let option_version = MetaInfo::read_to_file(&io_handler).await.ok()
+                .and_then(|meta_info| FormatVersion::from_u64(meta_info.version).ok());
+            if let Some(version) = option_version.filter(|version| *version != FormatVersion::CURRENT) {
+                vec_outdated.push((gen, version));
+            }
+        }
+
+        Ok(vec_outdated)
+    }
+
+    async fn rewrite(&self, dir_path: &Path, temp_dir_path: &Path, gen: i64, _version: FormatVersion) -> Result<()> {
+        let ss_table = SsTable::restore_from_file(IOHandlerFactory::new(dir_path).create(gen)?, false).await?;
+        let vec_cmd_data = ss_table.get_all_data().await?;
+        let io_handler = IOHandlerFactory::new(temp_dir_path).create(gen)?;
+
+        let _ignore = SsTable::create_for_immutable_table(self.config, io_handler, vec_cmd_data, ss_table.get_level(), ss_table.get_data_version()).await?;
+        Ok(())
+    }
+}
+
+impl RangeSource<'_> {
+    /// 通过已以Key排序且处于范围内的数据构建数据源
+    pub(crate) fn from_vec_cmd_data(vec_cmd_data: Vec<CommandData>) -> Self {
+        RangeSource {
+            ss_table: None,
+            vec_position: VecDeque::new(),
+            buffer: VecDeque::from(vec_cmd_data),
+        }
+    }
+
+    /// 该数据源后续数据可能的最小Key
+    ///
+    /// 缓冲为空时以下一段数据的索引Key作为下界
+    pub(crate) fn lower_bound(&self) -> Option<&[u8]> {
+        self.buffer.front()
+            .map(|cmd_data| cmd_data.get_key().as_slice())
+            .or_else(|| self.vec_position.front()
+                .map(|(key, _)| key.as_slice()))
+    }
+
+    pub(crate) fn is_loaded(&self) -> bool {
+        !self.buffer.is_empty()
+    }
+
+    /// 读取下一段数据，并保留其中处于start与end边界之间的数据
+    pub(crate) async fn load(&mut self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<()> {
+        if let (Some(ss_table), Some((_, position))) = (self.ss_table, self.vec_position.pop_front()) {
+            let bytes = ss_table.io_handler.read_with_pos(position.start, position.len).await?;
+
+            let vec_cmd_data = CommandPackage::from_bytes_to_unpack_vec(&bytes, ss_table.format_version, ss_table.io_handler.cipher())?
+                .into_iter()
+                .filter(|cmd_data| is_in_bound(cmd_data.get_key(), start, end))
+                .map(CommandData::decompress)
+                .collect::<Result<Vec<_>>>()?;
+            self.buffer.extend(vec_cmd_data);
+        }
+        Ok(())
+    }
+
+    /// 当缓冲中的第一个数据为该Key时将其取出
+    pub(crate) fn pop_with_key(&mut self, key: &[u8]) -> Option<CommandData> {
+        if self.buffer.front().map(|cmd_data| cmd_data.get_key().as_slice()) == Some(key) {
+            self.buffer.pop_front()
+        } else {
+            None
+        }
+    }
+}
+
+#[test]
+fn test_ss_table_crc_mismatch() -> Result<()> {
+    use std::fs::OpenOptions;
+    use std::io::{Read, Seek, SeekFrom, Write};
+    use tempfile::TempDir;
+    use crate::kernel::log_path;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let config = Config::default();
+        let factory = IOHandlerFactory::new(temp_dir.path());
+        let gen = config.create_gen();
+        let vec_data = (0..100)
+            .map(|i| CommandData::set(format!("key{:03}", i).into_bytes(), vec![b'v'; 10]))
+            .collect_vec();
+
+        let _ignore = SsTable::create_for_immutable_table(&config, factory.create(gen)?, vec_data.clone(), 0, gen as u64).await?;
+        let ss_table = SsTable::restore_from_file(factory.create(gen)?, true).await?;
+        assert!(ss_table.is_crc_match().await?);
+
+        // 篡改数据区中的一个字节
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(log_path(temp_dir.path(), gen))?;
+        let mut byte = [0; 1];
+        let _ignore = file.seek(SeekFrom::Start(10))?;
+        file.read_exact(&mut byte)?;
+        let _ignore = file.seek(SeekFrom::Start(10))?;
+        file.write_all(&[!byte[0]])?;
+        file.flush()?;
+
+        assert!(!ss_table.is_crc_match().await?);
+        assert!(matches!(
+            SsTable::restore_from_file(factory.create(gen)?, true).await,
+            Err(KvsError::CorruptedFile { gen: corrupted_gen, .. }) if corrupted_gen == gen
+        ));
+
+        // Key块同样处于crc_code的覆盖范围内
+        let key_block_gen = config.create_gen();
+        let ss_table = SsTable::create_for_immutable_table(&config, factory.create(key_block_gen)?, vec_data, 0, key_block_gen as u64).await?;
+        let key_block_last_pos = ss_table.get_size_of_disk() - TABLE_META_INFO_SIZE as u64 - 1;
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(log_path(temp_dir.path(), key_block_gen))?;
+        let _ignore = file.seek(SeekFrom::Start(key_block_last_pos))?;
+        file.read_exact(&mut byte)?;
+        let _ignore = file.seek(SeekFrom::Start(key_block_last_pos))?;
+        file.write_all(&[!byte[0]])?;
+        file.flush()?;
+
+        assert!(!ss_table.is_crc_match().await?);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_scope_empty_and_meet() -> Result<()> {
+    let scope_a_c = Scope { start: b"a".to_vec(), end: b"c".to_vec() };
+    let scope_d_f = Scope { start: b"d".to_vec(), end: b"f".to_vec() };
+    let scope_c_e = Scope { start: b"c".to_vec(), end: b"e".to_vec() };
+
+    // 空scope与任何scope均不相交，且不包含任何Key
+    let empty = Scope::empty();
+    assert!(empty.is_empty());
+    assert!(!empty.meet(&scope_a_c));
+    assert!(!scope_a_c.meet(&empty));
+    assert!(!empty.meet(&Scope::empty()));
+    assert!(!empty.contains(b""));
+    assert!(!empty.contains(b"a"));
+    let reversed = Scope { start: b"c".to_vec(), end: b"a".to_vec() };
+    assert!(reversed.is_empty());
+    assert!(!reversed.meet(&scope_a_c));
+    assert!(!reversed.contains(b"b"));
+
+    // 相邻但不相交
+    assert!(!scope_a_c.meet(&scope_d_f));
+    assert!(!scope_d_f.meet(&scope_a_c));
+    assert!(scope_a_c.is_before(&scope_d_f));
+    // 边界相同的单Key视为相交
+    assert!(scope_a_c.meet(&scope_c_e));
+    assert!(Scope::from_key(b"c").meet(&scope_a_c));
+    assert!(!Scope::from_key(b"c").is_empty());
+
+    // 空scope不参与融合
+    let fusion = Scope::fusion(vec![&empty, &scope_d_f, &scope_a_c])?;
+    assert_eq!(fusion, Scope { start: b"a".to_vec(), end: b"f".to_vec() });
+    assert!(Scope::fusion(vec![&empty])?.is_empty());
+    assert!(Scope::fusion_from_vec_ss_table(&[])?.is_empty());
+
+    // 序列化格式稳定：依次为start与end的长度与内容
+    let bytes = bincode::serialize(&scope_a_c)?;
+    assert_eq!(bytes, vec![1, 0, 0, 0, 0, 0, 0, 0, b'a', 1, 0, 0, 0, 0, 0, 0, 0, b'c']);
+    assert_eq!(bincode::deserialize::<Scope>(&bytes)?, scope_a_c);
+    assert_eq!(bincode::serialize(&empty)?, vec![0; 16]);
+    assert_eq!(bincode::deserialize::<Scope>(&[0; 16])?, empty);
+
+    Ok(())
+}
+
+#[test]
+fn test_ss_table_sparse_index_truncate() -> Result<()> {
+    use std::num::NonZeroUsize;
+    use tempfile::TempDir;
+
+    assert_eq!(shortest_separator(b"abc", b"abd"), b"abd".to_vec());
+    assert_eq!(shortest_separator(b"abc", b"abzzz"), b"abz".to_vec());
+    assert_eq!(shortest_separator(b"ab", b"abc"), b"abc".to_vec());
+    assert_eq!(shortest_separator(b"a", b"bcd"), b"b".to_vec());
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let factory = IOHandlerFactory::new(temp_dir.path());
+        // 长Key仅在开头的序号处有所区别
+        let vec_data = (0..1000_u32)
+            .map(|i| CommandData::set(format!("{i:06}{}", "k".repeat(250)).into_bytes(), vec![b'v'; 1024]))
+            .collect_vec();
+        let position_cache = Mutex::new(LruCache::new(NonZeroUsize::new(1).ok_or(KvsError::CacheSizeOverFlow)?));
+        let filter_cache = Mutex::new(LruCache::new(NonZeroUsize::new(1).ok_or(KvsError::CacheSizeOverFlow)?));
+
+        let mut vec_index_len = Vec::new();
+        for is_truncate in [false, true] {
+            let config = Config::default()
+                .sparse_index_interval_block_size(1)
+                .sparse_index_truncate(is_truncate);
+            let gen = config.create_gen();
+            let _ignore = SsTable::create_for_immutable_table(&config, factory.create(gen)?, vec_data.clone(), 0, gen as u64).await?;
+            let ss_table = SsTable::restore_from_file(factory.create(gen)?, true).await?;
+            assert!(ss_table.sparse_index.len() > 100);
+
+            // 截断后查找仍定位至正确的数据段
+            for cmd_data in vec_data.iter() {
+                assert_eq!(ss_table.query_with_key(cmd_data.get_key(), &position_cache, &filter_cache, None).await?.as_ref(), Some(cmd_data));
+            }
+            assert_eq!(ss_table.query_with_key(b"000001", &position_cache, &filter_cache, None).await?, None);
+            assert_eq!(ss_table.query_with_key(b"999999", &position_cache, &filter_cache, None).await?, None);
+            vec_index_len.push(ss_table.meta_info.index_len);
+        }
+        assert!(vec_index_len[1] < vec_index_len[0]);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_ss_table_block_size() -> Result<()> {
+    use std::num::NonZeroUsize;
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let factory = IOHandlerFactory::new(temp_dir.path());
+        let vec_data = (0..2000_u32)
+            .map(|i| CommandData::set(format!("{i:06}").into_bytes(), vec![b'v'; 256]))
+            .collect_vec();
+        let position_cache = Mutex::new(LruCache::new(NonZeroUsize::new(1).ok_or(KvsError::CacheSizeOverFlow)?));
+        let filter_cache = Mutex::new(LruCache::new(NonZeroUsize::new(1).ok_or(KvsError::CacheSizeOverFlow)?));
+
+        let mut vec_read_bytes = Vec::new();
+        let mut vec_index_size = Vec::new();
+        for block_size in [64 * 1024, 16 * 1024, 4 * 1024, 1024] {
+            let config = Config::default()
+                .block_size(block_size);
+            let gen = config.create_gen();
+            let _ignore = SsTable::create_for_immutable_table(&config, factory.create(gen)?, vec_data.clone(), 0, gen as u64).await?;
+            let ss_table = SsTable::restore_from_file(factory.create(gen)?, true).await?;
+
+            // 以固定的步长跳跃访问，模拟随机读
+            let read_bytes = factory.read_bytes();
+            for i in 0..200_usize {
+                let cmd_data = &vec_data[i * 7919 % vec_data.len()];
+                assert_eq!(ss_table.query_with_key(cmd_data.get_key(), &position_cache, &filter_cache, None).await?.as_ref(), Some(cmd_data));
+            }
+            vec_read_bytes.push(factory.read_bytes() - read_bytes);
+            vec_index_size.push(ss_table.sparse_index.len());
+        }
+
+        // block越小，随机读的字节数越少而稀疏索引越大
+        for i in 1..vec_read_bytes.len() {
+            assert!(vec_read_bytes[i] < vec_read_bytes[i - 1]);
+            assert!(vec_index_size[i] > vec_index_size[i - 1]);
+        }
+
+        Ok(())
+    })
+}
@@ -0,0 +1,250 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use crate::kernel::{CommandData, CommandPackage, FileKind, FileRef, FormatVersion, Result, sorted_gen_list};
+use crate::kernel::cipher::Cipher;
+use crate::kernel::io_handler::{IOHandler, IOHandlerFactory};
+use crate::kernel::lsm::lsm_kv::Config;
+use crate::kernel::value_reader::ValueReader;
+use crate::KvsError;
+
+/// vLog中value的磁盘指针
+/// gen vLog文件序号
+/// pos 开头指针
+/// len 数据长度
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub struct ValuePtr {
+    gen: i64,
+    pos: u64,
+    len: usize
+}
+
+/// Value Log
+///
+/// Key-Value分离时大value会被写入vLog之中，SSTable则仅存储Key与ValuePtr，
+/// 因此Major压缩时仅需搬运Key与指针
+/// vLog中的数据以CommandData::Set的形式存储，以便GC时能够得知value所属的Key
+#[derive(Debug)]
+pub(crate) struct ValueLog {
+    io_handler_factory: IOHandlerFactory,
+    inner: RwLock<ValueLogInner>
+}
+
+#[derive(Debug)]
+struct ValueLogInner {
+    /// vLog文件集合
+    handlers: BTreeMap<i64, Arc<IOHandler>>,
+    /// 当前写入的vLog文件Gen
+    /// 重新开启时已有的文件均视为已写满，首次写入时才会创建新的文件
+    current_gen: Option<i64>
+}
+
+impl ValueLog {
+    pub(crate) fn open(path: PathBuf, buffer_size: usize, cipher: Option<Arc<Cipher>>) -> Result<Self> {
+        fs::create_dir_all(&path)?;
+        let format_version = FormatVersion::load_or_init(&path)?;
+        let io_handler_factory = IOHandlerFactory::new_with_format_version(path.clone(), format_version)
+            .buffer_size(buffer_size)
+            .cipher(cipher);
+        let mut handlers = BTreeMap::new();
+
+        for gen in sorted_gen_list(&path)? {
+            let _ignore = handlers.insert(gen, Arc::new(io_handler_factory.create(gen)?));
+        }
+
+        Ok(ValueLog {
+            io_handler_factory,
+            inner: RwLock::new(ValueLogInner { handlers, current_gen: None })
+        })
+    }
+
+    /// 以只读模式开启，用于跟随其他进程正在写入的vLog目录
+    ///
+    /// 不会创建目录与文件，目录不存在时视为空的vLog，之后通过refresh加载新增的文件
+    pub(crate) fn open_read_only(path: PathBuf, buffer_size: usize, cipher: Option<Arc<Cipher>>) -> Result<Self> {
+        let format_version = if path.exists() {
+            FormatVersion::load(&path)?
+        } else {
+            FormatVersion::CURRENT
+        };
+        let io_handler_factory = IOHandlerFactory::new_with_format_version(path, format_version)
+            .buffer_size(buffer_size)
+            .cipher(cipher)
+            .read_only(true);
+        let mut handlers = BTreeMap::new();
+        Self::load_new_handlers(&io_handler_factory, &mut handlers)?;
+
+        Ok(ValueLog {
+            io_handler_factory,
+            inner: RwLock::new(ValueLogInner { handlers, current_gen: None })
+        })
+    }
+
+    /// 重新扫描只读模式下的目录，加载新增的vLog文件并移除已被删除的文件
+    pub(crate) async fn refresh(&self) -> Result<()> {
+        let mut inner = self.inner.write().await;
+
+        Self::load_new_handlers(&self.io_handler_factory, &mut inner.handlers)
+    }
+
+    /// 以目录中现有的vLog文件更新handlers，打开时已被删除的文件直接跳过
+    fn load_new_handlers(io_handler_factory: &IOHandlerFactory, handlers: &mut BTreeMap<i64, Arc<IOHandler>>) -> Result<()> {
+        let dir_path = io_handler_factory.get_dir_path();
+        let vec_gen = if dir_path.exists() {
+            sorted_gen_list(&dir_path)?
+        } else {
+            Vec::new()
+        };
+
+        handlers.retain(|gen, _| vec_gen.contains(gen));
+        for gen in vec_gen {
+            if !handlers.contains_key(&gen) {
+                if let Ok(io_handler) = io_handler_factory.create(gen) {
+                    let _ignore = handlers.insert(gen, Arc::new(io_handler));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 将value长度不小于threshold的Set写入vLog，并替换为对应的SetPtr
+    ///
+    /// 当前vLog文件超出`Config::value_log_file_size`时会切换至新的文件，
+    /// 返回值中附带是否发生了切换，切换后旧文件即可参与GC
+    pub(crate) async fn separate(&self, vec_cmd_data: Vec<CommandData>, threshold: usize, config: &Config) -> Result<(Vec<CommandData>, bool)> {
+        let mut inner = self.inner.write().await;
+        let option_io_handler = inner.current_gen
+            .and_then(|gen| inner.handlers.get(&gen))
+            .map(Arc::clone);
+        let io_handler = match option_io_handler {
+            Some(io_handler) => io_handler,
+            None => {
+                let gen = config.create_gen();
+                let io_handler = Arc::new(self.io_handler_factory.create(gen)?);
+                let _ignore = inner.handlers.insert(gen, Arc::clone(&io_handler));
+                inner.current_gen = Some(gen);
+                io_handler
+            }
+        };
+        let gen = io_handler.get_gen();
+
+        let mut vec_separated = Vec::with_capacity(vec_cmd_data.len());
+        for cmd_data in vec_cmd_data {
+            // 仅Set存在value
+            if cmd_data.get_value().map_or(false, |value| value.len() >= threshold) {
+                let (pos, len) = CommandPackage::write(&io_handler, &cmd_data).await?;
+                let key = cmd_data.get_key_owner();
+
+                vec_separated.push(CommandData::SetPtr { key, ptr: ValuePtr { gen, pos, len } });
+            } else {
+                vec_separated.push(cmd_data);
+            }
+        }
+        // SSTable中的ValuePtr依赖于此处的数据，需要先于SSTable落盘
+        io_handler.sync().await?;
+
+        // 下次写入时再创建新的文件
+        let is_rotated = io_handler.file_size().await? >= config.value_log_file_size;
+        if is_rotated {
+            inner.current_gen = None;
+        }
+
+        Ok((vec_separated, is_rotated))
+    }
+
+    /// 通过ValuePtr读取对应的value
+    pub(crate) async fn read(&self, ptr: &ValuePtr) -> Result<Vec<u8>> {
+        let io_handler = self.inner.read().await
+            .handlers.get(&ptr.gen)
+            .map(Arc::clone)
+            .ok_or(KvsError::ValueLogLostError)?;
+
+        CommandPackage::from_pos_unpack(&io_handler, ptr.pos, ptr.len).await?
+            .and_then(CommandData::get_value_owner)
+            .ok_or(KvsError::ValueLogLostError)
+    }
+
+    /// 创建从vLog中分块读取ValuePtr对应value的读取器
+    pub(crate) async fn reader(&self, key: &[u8], ptr: &ValuePtr) -> Result<ValueReader> {
+        let io_handler = self.inner.read().await
+            .handlers.get(&ptr.gen)
+            .map(Arc::clone)
+            .ok_or(KvsError::ValueLogLostError)?;
+
+        match ValueReader::from_log(&io_handler, key, ptr.pos, 0).await? {
+            Some(reader) => Ok(reader),
+            None => Ok(ValueReader::from_value(self.read(ptr).await?))
+        }
+    }
+
+    /// 获取数据的value，SetPtr会从vLog中读取对应的value
+    pub(crate) async fn unpack(&self, cmd_data: CommandData) -> Result<Option<Vec<u8>>> {
+        match cmd_data {
+            CommandData::SetPtr { ptr, .. } => Ok(Some(self.read(&ptr).await?)),
+            cmd_data => Ok(cmd_data.get_value_owner())
+        }
+    }
+
+    /// 获取已写满的vLog文件Gen，即除当前写入文件以外的所有文件
+    pub(crate) async fn sealed_gens(&self) -> Vec<i64> {
+        let inner = self.inner.read().await;
+
+        inner.handlers.keys()
+            .filter(|gen| inner.current_gen != Some(**gen))
+            .copied()
+            .collect()
+    }
+
+    /// 读取指定vLog文件中的所有数据及其对应的ValuePtr
+    pub(crate) async fn read_all_with_ptr(&self, gen: i64) -> Result<Vec<(ValuePtr, CommandData)>> {
+        let io_handler = self.inner.read().await
+            .handlers.get(&gen)
+            .map(Arc::clone)
+            .ok_or(KvsError::ValueLogLostError)?;
+
+        Ok(CommandPackage::from_read_to_vec(&io_handler).await?
+            .into_iter()
+            .map(|package| (ValuePtr { gen, pos: package.pos, len: package.len }, package.cmd))
+            .collect())
+    }
+
+    /// 删除指定的vLog文件
+    pub(crate) async fn remove(&self, gen: i64) -> Result<()> {
+        let _ignore = self.inner.write().await
+            .handlers.remove(&gen);
+        self.io_handler_factory.clean(gen)
+    }
+
+    /// 获取所有vLog文件及目录的格式版本文件，获取前会将写入缓冲刷入当前写入的文件
+    pub(crate) async fn live_files(&self) -> Result<Vec<FileRef>> {
+        let inner = self.inner.read().await;
+
+        let mut vec_file = Vec::with_capacity(inner.handlers.len() + 1);
+        for io_handler in inner.handlers.values() {
+            io_handler.flush().await?;
+            vec_file.push(io_handler.file_ref(FileKind::ValueLog)?);
+        }
+        vec_file.extend(FileRef::format_version(&self.io_handler_factory.get_dir_path())?);
+
+        Ok(vec_file)
+    }
+
+    pub(crate) async fn size_of_disk(&self) -> Result<u64> {
+        let mut size_of_disk = 0;
+        for io_handler in self.inner.read().await.handlers.values() {
+            size_of_disk += io_handler.file_size().await?;
+        }
+
+        Ok(size_of_disk)
+    }
+}
+
+impl ValuePtr {
+    pub(crate) fn get_len(&self) -> usize {
+        self.len
+    }
+}
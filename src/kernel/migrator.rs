@@ -0,0 +1,179 @@
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use async_trait::async_trait;
+use tracing::info;
+use crate::kernel::{CommandPackage, FORMAT_VERSION_FILE_NAME, FormatVersion, log_path, Result, sorted_gen_list};
+use crate::kernel::hash_kv::INDEX_SNAPSHOT_FILE_NAME;
+
+/// 迁移时存放重写后文件的临时目录名
+pub(crate) const MIGRATING_DIR_NAME: &str = "migrating";
+
+/// 迁移提交标记文件名
+/// 存在时表示临时目录中的文件均已重写并落盘，可直接用于替换原文件
+const MIGRATE_COMMIT_FILE_NAME: &str = "COMMIT";
+
+/// 数据文件的格式迁移器
+///
+/// 将目录中旧格式版本的文件以当前格式逐个重写，并替换同Gen的原文件
+/// 重写后的文件先写入临时目录并落盘，全部完成后写入提交标记，再通过rename逐个替换原文件：
+/// 提交前中断时丢弃临时目录，原文件保持不变；提交后中断时则在下次迁移时继续完成替换
+#[async_trait]
+pub(crate) trait Migrator: Sync {
+    /// 获取目录中需要迁移的文件Gen及其格式版本
+    async fn outdated_gens(&self, dir_path: &Path) -> Result<Vec<(i64, FormatVersion)>>;
+
+    /// 将旧格式的文件以当前格式重写至临时目录中的同Gen文件，返回前需确保已落盘
+    async fn rewrite(&self, dir_path: &Path, temp_dir_path: &Path, gen: i64, version: FormatVersion) -> Result<()>;
+
+    /// 所有文件替换完成后的收尾，如更新目录的格式版本
+    fn finish(&self, _dir_path: &Path) -> Result<()> {
+        Ok(())
+    }
+
+    /// 对目录进行迁移，返回被替换的文件数量
+    async fn migrate(&self, dir_path: &Path) -> Result<usize> {
+        let temp_dir_path = dir_path.join(MIGRATING_DIR_NAME);
+
+        if !dir_path.exists() {
+            return Ok(0);
+        }
+        if temp_dir_path.exists() {
+            // 上次迁移已提交但替换中断时继续完成替换
+            if temp_dir_path.join(MIGRATE_COMMIT_FILE_NAME).exists() {
+                return self.replace(dir_path, &temp_dir_path);
+            }
+            fs::remove_dir_all(&temp_dir_path)?;
+        }
+
+        let vec_outdated = self.outdated_gens(dir_path).await?;
+        if vec_outdated.is_empty() {
+            return Ok(0);
+        }
+        info!("[Migrator][migrate][Dir: {:?}][Outdated: {:?}]", dir_path, vec_outdated);
+        fs::create_dir_all(&temp_dir_path)?;
+        for (gen, version) in vec_outdated {
+            self.rewrite(dir_path, &temp_dir_path, gen, version).await?;
+        }
+        write_and_sync(&temp_dir_path.join(MIGRATE_COMMIT_FILE_NAME), &[])?;
+
+        self.replace(dir_path, &temp_dir_path)
+    }
+
+    /// 以临时目录中重写后的文件替换原文件
+    fn replace(&self, dir_path: &Path, temp_dir_path: &Path) -> Result<usize> {
+        let gen_list = sorted_gen_list(temp_dir_path)?;
+
+        for gen in gen_list.iter() {
+            fs::rename(log_path(temp_dir_path, *gen), log_path(dir_path, *gen))?;
+        }
+        self.finish(dir_path)?;
+        fs::remove_dir_all(temp_dir_path)?;
+
+        Ok(gen_list.len())
+    }
+}
+
+/// 日志目录的格式迁移器
+///
+/// 日志目录的格式版本记录于目录的版本文件之中，因此目录中的日志文件需全部迁移
+/// 迁移后日志中的数据位置发生变化，索引快照随之失效并被移除
+#[derive(Debug)]
+pub(crate) struct LogMigrator;
+
+#[async_trait]
+impl Migrator for LogMigrator {
+    async fn outdated_gens(&self, dir_path: &Path) -> Result<Vec<(i64, FormatVersion)>> {
+        let version = FormatVersion::load_or_init(dir_path)?;
+
+        if version == FormatVersion::CURRENT {
+            return Ok(Vec::new());
+        }
+        Ok(sorted_gen_list(dir_path)?.into_iter()
+            .map(|gen| (gen, version))
+            .collect())
+    }
+
+    async fn rewrite(&self, dir_path: &Path, temp_dir_path: &Path, gen: i64, version: FormatVersion) -> Result<()> {
+        let bytes = fs::read(log_path(dir_path, gen))?;
+        let mut migrated_bytes = Vec::with_capacity(bytes.len());
+
+        for (_, cmd_u8) in CommandPackage::get_vec_bytes(&bytes, version) {
+            migrated_bytes.append(&mut CommandPackage::len_head(cmd_u8.len(), FormatVersion::CURRENT));
+            migrated_bytes.extend_from_slice(cmd_u8);
+        }
+
+        write_and_sync(&log_path(temp_dir_path, gen), &migrated_bytes)
+    }
+
+    fn finish(&self, dir_path: &Path) -> Result<()> {
+        let snapshot_path = dir_path.join(INDEX_SNAPSHOT_FILE_NAME);
+        if snapshot_path.exists() {
+            fs::remove_file(snapshot_path)?;
+        }
+
+        let version_path = dir_path.join(FORMAT_VERSION_FILE_NAME);
+        let temp_path = version_path.with_extension("tmp");
+        write_and_sync(&temp_path, FormatVersion::CURRENT.as_u64().to_string().as_bytes())?;
+        fs::rename(temp_path, version_path)?;
+
+        Ok(())
+    }
+}
+
+/// 写入文件并fsync
+pub(crate) fn write_and_sync(path: &Path, bytes: &[u8]) -> Result<()> {
+    let mut file = File::create(path)?;
+
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+#[test]
+fn test_log_migrator() -> Result<()> {
+    use tempfile::TempDir;
+    use crate::kernel::{CommandData, KVStore};
+    use crate::kernel::hash_kv::HashStore;
+    use crate::kernel::io_handler::IOHandlerFactory;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let path = temp_dir.path();
+        let io_handler = IOHandlerFactory::new_with_format_version(path, FormatVersion::V0).create(1)?;
+        for key_id in 0..100_usize {
+            let cmd_data = CommandData::set(format!("key{}", key_id).into_bytes(), vec![b'v'; key_id]);
+            let _ignore = CommandPackage::write(&io_handler, &cmd_data).await?;
+        }
+        io_handler.flush().await?;
+
+        // 提交前中断：临时目录被丢弃后重新迁移
+        let temp_dir_path = path.join(MIGRATING_DIR_NAME);
+        fs::create_dir_all(&temp_dir_path)?;
+        fs::write(log_path(&temp_dir_path, 1), b"broken")?;
+        assert_eq!(LogMigrator.migrate(path).await?, 1);
+        assert!(!temp_dir_path.exists());
+        assert_eq!(FormatVersion::load_or_init(path)?, FormatVersion::CURRENT);
+        assert_eq!(LogMigrator.migrate(path).await?, 0);
+        let migrated_bytes = fs::read(log_path(path, 1))?;
+
+        // 提交后中断：直接使用临时目录中的文件完成替换
+        fs::write(path.join(FORMAT_VERSION_FILE_NAME), b"0")?;
+        fs::create_dir_all(&temp_dir_path)?;
+        let _ignore = fs::copy(log_path(path, 1), log_path(&temp_dir_path, 1))?;
+        fs::write(log_path(path, 1), b"replaced")?;
+        write_and_sync(&temp_dir_path.join(MIGRATE_COMMIT_FILE_NAME), &[])?;
+        assert_eq!(LogMigrator.migrate(path).await?, 1);
+        assert_eq!(fs::read(log_path(path, 1))?, migrated_bytes);
+        assert_eq!(FormatVersion::load_or_init(path)?, FormatVersion::CURRENT);
+
+        let kv_store = HashStore::open(path).await?;
+        for key_id in 0..100 {
+            assert_eq!(kv_store.get(format!("key{}", key_id).as_bytes()).await?, Some(vec![b'v'; key_id]));
+        }
+
+        Ok(())
+    })
+}
@@ -1,25 +1,62 @@
 use std::{path::PathBuf, fs};
+use std::borrow::Cow;
+use std::io;
+use std::sync::Arc;
+use std::collections::HashMap;
 use std::cmp::Ordering;
 use std::ffi::OsStr;
+use std::ops::Bound;
 use std::path::Path;
 use serde::{Deserialize, Serialize};
 use crate::kernel::io_handler::IOHandler;
+use crate::kernel::cipher::Cipher;
 use async_trait::async_trait;
+use bytes::Bytes;
 use futures::future;
 use itertools::Itertools;
 
 use crate::KvsError;
+use crate::kernel::diff::DiffReport;
+use crate::kernel::lsm::value_log::ValuePtr;
+use crate::kernel::value_reader::ValueReader;
 use crate::net::CommandOption;
 
 pub mod hash_kv;
 
 pub mod sled_kv;
+pub mod sharded_kv;
+pub mod typed_kv;
+pub mod indexed_kv;
+pub mod value_reader;
+pub mod blocking_kv;
 pub mod lsm;
 pub mod io_handler;
+pub mod cipher;
+pub(crate) mod migrator;
+pub(crate) mod dir_lock;
+pub mod diff;
+pub mod engine;
+pub(crate) mod hash_field;
 
 pub type Result<T> = std::result::Result<T, KvsError>;
 
+/// 格式版本文件名
+/// 存放于日志文件所在的目录之中，SSTable的格式版本则记录于各自的MetaInfo
+pub(crate) const FORMAT_VERSION_FILE_NAME: &str = "VERSION";
+
+/// `KVStore::ttl`中表示Key存在但未设置过期时间的返回值
+pub const NO_TTL: i64 = -1;
+
+/// varint长度头的最大字节数
+const MAX_VARINT_HEAD_LEN: usize = 5;
+
+/// scan_summary时每次分页读取的键值对数量
+const SUMMARY_PAGE_SIZE: usize = 1024;
+
 /// KV持久化内核 操作定义
+///
+/// 各内核均不支持空Key，对空Key进行set/get/remove时会返回`KvsError::DataEmpty`
+/// 空value则是合法的值，与Key不存在不同：set空value后get返回`Some(vec![])`，且同样参与遍历与扫描
 #[async_trait]
 pub trait KVStore: Send + 'static + Sized {
     /// 获取内核名
@@ -32,14 +69,319 @@ pub trait KVStore: Send + 'static + Sized {
     async fn flush(&self) -> Result<()>;
 
     /// 设置键值对
+    ///
+    /// value可以为空，空value不会被视为删除
     async fn set(&self, key: &[u8], value: Vec<u8>) -> Result<()>;
 
+    /// 设置键值对并立即落盘
+    ///
+    /// 返回Ok时该数据已通过fsync持久化至硬盘，此后即使断电也不会丢失，适用于关键数据；
+    /// 普通的set仅写入缓冲以保证吞吐，断电时尚未落盘的数据可能丢失
+    /// 默认在set后进行flush，内核可按需仅同步该数据所在的文件
+    #[inline]
+    async fn set_sync(&self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        self.set(key, value).await?;
+        self.flush().await
+    }
+
+    /// 仅当Key不存在时设置键值对
+    ///
+    /// 判断与写入原子完成，Key已存在时不覆盖并返回false，否则写入并返回true
+    /// value为空的Key同样视为已存在
+    async fn set_if_absent(&self, key: &[u8], value: Vec<u8>) -> Result<bool>;
+
+    /// 批量设置键值对
+    ///
+    /// 默认逐个进行set，内核可按需以单条数据整体写入
+    #[inline]
+    async fn set_batch(&self, pairs: Vec<(Vec<u8>, Vec<u8>)>) -> Result<()> {
+        for (key, value) in pairs {
+            self.set(&key, value).await?;
+        }
+
+        Ok(())
+    }
+
+    /// 原子地执行一组Set与Remove命令，全部成功或全部不生效
+    ///
+    /// 任意命令校验失败时不写入任何数据：存在Set与Remove以外的命令时返回`KvsError::NotMatchCmd`，
+    /// Remove的Key不存在时返回`KvsError::KeyNotFound`
+    /// 默认在校验通过后逐条执行，不保证崩溃时的原子性，内核可按需以单条数据整体落盘
+    #[inline]
+    async fn write_batch_atomic(&self, vec_cmd: Vec<CommandData>) -> Result<()> {
+        for key in batch_check(&vec_cmd)? {
+            if self.get(key).await?.is_none() {
+                return Err(KvsError::KeyNotFound);
+            }
+        }
+        for cmd in vec_cmd {
+            let _ignore = cmd.apply(self).await?;
+        }
+
+        Ok(())
+    }
+
+    /// 执行带有版本时间戳的命令，返回该命令是否生效
+    ///
+    /// 支持版本的内核(目前为HashStore与LsmStore)记录Set与Remove的ts并以LWW决定是否生效，
+    /// ts小于该Key当前记录的ts(包括Remove所保留墓碑的ts)时不生效；默认忽略ts直接执行
+    #[inline]
+    async fn apply_versioned(&self, versioned: VersionedCommand) -> Result<bool> {
+        let _ignore = versioned.cmd.apply(self).await?;
+        Ok(true)
+    }
+
     /// 通过键获取对应的值
     async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>>;
 
+    /// 通过键获取对应的值，并以Bytes返回
+    ///
+    /// Bytes直接接管读取出的value缓冲而不进行拷贝，且不持有内核中的任何锁，
+    /// 之后的clone与切片仅增加引用计数，适合大value需要在多处共享的场景
+    #[inline]
+    async fn get_bytes(&self, key: &[u8]) -> Result<Option<Bytes>> {
+        Ok(self.get(key).await?.map(Bytes::from))
+    }
+
+    /// 通过键获取对应value的异步读取器
+    ///
+    /// 支持的内核直接从文件中分块读取value，内存占用不随value大小增长，适合超大value的读取
+    /// 默认读取完整的value后以其创建读取器
+    #[inline]
+    async fn get_reader(&self, key: &[u8]) -> Result<Option<ValueReader>> {
+        Ok(self.get(key).await?.map(ValueReader::from_value))
+    }
+
+    /// 判断Key是否存在
+    ///
+    /// value为空的Key同样存在
+    #[inline]
+    async fn contains_key(&self, key: &[u8]) -> Result<bool> {
+        Ok(self.get(key).await?.is_some())
+    }
+
+    /// 获取Key剩余的存活时间(单位: 毫秒)
+    ///
+    /// Key不存在时返回None，Key存在但未设置过期时间时返回`NO_TTL`
+    /// 目前各内核均未存储过期时间，因此存在的Key总是返回`NO_TTL`，支持过期时间的内核需覆盖该方法
+    #[inline]
+    async fn ttl(&self, key: &[u8]) -> Result<Option<i64>> {
+        Ok(self.get(key).await?.map(|_| NO_TTL))
+    }
+
     /// 通过键删除键值对
     async fn remove(&self, key: &[u8]) -> Result<()>;
 
+    /// 通过键删除键值对并返回被删除的旧值
+    ///
+    /// 读取旧值与删除原子完成，Key不存在时返回None
+    async fn remove_and_get(&self, key: &[u8]) -> Result<Option<Vec<u8>>>;
+
+    /// 获取当前属于数据库的数据文件列表，供外部备份工具使用
+    ///
+    /// 仅包含数据文件，不包含索引快照、校验和清单等可重建的元数据文件
+    /// 需先调用`pause_file_deletion`再获取列表，恢复前列表中的文件不会被删除，可安全拷贝
+    /// 默认返回空列表，即该内核不支持(如SledStore的文件由sled自行管理)
+    #[inline]
+    async fn live_files(&self) -> Result<Vec<FileRef>> {
+        Ok(Vec::new())
+    }
+
+    /// 暂停压缩等操作对数据文件的删除
+    ///
+    /// 可嵌套调用，每次暂停都需要对应一次`resume_file_deletion`
+    /// 默认不进行任何操作
+    #[inline]
+    async fn pause_file_deletion(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// 恢复数据文件的删除
+    ///
+    /// 所有暂停均恢复后，暂停期间积压的待删除文件会被一并删除
+    /// 默认不进行任何操作
+    #[inline]
+    async fn resume_file_deletion(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// 批量删除键值对，返回实际被删除的Key数量
+    ///
+    /// 不存在的Key会被跳过而不会返回错误，也不计入删除数量
+    /// 默认逐个进行remove_and_get，内核可按需在一次加锁内整体完成
+    #[inline]
+    async fn remove_batch(&self, keys: &[&[u8]]) -> Result<usize> {
+        for key in keys {
+            key_check(key)?;
+        }
+        let mut count = 0;
+        for key in keys {
+            if self.remove_and_get(key).await?.is_some() {
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// 异步遍历所有键值对
+    ///
+    /// 已删除的数据不会被遍历，回调返回Err时中止遍历并返回该Err
+    async fn for_each<F>(&self, f: F) -> Result<()>
+        where F: FnMut(&[u8], &[u8]) -> Result<()> + Send;
+
+    /// 获取[start, end)范围内至多limit个键值对，以Key由小到大排列
+    ///
+    /// 已删除的数据不会被返回，与get/set一致，start为空Key时返回`KvsError::DataEmpty`
+    async fn scan(&self, start: &[u8], end: &[u8], limit: usize) -> Result<Vec<(Vec<u8>, Vec<u8>)>>;
+
+    /// 获取处于start与end边界之间的至多limit个键值对，以Key由小到大排列
+    ///
+    /// 边界以std的Bound表示是否包含该Key，`scan(start, end, limit)`即等同于以`Included(start)`与`Excluded(end)`调用
+    /// 默认将边界转换为[start, end)后以scan实现，end为Unbounded时以现有数据中最大的Key作为上界
+    #[inline]
+    async fn scan_with_bound(&self, start: Bound<&[u8]>, end: Bound<&[u8]>, limit: usize) -> Result<Vec<(Vec<u8>, Vec<u8>)>> where Self: Sync {
+        let start = match start {
+            Bound::Included(start) => start.to_vec(),
+            Bound::Excluded(start) => diff::successor(start),
+            Bound::Unbounded => diff::MIN_KEY.to_vec()
+        };
+        let end = match end {
+            Bound::Included(end) => diff::successor(end),
+            Bound::Excluded(end) => end.to_vec(),
+            Bound::Unbounded => match self.max_key().await? {
+                Some(max_key) => diff::successor(&max_key),
+                None => return Ok(Vec::new())
+            }
+        };
+        if start >= end {
+            return Ok(Vec::new());
+        }
+
+        self.scan(&start, &end, limit).await
+    }
+
+    /// 异步遍历所有Key
+    ///
+    /// 适用于重建二级索引等仅需Key的场景，内核可跳过value的读取以减少IO
+    /// 默认通过for_each实现
+    #[inline]
+    async fn for_each_key<F>(&self, mut f: F) -> Result<()>
+        where F: FnMut(&[u8]) -> Result<()> + Send
+    {
+        self.for_each(|key, _| f(key)).await
+    }
+
+    /// 获取不小于现有所有Key的最大Key，无数据时返回None
+    ///
+    /// 用作范围扫描的上界，返回的Key可能已被删除
+    /// 默认通过for_each_key遍历所有Key实现，内核应以索引或元数据直接获取
+    #[inline]
+    async fn max_key(&self) -> Result<Option<Vec<u8>>> {
+        let mut max_key: Option<Vec<u8>> = None;
+
+        self.for_each_key(|key| {
+            if max_key.as_deref() < Some(key) {
+                max_key = Some(key.to_vec());
+            }
+            Ok(())
+        }).await?;
+
+        Ok(max_key)
+    }
+
+    /// 获取[start, end)范围内至多limit个Key，以Key由小到大排列
+    ///
+    /// 内核可跳过value的读取以减少IO，默认通过scan实现
+    #[inline]
+    async fn scan_keys(&self, start: &[u8], end: &[u8], limit: usize) -> Result<Vec<Vec<u8>>> {
+        Ok(self.scan(start, end, limit).await?
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect())
+    }
+
+    /// 获取以prefix为前缀的至多limit个键值对，以Key由小到大排列
+    ///
+    /// 默认以scan实现，prefix全为0xFF时以现有数据中最大的Key作为上界
+    #[inline]
+    async fn prefix_scan(&self, prefix: &[u8], limit: usize) -> Result<Vec<(Vec<u8>, Vec<u8>)>> where Self: Sync {
+        key_check(prefix)?;
+
+        let end = match prefix_end(prefix) {
+            Some(end) => end,
+            None => match self.max_key().await?.filter(|max_key| max_key.as_slice() >= prefix) {
+                Some(max_key) => diff::successor(&max_key),
+                None => return Ok(Vec::new())
+            }
+        };
+        self.scan(prefix, &end, limit).await
+    }
+
+    /// 设置Key下field的value
+    ///
+    /// Key与field被编码为复合Key存储：Key中的分隔符会被转义，因此不同的Key与field不会编码为同一复合Key
+    /// 复合Key与普通的Key共用同一键空间，不应以普通的set直接写入形如复合Key的数据
+    #[inline]
+    async fn hset(&self, key: &[u8], field: &[u8], value: Vec<u8>) -> Result<()> {
+        self.set(&hash_field::encode_field_key(key, field), value).await
+    }
+
+    /// 获取Key下field的value
+    #[inline]
+    async fn hget(&self, key: &[u8], field: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.get(&hash_field::encode_field_key(key, field)).await
+    }
+
+    /// 获取Key下所有的field与value，以field由小到大排列
+    #[inline]
+    async fn hgetall(&self, key: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> where Self: Sync {
+        let prefix = hash_field::field_prefix(key);
+
+        Ok(self.prefix_scan(&prefix, usize::MAX).await?
+            .into_iter()
+            .map(|(field_key, value)| (field_key[prefix.len()..].to_vec(), value))
+            .collect())
+    }
+
+    /// 统计[start, end)范围内的键值对数量与Key、value的总字节数
+    ///
+    /// 不返回实际数据，已删除的数据不计入统计，结果与同范围内不限数量的scan一致
+    /// 默认以Key由小到大分页scan累加，内存中至多同时存在一页数据
+    #[inline]
+    async fn scan_summary(&self, start: &[u8], end: &[u8]) -> Result<RangeSummary> {
+        let mut summary = RangeSummary::default();
+        let mut start = start.to_vec();
+
+        loop {
+            let page = self.scan(&start, end, SUMMARY_PAGE_SIZE).await?;
+
+            for (key, value) in page.iter() {
+                summary.record(key, value);
+            }
+            match page.last() {
+                Some((last_key, _)) if page.len() == SUMMARY_PAGE_SIZE => start = diff::successor(last_key),
+                _ => break
+            }
+        }
+
+        Ok(summary)
+    }
+
+    /// 比较与另一内核之间的数据差异
+    ///
+    /// 以Key由小到大分页流式扫描两侧的数据并归并比较，报告仅存在于某一侧以及value不同的Key
+    #[inline]
+    async fn diff<O: KVStore + Sync>(&self, other: &O) -> Result<DiffReport> where Self: Sync {
+        self.diff_with_limit(other, usize::MAX).await
+    }
+
+    /// 比较与另一内核之间的数据差异，报告中的各类差异至多记录limit个Key
+    #[inline]
+    async fn diff_with_limit<O: KVStore + Sync>(&self, other: &O, limit: usize) -> Result<DiffReport> where Self: Sync {
+        diff::diff(self, other, limit).await
+    }
+
     /// 顺序批量执行
     #[inline]
     async fn batch_order(&self, vec_cmd: Vec<CommandData>) -> Result<Vec<Option<Vec<u8>>>> {
@@ -51,6 +393,19 @@ pub trait KVStore: Send + 'static + Sized {
         Ok(vec_result)
     }
 
+    /// 顺序批量执行，某一命令出错时仍继续执行之后的命令
+    ///
+    /// 返回值与命令一一对应，各自记录其成功结果或错误
+    #[inline]
+    async fn batch_order_lenient(&self, vec_cmd: Vec<CommandData>) -> Vec<Result<Option<Vec<u8>>>> {
+        let mut vec_result = Vec::with_capacity(vec_cmd.len());
+        for cmd in vec_cmd {
+            vec_result.push(cmd.apply(self).await.map(CommandOption::into))
+        }
+
+        vec_result
+    }
+
     /// 并行批量执行
     #[inline]
     async fn batch_parallel(&self, vec_cmd: Vec<CommandData>) -> Result<Vec<Option<Vec<u8>>>> {
@@ -74,20 +429,61 @@ pub trait KVStore: Send + 'static + Sized {
 #[derive(Debug)]
 struct CommandPackage {
     cmd: CommandData,
+    ts: u64,
     pos: u64,
     len: usize
 }
 
+/// 流式写入CommandData分片
+///
+/// 逐个分片序列化后写入同一IOHandler，至多同时持有一个分片的序列化数据，
+/// 而非将所有分片的序列化数据拼接后一次性写入，以此省去拼接所需的额外缓冲；
+/// 输入的分片本身仍由调用方持有，因此并不会减少输入数据所占用的内存
+/// 各分片连续写入，因此得到的起始Pos与各段长度与一次性写入时一致，写入期间该IOHandler不应有其他写入者
+#[derive(Debug)]
+pub(crate) struct ShardingWriter<'a> {
+    io_handler: &'a IOHandler,
+    version: FormatVersion,
+    cipher: Option<Arc<Cipher>>,
+    start_pos: Option<u64>,
+    batch_len: usize,
+    vec_sharding_len: Vec<usize>,
+    /// 已写入的分片中最大的序列化数据长度
+    max_sharding_len: usize
+}
+
 /// CommandPos Command磁盘指针
 /// 用于标记对应Command的位置
 /// gen 文件序号
 /// pos 开头指针
 /// len 命令长度
-#[derive(Debug, Copy, Clone)]
+/// ts 命令的版本时间戳，未带时间戳的命令为0
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
 struct CommandPos {
     gen: i64,
     pos: u64,
     len: usize,
+    #[serde(default)]
+    ts: u64
+}
+
+/// 带有版本时间戳的命令
+///
+/// 用于复制与冲突解决，同一Key的命令以ts较大者为准(LWW, last-write-wins)，ts相同时以后写入者为准
+/// 日志中以`[cmd, ts]`的形式存储，与CommandData的序列化形式不同，
+/// 因此解析时无法解析为VersionedCommand的旧记录会作为CommandData解析，其ts视为0
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct VersionedCommand {
+    pub(crate) cmd: CommandData,
+    #[serde(default)]
+    pub(crate) ts: u64
+}
+
+/// 以引用序列化VersionedCommand，避免写入时克隆命令
+#[derive(Serialize)]
+struct VersionedCommandRef<'a> {
+    cmd: &'a CommandData,
+    ts: u64
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Hash, PartialEq, Eq)]
@@ -95,9 +491,79 @@ struct CommandPos {
 pub enum CommandData {
     Set { key: Vec<u8>, value: Vec<u8> },
     Remove { key: Vec<u8> },
-    Get { key: Vec<u8> }
+    Get { key: Vec<u8> },
+    /// 批量Set，一组键值对共用一个长度头
+    /// 作为新增变体置于末尾以保证原有变体的序列化兼容
+    SetBatch { pairs: Vec<(Vec<u8>, Vec<u8>)> },
+    /// Key-Value分离时SSTable中存储的Set，value位于vLog之中
+    SetPtr { key: Vec<u8>, ptr: ValuePtr },
+    /// SSTable中存储的value经lz4压缩的Set，读取时还原为Set
+    SetCompressed { key: Vec<u8>, value: Vec<u8> },
+    /// LsmStore中尚未与更旧的数据合并的Merge操作数，由旧往新排列
+    /// 读取与压缩时通过`Config::merge_operator`与更旧的数据折叠
+    Merge { key: Vec<u8>, operands: Vec<Vec<u8>> }
+}
+
+/// 数据文件的格式版本，决定CommandData长度头的编码方式
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum FormatVersion {
+    /// 定长4字节的大端序长度头
+    V0,
+    /// varint编码的长度头，占用1-5字节
+    V1
 }
 
+/// 压缩统计
+/// 累计自开启以来完成的压缩次数与回收的磁盘大小(单位: 字节)，
+/// 以及自上次压缩以来被逻辑删除但尚未被物理回收的数据大小(单位: 字节，目前仅由HashStore统计)；
+/// LsmStore的压缩次数仅包含Major压缩，MemTable落盘的Minor压缩次数单独统计
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct CompactionStats {
+    pub(crate) count: u64,
+    pub(crate) minor_count: u64,
+    pub(crate) reclaimed_bytes: u64,
+    pub(crate) unreclaimed_bytes: u64
+}
+
+/// 数据文件的种类
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum FileKind {
+    /// HashStore的数据日志
+    Log,
+    /// LsmStore的SSTable
+    SsTable,
+    /// LsmStore的Wal日志
+    Wal,
+    /// LsmStore的vLog
+    ValueLog,
+    /// 日志目录的格式版本文件，gen恒为0
+    /// 缺失时目录中已有的日志会被视为最旧的格式版本，因此需随日志文件一同拷贝
+    FormatVersion
+}
+
+/// 属于数据库的数据文件
+/// 由`KVStore::live_files`创建，size为获取时的文件大小
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileRef {
+    pub(crate) gen: i64,
+    pub(crate) path: PathBuf,
+    pub(crate) size: u64,
+    pub(crate) kind: FileKind
+}
+
+/// 范围统计
+/// 记录范围内键值对的数量以及Key与value各自的总字节数
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct RangeSummary {
+    pub(crate) count: usize,
+    pub(crate) total_key_bytes: u64,
+    pub(crate) total_value_bytes: u64
+}
+
+/// SetBatch为空时get_key所返回的Key
+static EMPTY_KEY: Vec<u8> = Vec::new();
+
 impl CommandPos {
     /// 重写自身数据
     pub(crate) fn change(&mut self, file_gen: i64, pos: u64, len: usize) {
@@ -107,6 +573,311 @@ impl CommandPos {
     }
 }
 
+impl<'a> ShardingWriter<'a> {
+    pub(crate) fn new(io_handler: &'a IOHandler) -> Self {
+        ShardingWriter {
+            io_handler,
+            version: io_handler.format_version(),
+            cipher: io_handler.shared_cipher(),
+            start_pos: None,
+            batch_len: 0,
+            vec_sharding_len: Vec::new(),
+            max_sharding_len: 0
+        }
+    }
+
+    /// 序列化并写入一个分片
+    pub(crate) async fn write_sharding(&mut self, sharding: &[CommandData]) -> Result<()> {
+        let sharding_u8 = Self::serialize_sharding(sharding, self.version, self.cipher.as_deref())?;
+
+        self.write_sharding_u8(sharding_u8).await
+    }
+
+    /// 并行序列化并按原有顺序写入所有分片
+    ///
+    /// 以parallelism个分片为一组，在阻塞线程池中各自序列化，随后按分片顺序逐个写入，
+    /// 分片之间互不依赖且每个分片仅包含完整的CommandData，因此写入结果与逐个调用write_sharding一致
+    /// 内存中至多同时持有parallelism个分片的序列化数据，parallelism不大于1时不进行并行
+    pub(crate) async fn write_shardings_parallel(&mut self, vec_sharding: Vec<Vec<CommandData>>, parallelism: usize) -> Result<()> {
+        if parallelism <= 1 {
+            for sharding in vec_sharding.iter() {
+                self.write_sharding(sharding).await?;
+            }
+            return Ok(());
+        }
+        let mut iter = vec_sharding.into_iter().peekable();
+
+        while iter.peek().is_some() {
+            let vec_handle = iter.by_ref()
+                .take(parallelism)
+                .map(|sharding| {
+                    let version = self.version;
+                    let cipher = self.cipher.clone();
+                    tokio::task::spawn_blocking(move || Self::serialize_sharding(&sharding, version, cipher.as_deref()))
+                })
+                .collect_vec();
+            for handle in vec_handle {
+                let sharding_u8 = handle.await
+                    .map_err(io::Error::from)??;
+                self.write_sharding_u8(sharding_u8).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn serialize_sharding(sharding: &[CommandData], version: FormatVersion, cipher: Option<&Cipher>) -> Result<Vec<u8>> {
+        let mut sharding_u8 = Vec::new();
+        for cmd_data in sharding {
+            sharding_u8.append(&mut CommandPackage::trans_to_vec_u8(cmd_data, version, cipher)?);
+        }
+        Ok(sharding_u8)
+    }
+
+    async fn write_sharding_u8(&mut self, sharding_u8: Vec<u8>) -> Result<()> {
+        let (pos, len) = self.io_handler.write(sharding_u8).await?;
+
+        if self.start_pos.is_none() {
+            self.start_pos = Some(pos);
+        }
+        self.batch_len += len;
+        self.vec_sharding_len.push(len);
+        self.max_sharding_len = self.max_sharding_len.max(len);
+
+        Ok(())
+    }
+
+    pub(crate) fn max_sharding_len(&self) -> usize {
+        self.max_sharding_len
+    }
+
+    /// 结束写入，返回起始Pos、整段写入长度、每段数据序列化长度
+    ///
+    /// 未写入任何分片时起始Pos为当前的写入位置
+    pub(crate) async fn finish(self) -> Result<(u64, usize, Vec<usize>)> {
+        let start_pos = match self.start_pos {
+            Some(start_pos) => start_pos,
+            None => self.io_handler.write(Vec::new()).await?.0
+        };
+
+        Ok((start_pos, self.batch_len, self.vec_sharding_len))
+    }
+}
+
+impl FileRef {
+    /// 获取目录中格式版本文件的FileRef，文件不存在时返回None
+    pub(crate) fn format_version(dir_path: &Path) -> Result<Option<FileRef>> {
+        let path = dir_path.join(FORMAT_VERSION_FILE_NAME);
+
+        if !path.exists() {
+            return Ok(None);
+        }
+        let size = fs::metadata(&path)?.len();
+
+        Ok(Some(FileRef { gen: 0, path, size, kind: FileKind::FormatVersion }))
+    }
+
+    /// 文件的gen
+    #[inline]
+    pub fn gen(&self) -> i64 {
+        self.gen
+    }
+
+    /// 文件路径
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 文件大小(单位: 字节)
+    #[inline]
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// 文件种类
+    #[inline]
+    pub fn kind(&self) -> FileKind {
+        self.kind
+    }
+}
+
+impl CompactionStats {
+    /// 记录一次完成的压缩
+    pub(crate) fn record(&mut self, reclaimed_bytes: u64) {
+        self.count += 1;
+        self.reclaimed_bytes += reclaimed_bytes;
+    }
+
+    /// 记录一次完成的Minor压缩
+    pub(crate) fn record_minor(&mut self) {
+        self.minor_count += 1;
+    }
+
+    /// 记录一次逻辑删除所遗留的数据大小
+    pub(crate) fn record_removed(&mut self, removed_bytes: u64) {
+        self.unreclaimed_bytes += removed_bytes;
+    }
+
+    /// 扣除被压缩实际回收的逻辑删除数据大小
+    ///
+    /// 仅扣除压缩开始前已记录的部分，压缩期间新增的逻辑删除仍待下次压缩回收
+    pub(crate) fn record_removed_reclaimed(&mut self, removed_bytes: u64) {
+        self.unreclaimed_bytes = self.unreclaimed_bytes.saturating_sub(removed_bytes);
+    }
+
+    /// 获取完成的压缩次数
+    #[inline]
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// 获取完成的Minor压缩次数，目前仅由LsmStore统计
+    #[inline]
+    pub fn minor_count(&self) -> u64 {
+        self.minor_count
+    }
+
+    /// 获取压缩所回收的磁盘大小
+    #[inline]
+    pub fn reclaimed_bytes(&self) -> u64 {
+        self.reclaimed_bytes
+    }
+
+    /// 获取被逻辑删除但尚未被物理回收的数据大小
+    #[inline]
+    pub fn unreclaimed_bytes(&self) -> u64 {
+        self.unreclaimed_bytes
+    }
+}
+
+impl RangeSummary {
+    /// 记录一个键值对
+    pub(crate) fn record(&mut self, key: &[u8], value: &[u8]) {
+        self.count += 1;
+        self.total_key_bytes += key.len() as u64;
+        self.total_value_bytes += value.len() as u64;
+    }
+
+    /// 获取范围内键值对的数量
+    #[inline]
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// 获取范围内Key的总字节数
+    #[inline]
+    pub fn total_key_bytes(&self) -> u64 {
+        self.total_key_bytes
+    }
+
+    /// 获取范围内value的总字节数
+    #[inline]
+    pub fn total_value_bytes(&self) -> u64 {
+        self.total_value_bytes
+    }
+}
+
+impl FormatVersion {
+    /// 新建文件所使用的格式版本
+    pub(crate) const CURRENT: FormatVersion = FormatVersion::V1;
+
+    pub(crate) fn from_u64(version: u64) -> Result<Self> {
+        match version {
+            0 => Ok(FormatVersion::V0),
+            1 => Ok(FormatVersion::V1),
+            version => Err(KvsError::UnsupportedFormatVersion(version.to_string()))
+        }
+    }
+
+    pub(crate) fn as_u64(self) -> u64 {
+        match self {
+            FormatVersion::V0 => 0,
+            FormatVersion::V1 => 1
+        }
+    }
+
+    /// 获取目录中日志文件的格式版本
+    ///
+    /// 版本文件不存在时，若目录中已存在日志文件则为引入版本文件之前的V0，
+    /// 否则视为新目录并写入当前的格式版本
+    pub(crate) fn load_or_init(dir_path: &Path) -> Result<Self> {
+        let version_path = dir_path.join(FORMAT_VERSION_FILE_NAME);
+
+        if !version_path.exists() && sorted_gen_list(dir_path)?.is_empty() {
+            fs::write(version_path, Self::CURRENT.as_u64().to_string())?;
+        }
+        Self::load(dir_path)
+    }
+
+    /// 获取目录中日志文件的格式版本，不写入版本文件，用于只读地开启其他进程的目录
+    ///
+    /// 版本文件不存在时，若目录中已存在日志文件则为引入版本文件之前的V0，否则为当前的格式版本
+    pub(crate) fn load(dir_path: &Path) -> Result<Self> {
+        let version_path = dir_path.join(FORMAT_VERSION_FILE_NAME);
+
+        if version_path.exists() {
+            let version = fs::read_to_string(version_path)?;
+
+            version.trim()
+                .parse::<u64>()
+                .map_err(|err| KvsError::UnsupportedFormatVersion(format!("{version}: {err}")))
+                .and_then(Self::from_u64)
+        } else if sorted_gen_list(dir_path)?.is_empty() {
+            Ok(Self::CURRENT)
+        } else {
+            Ok(FormatVersion::V0)
+        }
+    }
+}
+
+impl VersionedCommand {
+    /// 以命令与其版本时间戳创建
+    #[inline]
+    pub fn new(cmd: CommandData, ts: u64) -> Self {
+        VersionedCommand { cmd, ts }
+    }
+
+    /// 获取命令
+    #[inline]
+    pub fn cmd(&self) -> &CommandData {
+        &self.cmd
+    }
+
+    /// 获取版本时间戳
+    #[inline]
+    pub fn ts(&self) -> u64 {
+        self.ts
+    }
+
+    /// 取出命令
+    #[inline]
+    pub fn into_cmd(self) -> CommandData {
+        self.cmd
+    }
+
+    /// 从一组命令中按LWW选出最新的命令，ts相同时以靠后者为准
+    #[inline]
+    pub fn latest(vec_versioned: impl IntoIterator<Item = Self>) -> Option<Self> {
+        vec_versioned.into_iter()
+            .max_by_key(VersionedCommand::ts)
+    }
+
+    /// 命令消费，交由内核以LWW记录并执行
+    #[inline]
+    pub async fn apply<K: KVStore>(self, kv_store: &K) -> Result<bool> {
+        kv_store.apply_versioned(self).await
+    }
+}
+
+impl From<CommandData> for VersionedCommand {
+    /// 未带时间戳的命令ts视为0
+    #[inline]
+    fn from(cmd: CommandData) -> Self {
+        VersionedCommand::new(cmd, 0)
+    }
+}
+
 impl PartialOrd<Self> for CommandData {
     #[inline]
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
@@ -127,137 +898,244 @@ impl CommandPackage {
         Ok(rmp_serde::to_vec(cmd)?)
     }
 
+    /// 编码带有版本时间戳的命令，与write_with_ts写入的数据一致
+    pub(crate) fn encode_with_ts(cmd: &CommandData, ts: u64) -> Result<Vec<u8>> {
+        if ts == 0 {
+            Self::encode(cmd)
+        } else {
+            Ok(rmp_serde::to_vec(&VersionedCommandRef { cmd, ts })?)
+        }
+    }
+
     pub(crate) fn decode(vec: &[u8]) -> Result<CommandData> {
         Ok(rmp_serde::from_slice(vec)?)
     }
 
+    /// 解析可能带有版本时间戳的命令
+    ///
+    /// 先作为CommandData解析，失败时再作为VersionedCommand解析，两者均失败时返回CommandData的解析错误
+    pub(crate) fn decode_versioned(vec: &[u8]) -> Result<VersionedCommand> {
+        match rmp_serde::from_slice::<CommandData>(vec) {
+            Ok(cmd) => Ok(VersionedCommand::from(cmd)),
+            Err(err) => rmp_serde::from_slice::<VersionedCommand>(vec)
+                .map_err(|_| err.into())
+        }
+    }
+
     /// 实例化一个Command
-    pub(crate) fn new(cmd: CommandData, pos: u64, len: usize) -> Self {
-        CommandPackage{ cmd, pos, len }
+    pub(crate) fn new(versioned: VersionedCommand, pos: u64, len: usize) -> Self {
+        let VersionedCommand { cmd, ts } = versioned;
+
+        CommandPackage{ cmd, ts, pos, len }
+    }
+
+    /// 写入一个带有版本时间戳的Command，ts为0时与write一致
+    /// 写入完成后该cmd的去除长度头后的写入起始位置与长度
+    pub(crate) async fn write_with_ts(io_handler: &IOHandler, cmd: &CommandData, ts: u64) -> Result<(u64, usize)> {
+        if ts == 0 {
+            Self::write(io_handler, cmd).await
+        } else {
+            Self::write(io_handler, &VersionedCommandRef { cmd, ts }).await
+        }
     }
 
     /// 写入一个Command
-    /// 写入完成后该cmd的去除len位置的写入起始位置与长度
-    pub(crate) async fn write(io_handler: &IOHandler, cmd: &CommandData) -> Result<(u64, usize)> {
-        let (start, len) = Self::write_back_real_pos(io_handler, cmd).await?;
-        Ok((start + 4, len - 4))
+    /// 写入完成后该cmd的去除长度头后的写入起始位置与长度
+    pub(crate) async fn write<T: Serialize + Sync>(io_handler: &IOHandler, cmd: &T) -> Result<(u64, usize)> {
+        let version = io_handler.format_version();
+        let vec_u8 = Self::trans_to_vec_u8(cmd, version, io_handler.cipher())?;
+        // 长度头的字节数随数据长度变化，因此从编码结果中解析
+        let (len, head_len) = Self::from_len_head_with_start(&vec_u8, version)
+            .ok_or(KvsError::DataEmpty)?;
+        let (start, _) = io_handler.write(vec_u8).await?;
+
+        Ok((start + head_len as u64, len))
     }
 
     /// 写入一个Command
     /// 写入完成后该cmd的真实写入起始位置与长度
     pub(crate) async fn write_back_real_pos(io_handler: &IOHandler, cmd: &CommandData) -> Result<(u64, usize)> {
-        io_handler.write(Self::trans_to_vec_u8(cmd)?).await
+        io_handler.write(Self::trans_to_vec_u8(cmd, io_handler.format_version(), io_handler.cipher())?).await
     }
 
-    /// 将数据分片集成写入， 返回起始Pos、整段写入Pos、每段数据序列化长度Pos
-    pub(crate) async fn write_batch_first_pos_with_sharding(io_handler: &IOHandler, vec_sharding: &Vec<Vec<CommandData>>) -> Result<(u64, usize, Vec<usize>)> {
-        let mut vec_sharding_len = Vec::with_capacity(vec_sharding.len());
-
-        let vec_sharding_u8  = vec_sharding.iter()
-            .flat_map(|sharding| {
-                let sharding_u8 = sharding.iter()
-                    .filter_map(|cmd_data| Self::trans_to_vec_u8(cmd_data).ok())
-                    .flatten()
-                    .collect_vec();
-                vec_sharding_len.push(sharding_u8.len());
-                sharding_u8
-            })
-            .collect_vec();
+    /// 将Command序列化并在开头附加对应格式版本的长度头
+    ///
+    /// cipher不为None时对序列化后的数据进行加密，长度头记录的是密文的长度且不加密，
+    /// 因此文件的分段与crc校验均基于密文，无需密钥即可完成
+    pub(crate) fn trans_to_vec_u8<T: Serialize>(cmd: &T, version: FormatVersion, cipher: Option<&Cipher>) -> Result<Vec<u8>> {
+        let mut vec = rmp_serde::to_vec(cmd)?;
+        if let Some(cipher) = cipher {
+            vec = cipher.encrypt(&vec)?;
+        }
+        let mut vec_head = Self::len_head(vec.len(), version);
+        vec_head.append(&mut vec);
+        Ok(vec_head)
+    }
 
-        let (start_pos, batch_len) = io_handler.write(vec_sharding_u8).await?;
+    /// 将数据长度编码为长度头
+    ///
+    /// V1使用varint编码，每个字节的低7位存放数据，最高位标识之后是否仍有字节，低位在前
+    pub(crate) fn len_head(len: usize, version: FormatVersion) -> Vec<u8> {
+        match version {
+            FormatVersion::V0 => vec![(len >> 24) as u8,
+                                      (len >> 16) as u8,
+                                      (len >> 8) as u8,
+                                      len as u8],
+            FormatVersion::V1 => {
+                let mut vec_head = Vec::with_capacity(MAX_VARINT_HEAD_LEN);
+                let mut len = len;
 
-        Ok((start_pos, batch_len, vec_sharding_len))
+                while len >= 0x80 {
+                    vec_head.push((len & 0x7f) as u8 | 0x80);
+                    len >>= 7;
+                }
+                vec_head.push(len as u8);
+                vec_head
+            }
+        }
     }
 
-    pub(crate) fn trans_to_vec_u8(cmd: &CommandData) -> Result<Vec<u8>> {
-        let mut vec = rmp_serde::to_vec(cmd)?;
-        let i = vec.len();
-        let mut vec_head = vec![(i >> 24) as u8,
-                                (i >> 16) as u8,
-                                (i >> 8) as u8,
-                                i as u8];
-        vec_head.append(&mut vec);
-        Ok(vec_head)
+    /// 解密去除长度头后的Command数据，cipher为None时原样返回
+    ///
+    /// 解密失败意味着密钥错误或数据被篡改，与无法解析的Command不同，不会被跳过
+    fn decrypt<'a>(cmd_u8: &'a [u8], cipher: Option<&Cipher>) -> Result<Cow<'a, [u8]>> {
+        Ok(match cipher {
+            Some(cipher) => Cow::Owned(cipher.decrypt(cmd_u8)?),
+            None => Cow::Borrowed(cmd_u8)
+        })
     }
 
     /// IOHandler的对应Gen，以起始位置与长度使用的单个Command，不进行CommandPackage包装
     pub(crate) async fn from_pos_unpack(io_handler: &IOHandler, start: u64, len: usize) -> Result<Option<CommandData>> {
         let cmd_u8 = io_handler.read_with_pos(start, len).await?;
-        Ok(rmp_serde::from_slice(cmd_u8.as_slice()).ok())
+        let cmd_u8 = Self::decrypt(&cmd_u8, io_handler.cipher())?;
+        Ok(Self::decode_versioned(&cmd_u8).ok()
+            .map(VersionedCommand::into_cmd))
     }
 
     /// 获取bytes之中所有的CommandPackage
-    pub(crate) fn from_bytes_to_vec(bytes: &[u8]) -> Result<Vec<CommandPackage>> {
-        let mut pos = 4;
-        Ok(Self::get_vec_bytes(bytes).into_iter()
-            .filter_map(|cmd_u8| {
-                let len = cmd_u8.len();
-                let option = rmp_serde::from_slice::<CommandData>(cmd_u8).ok()
-                    .map(|cmd_data| CommandPackage::new(cmd_data, pos, len));
-                // 对pos进行长度自增并对占位符进行跳过
-                pos += len as u64 + 4;
-                option
-            })
-            .collect_vec())
+    pub(crate) fn from_bytes_to_vec(bytes: &[u8], version: FormatVersion, cipher: Option<&Cipher>) -> Result<Vec<CommandPackage>> {
+        let mut vec_package = Vec::new();
+        for (pos, cmd_u8) in Self::get_vec_bytes(bytes, version) {
+            if let Ok(versioned) = Self::decode_versioned(&Self::decrypt(cmd_u8, cipher)?) {
+                vec_package.push(CommandPackage::new(versioned, pos as u64, cmd_u8.len()));
+            }
+        }
+
+        Ok(vec_package)
     }
 
     /// 获取bytes之中所有的CommandData
-    pub(crate) fn from_bytes_to_unpack_vec(bytes: &[u8]) -> Result<Vec<CommandData>> {
-        Ok(Self::get_vec_bytes(bytes).into_iter()
-            .filter_map(|cmd_u8| rmp_serde::from_slice(cmd_u8).ok())
-            .collect_vec())
+    pub(crate) fn from_bytes_to_unpack_vec(bytes: &[u8], version: FormatVersion, cipher: Option<&Cipher>) -> Result<Vec<CommandData>> {
+        let mut vec_cmd_data = Vec::new();
+        for (_, cmd_u8) in Self::get_vec_bytes(bytes, version) {
+            if let Ok(versioned) = Self::decode_versioned(&Self::decrypt(cmd_u8, cipher)?) {
+                vec_cmd_data.push(versioned.into_cmd());
+            }
+        }
+
+        Ok(vec_cmd_data)
     }
 
     /// 获取reader之中所有的Command
     pub(crate) async fn from_read_to_vec(io_handler: &IOHandler) -> Result<Vec<CommandPackage>> {
         let bytes = io_handler.read_to_end().await?;
-        Self::from_bytes_to_vec(bytes.as_slice())
+        Self::from_bytes_to_vec(bytes.as_slice(), io_handler.format_version(), io_handler.cipher())
+    }
+
+    /// 获取reader之中由start起始的所有Command
+    /// 所获取的CommandPackage的pos仍为文件中的绝对位置
+    ///
+    /// 与from_read_to_vec不同，存在无法解析的Command时不会跳过，
+    /// 而是返回带有文件种类kind、文件Gen与该Command偏移量的`KvsError::CorruptedFile`
+    pub(crate) async fn from_read_to_vec_with_start(io_handler: &IOHandler, start: u64, kind: FileKind) -> Result<Vec<CommandPackage>> {
+        let gen = io_handler.get_gen();
+        let version = io_handler.format_version();
+        let cipher = io_handler.cipher();
+        let len = io_handler.file_size().await?.saturating_sub(start);
+        let bytes = io_handler.read_with_pos(start, len as usize).await?;
+
+        Self::get_vec_bytes(bytes.as_slice(), version).into_iter()
+            .map(|(pos, cmd_u8)| {
+                // pos指向长度头之后的数据位置
+                let pos = start + pos as u64;
+                let len = cmd_u8.len();
+                let versioned = Self::decode_versioned(&Self::decrypt(cmd_u8, cipher)?)
+                    .map_err(|err| {
+                        let offset = pos - Self::len_head(len, version).len() as u64;
+                        KvsError::CorruptedFile { kind, gen, offset, reason: err.to_string() }
+                    })?;
+                Ok(CommandPackage::new(versioned, pos, len))
+            })
+            .collect()
     }
 
     /// 获取此reader的所有命令对应的字节数组段落
-    /// 返回字节数组Vec与对应的字节数组长度Vec
-    pub(crate) fn get_vec_bytes(bytes: &[u8]) -> Vec<&[u8]> {
+    /// 返回各段落去除长度头后在bytes中的起始位置与对应的字节数组
+    ///
+    /// 长度头不完整、长度为0(如预分配的空白空间)或数据不完整时停止解析
+    pub(crate) fn get_vec_bytes(bytes: &[u8], version: FormatVersion) -> Vec<(usize, &[u8])> {
         let mut vec_cmd_u8 = Vec::new();
         let mut last_pos = 0;
-        if bytes.len() < 4 {
-            return vec_cmd_u8;
-        }
 
-        loop {
-            let pos = last_pos + 4;
-            if pos >= bytes.len() {
-                break
+        while let Some((len, head_len)) = bytes.get(last_pos..)
+            .and_then(|bytes| Self::from_len_head_with_start(bytes, version))
+        {
+            let pos = last_pos + head_len;
+            match bytes.get(pos..pos + len) {
+                Some(cmd_u8) if len > 0 => vec_cmd_u8.push((pos, cmd_u8)),
+                _ => break
             }
-            let len_u8 = &bytes[last_pos..pos];
-            let len = Self::from_4_bit_with_start(len_u8);
-            if len < 1 || len > bytes.len() {
-                break
-            }
-
-            last_pos += len + 4;
-            vec_cmd_u8.push(&bytes[pos..last_pos]);
+            last_pos = pos + len;
         }
 
         vec_cmd_u8
     }
 
-    /// 从u8的slice中前四位获取数据的长度
-    fn from_4_bit_with_start(len_u8: &[u8]) -> usize {
-        usize::from(len_u8[3])
-            | usize::from(len_u8[2]) << 8
-            | usize::from(len_u8[1]) << 16
-            | usize::from(len_u8[0]) << 24
+    /// 从u8的slice开头的长度头中获取数据的长度
+    /// 返回数据长度与长度头所占用的字节数，长度头不完整时返回None
+    pub(crate) fn from_len_head_with_start(bytes: &[u8], version: FormatVersion) -> Option<(usize, usize)> {
+        match version {
+            FormatVersion::V0 => bytes.get(..4)
+                .map(|len_u8| {
+                    let len = usize::from(len_u8[3])
+                        | usize::from(len_u8[2]) << 8
+                        | usize::from(len_u8[1]) << 16
+                        | usize::from(len_u8[0]) << 24;
+                    (len, 4)
+                }),
+            FormatVersion::V1 => {
+                let mut len = 0;
+
+                for (i, byte) in bytes.iter().take(MAX_VARINT_HEAD_LEN).enumerate() {
+                    len |= usize::from(byte & 0x7f) << (7 * i);
+                    if byte & 0x80 == 0 {
+                        return Some((len, i + 1));
+                    }
+                }
+                None
+            }
+        }
     }
 }
 
 impl CommandData {
 
+    /// 获取Key
+    ///
+    /// SetBatch以第一个键值对的Key作为其Key
     #[inline]
     pub fn get_key(&self) -> &Vec<u8> {
         match self {
             CommandData::Set { key, .. } => { key }
             CommandData::Remove { key } => { key }
             CommandData::Get { key } => { key }
+            CommandData::SetBatch { pairs } => {
+                pairs.first().map_or(&EMPTY_KEY, |(key, _)| key)
+            }
+            CommandData::SetPtr { key, .. } => { key }
+            CommandData::SetCompressed { key, .. } => { key }
+            CommandData::Merge { key, .. } => { key }
         }
     }
 
@@ -272,14 +1150,24 @@ impl CommandData {
             CommandData::Set { key, .. } => { key }
             CommandData::Remove { key } => { key }
             CommandData::Get { key } => { key }
+            CommandData::SetBatch { pairs } => {
+                pairs.into_iter().next().map(|(key, _)| key).unwrap_or_default()
+            }
+            CommandData::SetPtr { key, .. } => { key }
+            CommandData::SetCompressed { key, .. } => { key }
+            CommandData::Merge { key, .. } => { key }
         }
     }
 
+    /// 获取value
+    ///
+    /// SetCompressed的value为压缩后的数据，Merge则尚未与旧数据合并，因此与SetPtr一样返回None
     #[inline]
     pub fn get_value(&self) -> Option<&Vec<u8>> {
         match self {
             CommandData::Set { value, .. } => { Some(value) }
-            CommandData::Remove{ .. } | CommandData::Get{ .. } => { None }
+            CommandData::Remove{ .. } | CommandData::Get{ .. } | CommandData::SetBatch{ .. }
+            | CommandData::SetPtr{ .. } | CommandData::SetCompressed{ .. } | CommandData::Merge{ .. } => { None }
         }
     }
 
@@ -287,7 +1175,8 @@ impl CommandData {
     pub fn get_value_clone(&self) -> Option<Vec<u8>> {
         match self {
             CommandData::Set { value, .. } => { Some(value.clone()) }
-            CommandData::Remove{ .. } | CommandData::Get{ .. } => { None }
+            CommandData::Remove{ .. } | CommandData::Get{ .. } | CommandData::SetBatch{ .. }
+            | CommandData::SetPtr{ .. } | CommandData::SetCompressed{ .. } | CommandData::Merge{ .. } => { None }
         }
     }
 
@@ -295,14 +1184,27 @@ impl CommandData {
     pub fn get_value_owner(self) -> Option<Vec<u8>> {
         match self {
             CommandData::Set { value, .. } => { Some(value) }
-            CommandData::Remove{ .. } | CommandData::Get{ .. } => { None }
+            CommandData::Remove{ .. } | CommandData::Get{ .. } | CommandData::SetBatch{ .. }
+            | CommandData::SetPtr{ .. } | CommandData::SetCompressed{ .. } | CommandData::Merge{ .. } => { None }
         }
     }
 
     #[inline]
     pub fn get_data_len_for_rmp(&self) -> usize {
+        if let CommandData::SetBatch { pairs } = self {
+            return pairs.iter()
+                .map(|(key, value)| key.len() + value.len() + 3)
+                .sum::<usize>() + self.get_cmd_len_for_rmp();
+        }
+        let value_len = match self {
+            CommandData::SetCompressed { value, .. } => value.len(),
+            CommandData::Merge { operands, .. } => operands.iter()
+                .map(|operand| operand.len() + 3)
+                .sum(),
+            cmd_data => cmd_data.get_value().map_or(0, Vec::len)
+        };
         self.get_key().len()
-            + self.get_value().map_or(0, |value| value.len())
+            + value_len
             + self.get_cmd_len_for_rmp()
     }
 
@@ -314,6 +1216,60 @@ impl CommandData {
             CommandData::Set { .. } => { 10 }
             CommandData::Remove { .. } => { 12 }
             CommandData::Get { .. } => { 9 }
+            // 每个键值对额外占用的3位已计入get_data_len_for_rmp
+            CommandData::SetBatch { .. } => { 14 }
+            // ValuePtr为三个整数，此处按其最大的序列化长度计入
+            CommandData::SetPtr { .. } => { 40 }
+            // 变体名比Set多出10个字符
+            CommandData::SetCompressed { .. } => { 20 }
+            // 变体名比Set多出2个字符，每个操作数额外占用的3位已计入get_data_len_for_rmp
+            CommandData::Merge { .. } => { 12 }
+        }
+    }
+
+    /// 使用lz4对value长度超过threshold的Set进行压缩
+    ///
+    /// 压缩后未变小时保持原样，避免负收益；其余指令原样返回
+    pub(crate) fn compress(self, threshold: usize) -> Self {
+        match self {
+            CommandData::Set { key, value } if value.len() > threshold => {
+                let compressed = lz4_flex::compress_prepend_size(&value);
+
+                if compressed.len() < value.len() {
+                    CommandData::SetCompressed { key, value: compressed }
+                } else {
+                    CommandData::Set { key, value }
+                }
+            }
+            cmd_data => cmd_data
+        }
+    }
+
+    /// 将SetCompressed解压还原为Set，其余指令原样返回
+    pub(crate) fn decompress(self) -> Result<Self> {
+        match self {
+            CommandData::SetCompressed { key, value } => {
+                let value = lz4_flex::decompress_size_prepended(&value)
+                    .map_err(|err| KvsError::DecompressError(err.to_string()))?;
+
+                Ok(CommandData::Set { key, value })
+            }
+            cmd_data => Ok(cmd_data)
+        }
+    }
+
+    /// 从SetBatch中拆分出该Key对应的Set，不存在该Key时返回None
+    ///
+    /// 其余指令则原样返回
+    #[inline]
+    pub fn split_with_key(self, key: &[u8]) -> Option<Self> {
+        match self {
+            CommandData::SetBatch { pairs } => {
+                pairs.into_iter()
+                    .rfind(|(pair_key, _)| pair_key.as_slice() == key)
+                    .map(|(key, value)| CommandData::Set { key, value })
+            }
+            cmd_data => Some(cmd_data)
         }
     }
 
@@ -334,6 +1290,11 @@ impl CommandData {
             CommandData::Get { key } => {
                 kv_store.get(&key).await.map(CommandOption::from)
             }
+            CommandData::SetBatch { pairs } => {
+                kv_store.set_batch(pairs).await.map(|_| CommandOption::None)
+            }
+            // SetPtr、SetCompressed与Merge仅存在于LsmStore内部，不作为命令使用
+            CommandData::SetPtr { .. } | CommandData::SetCompressed { .. } | CommandData::Merge { .. } => Err(KvsError::NotMatchCmd)
         }
     }
 
@@ -351,6 +1312,11 @@ impl CommandData {
     pub fn get(key: Vec<u8>) -> Self {
         Self::Get { key }
     }
+
+    #[inline]
+    pub fn set_batch(pairs: Vec<(Vec<u8>, Vec<u8>)>) -> Self {
+        Self::SetBatch { pairs }
+    }
 }
 
 /// Option<String>与CommandOption的转换方法
@@ -365,6 +1331,58 @@ impl From<Option<Vec<u8>>> for CommandOption {
     }
 }
 
+/// 获取大于所有以prefix为前缀的Key的最小Key，prefix全为0xFF时不存在
+pub(crate) fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return Some(end);
+        }
+    }
+    None
+}
+
+/// 校验Key是否合法
+/// 空Key在Scope与稀疏索引的比较中语义不明确，因此统一拒绝
+pub(crate) fn key_check(key: &[u8]) -> Result<()> {
+    if key.is_empty() {
+        Err(KvsError::DataEmpty)
+    } else {
+        Ok(())
+    }
+}
+
+/// 校验原子批次中的命令，返回需要在批次之外确认存在的Remove Key
+///
+/// 批次中仅允许Set与Remove，被批次中先前的命令写入过的Key无需再确认存在，
+/// 而对批次中已删除的Key再次Remove时直接返回`KvsError::KeyNotFound`
+pub(crate) fn batch_check(vec_cmd: &[CommandData]) -> Result<Vec<&[u8]>> {
+    let mut batch_exists = HashMap::new();
+    let mut vec_remove_key = Vec::new();
+
+    for cmd in vec_cmd {
+        match cmd {
+            CommandData::Set { key, .. } => {
+                key_check(key)?;
+                let _ignore = batch_exists.insert(key.as_slice(), true);
+            }
+            CommandData::Remove { key } => {
+                key_check(key)?;
+                match batch_exists.insert(key.as_slice(), false) {
+                    Some(true) => (),
+                    Some(false) => return Err(KvsError::KeyNotFound),
+                    None => vec_remove_key.push(key.as_slice())
+                }
+            }
+            _ => return Err(KvsError::NotMatchCmd)
+        }
+    }
+
+    Ok(vec_remove_key)
+}
+
 /// 现有日志文件序号排序
 fn sorted_gen_list(file_path: &Path) -> Result<Vec<i64>> {
     // 读取文件夹路径
@@ -429,4 +1447,157 @@ fn log_path(dir: &Path, gen: i64) -> PathBuf {
 //     info!("{}", cmd_len);
 //
 //     Ok(())
-// }
\ No newline at end of file
+// }
+#[test]
+fn test_len_head_varint() {
+    // varint的各长度边界及其长度头的字节数
+    let vec_len_with_head_len = vec![
+        (1, 1), (127, 1), (128, 2), (16383, 2), (16384, 3),
+        (2097151, 3), (2097152, 4), (268435455, 4), (268435456, 5), (u32::MAX as usize, 5)
+    ];
+
+    for (len, head_len) in vec_len_with_head_len {
+        let vec_head = CommandPackage::len_head(len, FormatVersion::V1);
+        assert_eq!(vec_head.len(), head_len);
+        assert_eq!(CommandPackage::from_len_head_with_start(&vec_head, FormatVersion::V1), Some((len, head_len)));
+        // 长度头不完整时无法解析
+        assert_eq!(CommandPackage::from_len_head_with_start(&vec_head[..head_len - 1], FormatVersion::V1), None);
+
+        let vec_head = CommandPackage::len_head(len, FormatVersion::V0);
+        assert_eq!(vec_head.len(), 4);
+        assert_eq!(CommandPackage::from_len_head_with_start(&vec_head, FormatVersion::V0), Some((len, 4)));
+    }
+}
+
+#[test]
+fn test_get_vec_bytes_with_format_version() {
+    let vec_len = vec![1, 127, 128, 300, 16383, 16384];
+
+    for version in [FormatVersion::V0, FormatVersion::V1] {
+        let mut bytes = Vec::new();
+        let mut vec_expected = Vec::new();
+        for (i, len) in vec_len.iter().enumerate() {
+            bytes.append(&mut CommandPackage::len_head(*len, version));
+            vec_expected.push((bytes.len(), vec![i as u8; *len]));
+            bytes.append(&mut vec![i as u8; *len]);
+        }
+        // 末尾不完整的数据不会被解析
+        bytes.append(&mut CommandPackage::len_head(16384, version));
+        bytes.append(&mut vec![0; 10]);
+
+        let vec_cmd_u8 = CommandPackage::get_vec_bytes(&bytes, version).into_iter()
+            .map(|(pos, cmd_u8)| (pos, cmd_u8.to_vec()))
+            .collect_vec();
+        assert_eq!(vec_cmd_u8, vec_expected);
+    }
+}
+
+#[test]
+fn test_format_version_load_or_init() -> Result<()> {
+    use tempfile::TempDir;
+    use crate::kernel::io_handler::IOHandlerFactory;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let cmd_data = CommandData::set(vec![b'k'], vec![b'v'; 200]);
+
+        // 新目录使用当前的格式版本并写入版本文件
+        let new_path = temp_dir.path().join("new");
+        fs::create_dir_all(&new_path)?;
+        assert_eq!(FormatVersion::load_or_init(&new_path)?, FormatVersion::CURRENT);
+        assert!(new_path.join(FORMAT_VERSION_FILE_NAME).exists());
+
+        // 不存在版本文件但已存在日志文件的目录视为V0
+        let old_path = temp_dir.path().join("old");
+        fs::create_dir_all(&old_path)?;
+        let io_handler = IOHandlerFactory::new_with_format_version(&old_path, FormatVersion::V0).create(1)?;
+        let (pos, len) = CommandPackage::write(&io_handler, &cmd_data).await?;
+        io_handler.flush().await?;
+        assert_eq!(pos, 4);
+        assert_eq!(FormatVersion::load_or_init(&old_path)?, FormatVersion::V0);
+        assert!(!old_path.join(FORMAT_VERSION_FILE_NAME).exists());
+
+        let io_handler = IOHandlerFactory::new_with_format_version(&old_path, FormatVersion::V0).create(1)?;
+        let vec_package = CommandPackage::from_read_to_vec(&io_handler).await?;
+        assert_eq!(vec_package.len(), 1);
+        assert_eq!((vec_package[0].pos, vec_package[0].len), (pos, len));
+        assert_eq!(CommandPackage::from_pos_unpack(&io_handler, pos, len).await?, Some(cmd_data.clone()));
+
+        // 同样的数据以varint长度头写入时长度头仅占用2字节
+        let io_handler = IOHandlerFactory::new_with_format_version(&new_path, FormatVersion::V1).create(1)?;
+        assert_eq!(CommandPackage::write(&io_handler, &cmd_data).await?, (2, len));
+
+        fs::write(new_path.join(FORMAT_VERSION_FILE_NAME), "2")?;
+        assert!(matches!(FormatVersion::load_or_init(&new_path), Err(KvsError::UnsupportedFormatVersion(_))));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_sharding_writer() -> Result<()> {
+    use tempfile::TempDir;
+    use crate::kernel::io_handler::IOHandlerFactory;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let factory = IOHandlerFactory::new(temp_dir.path());
+        let vec_sharding = (0..100_u32)
+            .map(|i| (0..100_u32)
+                .map(|j| CommandData::set((i * 100 + j).to_be_bytes().to_vec(), vec![b'v'; 100]))
+                .collect_vec())
+            .collect_vec();
+
+        let stream_handler = factory.create(1)?;
+        let _ignore = stream_handler.write(vec![b'h'; 10]).await?;
+        let mut writer = ShardingWriter::new(&stream_handler);
+        for sharding in vec_sharding.iter() {
+            writer.write_sharding(sharding).await?;
+        }
+        let max_sharding_len = writer.max_sharding_len();
+        let (start_pos, batch_len, vec_sharding_len) = writer.finish().await?;
+        stream_handler.flush().await?;
+
+        // 将所有分片拼接后一次性写入
+        let batch_handler = factory.create(2)?;
+        let _ignore = batch_handler.write(vec![b'h'; 10]).await?;
+        let mut vec_batch_u8 = Vec::new();
+        let mut expect_sharding_len = Vec::new();
+        for sharding in vec_sharding.iter() {
+            let batch_len = vec_batch_u8.len();
+            for cmd_data in sharding {
+                vec_batch_u8.append(&mut CommandPackage::trans_to_vec_u8(cmd_data, batch_handler.format_version(), None)?);
+            }
+            expect_sharding_len.push(vec_batch_u8.len() - batch_len);
+        }
+        let (batch_start_pos, batch_batch_len) = batch_handler.write(vec_batch_u8).await?;
+        batch_handler.flush().await?;
+
+        assert_eq!((start_pos, batch_len, vec_sharding_len), (batch_start_pos, batch_batch_len, expect_sharding_len.clone()));
+        assert_eq!(stream_handler.read_with_pos(0, 10 + batch_len).await?,
+                   batch_handler.read_with_pos(0, 10 + batch_len).await?);
+        // 每次写入仅序列化单个分片，远小于拼接所有分片所需的缓冲
+        assert!(max_sharding_len * 50 < batch_len);
+
+        // 并行序列化时分片数不为parallelism的整数倍，最后一组不满，写入结果仍与逐个写入一致
+        let parallel_handler = factory.create(3)?;
+        let _ignore = parallel_handler.write(vec![b'h'; 10]).await?;
+        let mut writer = ShardingWriter::new(&parallel_handler);
+        writer.write_shardings_parallel(vec_sharding.clone(), 8).await?;
+        let parallel_result = writer.finish().await?;
+        parallel_handler.flush().await?;
+
+        assert_eq!(parallel_result, (batch_start_pos, batch_batch_len, expect_sharding_len));
+        let parallel_u8 = parallel_handler.read_with_pos(0, 10 + batch_len).await?;
+        assert_eq!(parallel_u8, stream_handler.read_with_pos(0, 10 + batch_len).await?);
+        assert_eq!(CommandPackage::from_bytes_to_unpack_vec(&parallel_u8[10..], FormatVersion::CURRENT, None)?,
+                   vec_sharding.into_iter().flatten().collect_vec());
+
+        // 未写入任何分片时起始Pos为当前的写入位置
+        assert_eq!(ShardingWriter::new(&stream_handler).finish().await?, (10 + batch_len as u64, 0, vec![]));
+
+        Ok(())
+    })
+}
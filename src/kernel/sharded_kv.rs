@@ -0,0 +1,351 @@
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Bound;
+use std::path::PathBuf;
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::future;
+use itertools::Itertools;
+use crate::kernel::{CommandData, FileRef, KVStore, Result, VersionedCommand};
+use crate::kernel::value_reader::ValueReader;
+use crate::KvsError;
+
+/// 默认的分片数量
+pub(crate) const DEFAULT_SHARD_NUM: usize = 8;
+
+/// 分片目录名前缀，各分片位于`shard_{index}`目录之中
+const SHARD_DIR_PREFIX: &str = "shard_";
+
+/// 分片存储
+///
+/// 将多个底层内核实例聚合为一个，Key按其crc32哈希值路由至对应的分片，
+/// 各分片的锁互相独立，以此提升并发写入的吞吐
+/// 路由依赖于分片数量，因此已有数据的目录需以相同的分片数量开启
+#[derive(Debug)]
+pub struct ShardedStore<K: KVStore> {
+    shards: Vec<K>
+}
+
+impl<K: KVStore + Sync> ShardedStore<K> {
+
+    /// 以指定的分片数量开启数据库，分片数量至少为1
+    ///
+    /// 目录中已存在的分片数量与之不一致时返回`KvsError::ShardNumMismatch`
+    #[inline]
+    pub async fn open_with_shard_num(path: impl Into<PathBuf> + Send, shard_num: usize) -> Result<Self> {
+        let path = path.into();
+        let shard_num = shard_num.max(1);
+
+        fs::create_dir_all(&path)?;
+        let exist_shard_num = fs::read_dir(&path)?
+            .flat_map(|res| -> Result<_> { Ok(res?.path()) })
+            .filter(|shard_path| shard_path.is_dir() && shard_path.file_name()
+                .and_then(|name| name.to_str())
+                .map_or(false, |name| name.starts_with(SHARD_DIR_PREFIX)))
+            .count();
+        if exist_shard_num > 0 && exist_shard_num != shard_num {
+            return Err(KvsError::ShardNumMismatch { expected: shard_num, found: exist_shard_num });
+        }
+
+        let shards = future::try_join_all((0..shard_num)
+            .map(|index| K::open(path.join(format!("{SHARD_DIR_PREFIX}{index}"))))).await?;
+
+        Ok(ShardedStore { shards })
+    }
+
+    /// 获取分片数量
+    #[inline]
+    pub fn shard_num(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// 获取Key所属的分片序号
+    fn shard_index(&self, key: &[u8]) -> usize {
+        crc32fast::hash(key) as usize % self.shards.len()
+    }
+
+    /// 获取Key所属的分片
+    fn shard(&self, key: &[u8]) -> &K {
+        &self.shards[self.shard_index(key)]
+    }
+}
+
+#[async_trait]
+impl<K: KVStore + Sync> KVStore for ShardedStore<K> {
+
+    #[inline]
+    fn name() -> &'static str where Self: Sized {
+        "ShardedStore made in Kould"
+    }
+
+    #[inline]
+    async fn open(path: impl Into<PathBuf> + Send) -> Result<Self> {
+        Self::open_with_shard_num(path, DEFAULT_SHARD_NUM).await
+    }
+
+    #[inline]
+    async fn flush(&self) -> Result<()> {
+        let _ignore = future::try_join_all(self.shards.iter()
+            .map(|shard| shard.flush())).await?;
+        Ok(())
+    }
+
+    #[inline]
+    async fn set(&self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        self.shard(key).set(key, value).await
+    }
+
+    #[inline]
+    async fn set_sync(&self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        self.shard(key).set_sync(key, value).await
+    }
+
+    #[inline]
+    async fn set_if_absent(&self, key: &[u8], value: Vec<u8>) -> Result<bool> {
+        self.shard(key).set_if_absent(key, value).await
+    }
+
+    /// Set与Remove交由Key所属的分片以LWW执行，其余命令忽略ts直接执行
+    #[inline]
+    async fn apply_versioned(&self, versioned: VersionedCommand) -> Result<bool> {
+        match versioned.cmd() {
+            CommandData::Set { key, .. } | CommandData::Remove { key } => {
+                let shard = self.shard(key);
+                shard.apply_versioned(versioned).await
+            }
+            _ => {
+                let _ignore = versioned.into_cmd().apply(self).await?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// 按分片对键值对进行分组，各分片并行写入
+    /// 仅保证单个分片内的原子性
+    #[inline]
+    async fn set_batch(&self, pairs: Vec<(Vec<u8>, Vec<u8>)>) -> Result<()> {
+        let mut shard_pairs: HashMap<usize, Vec<(Vec<u8>, Vec<u8>)>> = HashMap::new();
+        for (key, value) in pairs {
+            shard_pairs.entry(self.shard_index(&key))
+                .or_default()
+                .push((key, value));
+        }
+
+        let _ignore = future::try_join_all(shard_pairs.into_iter()
+            .filter_map(|(index, pairs)| self.shards.get(index)
+                .map(|shard| shard.set_batch(pairs)))).await?;
+        Ok(())
+    }
+
+    #[inline]
+    async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.shard(key).get(key).await
+    }
+
+    #[inline]
+    async fn get_bytes(&self, key: &[u8]) -> Result<Option<Bytes>> {
+        self.shard(key).get_bytes(key).await
+    }
+
+    #[inline]
+    async fn get_reader(&self, key: &[u8]) -> Result<Option<ValueReader>> {
+        self.shard(key).get_reader(key).await
+    }
+
+    #[inline]
+    async fn remove(&self, key: &[u8]) -> Result<()> {
+        self.shard(key).remove(key).await
+    }
+
+    #[inline]
+    async fn remove_and_get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.shard(key).remove_and_get(key).await
+    }
+
+    /// 按分片对Key进行分组，各分片并行删除
+    #[inline]
+    async fn remove_batch(&self, keys: &[&[u8]]) -> Result<usize> {
+        let mut shard_keys: HashMap<usize, Vec<&[u8]>> = HashMap::new();
+        for key in keys {
+            shard_keys.entry(self.shard_index(key))
+                .or_default()
+                .push(*key);
+        }
+
+        Ok(future::try_join_all(shard_keys.iter()
+            .filter_map(|(index, keys)| self.shards.get(*index)
+                .map(|shard| shard.remove_batch(keys)))).await?
+            .into_iter()
+            .sum())
+    }
+
+    /// 依次遍历各分片，不保证Key的顺序
+    #[inline]
+    async fn for_each<F>(&self, mut f: F) -> Result<()>
+        where F: FnMut(&[u8], &[u8]) -> Result<()> + Send
+    {
+        for shard in self.shards.iter() {
+            shard.for_each(&mut f).await?;
+        }
+        Ok(())
+    }
+
+    /// 各分片并行获取至多limit个键值对，再归并为有序的结果
+    #[inline]
+    async fn scan(&self, start: &[u8], end: &[u8], limit: usize) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let vec_shard_kv = future::try_join_all(self.shards.iter()
+            .map(|shard| shard.scan(start, end, limit))).await?;
+
+        Ok(vec_shard_kv.into_iter()
+            .kmerge_by(|(key_a, _), (key_b, _)| key_a < key_b)
+            .take(limit)
+            .collect())
+    }
+
+    /// 各分片并行获取至多limit个键值对，再归并为有序的结果
+    #[inline]
+    async fn scan_with_bound(&self, start: Bound<&[u8]>, end: Bound<&[u8]>, limit: usize) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let vec_shard_kv = future::try_join_all(self.shards.iter()
+            .map(|shard| shard.scan_with_bound(start, end, limit))).await?;
+
+        Ok(vec_shard_kv.into_iter()
+            .kmerge_by(|(key_a, _), (key_b, _)| key_a < key_b)
+            .take(limit)
+            .collect())
+    }
+
+    /// 依次遍历各分片的Key，不保证Key的顺序
+    #[inline]
+    async fn for_each_key<F>(&self, mut f: F) -> Result<()>
+        where F: FnMut(&[u8]) -> Result<()> + Send
+    {
+        for shard in self.shards.iter() {
+            shard.for_each_key(&mut f).await?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    async fn max_key(&self) -> Result<Option<Vec<u8>>> {
+        let vec_shard_max_key = future::try_join_all(self.shards.iter()
+            .map(|shard| shard.max_key())).await?;
+
+        Ok(vec_shard_max_key.into_iter().flatten().max())
+    }
+
+    #[inline]
+    async fn scan_keys(&self, start: &[u8], end: &[u8], limit: usize) -> Result<Vec<Vec<u8>>> {
+        let vec_shard_key = future::try_join_all(self.shards.iter()
+            .map(|shard| shard.scan_keys(start, end, limit))).await?;
+
+        Ok(vec_shard_key.into_iter()
+            .kmerge()
+            .take(limit)
+            .collect())
+    }
+
+    #[inline]
+    async fn size_of_disk(&self) -> Result<u64> {
+        Ok(future::try_join_all(self.shards.iter()
+            .map(|shard| shard.size_of_disk())).await?
+            .into_iter()
+            .sum())
+    }
+
+    #[inline]
+    async fn live_files(&self) -> Result<Vec<FileRef>> {
+        Ok(future::try_join_all(self.shards.iter()
+            .map(|shard| shard.live_files())).await?
+            .into_iter()
+            .flatten()
+            .collect())
+    }
+
+    #[inline]
+    async fn pause_file_deletion(&self) -> Result<()> {
+        let _ignore = future::try_join_all(self.shards.iter()
+            .map(|shard| shard.pause_file_deletion())).await?;
+        Ok(())
+    }
+
+    #[inline]
+    async fn resume_file_deletion(&self) -> Result<()> {
+        let _ignore = future::try_join_all(self.shards.iter()
+            .map(|shard| shard.resume_file_deletion())).await?;
+        Ok(())
+    }
+
+    #[inline]
+    async fn len(&self) -> Result<usize> {
+        Ok(future::try_join_all(self.shards.iter()
+            .map(|shard| shard.len())).await?
+            .into_iter()
+            .sum())
+    }
+
+    #[inline]
+    async fn is_empty(&self) -> bool {
+        future::join_all(self.shards.iter()
+            .map(|shard| shard.is_empty())).await
+            .into_iter()
+            .all(|is_empty| is_empty)
+    }
+}
+
+#[test]
+fn test_sharded_store() -> Result<()> {
+    use tempfile::TempDir;
+    use crate::HashStore;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        {
+            let kv_store = ShardedStore::<HashStore>::open_with_shard_num(temp_dir.path(), 4).await?;
+            assert!(kv_store.is_empty().await);
+            for i in 0..1000_u32 {
+                kv_store.set(&i.to_be_bytes(), i.to_le_bytes().to_vec()).await?;
+            }
+            kv_store.set_batch((1000..1100_u32)
+                .map(|i| (i.to_be_bytes().to_vec(), i.to_le_bytes().to_vec()))
+                .collect_vec()).await?;
+            kv_store.remove(&0_u32.to_be_bytes()).await?;
+            kv_store.flush().await?;
+
+            // 数据分布于各分片之中，len与size_of_disk聚合所有分片
+            let mut vec_shard_len = Vec::new();
+            for shard in kv_store.shards.iter() {
+                vec_shard_len.push(shard.len().await?);
+            }
+            assert!(vec_shard_len.iter().all(|len| *len > 0));
+            assert_eq!(kv_store.len().await?, 1099);
+            assert_eq!(vec_shard_len.iter().sum::<usize>(), 1099);
+            assert!(kv_store.size_of_disk().await? > 0);
+
+            // scan归并所有分片并保持有序
+            let vec_kv = kv_store.scan(&10_u32.to_be_bytes(), &2000_u32.to_be_bytes(), 50).await?;
+            assert_eq!(vec_kv.len(), 50);
+            assert!(vec_kv.iter().tuple_windows().all(|((key_a, _), (key_b, _))| key_a < key_b));
+            assert_eq!(vec_kv[0], (10_u32.to_be_bytes().to_vec(), 10_u32.to_le_bytes().to_vec()));
+            assert_eq!(vec_kv[49].0, 59_u32.to_be_bytes().to_vec());
+
+            let mut count = 0;
+            kv_store.for_each(|_, _| { count += 1; Ok(()) }).await?;
+            assert_eq!(count, 1099);
+        }
+
+        // 重新开启后数据路由至原有的分片
+        let kv_store = ShardedStore::<HashStore>::open_with_shard_num(temp_dir.path(), 4).await?;
+        assert_eq!(kv_store.get(&0_u32.to_be_bytes()).await?, None);
+        for i in 1..1100_u32 {
+            assert_eq!(kv_store.get(&i.to_be_bytes()).await?, Some(i.to_le_bytes().to_vec()));
+        }
+        drop(kv_store);
+        assert!(matches!(
+            ShardedStore::<HashStore>::open_with_shard_num(temp_dir.path(), 8).await,
+            Err(KvsError::ShardNumMismatch { expected: 8, found: 4 })
+        ));
+
+        Ok(())
+    })
+}
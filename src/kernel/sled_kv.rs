@@ -1,8 +1,8 @@
 use std::path::PathBuf;
 use std::sync::Arc;
-use sled::Db;
+use sled::{Batch, Db};
 use async_trait::async_trait;
-use crate::kernel::KVStore;
+use crate::kernel::{batch_check, CommandData, key_check, KVStore};
 use crate::KvsError;
 
 #[derive(Debug)]
@@ -35,12 +35,41 @@ impl KVStore for SledStore {
 
     #[inline]
     async fn set(&self, key: &[u8], value: Vec<u8>) -> crate::kernel::Result<()> {
+        key_check(key)?;
         let _ignore = self.data_base.insert(key, value)?;
         Ok(())
     }
 
+    /// 以sled的compare_and_swap实现，旧值为None时才写入
+    #[inline]
+    async fn set_if_absent(&self, key: &[u8], value: Vec<u8>) -> crate::kernel::Result<bool> {
+        key_check(key)?;
+        Ok(self.data_base.compare_and_swap(key, None::<&[u8]>, Some(value))?.is_ok())
+    }
+
+    /// 校验通过后以sled::Batch整体落盘
+    #[inline]
+    async fn write_batch_atomic(&self, vec_cmd: Vec<CommandData>) -> crate::kernel::Result<()> {
+        for key in batch_check(&vec_cmd)? {
+            if !self.data_base.contains_key(key)? {
+                return Err(KvsError::KeyNotFound);
+            }
+        }
+        let mut batch = Batch::default();
+        for cmd in vec_cmd {
+            match cmd {
+                CommandData::Set { key, value } => batch.insert(key, value),
+                CommandData::Remove { key } => batch.remove(key),
+                _ => return Err(KvsError::NotMatchCmd)
+            }
+        }
+
+        Ok(self.data_base.apply_batch(batch)?)
+    }
+
     #[inline]
     async fn get(&self, key: &[u8]) -> crate::kernel::Result<Option<Vec<u8>>> {
+        key_check(key)?;
         match self.data_base.get(key)? {
             None => { Ok(None) }
             Some(i_vec) => {
@@ -51,6 +80,7 @@ impl KVStore for SledStore {
 
     #[inline]
     async fn remove(&self, key: &[u8]) -> crate::kernel::Result<()> {
+        key_check(key)?;
         match self.data_base.remove(key) {
             Ok(Some(_)) => { Ok(()) }
             Ok(None) => { Err(KvsError::KeyNotFound) }
@@ -58,6 +88,69 @@ impl KVStore for SledStore {
         }
     }
 
+    #[inline]
+    async fn remove_and_get(&self, key: &[u8]) -> crate::kernel::Result<Option<Vec<u8>>> {
+        key_check(key)?;
+        Ok(self.data_base.remove(key)?
+            .map(|i_vec| i_vec.to_vec()))
+    }
+
+    #[inline]
+    async fn for_each<F>(&self, mut f: F) -> crate::kernel::Result<()>
+        where F: FnMut(&[u8], &[u8]) -> crate::kernel::Result<()> + Send
+    {
+        for item in self.data_base.iter() {
+            let (key, value) = item?;
+            f(key.as_ref(), value.as_ref())?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    async fn scan(&self, start: &[u8], end: &[u8], limit: usize) -> crate::kernel::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        key_check(start)?;
+        let mut vec_kv = Vec::new();
+        // sled在start大于end时会panic
+        if start >= end {
+            return Ok(vec_kv);
+        }
+
+        for item in self.data_base.range(start..end).take(limit) {
+            let (key, value) = item?;
+            vec_kv.push((key.to_vec(), value.to_vec()));
+        }
+        Ok(vec_kv)
+    }
+
+    #[inline]
+    async fn for_each_key<F>(&self, mut f: F) -> crate::kernel::Result<()>
+        where F: FnMut(&[u8]) -> crate::kernel::Result<()> + Send
+    {
+        for key in self.data_base.iter().keys() {
+            f(key?.as_ref())?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    async fn max_key(&self) -> crate::kernel::Result<Option<Vec<u8>>> {
+        Ok(self.data_base.last()?.map(|(key, _)| key.to_vec()))
+    }
+
+    #[inline]
+    async fn scan_keys(&self, start: &[u8], end: &[u8], limit: usize) -> crate::kernel::Result<Vec<Vec<u8>>> {
+        let mut vec_key = Vec::new();
+        // sled在start大于end时会panic
+        if start >= end {
+            return Ok(vec_key);
+        }
+
+        for key in self.data_base.range(start..end).keys().take(limit) {
+            vec_key.push(key?.to_vec());
+        }
+        Ok(vec_key)
+    }
+
     #[inline]
     async fn size_of_disk(&self) -> crate::kernel::Result<u64> {
         Ok(self.data_base.size_on_disk()?)
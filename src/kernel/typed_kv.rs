@@ -0,0 +1,109 @@
+use std::marker::PhantomData;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use crate::kernel::{KVStore, Result};
+
+/// 泛型值存储
+///
+/// 对底层内核的包装，value在写入时以MessagePack序列化为`Vec<u8>`，读取时再反序列化为V，
+/// 序列化与反序列化失败时分别返回`KvsError::SerdeMPEncode`与`KvsError::SerdeMPDecode`
+#[derive(Debug)]
+pub struct TypedStore<K: KVStore, V> {
+    kv_store: K,
+    _marker: PhantomData<fn() -> V>
+}
+
+impl<K: KVStore, V: Serialize + DeserializeOwned> TypedStore<K, V> {
+
+    /// 以已开启的内核创建
+    #[inline]
+    pub fn new(kv_store: K) -> Self {
+        TypedStore { kv_store, _marker: PhantomData }
+    }
+
+    /// 获取底层内核
+    #[inline]
+    pub fn inner(&self) -> &K {
+        &self.kv_store
+    }
+
+    /// 取出底层内核
+    #[inline]
+    pub fn into_inner(self) -> K {
+        self.kv_store
+    }
+
+    /// 序列化value并设置键值对
+    #[inline]
+    pub async fn set(&self, key: &[u8], value: &V) -> Result<()> {
+        self.kv_store.set(key, rmp_serde::to_vec(value)?).await
+    }
+
+    /// 获取Key对应的value并反序列化
+    #[inline]
+    pub async fn get(&self, key: &[u8]) -> Result<Option<V>> {
+        self.kv_store.get(key).await?
+            .map(|bytes| rmp_serde::from_slice(&bytes))
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    /// 删除Key
+    #[inline]
+    pub async fn remove(&self, key: &[u8]) -> Result<()> {
+        self.kv_store.remove(key).await
+    }
+
+    /// 强制将数据刷入硬盘
+    #[inline]
+    pub async fn flush(&self) -> Result<()> {
+        self.kv_store.flush().await
+    }
+}
+
+#[test]
+fn test_typed_store() -> Result<()> {
+    use serde::Deserialize;
+    use tempfile::TempDir;
+    use crate::HashStore;
+    use crate::KvsError;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct User {
+        id: u64,
+        name: String,
+        tags: Vec<String>,
+        email: Option<String>
+    }
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let user = User {
+            id: 1,
+            name: "Kould".to_string(),
+            tags: vec!["rust".to_string(), "db".to_string()],
+            email: None
+        };
+        {
+            let typed_store = TypedStore::<HashStore, User>::new(HashStore::open(temp_dir.path()).await?);
+            typed_store.set(b"user1", &user).await?;
+            assert_eq!(typed_store.get(b"user1").await?, Some(user.clone()));
+            assert_eq!(typed_store.get(b"user2").await?, None);
+
+            // 底层数据无法反序列化为User时返回反序列化错误
+            typed_store.inner().set(b"user2", vec![0xc1]).await?;
+            assert!(matches!(typed_store.get(b"user2").await, Err(KvsError::SerdeMPDecode(_))));
+
+            typed_store.remove(b"user2").await?;
+            typed_store.flush().await?;
+        }
+
+        // 重新开启后仍能读取
+        let typed_store = TypedStore::<HashStore, User>::new(HashStore::open(temp_dir.path()).await?);
+        assert_eq!(typed_store.get(b"user1").await?, Some(user));
+        assert_eq!(typed_store.get(b"user2").await?, None);
+
+        Ok(())
+    })
+}
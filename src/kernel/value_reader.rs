@@ -0,0 +1,245 @@
+use std::fmt::Debug;
+use std::io;
+use std::io::{Cursor, SeekFrom};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::fs::File;
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncReadExt, AsyncSeekExt, BufReader, ReadBuf};
+use crate::kernel::{CommandData, CommandPackage, log_path, Result};
+use crate::kernel::io_handler::IOHandler;
+
+/// 流式读取时的默认读取缓冲大小
+pub(crate) const DEFAULT_VALUE_READER_BUFFER_SIZE: usize = 64 * 1024;
+
+/// MessagePack中uint8的标识，其后的1字节为数据
+const MSGPACK_UINT8: u8 = 0xcc;
+
+/// MessagePack中array16的标识，其后的2字节为数组长度
+const MSGPACK_ARRAY16: u8 = 0xdc;
+
+/// MessagePack中array32的标识，其后的4字节为数组长度
+const MSGPACK_ARRAY32: u8 = 0xdd;
+
+/// 可作为value读取来源的异步缓冲字节流
+trait ValueSource: AsyncBufRead + Debug + Send + Sync + Unpin {}
+
+impl<T: AsyncBufRead + Debug + Send + Sync + Unpin> ValueSource for T {}
+
+/// value的异步读取器
+///
+/// 从文件中读取时仅持有固定大小的读取缓冲，逐块读取并解码value，内存占用不随value大小增长
+/// value在文件中以MessagePack的字节数组存储，每个字节会被编码为1或2字节的整数元素，
+/// 因此元素可能跨越两次读取的缓冲，读取器会记录未读取完的元素并在下一块缓冲中继续解码
+#[derive(Debug)]
+pub struct ValueReader {
+    source: Box<dyn ValueSource>,
+    /// source中的value为MessagePack编码的字节数组，读取时需逐个元素解码
+    is_encoded: bool,
+    /// 尚未读取的value长度
+    remaining: usize,
+    /// 已读取uint8的标识而其数据位于下一块缓冲中
+    is_pending_uint8: bool,
+    /// 读取器在内存中持有的数据大小
+    buffer_len: usize
+}
+
+impl ValueReader {
+    /// 以已完整读取至内存中的value创建
+    #[inline]
+    pub fn from_value(value: Vec<u8>) -> Self {
+        let len = value.len();
+
+        ValueReader {
+            source: Box::new(Cursor::new(value)),
+            is_encoded: false,
+            remaining: len,
+            is_pending_uint8: false,
+            buffer_len: len
+        }
+    }
+
+    /// 创建从日志文件中分块读取value的读取器
+    ///
+    /// pos为Key对应的Set命令去除长度头后在文件中的位置，ts为该命令的版本时间戳
+    /// 该位置上的数据不为该Key的Set命令(如SetBatch)或数据经过加密时返回None，此时需读取完整的value
+    /// 读取器持有独立的文件句柄，类Unix系统中文件在读取期间被删除后仍能读取完毕
+    pub(crate) async fn from_log(io_handler: &IOHandler, key: &[u8], pos: u64, ts: u64) -> Result<Option<Self>> {
+        // 密文需完整读取后才能解密与校验
+        if io_handler.cipher().is_some() {
+            return Ok(None);
+        }
+        // 数据可能仍处于写入缓冲中，读取前需要先刷入
+        io_handler.flush().await?;
+        let prefix = Self::encoded_value_prefix(key, ts)?;
+
+        let mut file = File::open(log_path(&io_handler.get_dir_path(), io_handler.get_gen())).await?;
+        let _ignore = file.seek(SeekFrom::Start(pos)).await?;
+        let mut source = BufReader::with_capacity(DEFAULT_VALUE_READER_BUFFER_SIZE, file);
+
+        let mut cmd_prefix = vec![0; prefix.len()];
+        let _ignore = source.read_exact(&mut cmd_prefix).await?;
+        if cmd_prefix != prefix {
+            return Ok(None);
+        }
+        let len = match source.read_u8().await? {
+            marker @ 0x90..=0x9f => usize::from(marker & 0x0f),
+            MSGPACK_ARRAY16 => usize::from(source.read_u16().await?),
+            MSGPACK_ARRAY32 => source.read_u32().await? as usize,
+            _ => return Ok(None)
+        };
+
+        Ok(Some(ValueReader {
+            source: Box::new(source),
+            is_encoded: true,
+            remaining: len,
+            is_pending_uint8: false,
+            buffer_len: DEFAULT_VALUE_READER_BUFFER_SIZE
+        }))
+    }
+
+    /// Key对应的Set命令中value字节数组之前的编码
+    ///
+    /// 以value为空与仅含一个字节的两条命令的编码进行比较，首个不同的字节即为value数组的长度头
+    fn encoded_value_prefix(key: &[u8], ts: u64) -> Result<Vec<u8>> {
+        let mut prefix = CommandPackage::encode_with_ts(&CommandData::set(key.to_vec(), Vec::new()), ts)?;
+        let single = CommandPackage::encode_with_ts(&CommandData::set(key.to_vec(), vec![0]), ts)?;
+        let prefix_len = prefix.iter()
+            .zip(single.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        prefix.truncate(prefix_len);
+        Ok(prefix)
+    }
+
+    /// 尚未读取的value长度
+    #[inline]
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+
+    /// 读取器在内存中持有的数据大小
+    ///
+    /// 从文件中读取时为读取缓冲的大小，否则为完整的value大小
+    #[inline]
+    pub fn buffer_len(&self) -> usize {
+        self.buffer_len
+    }
+}
+
+impl AsyncRead for ValueReader {
+    #[inline]
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let reader = self.get_mut();
+        let filled_len = buf.filled().len();
+
+        while reader.remaining > 0 && buf.remaining() > 0 {
+            let available = match Pin::new(&mut reader.source).poll_fill_buf(cx) {
+                Poll::Ready(Ok(available)) => available,
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                // 已读取部分数据时先返回
+                Poll::Pending if buf.filled().len() > filled_len => return Poll::Ready(Ok(())),
+                Poll::Pending => return Poll::Pending
+            };
+            if available.is_empty() {
+                return Poll::Ready(Err(io::Error::from(io::ErrorKind::UnexpectedEof)));
+            }
+            let consumed = if reader.is_encoded {
+                decode_elements(available, buf, &mut reader.remaining, &mut reader.is_pending_uint8)?
+            } else {
+                let len = available.len().min(reader.remaining).min(buf.remaining());
+                buf.put_slice(&available[..len]);
+                reader.remaining -= len;
+                len
+            };
+            Pin::new(&mut reader.source).consume(consumed);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// 将available中MessagePack字节数组的元素解码至buf中，返回消费的字节数
+///
+/// 0-127以单字节的positive fixint编码，可连续拷贝；128-255则以uint8的标识与其后的1字节编码
+fn decode_elements(available: &[u8], buf: &mut ReadBuf<'_>, remaining: &mut usize, is_pending_uint8: &mut bool) -> io::Result<usize> {
+    let mut consumed = 0;
+
+    while consumed < available.len() && *remaining > 0 && buf.remaining() > 0 {
+        if *is_pending_uint8 {
+            buf.put_slice(&available[consumed..consumed + 1]);
+            *is_pending_uint8 = false;
+            *remaining -= 1;
+            consumed += 1;
+            continue;
+        }
+        let limit = (available.len() - consumed).min(*remaining).min(buf.remaining());
+        let fixint_len = available[consumed..consumed + limit].iter()
+            .take_while(|byte| **byte <= 0x7f)
+            .count();
+
+        if fixint_len > 0 {
+            buf.put_slice(&available[consumed..consumed + fixint_len]);
+            *remaining -= fixint_len;
+            consumed += fixint_len;
+        } else if available[consumed] == MSGPACK_UINT8 {
+            *is_pending_uint8 = true;
+            consumed += 1;
+        } else {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "unexpected msgpack marker in value"));
+        }
+    }
+
+    Ok(consumed)
+}
+
+#[test]
+fn test_value_reader_with_split_element() -> Result<()> {
+    use tempfile::TempDir;
+    use crate::kernel::io_handler::IOHandlerFactory;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let io_handler = IOHandlerFactory::new(temp_dir.path()).create(1)?;
+        let _ignore = CommandPackage::write(&io_handler, &CommandData::set(b"other".to_vec(), vec![b'o'; 10])).await?;
+        // value中包含以uint8编码的字节，且跨越多块读取缓冲
+        let value = (0..DEFAULT_VALUE_READER_BUFFER_SIZE * 3)
+            .map(|i| if i % 3 == 0 { 0xff } else { (i % 128) as u8 })
+            .collect::<Vec<u8>>();
+        let (pos, _) = CommandPackage::write(&io_handler, &CommandData::set(b"key".to_vec(), value.clone())).await?;
+        let (batch_pos, _) = CommandPackage::write(&io_handler, &CommandData::SetBatch {
+            pairs: vec![(b"key".to_vec(), value.clone())]
+        }).await?;
+
+        let mut reader = ValueReader::from_log(&io_handler, b"key", pos, 0).await?
+            .expect("value is not streamable");
+        let mut vec_value = Vec::new();
+        let mut buf = vec![0; 1000];
+        loop {
+            let len = reader.read(&mut buf).await?;
+            if len == 0 {
+                break;
+            }
+            vec_value.extend_from_slice(&buf[..len]);
+        }
+        assert_eq!(vec_value, value);
+        assert_eq!(reader.remaining(), 0);
+
+        // uint8元素的标识与数据分别位于两块缓冲中
+        let mut vec_u8 = vec![0; 3];
+        let mut buf = ReadBuf::new(&mut vec_u8);
+        let (mut remaining, mut is_pending_uint8) = (3, false);
+        assert_eq!(decode_elements(&[0x01, MSGPACK_UINT8], &mut buf, &mut remaining, &mut is_pending_uint8)?, 2);
+        assert!(is_pending_uint8);
+        assert_eq!(decode_elements(&[0xff, 0x02], &mut buf, &mut remaining, &mut is_pending_uint8)?, 2);
+        assert_eq!(buf.filled(), &[0x01, 0xff, 0x02]);
+        assert_eq!(remaining, 0);
+
+        // Key或命令不匹配时不进行流式读取
+        assert!(ValueReader::from_log(&io_handler, b"other_key", pos, 0).await?.is_none());
+        assert!(ValueReader::from_log(&io_handler, b"key", batch_pos, 0).await?.is_none());
+
+        Ok(())
+    })
+}
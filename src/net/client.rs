@@ -1,4 +1,11 @@
+use std::sync::Mutex;
 use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tracing::warn;
+#[cfg(unix)]
+use std::path::Path;
+#[cfg(unix)]
+use tokio::net::UnixStream;
 use crate::error::ConnectionError;
 use crate::kernel::CommandData;
 use crate::KvsError;
@@ -16,7 +23,20 @@ impl Client {
     pub async fn connect<T: ToSocketAddrs>(addr: T) -> Result<Client> {
         let socket = TcpStream::connect(addr).await?;
 
-        let connection = Connection::new(socket);
+        let connection = Connection::new(Box::new(socket));
+
+        Ok(Client{
+            connection
+        })
+    }
+
+    /// 通过Unix domain socket与客户端进行连接
+    #[cfg(unix)]
+    #[inline]
+    pub async fn connect_uds(path: impl AsRef<Path>) -> Result<Client> {
+        let socket = UnixStream::connect(path).await?;
+
+        let connection = Connection::new(Box::new(socket));
 
         Ok(Client{
             connection
@@ -64,6 +84,18 @@ impl Client {
         }
     }
 
+    /// 以事务的形式提交一组Set与Remove，服务端全部成功或整体回滚
+    ///
+    /// 服务端拒绝或回滚时返回由错误码还原的错误，旧版本的服务端则返回附带原因的`ConnectionError::TransactionAborted`
+    #[inline]
+    pub async fn transaction(&mut self, vec_cmd: Vec<CommandData>) -> Result<()> {
+        match self.send_cmd(CommandOption::Transaction(vec_cmd)).await? {
+            CommandOption::Committed => Ok(()),
+            CommandOption::Aborted(reason) => Err(ConnectionError::TransactionAborted(reason)),
+            _ => Err(ConnectionError::KvStoreError(KvsError::NotMatchCmd))
+        }
+    }
+
     /// 磁盘占用
     #[inline]
     pub async fn size_of_disk(&mut self) -> Result<u64> {
@@ -82,9 +114,222 @@ impl Client {
         }
     }
 
+    /// 发送命令并读取响应，连接已关闭时返回`ConnectionError::Disconnected`
+    ///
+    /// 服务端处理超时时返回`ConnectionError::Timeout`，处理失败时返回由错误码还原的错误，无法还原时返回附带错误信息的`ConnectionError::RemoteError`
     #[inline]
     async fn send_cmd(&mut self, cmd_option: CommandOption) -> Result<CommandOption>{
         self.connection.write(cmd_option).await?;
-        self.connection.read().await
+        match self.connection.read_option().await? {
+            Some(CommandOption::Timeout) => Err(ConnectionError::Timeout),
+            Some(CommandOption::Error(code, message)) => Err(ConnectionError::from_remote(code, message)),
+            Some(option) => Ok(option),
+            None => Err(ConnectionError::Disconnected)
+        }
+    }
+}
+
+/// get断连时的重试次数
+const GET_RETRY_TIMES: usize = 1;
+
+/// Client连接池
+///
+/// 维护固定数量的连接，每次请求取出一个空闲连接执行，执行完毕后归还
+/// 连接断开时会被丢弃，之后取出连接时自动重新连接
+///
+/// 仅幂等的get会在断连时重连并重试，
+/// set/remove等非幂等命令断连时可能已在服务端执行，为避免重复执行不会自动重试
+#[allow(missing_debug_implementations)]
+pub struct KipClientPool {
+    addr: String,
+    /// 空闲的连接
+    idle: Mutex<Vec<Client>>,
+    /// 可同时取出的连接数量
+    permits: Semaphore,
+    size: usize
+}
+
+impl KipClientPool {
+    /// 与服务端建立size个连接作为连接池
+    #[inline]
+    pub async fn connect(addr: impl Into<String>, size: usize) -> Result<KipClientPool> {
+        let addr = addr.into();
+        let mut idle = Vec::with_capacity(size);
+        for _ in 0..size {
+            idle.push(Client::connect(addr.as_str()).await?);
+        }
+
+        Ok(KipClientPool {
+            addr,
+            idle: Mutex::new(idle),
+            permits: Semaphore::new(size),
+            size
+        })
     }
-}
\ No newline at end of file
+
+    /// 存入数据
+    ///
+    /// 断连时不会重试
+    #[inline]
+    pub async fn set(&self, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
+        let (mut client, _permit) = self.acquire(false).await?;
+        let result = client.set(key, value).await;
+        self.release(client, &result);
+
+        result
+    }
+
+    /// 删除数据
+    ///
+    /// 断连时不会重试
+    #[inline]
+    pub async fn remove(&self, key: Vec<u8>) -> Result<()> {
+        let (mut client, _permit) = self.acquire(false).await?;
+        let result = client.remove(key).await;
+        self.release(client, &result);
+
+        result
+    }
+
+    /// 获取数据
+    ///
+    /// 断连时会使用新的连接进行重试
+    #[inline]
+    pub async fn get(&self, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        let mut retry_times = 0;
+
+        loop {
+            let (mut client, _permit) = self.acquire(retry_times > 0).await?;
+            let result = client.get(key.clone()).await;
+            self.release(client, &result);
+
+            match result {
+                Err(err) if Self::is_disconnected(&err) && retry_times < GET_RETRY_TIMES => {
+                    warn!("[KipClientPool][get][retry]: {:?}", err);
+                    retry_times += 1;
+                }
+                result => return result
+            }
+        }
+    }
+
+    /// 连接池大小
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// 取出一个连接，没有空闲连接或is_new为true时新建连接
+    ///
+    /// 持有返回的许可期间占用连接池中的一个位置
+    #[allow(clippy::unwrap_used)]
+    async fn acquire(&self, is_new: bool) -> Result<(Client, SemaphorePermit<'_>)> {
+        let permit = self.permits.acquire().await
+            .unwrap();
+        let option_client = if is_new {
+            None
+        } else {
+            self.idle.lock().unwrap().pop()
+        };
+        let client = match option_client {
+            Some(client) => client,
+            None => Client::connect(self.addr.as_str()).await?
+        };
+
+        Ok((client, permit))
+    }
+
+    /// 归还连接
+    ///
+    /// 连接断开时仅丢弃该连接，其余空闲连接若同样失效，则在之后被取出使用时丢弃
+    #[allow(clippy::unwrap_used)]
+    fn release<T>(&self, client: Client, result: &Result<T>) {
+        if !matches!(result, Err(err) if Self::is_disconnected(err)) {
+            self.idle.lock().unwrap().push(client);
+        }
+    }
+
+    fn is_disconnected(err: &ConnectionError) -> bool {
+        matches!(err, ConnectionError::Disconnected | ConnectionError::Io(_) | ConnectionError::WriteFailed)
+    }
+}
+
+#[test]
+#[allow(clippy::unwrap_used)]
+fn test_client_pool_reconnect() -> Result<()> {
+    use std::sync::Arc;
+    use tempfile::TempDir;
+    use tokio::net::TcpListener;
+    use tokio::sync::{broadcast, mpsc};
+    use crate::kernel::KVStore;
+    use crate::kernel::lsm::lsm_kv::LsmStore;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let kv_store = Arc::new(LsmStore::open(temp_dir.path()).await?);
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?.to_string();
+        // 通知服务端断开当前所有连接，用于模拟断连
+        let (disconnect_tx, _) = broadcast::channel::<()>(1);
+        // 服务端的连接关闭后通知，以此等待断连生效
+        let (closed_tx, mut closed_rx) = mpsc::unbounded_channel::<()>();
+
+        let server_disconnect_tx = disconnect_tx.clone();
+        let _ignore = tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let kv_store = Arc::clone(&kv_store);
+                let mut disconnect_rx = server_disconnect_tx.subscribe();
+                let closed_tx = closed_tx.clone();
+
+                let _ignore = tokio::spawn(async move {
+                    let mut connection = Connection::new(Box::new(socket));
+                    let result = async {
+                        loop {
+                            let option = tokio::select! {
+                                option = connection.read() => option,
+                                _ = disconnect_rx.recv() => break
+                            };
+                            match option {
+                                Ok(CommandOption::Cmd(cmd)) => {
+                                    let res_option = cmd.apply(&*kv_store).await?;
+                                    connection.write(res_option).await?;
+                                }
+                                _ => break
+                            }
+                        }
+                        Ok::<(), ConnectionError>(())
+                    }.await;
+                    drop(connection);
+                    let _ignore = closed_tx.send(());
+
+                    result
+                });
+            }
+        });
+
+        let pool = KipClientPool::connect(addr, 2).await?;
+        assert_eq!(pool.size(), 2);
+        pool.set(vec![b'k'], vec![b'1']).await?;
+        assert_eq!(pool.get(vec![b'k']).await?, Some(vec![b'1']));
+
+        // 断连后get自动重连并重试，仅丢弃断开的连接
+        let _ignore = disconnect_tx.send(());
+        for _ in 0..2 {
+            let _ignore = closed_rx.recv().await;
+        }
+        assert_eq!(pool.get(vec![b'k']).await?, Some(vec![b'1']));
+        assert_eq!(pool.idle.lock().unwrap().len(), 2);
+
+        // 断连后set不会重试，但之后的请求能够自动恢复
+        // 此时服务端仅剩重试时新建的连接
+        let _ignore = disconnect_tx.send(());
+        let _ignore = closed_rx.recv().await;
+        assert!(pool.set(vec![b'k'], vec![b'2']).await.is_err());
+        assert_eq!(pool.get(vec![b'k']).await?, Some(vec![b'1']));
+        pool.set(vec![b'k'], vec![b'2']).await?;
+        assert_eq!(pool.get(vec![b'k']).await?, Some(vec![b'2']));
+
+        Ok(())
+    })
+}
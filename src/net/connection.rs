@@ -1,6 +1,6 @@
 use futures::{SinkExt, StreamExt};
 use futures::stream::{SplitSink, SplitStream};
-use tokio::net::TcpStream;
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio_util::codec::Framed;
 
 use crate::error::ConnectionError;
@@ -8,8 +8,16 @@ use crate::net::codec::NetCommandCodec;
 use crate::net::Result;
 use crate::net::CommandOption;
 
-type CommandFramedStream = SplitStream<Framed<TcpStream, NetCommandCodec>>;
-type CommandFramedSink = SplitSink<Framed<TcpStream, NetCommandCodec>, CommandOption>;
+/// 可用于传输CommandOption的双向字节流
+/// 如TcpStream、UnixStream
+pub(crate) trait AsyncStream: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> AsyncStream for T {}
+
+pub(crate) type NetStream = Box<dyn AsyncStream>;
+
+type CommandFramedStream = SplitStream<Framed<NetStream, NetCommandCodec>>;
+type CommandFramedSink = SplitSink<Framed<NetStream, NetCommandCodec>, CommandOption>;
 
 pub(crate) struct Connection {
     writer: CommandFramedSink,
@@ -18,7 +26,7 @@ pub(crate) struct Connection {
 
 impl Connection {
     /// 新建连接
-    pub(crate) fn new(stream: TcpStream) -> Connection {
+    pub(crate) fn new(stream: NetStream) -> Connection {
         let framed = Framed::new(stream, NetCommandCodec::new());
         let (writer, reader) = framed.split::<CommandOption>();
         Connection{
@@ -42,6 +50,14 @@ impl Connection {
         }
     }
 
+    /// 读取CommandOption，连接关闭时返回None
+    ///
+    /// 与read不同，解码或IO错误时返回Err，用于客户端感知连接断开
+    pub(crate) async fn read_option(&mut self) -> Result<Option<CommandOption>> {
+        self.reader.next().await
+            .transpose()
+    }
+
     /// 写入CommandOption
     pub(crate) async fn write(&mut self, option: CommandOption) -> Result<()> {
         if self.writer.send(option).await.is_err() {
@@ -50,4 +66,31 @@ impl Connection {
             Ok(())
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(unix)]
+#[test]
+fn test_uds_connection() -> Result<()> {
+    use crate::kernel::CommandData;
+    use tokio::net::UnixStream;
+
+    tokio_test::block_on(async move {
+        let (stream_1, stream_2) = UnixStream::pair()?;
+        let mut connection_1 = Connection::new(Box::new(stream_1));
+        let mut connection_2 = Connection::new(Box::new(stream_2));
+
+        connection_1.write(CommandOption::Cmd(CommandData::get(vec![b'k']))).await?;
+        match connection_2.read().await? {
+            CommandOption::Cmd(cmd_data) => assert_eq!(cmd_data, CommandData::get(vec![b'k'])),
+            option => panic!("unexpected option: {option:?}")
+        }
+
+        connection_2.write(CommandOption::Value(vec![b'v'])).await?;
+        match connection_1.read().await? {
+            CommandOption::Value(value) => assert_eq!(value, vec![b'v']),
+            option => panic!("unexpected option: {option:?}")
+        }
+
+        Ok(())
+    })
+}
@@ -1,4 +1,5 @@
 use crate::error::ConnectionError;
+use crate::KvsError;
 
 use serde::{Deserialize, Serialize};
 use crate::kernel::CommandData;
@@ -22,7 +23,19 @@ pub enum CommandOption {
     SizeOfDisk(u64),
     Len(usize),
     Flush,
-    None
+    /// 事务请求，其中的Set与Remove会被原子地执行
+    Transaction(Vec<CommandData>),
+    /// 事务已提交
+    Committed,
+    /// 事务被拒绝或中途失败而整体回滚，附带失败原因
+    /// 服务端目前以`CommandOption::Error`回复错误码与错误信息，保留该变体以兼容旧版本的服务端
+    Aborted(String),
+    None,
+    /// 服务端处理命令超时
+    /// 作为新增变体置于末尾以保证原有变体的序列化兼容
+    Timeout,
+    /// 服务端处理命令失败，附带`KvsError::code`的错误码与错误信息
+    Error(u32, String)
 }
 
 impl From<CommandOption> for Option<Vec<u8>> {
@@ -33,4 +46,47 @@ impl From<CommandOption> for Option<Vec<u8>> {
             _ => { None }
         }
     }
-}
\ No newline at end of file
+}
+impl CommandOption {
+    /// 获取Value中的字节数组，其余情况返回None
+    #[inline]
+    pub fn into_bytes(self) -> Option<Vec<u8>> {
+        self.into()
+    }
+
+    /// 将Value中的字节数组转换为字符串，非法的UTF-8字符以U+FFFD替换，其余情况返回None
+    #[inline]
+    pub fn into_string_lossy(self) -> Option<String> {
+        self.into_bytes()
+            .map(|bytes| String::from_utf8(bytes)
+                .unwrap_or_else(|err| String::from_utf8_lossy(err.as_bytes()).into_owned()))
+    }
+
+    /// 以Result的形式获取Value中的字节数组
+    ///
+    /// None时返回`KvsError::KeyNotFound`，其余非Value的情况返回`KvsError::NotMatchCmd`
+    #[inline]
+    #[allow(clippy::wrong_self_convention)]
+    pub fn as_result(self) -> crate::kernel::Result<Vec<u8>> {
+        match self {
+            CommandOption::Value(value) => Ok(value),
+            CommandOption::None => Err(KvsError::KeyNotFound),
+            _ => Err(KvsError::NotMatchCmd)
+        }
+    }
+}
+
+#[test]
+fn test_command_option_conversion() {
+    assert_eq!(CommandOption::Value(b"value".to_vec()).into_bytes(), Some(b"value".to_vec()));
+    assert_eq!(CommandOption::None.into_bytes(), None);
+    assert_eq!(CommandOption::Len(1).into_bytes(), None);
+
+    assert_eq!(CommandOption::Value("值".as_bytes().to_vec()).into_string_lossy(), Some("值".to_owned()));
+    assert_eq!(CommandOption::Value(vec![b'a', 0xff, b'b']).into_string_lossy(), Some("a\u{fffd}b".to_owned()));
+    assert_eq!(CommandOption::None.into_string_lossy(), None);
+
+    assert_eq!(CommandOption::Value(b"value".to_vec()).as_result().ok(), Some(b"value".to_vec()));
+    assert!(matches!(CommandOption::None.as_result(), Err(KvsError::KeyNotFound)));
+    assert!(matches!(CommandOption::Flush.as_result(), Err(KvsError::NotMatchCmd)));
+}
@@ -1,27 +1,52 @@
 use std::future::Future;
+use std::io;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use chrono::Local;
-use tokio::net::{TcpListener, TcpStream};
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use std::{fs, path::PathBuf};
+#[cfg(unix)]
+use tokio::net::UnixListener;
 use tokio::sync::{broadcast, mpsc, Semaphore};
 use tokio::time;
-use tracing::{error, info};
-use crate::kernel::KVStore;
+use tracing::{error, info, warn};
+use crate::error::ConnectionError;
+use crate::KvsError;
+use crate::kernel::{CommandData, KVStore};
 use crate::kernel::lsm::lsm_kv::LsmStore;
-use crate::net::connection::Connection;
+use crate::net::connection::{Connection, NetStream};
 use crate::net::Result;
 use crate::net::CommandOption;
 use crate::net::shutdown::Shutdown;
 
 const MAX_CONNECTIONS: usize = 250;
 
+/// 单个事务中命令的数据大小上限
+const MAX_TRANSACTION_SIZE: usize = 4 * 1024 * 1024;
+
+/// 默认的单个命令处理超时时间
+pub const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 服务端的传输方式
+/// 同机部署时可使用Unix domain socket避免TCP loopback的开销
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Transport {
+    Tcp(TcpListener),
+    /// Unix domain socket与其socket文件路径
+    #[cfg(unix)]
+    Uds(UnixListener, PathBuf)
+}
+
 /// 服务器监听器
 /// 用于监听端口的连接并分发给Handler进行多线程处理连接
 #[derive(Debug)]
 pub struct Listener {
     kv_store_root: Arc<LsmStore>,
-    listener: TcpListener,
+    listener: Transport,
     limit_connections: Arc<Semaphore>,
+    command_timeout: Duration,
     notify_shutdown: broadcast::Sender<()>,
     shutdown_complete_rx: mpsc::Receiver<()>,
     shutdown_complete_tx: mpsc::Sender<()>
@@ -33,12 +58,28 @@ struct Handler {
     kv_store: Arc<LsmStore>,
     connection: Connection,
     shutdown: Shutdown,
+    /// 单个只读命令的处理超时时间
+    command_timeout: Duration,
     // 用于与Listener保持连接而感应是否全部关闭
     _shutdown_complete: mpsc::Sender<()>
 }
 
 #[inline]
 pub async fn run(listener: TcpListener, shutdown: impl Future) -> Result<()> {
+    run_with_transport(Transport::Tcp(listener), shutdown).await
+}
+
+/// 以指定的传输方式启动服务
+#[inline]
+pub async fn run_with_transport(listener: Transport, shutdown: impl Future) -> Result<()> {
+    run_with_command_timeout(listener, shutdown, DEFAULT_COMMAND_TIMEOUT).await
+}
+
+/// 以指定的传输方式与单个命令的处理超时时间启动服务
+///
+/// 只读命令处理超时时向客户端回复`CommandOption::Timeout`，并记录慢请求日志；写入命令不设超时
+#[inline]
+pub async fn run_with_command_timeout(listener: Transport, shutdown: impl Future, command_timeout: Duration) -> Result<()> {
     let kv_store_root = Arc::new(LsmStore::open("./data").await?);
     let (notify_shutdown, _) = broadcast::channel(1);
     let (shutdown_complete_tx, shutdown_complete_rx) = mpsc::channel(1);
@@ -47,6 +88,7 @@ pub async fn run(listener: TcpListener, shutdown: impl Future) -> Result<()> {
         listener,
         kv_store_root,
         limit_connections: Arc::new(Semaphore::new(MAX_CONNECTIONS)),
+        command_timeout,
         notify_shutdown,
         shutdown_complete_tx,
         shutdown_complete_rx,
@@ -99,13 +141,13 @@ impl Listener {
                 .await
                 .unwrap();
 
-            let socket = self.accept().await?;
-            let addr = socket.peer_addr()?;
+            let (socket, addr) = self.accept().await?;
 
             let mut handler = Handler {
                 kv_store: Arc::clone(&self.kv_store_root),
                 connection: Connection::new(socket),
                 shutdown: Shutdown::new(self.notify_shutdown.subscribe()),
+                command_timeout: self.command_timeout,
                 _shutdown_complete: self.shutdown_complete_tx.clone()
             };
 
@@ -123,14 +165,25 @@ impl Listener {
 
     }
 
-    /// 获取连接
-    async fn accept(&mut self) -> Result<TcpStream> {
+    /// 获取连接与其对端地址
+    async fn accept(&mut self) -> Result<(NetStream, String)> {
         let mut backoff = 1;
 
         loop {
-            match self.listener.accept().await {
-                Ok((socket, _)) => {
-                    return Ok(socket)
+            let accept_result: io::Result<(NetStream, String)> = match &self.listener {
+                Transport::Tcp(listener) => listener.accept().await
+                    .map(|(socket, addr)| -> (NetStream, String) {
+                        (Box::new(socket), addr.to_string())
+                    }),
+                #[cfg(unix)]
+                Transport::Uds(listener, _) => listener.accept().await
+                    .map(|(socket, addr)| -> (NetStream, String) {
+                        (Box::new(socket), format!("{addr:?}"))
+                    }),
+            };
+            match accept_result {
+                Ok(socket_with_addr) => {
+                    return Ok(socket_with_addr)
                 }
                 Err(err) => {
                     if backoff > 64 {
@@ -147,52 +200,286 @@ impl Listener {
     }
 }
 
+impl Transport {
+    /// 绑定Unix domain socket
+    /// 绑定前会清除残留的socket文件，并在Drop时清除
+    #[cfg(unix)]
+    #[inline]
+    pub fn bind_uds(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        let listener = UnixListener::bind(&path)?;
+
+        Ok(Transport::Uds(listener, path))
+    }
+}
+
+impl Drop for Transport {
+    #[inline]
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        {
+            if let Transport::Uds(_, path) = self {
+                if let Err(err) = fs::remove_file(path) {
+                    error!(cause = ?err, "[Transport][Failed To Clean Socket File]");
+                }
+            }
+        }
+    }
+}
+
 impl Handler {
     async fn run(&mut self) -> Result<()> {
         while !self.shutdown.is_shutdown() {
 
-            match tokio::select! {
+            let option = tokio::select! {
                 res = self.connection.read() => res?,
                 _ = self.shutdown.recv() => {
                     // If a shutdown signal is received, return from `run`.
                     // This will result in the task terminating.
                     return Ok(());
                 }
-            } {
-                CommandOption::Cmd(cmd) => {
-                    let res_option = cmd.apply(&*self.kv_store).await?;
-
-                    self.connection.write(res_option).await?;
-                }
-                CommandOption::VecCmd(vec_cmd, is_parallel) => {
-                    let vec_value = match is_parallel {
-                        true => { self.kv_store.batch_parallel(vec_cmd).await? }
-                        false => { self.kv_store.batch_order(vec_cmd).await? }
-                    };
-                    let res_option = CommandOption::ValueVec(vec_value);
-                    self.connection.write(res_option).await?;
-                }
-                CommandOption::SizeOfDisk(_) => {
-                    let size_of_disk = self.kv_store.size_of_disk().await?;
-                    self.connection.write(CommandOption::SizeOfDisk(size_of_disk)).await?;
-                }
-                CommandOption::Len(_) => {
-                    let len = self.kv_store.len().await?;
-                    self.connection.write(CommandOption::Len(len)).await?;
+            };
+            if let CommandOption::None = option {
+                break;
+            }
+            let (command, key_len) = command_info(&option);
+            let read_only = is_read_only(&option);
+            let start = Instant::now();
 
+            // 仅对只读命令设置超时，超时时直接丢弃处理中的Future以释放其持有的锁；
+            // 写入命令若中途被取消可能使Wal与MemTable仅写入了部分数据，因此始终等待其完成
+            let process = Self::process(&self.kv_store, option);
+            let result = match read_only {
+                true => time::timeout(self.command_timeout, process).await,
+                false => Ok(process.await)
+            };
+            let res_option = match result {
+                Ok(Ok(res_option)) => res_option,
+                // 内核处理失败时回复错误码与错误信息，而不断开连接
+                Ok(Err(ConnectionError::KvStoreError(err))) => {
+                    warn!(command, code = err.code(), cause = ?err, "[Handler][Command Failed]");
+                    Some(CommandOption::Error(err.code(), err.to_string()))
                 }
-                CommandOption::Flush => {
-                    self.kv_store.flush().await?;
-                    self.connection.write(CommandOption::Flush).await?;
-                }
-                CommandOption::None => {
-                    break;
+                Ok(Err(err)) => return Err(err),
+                Err(_) => {
+                    warn!(command, elapsed = ?start.elapsed(), key_len, "[Handler][Slow Command][Timeout]");
+                    Some(CommandOption::Timeout)
                 }
-                _ => {}
+            };
+            if let Some(res_option) = res_option {
+                self.connection.write(res_option).await?;
             }
         }
 
         Ok(())
     }
+
+    /// 处理命令并获取需要回复的CommandOption，无需回复时返回None
+    async fn process(kv_store: &LsmStore, option: CommandOption) -> Result<Option<CommandOption>> {
+        Ok(match option {
+            CommandOption::Cmd(cmd) => Some(cmd.apply(kv_store).await?),
+            CommandOption::VecCmd(vec_cmd, is_parallel) => {
+                let vec_value = match is_parallel {
+                    true => { kv_store.batch_parallel(vec_cmd).await? }
+                    false => { kv_store.batch_order(vec_cmd).await? }
+                };
+                Some(CommandOption::ValueVec(vec_value))
+            }
+            CommandOption::Transaction(vec_cmd) => {
+                // 事务失败时仅回复错误码与错误信息，不断开连接
+                Some(match apply_transaction(kv_store, vec_cmd).await {
+                    Ok(()) => CommandOption::Committed,
+                    Err(err) => CommandOption::Error(err.code(), err.to_string())
+                })
+            }
+            CommandOption::SizeOfDisk(_) => Some(CommandOption::SizeOfDisk(kv_store.size_of_disk().await?)),
+            CommandOption::Len(_) => Some(CommandOption::Len(kv_store.len().await?)),
+            CommandOption::Flush => {
+                kv_store.flush().await?;
+                Some(CommandOption::Flush)
+            }
+            _ => None
+        })
+    }
 }
 
+/// 获取用于慢请求日志的命令类型与Key长度
+///
+/// 批量命令与事务的Key长度为其中所有命令的Key长度之和
+fn command_info(option: &CommandOption) -> (&'static str, usize) {
+    let sum_key_len = |vec_cmd: &[CommandData]| vec_cmd.iter()
+        .map(|cmd| cmd.get_key().len())
+        .sum();
+
+    match option {
+        CommandOption::Cmd(CommandData::Set { key, .. }) => ("Set", key.len()),
+        CommandOption::Cmd(CommandData::Remove { key }) => ("Remove", key.len()),
+        CommandOption::Cmd(CommandData::Get { key }) => ("Get", key.len()),
+        CommandOption::Cmd(cmd) => ("Cmd", cmd.get_key().len()),
+        CommandOption::VecCmd(vec_cmd, _) => ("VecCmd", sum_key_len(vec_cmd)),
+        CommandOption::Transaction(vec_cmd) => ("Transaction", sum_key_len(vec_cmd)),
+        CommandOption::SizeOfDisk(_) => ("SizeOfDisk", 0),
+        CommandOption::Len(_) => ("Len", 0),
+        CommandOption::Flush => ("Flush", 0),
+        _ => ("Other", 0)
+    }
+}
+
+/// 命令是否为只读命令，仅只读命令会被超时取消
+///
+/// 批量命令仅在其中全部为Get时视为只读
+fn is_read_only(option: &CommandOption) -> bool {
+    match option {
+        CommandOption::Cmd(cmd) => matches!(cmd, CommandData::Get { .. }),
+        CommandOption::VecCmd(vec_cmd, _) => vec_cmd.iter()
+            .all(|cmd| matches!(cmd, CommandData::Get { .. })),
+        CommandOption::SizeOfDisk(_) | CommandOption::Len(_) => true,
+        _ => false
+    }
+}
+
+/// 原子地执行事务，数据大小超出`MAX_TRANSACTION_SIZE`时直接拒绝
+async fn apply_transaction<K: KVStore>(kv_store: &K, vec_cmd: Vec<CommandData>) -> crate::kernel::Result<()> {
+    let size = vec_cmd.iter()
+        .map(CommandData::get_data_len_for_rmp)
+        .sum::<usize>();
+    if size > MAX_TRANSACTION_SIZE {
+        return Err(KvsError::TransactionTooLarge { size, limit: MAX_TRANSACTION_SIZE });
+    }
+
+    kv_store.write_batch_atomic(vec_cmd).await
+}
+
+#[test]
+fn test_apply_transaction() -> Result<()> {
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    tokio_test::block_on(async move {
+        let kv_store = LsmStore::open(temp_dir.path()).await?;
+        kv_store.set(b"k0", b"v0".to_vec()).await?;
+
+        apply_transaction(&kv_store, vec![
+            CommandData::set(b"k1".to_vec(), b"v1".to_vec()),
+            CommandData::remove(b"k0".to_vec()),
+        ]).await?;
+        assert_eq!(kv_store.get(b"k0").await?, None);
+        assert_eq!(kv_store.get(b"k1").await?, Some(b"v1".to_vec()));
+
+        // 中途失败时整体回滚，之前的命令同样不生效
+        assert!(matches!(
+            apply_transaction(&kv_store, vec![
+                CommandData::set(b"k2".to_vec(), b"v2".to_vec()),
+                CommandData::remove(b"k1".to_vec()),
+                CommandData::remove(b"k0".to_vec()),
+            ]).await,
+            Err(KvsError::KeyNotFound)
+        ));
+        assert!(matches!(
+            apply_transaction(&kv_store, vec![
+                CommandData::set(b"k2".to_vec(), b"v2".to_vec()),
+                CommandData::get(b"k1".to_vec()),
+            ]).await,
+            Err(KvsError::NotMatchCmd)
+        ));
+        assert_eq!(kv_store.get(b"k1").await?, Some(b"v1".to_vec()));
+        assert_eq!(kv_store.get(b"k2").await?, None);
+
+        // 事务过大时直接拒绝
+        assert!(matches!(
+            apply_transaction(&kv_store, vec![
+                CommandData::set(b"k3".to_vec(), vec![b'v'; MAX_TRANSACTION_SIZE]),
+            ]).await,
+            Err(KvsError::TransactionTooLarge { limit: MAX_TRANSACTION_SIZE, .. })
+        ));
+        assert_eq!(kv_store.get(b"k3").await?, None);
+
+        // 事务失败时回复错误码与错误信息
+        assert!(matches!(
+            Handler::process(&kv_store, CommandOption::Transaction(vec![CommandData::remove(b"k0".to_vec())])).await?,
+            Some(CommandOption::Error(code, message)) if code == KvsError::KeyNotFound.code() && message == KvsError::KeyNotFound.to_string()
+        ));
+
+        Ok(())
+    })
+}
+
+#[cfg(unix)]
+#[test]
+fn test_command_timeout() -> Result<()> {
+    use std::sync::Mutex;
+    use tempfile::TempDir;
+    use tokio::net::UnixStream;
+
+    /// 将日志写入共享缓冲以便断言
+    #[derive(Clone)]
+    struct LogWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for LogWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if let Ok(mut log) = self.0.lock() {
+                log.extend_from_slice(buf);
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let log_writer = LogWriter(Arc::clone(&log));
+    let subscriber = tracing_subscriber::fmt()
+        .with_ansi(false)
+        .with_writer(move || log_writer.clone())
+        .finish();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    tokio_test::block_on(async move {
+        let kv_store = Arc::new(LsmStore::open(temp_dir.path()).await?);
+        let (notify_shutdown, _) = broadcast::channel(1);
+        let (shutdown_complete_tx, _shutdown_complete_rx) = mpsc::channel(1);
+        let (stream_1, stream_2) = UnixStream::pair()?;
+        let mut client = Connection::new(Box::new(stream_1));
+        let mut handler = Handler {
+            kv_store: Arc::clone(&kv_store),
+            connection: Connection::new(Box::new(stream_2)),
+            shutdown: Shutdown::new(notify_shutdown.subscribe()),
+            command_timeout: Duration::from_millis(50),
+            _shutdown_complete: shutdown_complete_tx
+        };
+        let handle = tokio::spawn(async move { handler.run().await });
+
+        // 持有Manifest的写锁，使MemTable中不存在的Key的读取人为地阻塞
+        let manifest = kv_store.manifest().write().await;
+        client.write(CommandOption::Cmd(CommandData::get(b"slow_key".to_vec()))).await?;
+        assert!(matches!(client.read().await?, CommandOption::Timeout));
+        let log = String::from_utf8_lossy(&log.lock().expect("log lock poisoned")).to_string();
+        assert!(log.contains("Slow Command"));
+        assert!(log.contains("command=\"Get\""));
+        assert!(log.contains("key_len=8"));
+
+        // 超时的命令已被取消，后续命令可正常处理
+        client.write(CommandOption::Cmd(CommandData::set(b"key".to_vec(), b"value".to_vec()))).await?;
+        assert!(matches!(client.read().await?, CommandOption::None));
+
+        // 写入命令不会因超时而被取消，而是等待锁释放后完成
+        client.write(CommandOption::Flush).await?;
+        assert!(time::timeout(Duration::from_millis(200), client.read()).await.is_err());
+        drop(manifest);
+        assert!(matches!(client.read().await?, CommandOption::Flush));
+        client.write(CommandOption::Cmd(CommandData::get(b"key".to_vec()))).await?;
+        assert!(matches!(client.read().await?, CommandOption::Value(value) if value == b"value".to_vec()));
+
+        client.write(CommandOption::None).await?;
+        handle.await.expect("handler panicked")?;
+
+        Ok(())
+    })
+}
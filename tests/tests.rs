@@ -1,11 +1,17 @@
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
+use bytes::Bytes;
 use tempfile::TempDir;
 use walkdir::WalkDir;
+use kip_db::kernel::cipher::Cipher;
 use kip_db::kernel::hash_kv::HashStore;
 use kip_db::kernel::io_handler::IOHandlerFactory;
-use kip_db::kernel::KVStore;
-use kip_db::kernel::lsm::lsm_kv::LsmStore;
+use kip_db::kernel::{CommandData, KVStore, NO_TTL};
+use kip_db::kernel::engine::{self, EngineKind};
+use kip_db::kernel::lsm::lsm_kv::{Checkpoint, Config, LsmStore};
 use kip_db::kernel::Result;
 use kip_db::kernel::sled_kv::SledStore;
+use kip_db::KvsError;
 
 #[test]
 fn get_stored_value() -> Result<()> {
@@ -134,6 +140,32 @@ fn remove_non_existent_key_with_kv_store<T: KVStore>() -> Result<()> {
     })
 }
 
+#[test]
+fn ttl() -> Result<()> {
+    ttl_with_kv_store::<HashStore>()?;
+    ttl_with_kv_store::<SledStore>()?;
+    ttl_with_kv_store::<LsmStore>()?;
+
+    Ok(())
+}
+
+fn ttl_with_kv_store<T: KVStore>() -> Result<()> {
+    tokio_test::block_on(async move {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let kv_store = T::open(temp_dir.path()).await?;
+
+        // 未设置过期时间的Key返回NO_TTL，不存在或已被删除的Key返回None
+        kv_store.set(b"key1", b"value1".to_vec()).await?;
+        assert_eq!(kv_store.ttl(b"key1").await?, Some(NO_TTL));
+        assert_eq!(kv_store.ttl(b"key2").await?, None);
+        kv_store.remove(b"key1").await?;
+        assert_eq!(kv_store.ttl(b"key1").await?, None);
+        assert!(matches!(kv_store.ttl(&[]).await, Err(KvsError::DataEmpty)));
+
+        Ok(())
+    })
+}
+
 #[test]
 fn remove_key() -> Result<()> {
     remove_key_with_kv_store::<HashStore>()?;
@@ -158,7 +190,247 @@ fn remove_key_with_kv_store<T: KVStore>() -> Result<()> {
     })
 }
 
-// Insert data until total size of the directory decreases.
+#[test]
+fn remove_and_get() -> Result<()> {
+    remove_and_get_with_kv_store::<HashStore>()?;
+    remove_and_get_with_kv_store::<SledStore>()?;
+    remove_and_get_with_kv_store::<LsmStore>()?;
+
+    Ok(())
+}
+
+fn remove_and_get_with_kv_store<T: KVStore>() -> Result<()> {
+    tokio_test::block_on(async move {
+        let key1: Vec<u8> = encode_key("key1")?;
+        let key2: Vec<u8> = encode_key("key2")?;
+        let value1: Vec<u8> = encode_key("value1")?;
+        let value2: Vec<u8> = encode_key("value2")?;
+
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let kv_store = T::open(temp_dir.path()).await?;
+        kv_store.set(&key1, value1.clone()).await?;
+        kv_store.flush().await?;
+        kv_store.set(&key2, value2.clone()).await?;
+
+        assert_eq!(kv_store.remove_and_get(&key1).await?, Some(value1));
+        assert_eq!(kv_store.remove_and_get(&key2).await?, Some(value2));
+        assert_eq!(kv_store.get(&key1).await?, None);
+        assert_eq!(kv_store.get(&key2).await?, None);
+
+        // 不存在或已被删除的Key返回None
+        assert_eq!(kv_store.remove_and_get(&key1).await?, None);
+        assert_eq!(kv_store.remove_and_get(&encode_key("key3")?).await?, None);
+        assert!(matches!(kv_store.remove_and_get(&[]).await, Err(KvsError::DataEmpty)));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn remove_batch() -> Result<()> {
+    remove_batch_with_kv_store::<HashStore>()?;
+    remove_batch_with_kv_store::<SledStore>()?;
+    remove_batch_with_kv_store::<LsmStore>()?;
+
+    Ok(())
+}
+
+fn remove_batch_with_kv_store<T: KVStore + Sync>() -> Result<()> {
+    tokio_test::block_on(async move {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let kv_store = T::open(temp_dir.path()).await?;
+        let vec_key = (0..100)
+            .map(|i| encode_key(&format!("key{i}")))
+            .collect::<Result<Vec<_>>>()?;
+        for key in vec_key.iter().take(50) {
+            kv_store.set(key, key.clone()).await?;
+        }
+        kv_store.flush().await?;
+        for key in vec_key.iter().skip(50) {
+            kv_store.set(key, key.clone()).await?;
+        }
+
+        // 不存在与重复的Key不计入删除数量
+        let not_exist = encode_key("not_exist")?;
+        let keys = vec_key.iter()
+            .step_by(2)
+            .map(Vec::as_slice)
+            .chain([vec_key[0].as_slice(), not_exist.as_slice()])
+            .collect::<Vec<_>>();
+        assert_eq!(kv_store.remove_batch(&keys).await?, 50);
+        for (i, key) in vec_key.iter().enumerate() {
+            let expected = (i % 2 == 1).then(|| key.clone());
+            assert_eq!(kv_store.get(key).await?, expected);
+        }
+        assert_eq!(kv_store.remove_batch(&keys).await?, 0);
+        assert!(matches!(kv_store.remove_batch(&[vec_key[1].as_slice(), &[][..]]).await, Err(KvsError::DataEmpty)));
+        assert_eq!(kv_store.get(&vec_key[1]).await?, Some(vec_key[1].clone()));
+
+        // 重新开启后删除仍然生效
+        kv_store.flush().await?;
+        drop(kv_store);
+        let kv_store = T::open(temp_dir.path()).await?;
+        for key in keys.iter() {
+            assert_eq!(kv_store.get(key).await?, None);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn set_if_absent() -> Result<()> {
+    set_if_absent_with_kv_store::<HashStore>()?;
+    set_if_absent_with_kv_store::<SledStore>()?;
+    set_if_absent_with_kv_store::<LsmStore>()?;
+
+    Ok(())
+}
+
+fn set_if_absent_with_kv_store<T: KVStore>() -> Result<()> {
+    tokio_test::block_on(async move {
+        let key1: Vec<u8> = encode_key("key1")?;
+        let key2: Vec<u8> = encode_key("key2")?;
+
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let kv_store = T::open(temp_dir.path()).await?;
+
+        // 并发写入同一Key时仅有一个成功，且value为成功者写入的值
+        let vec_result = futures::future::try_join_all((0..16_u8)
+            .map(|i| kv_store.set_if_absent(&key1, vec![i]))).await?;
+        assert_eq!(vec_result.iter().filter(|is_set| **is_set).count(), 1);
+        let winner = vec_result.iter().position(|is_set| *is_set)
+            .map(|i| vec![i as u8]);
+        assert_eq!(kv_store.get(&key1).await?, winner);
+
+        // 已存在时不覆盖，包括已落盘的数据与空value
+        kv_store.flush().await?;
+        assert!(!kv_store.set_if_absent(&key1, b"other".to_vec()).await?);
+        assert_eq!(kv_store.get(&key1).await?, winner);
+        assert!(kv_store.set_if_absent(&key2, vec![]).await?);
+        assert!(!kv_store.set_if_absent(&key2, b"other".to_vec()).await?);
+        assert_eq!(kv_store.get(&key2).await?, Some(vec![]));
+
+        // 删除后可再次写入
+        kv_store.remove(&key1).await?;
+        assert!(kv_store.set_if_absent(&key1, b"again".to_vec()).await?);
+        assert_eq!(kv_store.get(&key1).await?, Some(b"again".to_vec()));
+        assert!(matches!(kv_store.set_if_absent(&[], vec![]).await, Err(KvsError::DataEmpty)));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn get_bytes() -> Result<()> {
+    get_bytes_with_kv_store::<HashStore>()?;
+    get_bytes_with_kv_store::<SledStore>()?;
+    get_bytes_with_kv_store::<LsmStore>()?;
+
+    Ok(())
+}
+
+fn get_bytes_with_kv_store<T: KVStore>() -> Result<()> {
+    tokio_test::block_on(async move {
+        let key1: Vec<u8> = encode_key("key1")?;
+        let value1: Vec<u8> = vec![b'v'; 1024 * 1024];
+
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let kv_store = T::open(temp_dir.path()).await?;
+        kv_store.set(&key1, value1.clone()).await?;
+        kv_store.flush().await?;
+
+        let bytes = kv_store.get_bytes(&key1).await?.expect("value not found");
+        assert_eq!(bytes.as_ref(), value1.as_slice());
+        // Bytes不持有内核中的锁，持有期间仍可写入，且不受之后写入的影响
+        kv_store.set(&key1, vec![b'k']).await?;
+        kv_store.flush().await?;
+        assert_eq!(bytes.slice(0..3).as_ref(), b"vvv");
+        assert_eq!(bytes.len(), value1.len());
+        assert_eq!(kv_store.get_bytes(&key1).await?.as_deref(), Some(&b"k"[..]));
+        assert_eq!(kv_store.get_bytes(&encode_key("key2")?).await?, None);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn get_reader() -> Result<()> {
+    get_reader_with_kv_store::<HashStore>()?;
+    get_reader_with_kv_store::<SledStore>()?;
+    get_reader_with_kv_store::<LsmStore>()?;
+
+    Ok(())
+}
+
+fn get_reader_with_kv_store<T: KVStore>() -> Result<()> {
+    use tokio::io::AsyncReadExt;
+
+    tokio_test::block_on(async move {
+        let key1: Vec<u8> = encode_key("key1")?;
+        // 包含所有字节取值的value
+        let value1: Vec<u8> = (0..1024 * 1024).map(|i| (i % 251) as u8).collect();
+
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let kv_store = T::open(temp_dir.path()).await?;
+        kv_store.set(&key1, value1.clone()).await?;
+        kv_store.flush().await?;
+
+        let mut reader = kv_store.get_reader(&key1).await?.expect("value not found");
+        let mut vec_value = Vec::new();
+        let _ignore = reader.read_to_end(&mut vec_value).await?;
+        assert_eq!(vec_value, value1);
+        assert_eq!(reader.remaining(), 0);
+
+        kv_store.set(&key1, Vec::new()).await?;
+        let mut vec_value = Vec::new();
+        let _ignore = kv_store.get_reader(&key1).await?
+            .expect("value not found")
+            .read_to_end(&mut vec_value).await?;
+        assert!(vec_value.is_empty());
+        assert!(kv_store.get_reader(&encode_key("key2")?).await?.is_none());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn set_sync() -> Result<()> {
+    set_sync_with_kv_store::<HashStore>()?;
+    set_sync_with_kv_store::<SledStore>()?;
+    set_sync_with_kv_store::<LsmStore>()?;
+
+    Ok(())
+}
+
+// 以复制目录中的文件模拟断电，set_sync返回后的数据在副本中必然存在
+fn set_sync_with_kv_store<T: KVStore>() -> Result<()> {
+    tokio_test::block_on(async move {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let crash_dir = TempDir::new().expect("unable to create temporary working directory");
+        let kv_store = T::open(temp_dir.path()).await?;
+
+        kv_store.set(&encode_key("key1")?, b"value1".to_vec()).await?;
+        kv_store.set_sync(&encode_key("key2")?, b"value2".to_vec()).await?;
+
+        for entry in WalkDir::new(temp_dir.path()) {
+            let entry = entry.expect("unable to walk the directory");
+            let relative = entry.path().strip_prefix(temp_dir.path()).expect("unable to strip prefix");
+            let target = crash_dir.path().join(relative);
+            if entry.file_type().is_dir() {
+                std::fs::create_dir_all(target)?;
+            } else {
+                let _ignore = std::fs::copy(entry.path(), target)?;
+            }
+        }
+
+        let crashed_store = T::open(crash_dir.path()).await?;
+        assert_eq!(crashed_store.get(&encode_key("key2")?).await?, Some(b"value2".to_vec()));
+
+        Ok(())
+    })
+}
+
 // Test data correctness after compaction.
 #[test]
 fn compaction() -> Result<()> {
@@ -216,6 +488,675 @@ fn compaction_with_kv_store<T: KVStore>() -> Result<()> {
     })
 }
 
+#[test]
+fn for_each_kv() -> Result<()> {
+    for_each_kv_with_kv_store::<HashStore>()?;
+    for_each_kv_with_kv_store::<SledStore>()?;
+    for_each_kv_with_kv_store::<LsmStore>()?;
+
+    Ok(())
+}
+
+fn for_each_kv_with_kv_store<T: KVStore>() -> Result<()> {
+    tokio_test::block_on(async move {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let kv_store = T::open(temp_dir.path()).await?;
+
+        for key_id in 0..100 {
+            let key = encode_key(format!("key{}", key_id).as_str())?;
+            kv_store.set(&key, key.clone()).await?;
+        }
+        for key_id in 0..10 {
+            kv_store.remove(&encode_key(format!("key{}", key_id).as_str())?).await?;
+        }
+        kv_store.flush().await?;
+
+        let mut count = 0;
+        kv_store.for_each(|key, value| {
+            assert_eq!(key, value);
+            count += 1;
+            Ok(())
+        }).await?;
+        assert_eq!(count, 90);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn empty_key() -> Result<()> {
+    empty_key_with_kv_store::<HashStore>()?;
+    empty_key_with_kv_store::<SledStore>()?;
+    empty_key_with_kv_store::<LsmStore>()?;
+
+    Ok(())
+}
+
+fn empty_key_with_kv_store<T: KVStore>() -> Result<()> {
+    tokio_test::block_on(async move {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let kv_store = T::open(temp_dir.path()).await?;
+
+        assert!(matches!(kv_store.set(&[], vec![b'v']).await, Err(KvsError::DataEmpty)));
+        assert!(matches!(kv_store.get(&[]).await, Err(KvsError::DataEmpty)));
+        assert!(matches!(kv_store.remove(&[]).await, Err(KvsError::DataEmpty)));
+        assert!(matches!(kv_store.scan(&[], b"key", 10).await, Err(KvsError::DataEmpty)));
+        assert!(kv_store.is_empty().await);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn scan_with_limit() -> Result<()> {
+    scan_with_limit_with_kv_store::<HashStore>()?;
+    scan_with_limit_with_kv_store::<SledStore>()?;
+    scan_with_limit_with_kv_store::<LsmStore>()?;
+
+    Ok(())
+}
+
+fn scan_with_limit_with_kv_store<T: KVStore>() -> Result<()> {
+    tokio_test::block_on(async move {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let kv_store = T::open(temp_dir.path()).await?;
+
+        for key_id in (0..100).rev() {
+            let key = format!("key{:03}", key_id).into_bytes();
+            kv_store.set(&key, key.clone()).await?;
+        }
+        kv_store.flush().await?;
+        for key_id in 10..15 {
+            kv_store.remove(format!("key{:03}", key_id).as_bytes()).await?;
+        }
+
+        let vec_kv = kv_store.scan(b"key010", b"key050", 10).await?;
+        let vec_expected = (15..25)
+            .map(|key_id| format!("key{:03}", key_id).into_bytes())
+            .collect::<Vec<Vec<u8>>>();
+        assert_eq!(vec_kv.len(), 10);
+        for ((key, value), expected) in vec_kv.iter().zip(vec_expected.iter()) {
+            assert_eq!(key, expected);
+            assert_eq!(value, expected);
+        }
+
+        assert_eq!(kv_store.scan(b"key090", b"key100", 100).await?.len(), 10);
+        assert!(kv_store.scan(b"key050", b"key010", 10).await?.is_empty());
+        assert!(kv_store.scan(b"key010", b"key050", 0).await?.is_empty());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn scan_with_bound() -> Result<()> {
+    scan_with_bound_with_kv_store::<HashStore>()?;
+    scan_with_bound_with_kv_store::<SledStore>()?;
+    scan_with_bound_with_kv_store::<LsmStore>()?;
+
+    Ok(())
+}
+
+fn scan_with_bound_with_kv_store<T: KVStore + Sync>() -> Result<()> {
+    tokio_test::block_on(async move {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let kv_store = T::open(temp_dir.path()).await?;
+
+        let vec_key = (0..10)
+            .map(|key_id| format!("key{:03}", key_id).into_bytes())
+            .collect::<Vec<Vec<u8>>>();
+        // 偶数Key持久化后再写入奇数Key，使LsmStore的数据同时位于SSTable与MemTable
+        for key in vec_key.iter().step_by(2) {
+            kv_store.set(key, key.clone()).await?;
+        }
+        kv_store.flush().await?;
+        for key in vec_key.iter().skip(1).step_by(2) {
+            kv_store.set(key, key.clone()).await?;
+        }
+
+        let start_key = b"key003".as_slice();
+        let end_key = b"key007".as_slice();
+        let vec_start = [Bound::Included(start_key), Bound::Excluded(start_key), Bound::Unbounded];
+        let vec_end = [Bound::Included(end_key), Bound::Excluded(end_key), Bound::Unbounded];
+        for start in vec_start {
+            for end in vec_end {
+                let vec_expected = vec_key.iter()
+                    .filter(|key| RangeBounds::<[u8]>::contains(&(start, end), key.as_slice()))
+                    .map(|key| (key.clone(), key.clone()))
+                    .collect::<Vec<(Vec<u8>, Vec<u8>)>>();
+                assert_eq!(kv_store.scan_with_bound(start, end, usize::MAX).await?, vec_expected, "{:?} {:?}", start, end);
+                assert_eq!(kv_store.scan_with_bound(start, end, 1).await?, vec_expected[..1].to_vec());
+            }
+        }
+        // 边界Key命中与排除
+        assert_eq!(kv_store.scan_with_bound(Bound::Included(start_key), Bound::Included(start_key), 10).await?,
+                   vec![(start_key.to_vec(), start_key.to_vec())]);
+        assert!(kv_store.scan_with_bound(Bound::Included(start_key), Bound::Excluded(start_key), 10).await?.is_empty());
+        assert!(kv_store.scan_with_bound(Bound::Excluded(start_key), Bound::Included(start_key), 10).await?.is_empty());
+        assert!(kv_store.scan_with_bound(Bound::Included(end_key), Bound::Included(start_key), 10).await?.is_empty());
+        // 与scan一致
+        assert_eq!(kv_store.scan_with_bound(Bound::Included(start_key), Bound::Excluded(end_key), 10).await?,
+                   kv_store.scan(start_key, end_key, 10).await?);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn max_key() -> Result<()> {
+    max_key_with_kv_store::<HashStore>()?;
+    max_key_with_kv_store::<SledStore>()?;
+    max_key_with_kv_store::<LsmStore>()?;
+
+    Ok(())
+}
+
+fn max_key_with_kv_store<T: KVStore>() -> Result<()> {
+    tokio_test::block_on(async move {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let kv_store = T::open(temp_dir.path()).await?;
+
+        assert_eq!(kv_store.max_key().await?, None);
+
+        kv_store.set(b"key005", b"value".to_vec()).await?;
+        kv_store.set(b"key001", b"value".to_vec()).await?;
+        kv_store.flush().await?;
+        assert_eq!(kv_store.max_key().await?, Some(b"key005".to_vec()));
+
+        // 未持久化的Key大于已持久化的Key
+        kv_store.set(b"key009", b"value".to_vec()).await?;
+        assert_eq!(kv_store.max_key().await?, Some(b"key009".to_vec()));
+        // 已删除的Key仍可能作为上界返回，但不会小于现存的Key
+        kv_store.remove(b"key009").await?;
+        assert!(kv_store.max_key().await? >= Some(b"key005".to_vec()));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn keys_only() -> Result<()> {
+    keys_only_with_kv_store::<HashStore>()?;
+    keys_only_with_kv_store::<SledStore>()?;
+    keys_only_with_kv_store::<LsmStore>()?;
+
+    Ok(())
+}
+
+fn keys_only_with_kv_store<T: KVStore>() -> Result<()> {
+    tokio_test::block_on(async move {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let kv_store = T::open(temp_dir.path()).await?;
+
+        for key_id in (0..100).rev() {
+            let key = format!("key{:03}", key_id).into_bytes();
+            kv_store.set(&key, key.clone()).await?;
+        }
+        kv_store.flush().await?;
+        for key_id in 10..15 {
+            kv_store.remove(format!("key{:03}", key_id).as_bytes()).await?;
+        }
+
+        let vec_key = kv_store.scan_keys(b"key010", b"key050", 10).await?;
+        let vec_expected = (15..25)
+            .map(|key_id| format!("key{:03}", key_id).into_bytes())
+            .collect::<Vec<Vec<u8>>>();
+        assert_eq!(vec_key, vec_expected);
+        assert!(kv_store.scan_keys(b"key050", b"key010", 10).await?.is_empty());
+
+        let mut vec_key = Vec::new();
+        kv_store.for_each_key(|key| {
+            vec_key.push(key.to_vec());
+            Ok(())
+        }).await?;
+        vec_key.sort();
+        let vec_expected = (0..100)
+            .filter(|key_id| !(10..15).contains(key_id))
+            .map(|key_id| format!("key{:03}", key_id).into_bytes())
+            .collect::<Vec<Vec<u8>>>();
+        assert_eq!(vec_key, vec_expected);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn scan_summary() -> Result<()> {
+    scan_summary_with_kv_store::<HashStore>()?;
+    scan_summary_with_kv_store::<SledStore>()?;
+    scan_summary_with_kv_store::<LsmStore>()?;
+
+    Ok(())
+}
+
+fn scan_summary_with_kv_store<T: KVStore>() -> Result<()> {
+    tokio_test::block_on(async move {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let kv_store = T::open(temp_dir.path()).await?;
+
+        // 数据量超过单页，且value长度各不相同
+        for key_id in 0..3000 {
+            let key = format!("key{:04}", key_id).into_bytes();
+            kv_store.set(&key, vec![b'v'; key_id % 17]).await?;
+        }
+        kv_store.flush().await?;
+        for key_id in 100..200 {
+            kv_store.remove(format!("key{:04}", key_id).as_bytes()).await?;
+        }
+
+        for (start, end) in [(b"key0000", b"key3000"), (b"key0050", b"key2500"), (b"key0100", b"key0200")] {
+            let summary = kv_store.scan_summary(start, end).await?;
+            let vec_kv = kv_store.scan(start, end, usize::MAX).await?;
+
+            assert_eq!(summary.count(), vec_kv.len());
+            assert_eq!(summary.total_key_bytes(), vec_kv.iter().map(|(key, _)| key.len() as u64).sum::<u64>());
+            assert_eq!(summary.total_value_bytes(), vec_kv.iter().map(|(_, value)| value.len() as u64).sum::<u64>());
+        }
+        assert_eq!(kv_store.scan_summary(b"key0000", b"key3000").await?.count(), 2900);
+        assert_eq!(kv_store.scan_summary(b"key0100", b"key0200").await?.count(), 0);
+        assert_eq!(kv_store.scan_summary(b"key2000", b"key1000").await?.count(), 0);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn hash_field() -> Result<()> {
+    hash_field_with_kv_store::<HashStore>()?;
+    hash_field_with_kv_store::<SledStore>()?;
+    hash_field_with_kv_store::<LsmStore>()?;
+
+    Ok(())
+}
+
+fn hash_field_with_kv_store<T: KVStore + Sync>() -> Result<()> {
+    tokio_test::block_on(async move {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let kv_store = T::open(temp_dir.path()).await?;
+
+        kv_store.hset(b"user", b"name", b"Kould".to_vec()).await?;
+        kv_store.hset(b"user", b"age", b"18".to_vec()).await?;
+        kv_store.hset(b"user", b"name", b"KipDB".to_vec()).await?;
+        // 含有分隔符的Key与field不会与其他Key的field混淆
+        kv_store.hset(b"user\x00\x01name", b"x", b"1".to_vec()).await?;
+        kv_store.hset(b"user", b"\x00\x01x", b"2".to_vec()).await?;
+        kv_store.hset(b"user\x00", b"name", b"3".to_vec()).await?;
+        kv_store.hset(b"users", b"name", b"4".to_vec()).await?;
+        kv_store.set(b"user", b"plain".to_vec()).await?;
+        kv_store.flush().await?;
+
+        assert_eq!(kv_store.hget(b"user", b"name").await?, Some(b"KipDB".to_vec()));
+        assert_eq!(kv_store.hget(b"user", b"email").await?, None);
+        assert_eq!(kv_store.hget(b"user\x00", b"name").await?, Some(b"3".to_vec()));
+        assert_eq!(kv_store.get(b"user").await?, Some(b"plain".to_vec()));
+        assert_eq!(kv_store.hgetall(b"user").await?, vec![
+            (b"\x00\x01x".to_vec(), b"2".to_vec()),
+            (b"age".to_vec(), b"18".to_vec()),
+            (b"name".to_vec(), b"KipDB".to_vec())
+        ]);
+        assert_eq!(kv_store.hgetall(b"user\x00\x01name").await?, vec![(b"x".to_vec(), b"1".to_vec())]);
+        assert_eq!(kv_store.hgetall(b"nobody").await?, vec![]);
+
+        // 普通的前缀扫描
+        assert_eq!(kv_store.prefix_scan(b"use", usize::MAX).await?.len(), 7);
+        assert_eq!(kv_store.prefix_scan(b"users", usize::MAX).await?.len(), 1);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn set_batch() -> Result<()> {
+    set_batch_with_kv_store::<HashStore>()?;
+    set_batch_with_kv_store::<SledStore>()?;
+    set_batch_with_kv_store::<LsmStore>()?;
+
+    Ok(())
+}
+
+fn set_batch_with_kv_store<T: KVStore>() -> Result<()> {
+    tokio_test::block_on(async move {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let kv_store = T::open(temp_dir.path()).await?;
+
+        let pairs = (0..100)
+            .map(|key_id| {
+                let key = format!("key{}", key_id).into_bytes();
+                (key.clone(), key)
+            })
+            .collect::<Vec<(Vec<u8>, Vec<u8>)>>();
+        kv_store.set_batch(pairs).await?;
+        // 同一批中重复的Key以靠后的为准
+        kv_store.set_batch(vec![
+            (b"key0".to_vec(), vec![b'1']),
+            (b"key0".to_vec(), vec![b'2'])
+        ]).await?;
+        kv_store.remove(b"key1").await?;
+        assert!(matches!(kv_store.set_batch(vec![(vec![], vec![b'v'])]).await, Err(KvsError::DataEmpty)));
+        kv_store.flush().await?;
+
+        for key_id in 2..100 {
+            let key = format!("key{}", key_id).into_bytes();
+            assert_eq!(kv_store.get(&key).await?, Some(key));
+        }
+        assert_eq!(kv_store.get(b"key0").await?, Some(vec![b'2']));
+        assert_eq!(kv_store.get(b"key1").await?, None);
+
+        // Open from disk again and check persistent data.
+        kv_store.flush().await?;
+        drop(kv_store);
+        let kv_store = T::open(temp_dir.path()).await?;
+        for key_id in 2..100 {
+            let key = format!("key{}", key_id).into_bytes();
+            assert_eq!(kv_store.get(&key).await?, Some(key));
+        }
+        assert_eq!(kv_store.get(b"key0").await?, Some(vec![b'2']));
+        assert_eq!(kv_store.get(b"key1").await?, None);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn write_batch_atomic() -> Result<()> {
+    write_batch_atomic_with_kv_store::<HashStore>()?;
+    write_batch_atomic_with_kv_store::<SledStore>()?;
+    write_batch_atomic_with_kv_store::<LsmStore>()?;
+
+    Ok(())
+}
+
+fn write_batch_atomic_with_kv_store<T: KVStore>() -> Result<()> {
+    tokio_test::block_on(async move {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        {
+            let kv_store = T::open(temp_dir.path()).await?;
+            kv_store.set(b"key0", b"0".to_vec()).await?;
+
+            // 批次中先写入的Key可被之后的Remove删除
+            kv_store.write_batch_atomic(vec![
+                CommandData::set(b"key1".to_vec(), b"1".to_vec()),
+                CommandData::set(b"key2".to_vec(), b"2".to_vec()),
+                CommandData::remove(b"key2".to_vec()),
+                CommandData::remove(b"key0".to_vec()),
+            ]).await?;
+
+            // 中途失败时整体不生效
+            assert!(matches!(
+                kv_store.write_batch_atomic(vec![
+                    CommandData::set(b"key3".to_vec(), b"3".to_vec()),
+                    CommandData::remove(b"key1".to_vec()),
+                    CommandData::remove(b"key4".to_vec()),
+                ]).await,
+                Err(KvsError::KeyNotFound)
+            ));
+            assert!(matches!(
+                kv_store.write_batch_atomic(vec![
+                    CommandData::set(b"key3".to_vec(), b"3".to_vec()),
+                    CommandData::set(Vec::new(), b"3".to_vec()),
+                ]).await,
+                Err(KvsError::DataEmpty)
+            ));
+            kv_store.flush().await?;
+        }
+
+        let kv_store = T::open(temp_dir.path()).await?;
+        assert_eq!(kv_store.get(b"key0").await?, None);
+        assert_eq!(kv_store.get(b"key1").await?, Some(b"1".to_vec()));
+        assert_eq!(kv_store.get(b"key2").await?, None);
+        assert_eq!(kv_store.get(b"key3").await?, None);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn batch_order_lenient() -> Result<()> {
+    batch_order_lenient_with_kv_store::<HashStore>()?;
+    batch_order_lenient_with_kv_store::<SledStore>()?;
+    batch_order_lenient_with_kv_store::<LsmStore>()?;
+
+    Ok(())
+}
+
+fn batch_order_lenient_with_kv_store<T: KVStore>() -> Result<()> {
+    tokio_test::block_on(async move {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let kv_store = T::open(temp_dir.path()).await?;
+
+        // 删除不存在的Key出错，其余命令仍被执行
+        let vec_result = kv_store.batch_order_lenient(vec![
+            CommandData::set(b"key1".to_vec(), b"1".to_vec()),
+            CommandData::remove(b"key2".to_vec()),
+            CommandData::set(b"key3".to_vec(), b"3".to_vec()),
+            CommandData::get(b"key1".to_vec()),
+        ]).await;
+
+        assert_eq!(vec_result.len(), 4);
+        assert!(matches!(vec_result[0], Ok(None)));
+        assert!(matches!(vec_result[1], Err(KvsError::KeyNotFound)));
+        assert!(matches!(vec_result[2], Ok(None)));
+        assert!(matches!(&vec_result[3], Ok(Some(value)) if value == b"1"));
+        assert_eq!(kv_store.get(b"key3").await?, Some(b"3".to_vec()));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn diff() -> Result<()> {
+    diff_with_kv_store::<HashStore, LsmStore>()?;
+    diff_with_kv_store::<SledStore, HashStore>()?;
+    diff_with_kv_store::<LsmStore, SledStore>()?;
+
+    Ok(())
+}
+
+fn diff_with_kv_store<L: KVStore + Sync, R: KVStore + Sync>() -> Result<()> {
+    tokio_test::block_on(async move {
+        let left_dir = TempDir::new().expect("unable to create temporary working directory");
+        let right_dir = TempDir::new().expect("unable to create temporary working directory");
+        let left_store = L::open(left_dir.path()).await?;
+        let right_store = R::open(right_dir.path()).await?;
+        assert!(left_store.diff(&right_store).await?.is_consistent());
+
+        // 数据量超过一页以覆盖分页归并
+        let vec_kv = (0..3000)
+            .map(|i| (format!("key{:05}", i).into_bytes(), format!("value{}", i).into_bytes()))
+            .collect::<Vec<_>>();
+        left_store.set_batch(vec_kv.clone()).await?;
+        right_store.set_batch(vec_kv).await?;
+        assert!(left_store.diff(&right_store).await?.is_consistent());
+
+        left_store.remove(b"key00010").await?;
+        right_store.remove(b"key02000").await?;
+        right_store.set(b"key01500", b"changed".to_vec()).await?;
+        right_store.set(b"key99999", b"extra".to_vec()).await?;
+
+        let report = left_store.diff(&right_store).await?;
+        assert_eq!(report.only_left(), &[b"key02000".to_vec()]);
+        assert_eq!(report.only_right(), &[b"key00010".to_vec(), b"key99999".to_vec()]);
+        assert_eq!(report.value_mismatch(), &[b"key01500".to_vec()]);
+        assert_eq!(report.count(), 4);
+
+        // 限制数量时仅记录前limit个差异，count仍为差异总数
+        let report = left_store.diff_with_limit(&right_store, 1).await?;
+        assert_eq!(report.only_right(), &[b"key00010".to_vec()]);
+        assert_eq!(report.count(), 4);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn estimate_range_size() -> Result<()> {
+    tokio_test::block_on(async move {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let config = Config::default()
+            .dir_path(temp_dir.path().to_path_buf())
+            .wal_enable(false);
+        let kv_store = LsmStore::open_with_config(config).await?;
+
+        for i in 0..10000 {
+            let key = format!("key{:05}", i).into_bytes();
+            kv_store.set(&key, vec![b'v'; 100]).await?;
+        }
+        kv_store.flush().await?;
+
+        let size_small = kv_store.estimate_range_size(b"key00000", b"key00999").await?;
+        let size_middle = kv_store.estimate_range_size(b"key00000", b"key04999").await?;
+        let size_full = kv_store.estimate_range_size(b"key00000", b"key09999").await?;
+
+        assert!(size_small > 0);
+        assert!(size_small <= size_middle);
+        assert!(size_middle <= size_full);
+        assert!(size_full <= kv_store.size_of_disk().await?);
+        assert_eq!(kv_store.estimate_range_size(b"z", b"zz").await?, 0);
+        // 仅包含单个Key的范围同样计入其所处的整个数据段
+        let size_single = kv_store.estimate_range_size(b"key00001", b"key00001").await?;
+        assert!(size_single > 1024);
+        assert!(size_single <= size_small);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn open_snapshot() -> Result<()> {
+    tokio_test::block_on(async move {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let key1: Vec<u8> = encode_key("key1")?;
+        let key2: Vec<u8> = encode_key("key2")?;
+        let value1: Vec<u8> = encode_key("value1")?;
+        let value2: Vec<u8> = encode_key("value2")?;
+
+        let kv_store = LsmStore::open(temp_dir.path()).await?;
+        kv_store.set(&key1, value1.clone()).await?;
+        kv_store.flush().await?;
+
+        let snapshot = LsmStore::open_snapshot(temp_dir.path()).await?;
+        kv_store.set(&key2, value2.clone()).await?;
+        kv_store.flush().await?;
+
+        assert_eq!(snapshot.get(&key1).await?, Some(value1));
+        assert_eq!(snapshot.get(&key2).await?, None);
+        assert_eq!(kv_store.get(&key2).await?, Some(value2));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn open_snapshot_with_config() -> Result<()> {
+    tokio_test::block_on(async move {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let config = || Config::default()
+            .dir_path(temp_dir.path().to_path_buf())
+            .cipher(Cipher::new(&[7; 32]));
+        let key1: Vec<u8> = encode_key("key1")?;
+        let value1: Vec<u8> = encode_key("value1")?;
+
+        let kv_store = LsmStore::open_with_config(config()).await?;
+        kv_store.set(&key1, value1.clone()).await?;
+        kv_store.flush().await?;
+
+        // 以原数据库的Config开启快照，才能以相同的密钥读取加密的SSTable
+        let snapshot = LsmStore::open_snapshot_with_config(config()).await?;
+        assert_eq!(snapshot.get(&key1).await?, Some(value1));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn checkpoint() -> Result<()> {
+    tokio_test::block_on(async move {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let kv_store = LsmStore::open(temp_dir.path()).await?;
+
+        for i in 0..100 {
+            let key = format!("key{:03}", i).into_bytes();
+            kv_store.set(&key, key.clone()).await?;
+        }
+        let checkpoint = kv_store.checkpoint().await?;
+        assert!(!checkpoint.gens_with_crc().is_empty());
+
+        // 仅读取时数据未变化
+        for i in 0..100 {
+            let key = format!("key{:03}", i).into_bytes();
+            assert_eq!(kv_store.get(&key).await?, Some(key));
+        }
+        assert!(kv_store.verify_against(&checkpoint).await?);
+        assert_eq!(kv_store.checkpoint().await?, checkpoint);
+
+        kv_store.set(b"key100", vec![b'v']).await?;
+        kv_store.flush().await?;
+        assert!(!kv_store.verify_against(&checkpoint).await?);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn incremental_backup() -> Result<()> {
+    tokio_test::block_on(async move {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let backup_dir = TempDir::new().expect("unable to create temporary working directory");
+        let vec_backup_path = vec![backup_dir.path().join("backup_1"), backup_dir.path().join("backup_2")];
+        // value较大的数据分离至vLog之中
+        let config = |path| Config::default()
+            .dir_path(path)
+            .kv_separation_enable(true)
+            .kv_separation_threshold(64);
+        let value = |i: usize| vec![b'v'; if i % 2 == 0 { 8 } else { 128 }];
+        let kv_store = LsmStore::open_with_config(config(temp_dir.path().join("data"))).await?;
+        // 备份目录中SSTable位于根目录，Wal、vLog与ts记录的文件位于各自的子目录中
+        let ss_table_count = |path: &Path| -> Result<usize> {
+            Ok(std::fs::read_dir(path)?
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.path().is_file())
+                .count())
+        };
+
+        for i in 0..100 {
+            kv_store.set(format!("key{:03}", i).as_bytes(), value(i)).await?;
+        }
+        kv_store.flush().await?;
+        let checkpoint_1 = kv_store.incremental_backup(Checkpoint::default(), &vec_backup_path[0]).await?;
+        assert_eq!(ss_table_count(&vec_backup_path[0])?, checkpoint_1.gens_with_crc().len());
+        assert!(std::fs::read_dir(vec_backup_path[0].join("vlog"))?.count() > 0);
+
+        for i in 100..200 {
+            kv_store.set(format!("key{:03}", i).as_bytes(), value(i)).await?;
+        }
+        kv_store.flush().await?;
+        // 备份时仍处于MemTable中的数据随Wal一同备份
+        for i in 200..300 {
+            kv_store.set(format!("key{:03}", i).as_bytes(), value(i)).await?;
+        }
+        // 第二次增量备份仅拷贝新增的SSTable，且不会将MemTable落盘
+        let checkpoint_2 = kv_store.incremental_backup(checkpoint_1.clone(), &vec_backup_path[1]).await?;
+        let vec_new_gen = checkpoint_2.gens_with_crc()
+            .into_iter()
+            .filter(|gen_with_crc| !checkpoint_1.gens_with_crc().contains(gen_with_crc))
+            .collect::<Vec<_>>();
+        assert!(!vec_new_gen.is_empty());
+        assert_eq!(ss_table_count(&vec_backup_path[1])?, vec_new_gen.len());
+        // 备份后MemTable中的数据仍未落盘，落盘后生成新的SSTable
+        assert_ne!(kv_store.checkpoint().await?, checkpoint_2);
+
+        // 按备份链叠加恢复
+        let restore_path = temp_dir.path().join("restore");
+        LsmStore::restore_from_backups(&checkpoint_2, &vec_backup_path, &restore_path)?;
+        let restored_store = LsmStore::open_with_config(config(restore_path)).await?;
+        for i in 0..300 {
+            assert_eq!(restored_store.get(format!("key{:03}", i).as_bytes()).await?, Some(value(i)));
+        }
+
+        Ok(())
+    })
+}
+
 #[test]
 fn test_io() -> Result<()> {
     let temp_dir = TempDir::new().expect("unable to create temporary working directory");
@@ -241,6 +1182,106 @@ fn test_io() -> Result<()> {
     })
 }
 
+// 空value是合法的值，与Key不存在不同
+#[test]
+fn empty_value() -> Result<()> {
+    empty_value_with_kv_store::<HashStore>()?;
+    empty_value_with_kv_store::<SledStore>()?;
+    empty_value_with_kv_store::<LsmStore>()?;
+
+    Ok(())
+}
+
+fn empty_value_with_kv_store<T: KVStore>() -> Result<()> {
+    tokio_test::block_on(async move {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let key1 = encode_key("key1")?;
+        let key2 = encode_key("key2")?;
+        let key3 = encode_key("key3")?;
+        {
+            let kv_store = T::open(temp_dir.path()).await?;
+            kv_store.set(&key1, vec![]).await?;
+            kv_store.set_batch(vec![(key2.clone(), vec![])]).await?;
+
+            assert!(kv_store.contains_key(&key1).await?);
+            assert!(kv_store.contains_key(&key2).await?);
+            assert!(!kv_store.contains_key(&key3).await?);
+            assert_eq!(kv_store.get(&key1).await?, Some(vec![]));
+            assert_eq!(kv_store.get(&key3).await?, None);
+            kv_store.flush().await?;
+        }
+
+        // 持久化后空value仍与Key不存在不同
+        let kv_store = T::open(temp_dir.path()).await?;
+        assert!(kv_store.contains_key(&key1).await?);
+        assert_eq!(kv_store.get(&key1).await?, Some(vec![]));
+        assert_eq!(kv_store.get(&key2).await?, Some(vec![]));
+        assert_eq!(kv_store.scan(&key1, &key3, 10).await?, vec![(key1.clone(), vec![]), (key2.clone(), vec![])]);
+
+        assert_eq!(kv_store.remove_and_get(&key1).await?, Some(vec![]));
+        assert!(!kv_store.contains_key(&key1).await?);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn open_engine() -> Result<()> {
+    tokio_test::block_on(async move {
+        for name in ["hash", "sled", "LSM"] {
+            let kind: EngineKind = name.parse()?;
+            let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+            {
+                let kv_store = engine::open_engine(kind, temp_dir.path()).await?;
+                assert!(kv_store.is_empty().await);
+                for key_id in 0..100 {
+                    let key = format!("key{:03}", key_id).into_bytes();
+                    kv_store.set(&key, key.clone()).await?;
+                }
+                kv_store.remove(b"key000").await?;
+                kv_store.flush().await?;
+
+                assert_eq!(kv_store.get(b"key001").await?, Some(b"key001".to_vec()));
+                assert_eq!(kv_store.get(b"key000").await?, None);
+                assert_eq!(kv_store.len().await?, 99);
+                assert_eq!(kv_store.scan_keys(b"key010", b"key013", 10).await?,
+                           vec![b"key010".to_vec(), b"key011".to_vec(), b"key012".to_vec()]);
+            }
+
+            // 以同一引擎重新开启后数据一致
+            let kv_store = engine::open_engine(kind, temp_dir.path()).await?;
+            assert_eq!(kv_store.len().await?, 99);
+            assert_eq!(kv_store.remove_and_get(b"key099").await?, Some(b"key099".to_vec()));
+            assert!(!kv_store.contains_key(b"key099").await?);
+        }
+        assert!(matches!("rocksdb".parse::<EngineKind>(), Err(KvsError::UnknownEngine(_))));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_io_pre_allocate() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    tokio_test::block_on(async move {
+        let factory = IOHandlerFactory::new(temp_dir.path());
+        let handler1 = factory.create(1)?;
+        handler1.pre_allocate(1024).await?;
+        assert_eq!(handler1.file_size().await?, 1024);
+
+        let data_write = vec![b'1', b'2', b'3', b'4', b'5', b'6'];
+        let (pos, len) = handler1.write(data_write.clone()).await?;
+        handler1.truncate().await?;
+
+        assert_eq!(pos, 0);
+        assert_eq!(len, 6);
+        assert_eq!(handler1.file_size().await?, 6);
+        assert_eq!(handler1.read_with_pos(0, 6).await?, data_write);
+
+        Ok(())
+    })
+}
+
 fn encode_key(key: &str) -> Result<Vec<u8>>{
     Ok(rmp_serde::to_vec(key)?)
-}
\ No newline at end of file
+}